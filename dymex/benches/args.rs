@@ -0,0 +1,37 @@
+//! `cargo +nightly bench --bench args`: evaluation of a large generated
+//! expression of operators and functions of up to four arguments, whose
+//! evaluands keep their argument ids inline (`ArgIds`). Run it on the revision
+//! before the inline ids (a `Vec` per evaluand) to compare.
+#![feature(test)]
+extern crate test;
+
+use test::Bencher;
+use dymex::*;
+
+/// Number of the generated terms, about 10 nodes each
+const TERMS: usize = 2_000;
+
+fn inputs() -> InputVars {
+    let mut inputs = InputVars::new();
+    inputs.insert_owned("x".into(), 1.5);
+    inputs.insert_owned("y".into(), -0.25);
+    inputs.insert_owned("z".into(), 4.0);
+    inputs
+}
+
+/// `(x * 1 - y) / (z + 1) + max(x, y, z, 1) - ...`, operators and functions
+/// of up to four arguments, the common case
+fn expression() -> String {
+    (0..TERMS).map(|i| match i % 3 {
+        0 => format!("(x * {} - y) / (z + {})", i, i + 1),
+        1 => format!("max(x, y, z, {}) * x", i),
+        _ => format!("where(x > {}, y, z) ** 2", i),
+    }).collect::<Vec<String>>().join(" + ")
+}
+
+#[bench]
+fn evaluate_large(b: &mut Bencher) {
+    let inputs = inputs();
+    let mut evaluator = Evaluator::new(&expression(), &inputs.names()).unwrap();
+    b.iter(|| evaluator.evaluate(&inputs).unwrap());
+}
@@ -0,0 +1,34 @@
+//! `cargo +nightly bench --bench pow`: powers with a small literal exponent
+//! (`v ** 3`, evaluated as `v * v * v`) against the same powers through `powf()`
+//! (the exponent is a variable)
+#![feature(test)]
+extern crate test;
+
+use test::Bencher;
+use dymex::*;
+
+fn inputs() -> InputVars {
+    let mut inputs = InputVars::new();
+    inputs.insert_owned("v".into(), (0..10_000).map(|i| i as Float * 1e-3).collect::<Vec<Float>>());
+    inputs.insert_owned("two".into(), 2.0);
+    inputs.insert_owned("three".into(), 3.0);
+    inputs
+}
+
+fn bench(b: &mut Bencher, expression: &str) {
+    let inputs = inputs();
+    let mut evaluator = Evaluator::new(expression, &inputs.names()).unwrap();
+    b.iter(|| evaluator.evaluate(&inputs).unwrap());
+}
+
+#[bench]
+fn square_literal(b: &mut Bencher) { bench(b, "v ** 2") }
+
+#[bench]
+fn square_powf(b: &mut Bencher) { bench(b, "v ** two") }
+
+#[bench]
+fn cube_literal(b: &mut Bencher) { bench(b, "v ** 3") }
+
+#[bench]
+fn cube_powf(b: &mut Bencher) { bench(b, "v ** three") }
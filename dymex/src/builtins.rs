@@ -0,0 +1,199 @@
+//! Metadata of the built in functions and constants, for autocompletion and help
+
+use crate::{Constant, Function, MAX_FUNC_ARGS};
+
+
+/// Group of a built in, for listing them in help panels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BuiltinCategory {
+    /// `min`, `sum`, ... of arrays or of their arguments
+    Aggregation,
+    /// Elementwise mathematical functions
+    Math,
+    /// Rounding to an increment
+    Rounding,
+    /// Time series and durations
+    Timeseries,
+    /// Functions with anonymous function arguments and local variables
+    Functional,
+    /// Conditions and missing values
+    Conditional,
+    /// Indices of elements
+    Search,
+    /// Numerical integration, differentiation and root finding
+    Calculus,
+    Polynomial,
+    Units,
+    Bitwise,
+    Text,
+    Constant,
+}
+
+/// What a built in name refers to
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuiltinKind {
+    Function(Function),
+    Constant(Constant),
+}
+
+/// Description of a built in function or constant, see `builtins()`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Builtin {
+    /// Name in expressions
+    pub name: &'static str,
+    pub kind: BuiltinKind,
+    /// Minimal number of arguments, 0 for the constants
+    pub min_args: usize,
+    /// Maximal number of arguments, the limit of the arguments (64) for the variadic functions
+    pub max_args: usize,
+    /// Names of the parameters, empty for the variadic functions and the constants
+    pub params: &'static [&'static str],
+    pub category: BuiltinCategory,
+    /// One line description
+    pub description: &'static str,
+    /// Valid expression using the built in
+    pub example: &'static str,
+}
+
+impl Builtin {
+    pub fn is_variadic(&self) -> bool {
+        self.max_args == MAX_FUNC_ARGS
+    }
+
+    /// Signature for help texts: `shift(series, n)`, `min(...)` or `pi`
+    pub fn signature(&self) -> String {
+        match self.kind {
+            BuiltinKind::Constant(_) => self.name.to_string(),
+            _ if self.is_variadic() => format!("{}(...)", self.name),
+            _ => format!("{}({})", self.name, self.params.join(", "))
+        }
+    }
+}
+
+/// The built in functions (in the order of `Function::ALL`) and the named
+/// constants of the expressions:
+/// ```
+/// # use dymex::*;
+/// let sqrt = builtins().into_iter().find(|b| b.name == "sqrt").unwrap();
+/// assert_eq!(sqrt.signature(), "sqrt(x)");
+/// assert_eq!(sqrt.category, BuiltinCategory::Math);
+/// ```
+pub fn builtins() -> Vec<Builtin> {
+    let functions = Function::ALL.iter().map(|f| {
+        let (category, description, example) = describe_function(f);
+        Builtin {
+            name: f.name(),
+            kind: BuiltinKind::Function(f.clone()),
+            min_args: f.min_args(),
+            max_args: f.max_args(),
+            params: f.param_names(),
+            category,
+            description,
+            example,
+        }
+    });
+    let constants = CONSTANTS.iter().map(|(name, constant, description)| {
+        Builtin {
+            name,
+            kind: BuiltinKind::Constant(constant.clone()),
+            min_args: 0,
+            max_args: 0,
+            params: &[],
+            category: BuiltinCategory::Constant,
+            description,
+            example: name,
+        }
+    });
+    functions.chain(constants).collect()
+}
+
+/// The constants with a name in expressions, see `parse_const()` of the tokenizer
+const CONSTANTS: [(&str, Constant, &str); 4] = [
+    ("e", Constant::Euler, "Euler's number, 2.71828..."),
+    ("pi", Constant::Pi, "Ratio of the circumference of a circle to its diameter, 3.14159..., also `π`"),
+    ("sqrt2", Constant::Sqrt2, "Square root of 2, 1.41421..."),
+    ("sqrt3", Constant::Sqrt3, "Square root of 3, 1.73205..."),
+];
+
+fn describe_function(function: &Function) -> (BuiltinCategory, &'static str, &'static str) {
+    use BuiltinCategory::*;
+    match function {
+        Function::Min => (Aggregation, "Minimum of an array or of the arguments", "min(v)"),
+        Function::Max => (Aggregation, "Maximum of an array or of the arguments", "max(a, b, 0)"),
+        Function::Avg => (Aggregation, "Mean of an array or of the arguments, also `mean`", "avg(v)"),
+        Function::Std => (Aggregation, "Standard deviation of an array or of the arguments", "std(v)"),
+        Function::Sum => (Aggregation, "Sum of an array, of the arguments or of a function over an index range", "sum(i -> 1 / i ** 2, 1, 10)"),
+        Function::Prod => (Aggregation, "Product of an array, of the arguments or of a function over an index range", "prod(v)"),
+        Function::Range => (Aggregation, "Difference of the maximum and the minimum", "range(v)"),
+        Function::Abs => (Math, "Absolute value", "abs(x)"),
+        Function::Sin => (Math, "Sine of an angle in radians", "sin(x)"),
+        Function::Cos => (Math, "Cosine of an angle in radians", "cos(x)"),
+        Function::Tan => (Math, "Tangent of an angle in radians", "tan(x)"),
+        Function::Cot => (Math, "Cotangent of an angle in radians", "cotan(x)"),
+        Function::Exp => (Math, "Exponential function, `e ** x`", "exp(x)"),
+        Function::Log => (Math, "Natural logarithm", "ln(x)"),
+        Function::Log2 => (Math, "Base 2 logarithm", "log2(x)"),
+        Function::Log10 => (Math, "Base 10 logarithm", "log10(x)"),
+        Function::Sqrt => (Math, "Square root", "sqrt(x)"),
+        Function::Resample => (Timeseries, "Linearly interpolated values of a time series on a regular grid with `dt` spacing", "resample(ts, 60)"),
+        Function::Shift => (Timeseries, "Values shifted by `n` samples, a lag for positive `n`", "ts - shift(ts, 1)"),
+        Function::Now => (Timeseries, "Current time in seconds since the Unix epoch", "now()"),
+        Function::Days => (Timeseries, "Duration of `x` days in seconds", "now() - days(2)"),
+        Function::Hours => (Timeseries, "Duration of `x` hours in seconds", "hours(12)"),
+        Function::Map => (Functional, "Applies the function to each element", "map(v, x -> x * x)"),
+        Function::Filter => (Functional, "Elements for which the predicate is true", "filter(v, x -> x > 0)"),
+        Function::Reduce => (Functional, "Combines the elements with the function, starting with `init`", "reduce(v, (a, b) -> a + b, 0)"),
+        Function::With => (Functional, "Expression with local variables", "with(t = x * 2, sin(t) + t)"),
+        Function::Between => (Conditional, "Whether `x` is in the closed interval `[lo, hi]`", "between(x, 0, 1)"),
+        Function::IsNa => (Conditional, "Whether the values are missing (NA)", "isna(v)"),
+        Function::Coalesce => (Conditional, "Missing values replaced by the values of the next argument", "coalesce(v, 0)"),
+        Function::Where => (Conditional, "Elements of `a` where the mask is true, of `b` elsewhere", "where(v > 0, v, 0)"),
+        Function::Argmin => (Search, "Index of the minimum", "argmin(v)"),
+        Function::Argmax => (Search, "Index of the maximum", "argmax(v)"),
+        Function::Find => (Search, "Index of the first element equal to `x`, NA if there is none", "find(v, 3)"),
+        Function::Trapz => (Calculus, "Integral of `y` over `x` by the trapezoidal rule", "trapz(y, x)"),
+        Function::Gradient => (Calculus, "Derivative of `y` with respect to `x` by finite differences", "gradient(y, x)"),
+        Function::Solve => (Calculus, "Root of `f(x) - target` in the interval `[lo, hi]`", "solve(x -> x ** 3, 8, 0, 10)"),
+        Function::Polyval => (Polynomial, "Value of the polynomial with the coefficients at `x`", "polyval(c, x)"),
+        Function::Polyfit => (Polynomial, "Coefficients of the least squares polynomial fit of degree `deg`", "polyfit(x, y, 2)"),
+        Function::Convert => (Units, "Value converted from one unit to another", "convert(x, \"eV\", \"nm\")"),
+        Function::RoundTo => (Rounding, "Rounded to the nearest multiple of `increment`", "round_to(x, 0.5)"),
+        Function::FloorTo => (Rounding, "Rounded down to a multiple of `increment`", "floor_to(x, 0.5)"),
+        Function::CeilTo => (Rounding, "Rounded up to a multiple of `increment`", "ceil_to(x, 0.5)"),
+        Function::RoundEven => (Rounding, "Rounded to the nearest multiple of `increment`, ties to even", "round_even(x, 1)"),
+        Function::BitAnd => (Bitwise, "Bitwise and of integers", "band(flags, 4)"),
+        Function::BitOr => (Bitwise, "Bitwise or of integers", "bor(flags, 4)"),
+        Function::BitXor => (Bitwise, "Bitwise exclusive or of integers", "bxor(flags, 4)"),
+        Function::Shl => (Bitwise, "Integer shifted left by `n` bits", "shl(x, 2)"),
+        Function::Shr => (Bitwise, "Integer shifted right by `n` bits", "shr(x, 2)"),
+        Function::Format => (Text, "Text with the `{}` placeholders replaced by the arguments", "format(\"peak at {:.1} nm\", x)"),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_builtins() {
+        let builtins = builtins();
+        assert_eq!(builtins.len(), Function::ALL.len() + 4);
+        for (function, builtin) in Function::ALL.iter().zip(&builtins) {
+            assert_eq!(Function::from_name(builtin.name).as_ref(), Some(function));
+        }
+        for builtin in &builtins {
+            assert!(AST::from_expression(builtin.example).is_ok(), "invalid example: {}", builtin.example);
+            assert!(builtin.example.contains(builtin.name));
+        }
+
+        let shift = builtins.iter().find(|b| b.name == "shift").unwrap();
+        assert_eq!((shift.min_args, shift.max_args, shift.signature()), (2, 2, "shift(series, n)".to_string()));
+        let sum = builtins.iter().find(|b| b.name == "sum").unwrap();
+        assert!(sum.is_variadic());
+        assert_eq!(sum.signature(), "sum(...)");
+        let pi = builtins.iter().find(|b| b.name == "pi").unwrap();
+        assert_eq!(pi.kind, BuiltinKind::Constant(Constant::Pi));
+        assert_eq!(Evaluator::new(pi.example, &[]).unwrap().constant_value().unwrap().as_number(), float::consts::PI);
+    }
+}
@@ -0,0 +1,204 @@
+//! Translations of the user facing error messages (`UserMessage`)
+
+use std::collections::HashMap;
+
+
+/// Identifier of a user facing error message, the key of its translations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageId {
+    SyntaxError,
+    InvalidCharacter,
+    InvalidNumberFormat,
+    UnclosedEscape,
+    UnclosedString,
+    UnclosedComment,
+    InvalidVariableName,
+    UnsupportedSyntax,
+    UnexpectedToken,
+    UnexpectedLP,
+    MissingRP,
+    MissingArgument,
+    TooManyArguments,
+    InvalidOperation,
+    NotImplemented,
+    UndefinedVariable,
+    InvalidAssignment,
+    InvalidArgument,
+    InvalidJson,
+    MissingParameter,
+    UnusedVariable,
+    ConstantComparison,
+    IntegerDivision,
+    ShadowedAssignment,
+    UnknownFunction,
+    ForbiddenFunction,
+    InvalidConfiguration,
+}
+
+impl MessageId {
+    pub const ALL: [MessageId; 27] = [
+        Self::SyntaxError, Self::InvalidCharacter, Self::InvalidNumberFormat, Self::UnclosedEscape,
+        Self::UnclosedString, Self::UnclosedComment, Self::InvalidVariableName, Self::UnsupportedSyntax,
+        Self::UnexpectedToken, Self::UnexpectedLP, Self::MissingRP, Self::MissingArgument,
+        Self::TooManyArguments, Self::InvalidOperation, Self::NotImplemented, Self::UndefinedVariable,
+        Self::InvalidAssignment, Self::InvalidArgument, Self::InvalidJson, Self::MissingParameter,
+        Self::UnusedVariable, Self::ConstantComparison, Self::IntegerDivision, Self::ShadowedAssignment,
+        Self::UnknownFunction, Self::ForbiddenFunction, Self::InvalidConfiguration,
+    ];
+
+    /// Stable name of the message in the translation files: `undefined-variable`
+    pub fn key(&self) -> &'static str {
+        match self {
+            Self::SyntaxError => "syntax-error",
+            Self::InvalidCharacter => "invalid-character",
+            Self::InvalidNumberFormat => "invalid-number-format",
+            Self::UnclosedEscape => "unclosed-escape",
+            Self::UnclosedString => "unclosed-string",
+            Self::UnclosedComment => "unclosed-comment",
+            Self::InvalidVariableName => "invalid-variable-name",
+            Self::UnsupportedSyntax => "unsupported-syntax",
+            Self::UnexpectedToken => "unexpected-token",
+            Self::UnexpectedLP => "unexpected-lp",
+            Self::MissingRP => "missing-rp",
+            Self::MissingArgument => "missing-argument",
+            Self::TooManyArguments => "too-many-arguments",
+            Self::InvalidOperation => "invalid-operation",
+            Self::NotImplemented => "not-implemented",
+            Self::UndefinedVariable => "undefined-variable",
+            Self::InvalidAssignment => "invalid-assignment",
+            Self::InvalidArgument => "invalid-argument",
+            Self::InvalidJson => "invalid-json",
+            Self::MissingParameter => "missing-parameter",
+            Self::UnusedVariable => "unused-variable",
+            Self::ConstantComparison => "constant-comparison",
+            Self::IntegerDivision => "integer-division",
+            Self::ShadowedAssignment => "shadowed-assignment",
+            Self::UnknownFunction => "unknown-function",
+            Self::ForbiddenFunction => "forbidden-function",
+            Self::InvalidConfiguration => "invalid-configuration",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|id| id.key() == key)
+    }
+}
+
+/// Source of translated messages for `UserMessage::translate()`. The messages
+/// are templates, `{name}` is replaced by the argument `name` of the message
+/// (see `UserMessage::args()`), e.g. `Nem definiált változó: {name}`.
+/// Messages without a translation are kept in English.
+pub trait MessageCatalog {
+    /// The message `id`
+    fn message(&self, id: MessageId) -> Option<&str>;
+
+    /// The hint shown after the expression for the message `id`, it replaces
+    /// the hint of the messages that have one
+    fn hint(&self, _id: MessageId) -> Option<&str> {
+        None
+    }
+}
+
+/// Translation table, filled by `insert()` or read from a file:
+/// ```text
+/// # comment
+/// undefined-variable = Nem definiált változó: `{name}`
+/// undefined-variable.hint = Erre gondolt: `{suggestion}`?
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Translations {
+    messages: HashMap<MessageId, String>,
+    hints: HashMap<MessageId, String>,
+}
+
+impl Translations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse `key = message` and `key.hint = hint` lines, see `MessageId::key()`.
+    /// The error is the line number and the reason.
+    pub fn parse(text: &str) -> Result<Self, (usize, String)> {
+        let mut translations = Self::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, text)) = line.split_once('=') else {
+                return Err((n + 1, "expected `key = message`".into()));
+            };
+            let (key, is_hint) = match key.trim().strip_suffix(".hint") {
+                Some(key) => (key, true),
+                None => (key.trim(), false)
+            };
+            let Some(id) = MessageId::from_key(key) else {
+                return Err((n + 1, format!("unknown message `{}`", key)));
+            };
+            match is_hint {
+                true => translations.insert_hint(id, text.trim()),
+                false => translations.insert(id, text.trim())
+            }
+        }
+        Ok(translations)
+    }
+
+    pub fn insert(&mut self, id: MessageId, message: impl Into<String>) {
+        self.messages.insert(id, message.into());
+    }
+
+    pub fn insert_hint(&mut self, id: MessageId, hint: impl Into<String>) {
+        self.hints.insert(id, hint.into());
+    }
+}
+
+impl MessageCatalog for Translations {
+    fn message(&self, id: MessageId) -> Option<&str> {
+        self.messages.get(&id).map(|m| m.as_str())
+    }
+
+    fn hint(&self, id: MessageId) -> Option<&str> {
+        self.hints.get(&id).map(|h| h.as_str())
+    }
+}
+
+/// `template` with the `{name}`s replaced by the values of `args`
+pub(crate) fn fill_template(template: &str, args: &[(&'static str, String)]) -> String {
+    let mut text = template.to_string();
+    for (name, value) in args {
+        text = text.replace(&format!("{{{}}}", name), value);
+    }
+    text
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_translated_messages() {
+        let translations = Translations::parse("
+            # hungarian
+            undefined-variable = Nem definiált változó: `{name}`
+            undefined-variable.hint = Erre gondolt: `{suggestion}`?
+        ").unwrap();
+        let expression = "x + yy";
+        let err = validate(expression, &["x", "y"]).err().unwrap();
+        let message = err.user_message();
+        assert_eq!(message.id(), Some(MessageId::UndefinedVariable));
+        assert_eq!(message.args(), [("name", "yy".to_string()), ("suggestion", "y".to_string())]);
+        assert_eq!(message.translate(&translations).full_message(expression),
+            "Nem definiált változó: `yy`\nx + yy\n    ^\nErre gondolt: `y`?\n");
+
+        // untranslated messages stay in English
+        let err = TokenStream::new("x + 1.2.3").unwrap_err();
+        let message = err.user_message().translate(&translations);
+        assert_eq!(message.to_string(), "Invalid number formatting:\n");
+        assert_eq!(message.hint(), Some("Valid formats are: 1, 3.14, 1e-10, 1.23E10, 1E+9, 1_000_000"));
+
+        assert!(MessageId::ALL.iter().all(|id| MessageId::from_key(id.key()) == Some(*id)));
+        assert_eq!(Translations::parse("no-such-message = ?").unwrap_err().0, 1);
+        assert_eq!(Translations::parse("\nmissing separator").unwrap_err().0, 2);
+    }
+}
@@ -0,0 +1,115 @@
+use crate::Float;
+use super::{DynMath, EvaluationError};
+
+
+/// Handling of the results outside of `[min, max]`, applied to the result of
+/// every operation (not to the inputs), see `Evaluator::set_arithmetic_mode()`.
+/// Only numbers and arrays of numbers are bounded, NaN is left as it is.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ArithmeticMode {
+    /// The results are not bounded
+    #[default]
+    Unbounded,
+    /// The evaluation fails with `EvaluationError::OutOfRange`
+    Checked { min: Float, max: Float },
+    /// The results are clamped to the bounds
+    Saturating { min: Float, max: Float },
+    /// The results wrap around into `[min, max)`, as the integers of fixed
+    /// width: with the bounds `0` and `360`, `350 + 20` is `10`
+    Wrapping { min: Float, max: Float },
+}
+
+impl ArithmeticMode {
+    /// Checks the bounds: none of them is NaN, `min <= max`, and for `Wrapping`
+    /// they are finite and `min < max`. The error is the reason.
+    pub fn validate(&self) -> Result<(), String> {
+        let (min, max) = match *self {
+            Self::Unbounded => return Ok(()),
+            Self::Checked { min, max } | Self::Saturating { min, max } | Self::Wrapping { min, max } => (min, max),
+        };
+        if min.is_nan() || max.is_nan() {
+            return Err(format!("a bound of [{}, {}] is NaN", min, max));
+        }
+        match self {
+            Self::Wrapping { .. } if !min.is_finite() || !max.is_finite() || min >= max =>
+                Err(format!("the bounds [{}, {}] of wrapping need to be finite and min < max", min, max)),
+            _ if min > max => Err(format!("the bounds [{}, {}] are not ordered", min, max)),
+            _ => Ok(())
+        }
+    }
+
+    /// The number `x` according to the mode
+    pub fn apply_number(&self, x: Float) -> Result<Float, EvaluationError> {
+        match *self {
+            _ if x.is_nan() => Ok(x),
+            Self::Unbounded => Ok(x),
+            Self::Checked { min, max } if x < min || x > max => Err(EvaluationError::OutOfRange { value: x, min, max }),
+            Self::Checked { .. } => Ok(x),
+            Self::Saturating { min, max } => Ok(x.clamp(min, max)),
+            Self::Wrapping { min, max } => Ok(min + (x - min).rem_euclid(max - min)),
+        }
+    }
+
+    /// The result of an operation according to the mode
+    pub(crate) fn apply(&self, value: Box<dyn DynMath>) -> Result<Box<dyn DynMath>, EvaluationError> {
+        if *self == Self::Unbounded {
+            return Ok(value);
+        }
+        if let Some(x) = value.downcast_ref::<Float>() {
+            return self.apply_number(*x).map(|x| Box::new(x) as Box<dyn DynMath>);
+        }
+        if let Some(v) = value.downcast_ref::<Vec<Float>>() {
+            let bounded: Result<Vec<Float>, EvaluationError> = v.iter().map(|x| self.apply_number(*x)).collect();
+            return bounded.map(|v| Box::new(v) as Box<dyn DynMath>);
+        }
+        Ok(value)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn evaluate(expression: &str, mode: ArithmeticMode) -> Result<Box<dyn DynMath>, EvaluationError> {
+        let config = EvaluatorConfig { arithmetic: mode, ..Default::default() };
+        let Ok(mut evaluator) = Evaluator::configured(expression, &["x", "v"], &config) else { panic!() };
+        let mut inputs = InputVars::new();
+        inputs.insert_owned("x".into(), 250.0);
+        inputs.insert_owned("v".into(), vec![-10.0, 50.0, 300.0]);
+        evaluator.evaluate(&inputs)
+    }
+
+    #[test]
+    fn test_arithmetic_modes() {
+        let checked = ArithmeticMode::Checked { min: 0.0, max: 255.0 };
+        assert_eq!(evaluate("x + 5", checked).unwrap().as_number(), 255.0);
+        assert!(matches!(evaluate("x + 10 - 10", checked), Err(EvaluationError::OutOfRange { value: 260.0, .. })));
+        assert_eq!(evaluate("v", checked).unwrap().downcast_ref::<Vec<Float>>(), Some(&vec![-10.0, 50.0, 300.0]));
+        assert!(matches!(evaluate("max(v)", checked), Err(EvaluationError::OutOfRange { value: 300.0, .. })));
+
+        let saturating = ArithmeticMode::Saturating { min: 0.0, max: 255.0 };
+        assert_eq!(evaluate("x + 10 - 10", saturating).unwrap().as_number(), 245.0);
+        assert_eq!(evaluate("v * 1", saturating).unwrap().downcast_ref::<Vec<Float>>(), Some(&vec![0.0, 50.0, 255.0]));
+        assert_eq!(evaluate("x * 2 > 300", saturating).unwrap().downcast_ref::<bool>(), Some(&false));
+
+        let wrapping = ArithmeticMode::Wrapping { min: 0.0, max: 360.0 };
+        assert_eq!(evaluate("x + 120", wrapping).unwrap().as_number(), 10.0);
+        assert_eq!(evaluate("-x", wrapping).unwrap().as_number(), 110.0);
+        assert_eq!(evaluate("map(v, a -> a + 100)", wrapping).unwrap().downcast_ref::<Vec<Float>>(), Some(&vec![90.0, 150.0, 40.0]));
+
+        assert!(evaluate("x * 0 / 0", checked).unwrap().as_number().is_nan());
+
+        for mode in [ArithmeticMode::Saturating { min: 1.0, max: 0.0 }, ArithmeticMode::Checked { min: Float::NAN, max: 1.0 },
+            ArithmeticMode::Wrapping { min: 1.0, max: 1.0 }, ArithmeticMode::Wrapping { min: 0.0, max: Float::INFINITY }] {
+            assert!(mode.validate().is_err());
+            let config = EvaluatorConfig { arithmetic: mode, ..Default::default() };
+            assert!(matches!(Evaluator::configured("x", &["x"], &config),
+                Err(DymexError::ParsingError(ParsingError::InvalidConfiguration(_)))));
+            let mut evaluator = Evaluator::new("x", &["x"]).unwrap();
+            assert!(matches!(evaluator.set_arithmetic_mode(mode), Err(EvaluationError::InvalidArguments { .. })));
+        }
+        assert!(ArithmeticMode::Checked { min: 1.0, max: 1.0 }.validate().is_ok());
+        assert_eq!(evaluate("x * 1e300 * 1e300", ArithmeticMode::Unbounded).unwrap().as_number(), float::INFINITY);
+    }
+}
@@ -0,0 +1,233 @@
+use std::any::Any;
+use std::fmt;
+use std::slice::Iter;
+use std::sync::Arc;
+use arrow_array::{Array, ArrayRef, BooleanArray, Float64Array, RecordBatch};
+use crate::{float, Float, MAXDIM, RelationalOperator};
+use super::{DynMath, ResultFormatter, EvaluationError, Category, InputVars, Unary};
+use super::boolean::compare_numbers;
+
+type Binary = fn(Float, Float) -> Float;
+
+
+/// Arrow `Float64Array` column (without nulls) as an array input. The buffer
+/// of the column is shared, not copied: cloning the column or the value is cheap
+/// and the elements are read in place. Results of operations are `Vec<Float>`.
+#[derive(Debug, Clone)]
+pub struct ArrowColumn(Float64Array);
+
+impl ArrowColumn {
+    /// `None` if the column has null values, see `InputVars::insert_arrow()`
+    pub fn new(column: Float64Array) -> Option<Self> {
+        match column.null_count() {
+            0 => Some(Self(column)),
+            _ => None
+        }
+    }
+
+    pub fn values(&self) -> &[Float] {
+        self.0.values()
+    }
+
+    pub fn into_inner(self) -> Float64Array {
+        self.0
+    }
+
+    /// Apply `f` elementwise with a number or another array, other values are
+    /// handled by `fallback` (called with a copy of the column)
+    fn binary<F>(&self, other: &dyn DynMath, f: Binary, fallback: F) -> Result<Box<dyn DynMath>, EvaluationError>
+    where F: FnOnce(&Vec<Float>) -> Result<Box<dyn DynMath>, EvaluationError> {
+        match other.category() {
+            Category::Number => Ok(Box::new(
+                self.values().iter().map(|a| f(*a, other.as_number())).collect::<Vec<Float>>()
+            )),
+            Category::Array => Ok(Box::new(
+                self.values().iter().zip(other.iterate()).map(|(a, b)| f(*a, *b)).collect::<Vec<Float>>()
+            )),
+            Category::Unqiue => fallback(&self.values().to_vec())
+        }
+    }
+}
+
+impl DynMath for ArrowColumn {
+
+    fn clone_boxed(&self) -> Box<dyn DynMath> { Box::new(self.clone()) }
+
+    fn category(&self) -> Category { Category::Array }
+
+    fn shape(&self) -> [usize; MAXDIM] {
+        let mut shape = [0; MAXDIM];
+        shape[0] = self.0.len();
+        shape
+    }
+
+    /// The buffer is shared with the Arrow column, it is not counted
+    fn memory_size(&self) -> usize { size_of::<Self>() }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn fmt_value(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", ResultFormatter::new().display(self))
+    }
+
+    fn iterate(&self) -> Iter<'_, Float> {
+        self.values().iter()
+    }
+
+    fn elementwise(&self, func: Unary) -> Result<Box<dyn DynMath>, EvaluationError> {
+        Ok(Box::new(self.values().iter().map(|x| func(*x)).collect::<Vec<Float>>()))
+    }
+
+    fn add(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| a + b, |v| v.add(other))
+    }
+    fn sub(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| a - b, |v| v.sub(other))
+    }
+    fn sub_inv(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| b - a, |v| v.sub_inv(other))
+    }
+    fn mul(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| a * b, |v| v.mul(other))
+    }
+    fn div(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| a / b, |v| v.div(other))
+    }
+    fn div_inv(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| b / a, |v| v.div_inv(other))
+    }
+    fn pow(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| a.powf(b), |v| v.pow(other))
+    }
+    fn pow_inv(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| b.powf(a), |v| v.pow_inv(other))
+    }
+
+    fn compare(&self, other: &dyn DynMath, op: &RelationalOperator) -> Result<Box<dyn DynMath>, EvaluationError> {
+        match other.category() {
+            Category::Number => Ok(Box::new(
+                self.values().iter().map(|a| compare_numbers(*a, other.as_number(), op)).collect::<Vec<bool>>()
+            )),
+            Category::Array => Ok(Box::new(
+                self.values().iter().zip(other.iterate()).map(|(a, b)| compare_numbers(*a, *b, op)).collect::<Vec<bool>>()
+            )),
+            Category::Unqiue => self.values().to_vec().compare(other, op)
+        }
+    }
+
+    fn index(&self, index: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.values().to_vec().index(index)
+    }
+
+    fn min(&self) -> Result<Float, EvaluationError> {
+        Ok(self.values().iter().fold(float::INFINITY, |a, &b| a.min(b)))
+    }
+    fn max(&self) -> Result<Float, EvaluationError> {
+        Ok(self.values().iter().fold(float::NEG_INFINITY, |a, &b| a.max(b)))
+    }
+    fn range(&self) -> Result<Float, EvaluationError> {
+        Ok(self.max()? - self.min()?)
+    }
+    fn sum(&self) -> Result<Float, EvaluationError> {
+        Ok(self.values().iter().sum::<Float>())
+    }
+    fn prod(&self) -> Result<Float, EvaluationError> {
+        Ok(self.values().iter().product::<Float>())
+    }
+    fn avg(&self) -> Result<Float, EvaluationError> {
+        Ok(self.sum()? / (self.0.len() as Float))
+    }
+    fn std(&self) -> Result<Float, EvaluationError> {
+        let avg = self.avg()?;
+        let sq_err = self.values().iter().map(|e| (e-avg)*(e-avg)).sum::<Float>();
+        Ok((sq_err / (self.0.len() as Float)).sqrt())
+    }
+    fn l2_norm(&self) -> Result<Float, EvaluationError> {
+        Ok(self.values().iter().map(|e| e*e).sum::<Float>().sqrt())
+    }
+    fn l1_norm(&self) -> Result<Float, EvaluationError> {
+        Ok(self.values().iter().map(|e| e.abs()).sum::<Float>())
+    }
+}
+
+
+impl InputVars {
+    /// Insert an Arrow column as the array variable `name`. Columns without
+    /// nulls are shared (`ArrowColumn`), the ones with nulls are copied into
+    /// a `Vec<Option<Float>>`, where the nulls are missing values (`NA`).
+    pub fn insert_arrow(&mut self, name: String, column: &Float64Array) {
+        match ArrowColumn::new(column.clone()) {
+            Some(column) => self.insert_owned(name, column),
+            None => self.insert_owned(name, column.iter().collect::<Vec<Option<Float>>>())
+        }
+    }
+
+    /// The `Float64` columns of `batch` as array variables, named after the fields
+    /// of the schema. Record batches are also what Parquet readers produce.
+    pub fn from_record_batch(batch: &RecordBatch) -> Self {
+        let mut inputs = Self::new();
+        let schema = batch.schema();
+        for (field, column) in schema.fields().iter().zip(batch.columns()) {
+            if let Some(column) = column.as_any().downcast_ref::<Float64Array>() {
+                inputs.insert_arrow(field.name().clone(), column);
+            }
+        }
+        inputs
+    }
+}
+
+impl dyn DynMath {
+    /// The value as an Arrow array: arrays as `Float64Array` (`ArrowColumn` without
+    /// copying), arrays with missing values with nulls and boolean arrays as
+    /// `BooleanArray`. `None` for other values, like numbers.
+    pub fn to_arrow(&self) -> Option<ArrayRef> {
+        if let Some(column) = self.downcast_ref::<ArrowColumn>() {
+            return Some(Arc::new(column.0.clone()));
+        }
+        match self.category() {
+            Category::Array => Some(Arc::new(Float64Array::from(self.iterate().as_slice().to_vec()))),
+            _ => if let Some(v) = self.downcast_ref::<Vec<bool>>() {
+                Some(Arc::new(BooleanArray::from(v.clone())))
+            } else {
+                self.downcast_ref::<Vec<Option<Float>>>().map(|v| Arc::new(Float64Array::from(v.clone())) as ArrayRef)
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use arrow_array::{Array, ArrayRef, BooleanArray, Float64Array, RecordBatch};
+    use crate::*;
+
+    #[test]
+    fn test_arrow_columns() {
+        let x = Float64Array::from(vec![1.0, 2.0, 4.0]);
+        let y = Float64Array::from(vec![Some(1.0), None, Some(3.0)]);
+        let batch = RecordBatch::try_from_iter([("x", Arc::new(x.clone()) as ArrayRef), ("y", Arc::new(y) as ArrayRef)]).unwrap();
+        let inputs = InputVars::from_record_batch(&batch);
+
+        // the column without nulls shares the buffer
+        let column = inputs.get("x").unwrap().downcast_ref::<ArrowColumn>().unwrap();
+        assert_eq!(column.values().as_ptr(), x.values().as_ptr());
+        assert!(inputs.get("y").unwrap().is::<Vec<Option<Float>>>());
+
+        let mut evaluate = |expr: &str| Evaluator::new(expr, &inputs.names()).unwrap().evaluate(&inputs).unwrap();
+        assert_eq!(evaluate("sum(x) + max(x)").as_number(), 11.0);
+        assert_eq!(evaluate("1 - x / 2").iterate().as_slice(), &[0.5, 0.0, -1.0]);
+        assert_eq!(evaluate("x[-1]").as_number(), 4.0);
+
+        let result = evaluate("x * 2").to_arrow().unwrap();
+        assert_eq!(result.as_any().downcast_ref::<Float64Array>().unwrap(), &Float64Array::from(vec![2.0, 4.0, 8.0]));
+        let result = evaluate("x > 1").to_arrow().unwrap();
+        assert_eq!(result.as_any().downcast_ref::<BooleanArray>().unwrap(), &BooleanArray::from(vec![false, true, true]));
+        let result = evaluate("y + x").to_arrow().unwrap();
+        assert_eq!(result.null_count(), 1);
+        assert!(evaluate("x").to_arrow().is_some());
+        assert!(evaluate("sum(x)").to_arrow().is_none());
+    }
+}
@@ -0,0 +1,117 @@
+use std::cell::RefCell;
+use std::time::Duration;
+use crate::{Category, DynMath, EvaluationError, Fingerprint, ResultFormatter};
+
+
+/// Record of a single `Evaluator::evaluate()` call, see `Evaluator::set_audit_sink()`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    /// Fingerprint of the evaluated expression, see `AST::fingerprint()`
+    pub fingerprint: Fingerprint,
+    /// The input variables read by the expression, sorted
+    pub inputs: Vec<String>,
+    pub duration: Duration,
+    pub outcome: AuditOutcome,
+}
+
+/// Result of an audited evaluation, without the values of arrays
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuditOutcome {
+    /// Numbers as they are, other values as their type and shape: `Array [1000]`
+    Value(String),
+    /// The code (see `EvaluationError::code()`) and the message of the error
+    Error { code: &'static str, message: String },
+}
+
+impl AuditOutcome {
+    pub(crate) fn of(result: &Result<Box<dyn DynMath>, EvaluationError>) -> Self {
+        match result {
+            Ok(value) if matches!(value.category(), Category::Number) => Self::Value(ResultFormatter::new().format(&**value)),
+            Ok(value) => {
+                let shape: Vec<String> = value.shape().iter()
+                    .take_while(|n| **n > 0)
+                    .map(|n| n.to_string())
+                    .collect();
+                Self::Value(format!("{} [{}]", value.type_name(), shape.join(", ")))
+            }
+            Err(err) => Self::Error { code: err.code(), message: err.to_string() }
+        }
+    }
+}
+
+/// Destination of the audit entries, e.g. a compliance log. The entries are
+/// recorded by the evaluator itself, so no evaluation is missed by the call sites.
+pub trait AuditSink {
+    fn record(&self, entry: AuditEntry);
+}
+
+/// Audit sink keeping the entries in memory
+#[derive(Debug, Default)]
+pub struct AuditLog {
+    entries: RefCell<Vec<AuditEntry>>,
+}
+
+impl AuditLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entries(&self) -> Vec<AuditEntry> {
+        self.entries.borrow().clone()
+    }
+
+    /// Remove and return the recorded entries
+    pub fn drain(&self) -> Vec<AuditEntry> {
+        self.entries.take()
+    }
+}
+
+impl AuditSink for AuditLog {
+    fn record(&self, entry: AuditEntry) {
+        self.entries.borrow_mut().push(entry);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use crate::*;
+
+    #[test]
+    fn test_audit_log() {
+        let log = Rc::new(AuditLog::new());
+        let mut evaluator = Evaluator::new("sum(v) / n", &["v", "n"]).unwrap();
+        evaluator.set_audit_sink(Some(log.clone()));
+        let mut inputs = InputVars::new();
+        inputs.insert_owned("v".into(), vec![1.0, 2.0, 3.0]);
+        inputs.insert_owned("n".into(), 2.0);
+        inputs.insert_owned("unused".into(), 0.0);
+        evaluator.evaluate(&inputs).unwrap();
+        let mut inputs = InputVars::new();
+        inputs.insert_owned("n".into(), 2.0);
+        assert!(evaluator.evaluate(&inputs).is_err());
+
+        let entries = log.drain();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].fingerprint, evaluator.fingerprint());
+        assert_eq!(entries[0].inputs, ["n", "v"]);
+        assert_eq!(entries[0].outcome, AuditOutcome::Value("3".into()));
+        assert!(matches!(&entries[1].outcome, AuditOutcome::Error { code: "E205", .. }));
+        assert!(log.entries().is_empty());
+
+        // arrays are summarized, constant results are also recorded
+        let mut evaluator = Evaluator::new("v * 2", &["v"]).unwrap();
+        evaluator.set_audit_sink(Some(log.clone()));
+        let mut inputs = InputVars::new();
+        inputs.insert_owned("v".into(), vec![1.0, 2.0, 3.0]);
+        evaluator.evaluate(&inputs).unwrap();
+        let mut constant = Evaluator::new("2 ** 10", &[]).unwrap();
+        constant.set_audit_sink(Some(log.clone()));
+        constant.evaluate(&InputVars::new()).unwrap();
+        // folding the constant again is not an evaluation
+        constant.set_missing_policy(MissingPolicy::Skip);
+        let outcomes: Vec<AuditOutcome> = log.entries().into_iter().map(|e| e.outcome).collect();
+        assert_eq!(outcomes, [AuditOutcome::Value("Array [3]".into()), AuditOutcome::Value("1024".into())]);
+    }
+}
@@ -0,0 +1,78 @@
+use crate::Float;
+use super::{DynMath, EvaluationError, Category, Missing};
+use super::boolean::broadcast;
+use super::number::as_integer;
+
+
+/// Bitwise functions of integers, for status registers and flags:
+/// `band(status, 4) > 0`. As for `xor`, the integers are numbers without a
+/// fractional part, the bits are those of their 64 bit two's complement.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bitwise {
+    /// `band(a, b)`
+    And,
+    /// `bor(a, b)`
+    Or,
+    /// `bxor(a, b)`
+    Xor,
+    /// `shl(x, n)`: `x` shifted left by `n` bits
+    Shl,
+    /// `shr(x, n)`: `x` shifted right by `n` bits, keeping the sign
+    Shr,
+}
+
+impl Bitwise {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::And => "band",
+            Self::Or => "bor",
+            Self::Xor => "bxor",
+            Self::Shl => "shl",
+            Self::Shr => "shr",
+        }
+    }
+
+    fn apply(&self, a: Float, b: Float) -> Result<Float, String> {
+        let (a, b) = match (as_integer(a), as_integer(b)) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return Err(format!("expects integers, not {} and {}", a, b))
+        };
+        let shift = || match u32::try_from(b) {
+            Ok(n) if n < i64::BITS => Ok(n),
+            _ => Err(format!("the shift should be between 0 and {}, not {}", i64::BITS - 1, b))
+        };
+        let result = match self {
+            Self::And => a & b,
+            Self::Or => a | b,
+            Self::Xor => a ^ b,
+            Self::Shl => a << shift()?,
+            Self::Shr => a >> shift()?,
+        };
+        Ok(result as Float)
+    }
+}
+
+/// Bitwise `op` of the integers `a` and `b`, elementwise if either is an array
+pub fn dynmath_bitwise(a: &dyn DynMath, b: &dyn DynMath, op: Bitwise) -> Result<Box<dyn DynMath>, EvaluationError> {
+    let invalid = |details: String| EvaluationError::InvalidArguments { function: op.name().into(), details };
+    if a.is::<Missing>() || b.is::<Missing>() {
+        return Ok(Box::new(Missing));
+    }
+    match (a.category(), b.category()) {
+        (Category::Number, Category::Number) => op.apply(a.as_number(), b.as_number())
+            .map(|x| Box::new(x) as Box<dyn DynMath>)
+            .map_err(invalid),
+        (Category::Array, _) | (_, Category::Array) => {
+            let n = a.shape()[0].max(b.shape()[0]);
+            match (broadcast(a, n), broadcast(b, n)) {
+                (Some(a), Some(b)) => a.zip(b)
+                    .map(|(a, b)| op.apply(a, b))
+                    .collect::<Result<Vec<Float>, String>>()
+                    .map(|v| Box::new(v) as Box<dyn DynMath>)
+                    .map_err(invalid),
+                _ => Err(invalid("expects arrays of the same length".into()))
+            }
+        }
+        _ => Err(invalid(format!("expects numbers or arrays, not `{}` and `{}`", a.type_name(), b.type_name())))
+    }
+}
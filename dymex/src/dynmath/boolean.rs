@@ -0,0 +1,151 @@
+use crate::{Float, MAXDIM, RelationalOperator};
+use super::{DynMath, ResultFormatter, EvaluationError, Category, Missing, unimpl_binary};
+use std::any::Any;
+use std::fmt;
+
+
+/// `a op b` for numbers, NaN compares unequal to everything
+pub(super) fn compare_numbers(a: Float, b: Float, op: &RelationalOperator) -> bool {
+    match op {
+        RelationalOperator::Greater => a > b,
+        RelationalOperator::Lesser => a < b,
+        RelationalOperator::Eq => a == b,
+        RelationalOperator::Neq => a != b,
+        RelationalOperator::Leq => a <= b,
+        RelationalOperator::Geq => a >= b,
+    }
+}
+
+
+/// Result of comparisons and the logical operators
+impl DynMath for bool {
+
+    fn clone_boxed(&self) -> Box<dyn DynMath> { Box::new(*self) }
+
+    fn category(&self) -> Category { Category::Unqiue }
+
+    fn shape(&self) -> [usize; MAXDIM] { [0; MAXDIM] }
+
+    fn type_name(&self) -> &'static str { "Bool" }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn fmt_value(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", ResultFormatter::new().display(self))
+    }
+
+    fn compare(&self, other: &dyn DynMath, op: &RelationalOperator) -> Result<Box<dyn DynMath>, EvaluationError>
+    {
+        match (other.downcast_ref::<bool>(), op) {
+            (Some(other), RelationalOperator::Eq) => Ok(Box::new(self == other)),
+            (Some(other), RelationalOperator::Neq) => Ok(Box::new(self != other)),
+            _ => unimpl_binary(self.type_name(), other.type_name(), &op.to_string())
+        }
+    }
+
+    fn and(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
+    {
+        match other.downcast_ref::<bool>() {
+            Some(other) => Ok(Box::new(*self && *other)),
+            None if other.is::<Missing>() => Missing.and(self),
+            None if other.is::<Vec<bool>>() => other.and(self),
+            None => unimpl_binary(self.type_name(), other.type_name(), "and")
+        }
+    }
+
+    fn or(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
+    {
+        match other.downcast_ref::<bool>() {
+            Some(other) => Ok(Box::new(*self || *other)),
+            None if other.is::<Missing>() => Missing.or(self),
+            None if other.is::<Vec<bool>>() => other.or(self),
+            None => unimpl_binary(self.type_name(), other.type_name(), "or")
+        }
+    }
+}
+
+
+/// Elementwise comparison of arrays, used as a mask: `v[v > 0]`, `where(v > 0, v, 0)`
+impl DynMath for Vec<bool> {
+
+    fn clone_boxed(&self) -> Box<dyn DynMath> { Box::new(self.clone()) }
+
+    fn category(&self) -> Category { Category::Unqiue }
+
+    fn shape(&self) -> [usize; MAXDIM] {
+        let mut shape = [0; MAXDIM];
+        shape[0] = self.len();
+        shape
+    }
+
+    fn type_name(&self) -> &'static str { "Bool array" }
+
+    fn memory_size(&self) -> usize { size_of::<Self>() + self.len() * size_of::<bool>() }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn fmt_value(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", ResultFormatter::new().display(self))
+    }
+
+    fn compare(&self, other: &dyn DynMath, op: &RelationalOperator) -> Result<Box<dyn DynMath>, EvaluationError>
+    {
+        match op {
+            RelationalOperator::Eq => elementwise(self, other, |a, b| a == b, "=="),
+            RelationalOperator::Neq => elementwise(self, other, |a, b| a != b, "≠"),
+            _ => unimpl_binary(self.type_name(), other.type_name(), &op.to_string())
+        }
+    }
+
+    fn and(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
+    {
+        elementwise(self, other, |a, b| a && b, "and")
+    }
+
+    fn or(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
+    {
+        elementwise(self, other, |a, b| a || b, "or")
+    }
+}
+
+/// `f(a, b)` for the elements of `mask` and `other` (a `bool` or a `Vec<bool>`)
+fn elementwise(mask: &[bool], other: &dyn DynMath, f: fn(bool, bool) -> bool, op: &str)
+-> Result<Box<dyn DynMath>, EvaluationError> {
+    if let Some(b) = other.downcast_ref::<bool>() {
+        return Ok(Box::new(mask.iter().map(|a| f(*a, *b)).collect::<Vec<bool>>()));
+    }
+    match other.downcast_ref::<Vec<bool>>() {
+        Some(w) => Ok(Box::new(mask.iter().zip(w).map(|(a, b)| f(*a, *b)).collect::<Vec<bool>>())),
+        None => unimpl_binary("Bool array", other.type_name(), op)
+    }
+}
+
+/// `where(mask, a, b)`: `a` where `mask` is true and `b` elsewhere. The mask is a
+/// `bool` or a `Vec<bool>`, the values are numbers or arrays of the same length.
+pub fn dynmath_where(mask: &dyn DynMath, a: &dyn DynMath, b: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+    if let Some(m) = mask.downcast_ref::<bool>() {
+        return Ok(if *m { a.clone_boxed() } else { b.clone_boxed() });
+    }
+    let invalid = |details: String| Err(EvaluationError::InvalidArguments { function: "where".into(), details });
+    let Some(mask) = mask.downcast_ref::<Vec<bool>>() else {
+        return invalid(format!("the mask should be a boolean, not `{}`", mask.type_name()));
+    };
+    let (Some(a), Some(b)) = (broadcast(a, mask.len()), broadcast(b, mask.len())) else {
+        return invalid(format!("expects numbers or arrays of {} elements", mask.len()));
+    };
+    let selected: Vec<Float> = mask.iter().zip(a.zip(b)).map(|(m, (a, b))| if *m { a } else { b }).collect();
+    Ok(Box::new(selected))
+}
+
+/// The `n` elements of an array of length `n`, or a number repeated `n` times
+pub(super) fn broadcast(x: &dyn DynMath, n: usize) -> Option<Box<dyn Iterator<Item = Float> + '_>> {
+    match x.category() {
+        Category::Number => Some(Box::new(std::iter::repeat_n(x.as_number(), n))),
+        Category::Array if x.shape()[0] == n => Some(Box::new(x.iterate().copied())),
+        _ => None
+    }
+}
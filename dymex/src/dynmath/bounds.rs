@@ -0,0 +1,132 @@
+use crate::{Category, DynMath, EvaluationError, Float, InputVars};
+
+
+/// What happens when a variable leaves its declared range, see `Evaluator::declare_range()`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BoundsAction {
+    /// The evaluation fails with `EvaluationError::OutOfBounds`
+    #[default]
+    Error,
+    /// The evaluation continues, the violations are returned by `bounds_violations()`
+    Warn,
+}
+
+/// A variable outside of its declared range: the first value outside of it
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoundsViolation {
+    pub variable: String,
+    pub value: Float,
+    pub min: Float,
+    pub max: Float,
+}
+
+/// Declared ranges of the inputs and assigned variables, checked in every evaluation
+#[derive(Debug, Clone, Default)]
+pub(crate) struct VariableBounds {
+    ranges: Vec<(String, Float, Float)>,
+    pub(crate) action: BoundsAction,
+    /// Violations of the last evaluation, with `BoundsAction::Warn`
+    pub(crate) violations: Vec<BoundsViolation>,
+}
+
+impl VariableBounds {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub(crate) fn is_declared(&self, name: &str) -> bool {
+        self.ranges.iter().any(|(n, _, _)| n == name)
+    }
+
+    /// Declare the range of `name`, or replace its range
+    pub(crate) fn declare(&mut self, name: &str, min: Float, max: Float) {
+        match self.ranges.iter_mut().find(|(n, _, _)| n == name) {
+            Some(range) => *range = (name.to_string(), min, max),
+            None => self.ranges.push((name.to_string(), min, max)),
+        }
+    }
+
+    /// Start a new evaluation and check the declared inputs (the inputs that
+    /// are not declared or not given are not checked)
+    pub(crate) fn check_inputs(&mut self, inputs: &InputVars) -> Result<(), EvaluationError> {
+        self.violations.clear();
+        for i in 0..self.ranges.len() {
+            if let Ok(value) = inputs.resolve(&self.ranges[i].0) {
+                let name = self.ranges[i].0.clone();
+                self.check(&name, &*value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the value of the variable `name`, if its range is declared.
+    /// Numbers and arrays of numbers are checked, NaN is accepted.
+    pub(crate) fn check(&mut self, name: &str, value: &dyn DynMath) -> Result<(), EvaluationError> {
+        let Some((_, min, max)) = self.ranges.iter().find(|(n, _, _)| n == name) else {
+            return Ok(());
+        };
+        let (min, max) = (*min, *max);
+        let outside = |x: &Float| *x < min || *x > max;
+        let value = match value.category() {
+            Category::Number => Some(value.as_number()).filter(outside),
+            Category::Array => value.iterate().copied().find(outside),
+            Category::Unqiue => None
+        };
+        let Some(value) = value else {
+            return Ok(());
+        };
+        let violation = BoundsViolation { variable: name.to_string(), value, min, max };
+        match self.action {
+            BoundsAction::Error => Err(EvaluationError::OutOfBounds(violation)),
+            BoundsAction::Warn => {
+                self.violations.push(violation);
+                Ok(())
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_declared_ranges() {
+        let mut evaluator = Evaluator::new("y = x * gain", &["x", "gain"]).unwrap();
+        evaluator.declare_range("x", 0.0, 100.0);
+        evaluator.declare_range("y", -1.0, 1000.0);
+        let mut inputs = InputVars::new();
+        inputs.insert_owned("x".into(), vec![10.0, 50.0, 100.0]);
+        inputs.insert_owned("gain".into(), 5.0);
+        assert!(evaluator.evaluate(&inputs).is_ok());
+
+        inputs.insert_owned("x".into(), vec![10.0, 150.0, -1.0]);
+        let err = evaluator.evaluate(&inputs).unwrap_err();
+        assert_eq!(err.code(), "E213");
+        assert!(matches!(err, EvaluationError::OutOfBounds(v) if v.variable == "x" && v.value == 150.0));
+
+        // the assigned result, only reported with warnings
+        inputs.insert_owned("x".into(), 20.0);
+        inputs.insert_owned("gain".into(), 100.0);
+        assert!(matches!(evaluator.evaluate(&inputs), Err(EvaluationError::OutOfBounds(v)) if v.variable == "y"));
+        evaluator.set_bounds_action(BoundsAction::Warn);
+        assert_eq!(evaluator.evaluate(&inputs).unwrap().as_number(), 2000.0);
+        assert_eq!(evaluator.bounds_violations(), [BoundsViolation { variable: "y".into(), value: 2000.0, min: -1.0, max: 1000.0 }]);
+        inputs.insert_owned("gain".into(), 1.0);
+        evaluator.evaluate(&inputs).unwrap();
+        assert!(evaluator.bounds_violations().is_empty());
+        inputs.insert_owned("x".into(), float::NAN);
+        evaluator.evaluate(&inputs).unwrap();
+        assert!(evaluator.bounds_violations().is_empty());
+
+        // the intermediate variables of the statements
+        let mut evaluator = MultiExpEvaluator::new("t = x * 2\nsqrt(t)", &["x"]).unwrap();
+        evaluator.declare_range("t", 0.0, 10.0);
+        let mut inputs = InputVars::new();
+        inputs.insert_owned("x".into(), 4.0);
+        assert!(evaluator.evaluate(&inputs).is_ok());
+        inputs.insert_owned("x".into(), 8.0);
+        assert!(matches!(evaluator.evaluate(&inputs), Err(EvaluationError::OutOfBounds(v)) if v.variable == "t"));
+    }
+}
@@ -0,0 +1,38 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+
+/// Handle to abort a running evaluation from another thread, such as the UI
+/// thread when a formula takes too long. Clones share the same flag:
+/// ```ignore
+/// let handle = CancelHandle::new();
+/// evaluator.set_cancel_handle(Some(handle.clone()));
+/// // on the UI thread
+/// handle.cancel();
+/// ```
+/// The evaluation checks the flag between its operations and before every call
+/// of the anonymous functions (every element of `map()`, term of `sum(i -> ...)`,
+/// step of `solve()`), and fails with `EvaluationError::Cancelled`. A single
+/// operation on an array (`v * 2`, `sum(v)`) runs to its end.
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request the cancellation of the evaluations using this handle
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Clear the cancellation, so the handle can be used for the next evaluation
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
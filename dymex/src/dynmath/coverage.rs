@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::fmt;
+use crate::TokenContext;
+use super::DynMath;
+
+
+/// Number of evaluations of an operation
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeCoverage {
+    /// The operator, function or field access
+    pub operation: String,
+    /// Position of the operation in the expression
+    pub at: usize,
+    pub hits: u64,
+}
+
+/// Outcomes of the condition of a conditional operation: the left operand of
+/// `and` / `or` and the mask of `where()` (an array mask counts for both
+/// outcomes if it has both true and false elements)
+#[derive(Debug, Clone, PartialEq)]
+pub struct BranchCoverage {
+    pub operation: String,
+    pub at: usize,
+    pub true_count: u64,
+    pub false_count: u64,
+}
+
+impl BranchCoverage {
+    /// True if the condition was both true and false
+    pub fn is_covered(&self) -> bool {
+        self.true_count > 0 && self.false_count > 0
+    }
+}
+
+/// The operations and conditions exercised by the evaluations of an
+/// expression, see `Evaluator::enable_coverage()`. Evaluating a formula
+/// of business rules with a suite of test inputs, the operations never
+/// evaluated (skipped by `and` / `or`) and the conditions that were always
+/// true or always false point to the untested parts of the formula.
+/// Operations are identified by their position in the expression, like the
+/// nodes of the AST.
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    nodes: HashMap<usize, NodeCoverage>,
+    branches: HashMap<usize, BranchCoverage>,
+    evaluations: u64,
+}
+
+impl Coverage {
+    /// Coverage of the operations `ops`, none of them evaluated yet
+    pub(crate) fn new<'a>(ops: impl Iterator<Item = &'a TokenContext>) -> Self {
+        let nodes = ops.map(|op| (op.at, NodeCoverage { operation: op.token.to_string(), at: op.at, hits: 0 })).collect();
+        Self { nodes, ..Default::default() }
+    }
+
+    /// Number of `evaluate()` calls
+    pub fn evaluations(&self) -> u64 {
+        self.evaluations
+    }
+
+    /// Coverage of the operation at position `at` in the expression
+    pub fn get(&self, at: usize) -> Option<&NodeCoverage> {
+        self.nodes.get(&at)
+    }
+
+    /// Every operation, in order of position
+    pub fn nodes(&self) -> Vec<&NodeCoverage> {
+        let mut nodes: Vec<&NodeCoverage> = self.nodes.values().collect();
+        nodes.sort_by_key(|n| n.at);
+        nodes
+    }
+
+    /// The operations that were never evaluated, in order of position
+    pub fn unexecuted(&self) -> Vec<&NodeCoverage> {
+        self.nodes().into_iter().filter(|n| n.hits == 0).collect()
+    }
+
+    /// Every conditional operation, in order of position
+    pub fn branches(&self) -> Vec<&BranchCoverage> {
+        let mut branches: Vec<&BranchCoverage> = self.branches.values().collect();
+        branches.sort_by_key(|b| b.at);
+        branches
+    }
+
+    /// The conditions that were not both true and false
+    pub fn uncovered_branches(&self) -> Vec<&BranchCoverage> {
+        self.branches().into_iter().filter(|b| !b.is_covered()).collect()
+    }
+
+    pub(crate) fn record(&mut self, op: &TokenContext) {
+        if let Some(node) = self.nodes.get_mut(&op.at) {
+            node.hits += 1;
+        }
+    }
+
+    /// Record the outcome of the condition of the operation `op`, a `bool` or `Vec<bool>`
+    pub(crate) fn record_condition(&mut self, op: &TokenContext, condition: &dyn DynMath) {
+        let (any_true, any_false) = match (condition.downcast_ref::<bool>(), condition.downcast_ref::<Vec<bool>>()) {
+            (Some(b), _) => (*b, !*b),
+            (_, Some(mask)) => (mask.contains(&true), mask.contains(&false)),
+            _ => return
+        };
+        let branch = self.branches.entry(op.at).or_insert_with(|| BranchCoverage {
+            operation: op.token.to_string(),
+            at: op.at,
+            true_count: 0,
+            false_count: 0,
+        });
+        branch.true_count += u64::from(any_true);
+        branch.false_count += u64::from(any_false);
+    }
+
+    pub(crate) fn count_evaluation(&mut self) {
+        self.evaluations += 1;
+    }
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let unexecuted = self.unexecuted();
+        writeln!(f, "{} evaluations, {} of {} operations executed", self.evaluations,
+            self.nodes.len() - unexecuted.len(), self.nodes.len())?;
+        for n in unexecuted {
+            writeln!(f, "{:>5}  {:<12} never executed", n.at, n.operation)?;
+        }
+        for b in self.uncovered_branches() {
+            let outcome = if b.true_count > 0 { "true" } else if b.false_count > 0 { "false" } else { "not evaluated" };
+            writeln!(f, "{:>5}  {:<12} condition always {}", b.at, b.operation, outcome)?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_coverage() {
+        let mut evaluator = Evaluator::new("where(x > 10, x * 2, x) + where(x < 0 and sqrt(-x) > 1, 1, 0)", &["x"]).unwrap();
+        evaluator.enable_coverage();
+        let mut inputs = InputVars::new();
+        for x in [1.0, 5.0] {
+            inputs.insert_owned("x".into(), x);
+            evaluator.evaluate(&inputs).unwrap();
+        }
+        let coverage = evaluator.coverage().unwrap();
+        assert_eq!(coverage.evaluations(), 2);
+        // the right side of `and` was never evaluated
+        let unexecuted: Vec<&str> = coverage.unexecuted().iter().map(|n| n.operation.as_str()).collect();
+        assert_eq!(unexecuted, ["Sqrt", "-", ">"]);
+        assert_eq!(coverage.get(0).unwrap().hits, 2);
+        let uncovered: Vec<(&str, u64, u64)> = coverage.uncovered_branches().iter()
+            .map(|b| (b.operation.as_str(), b.true_count, b.false_count))
+            .collect();
+        assert_eq!(uncovered, [("Where", 0, 2), ("Where", 0, 2), ("and", 0, 2)]);
+        assert!(coverage.to_string().contains("condition always false"));
+
+        // more inputs cover the rest
+        for x in [20.0, -4.0] {
+            inputs.insert_owned("x".into(), x);
+            evaluator.evaluate(&inputs).unwrap();
+        }
+        let coverage = evaluator.disable_coverage().unwrap();
+        assert!(coverage.unexecuted().is_empty());
+        assert!(coverage.uncovered_branches().is_empty());
+        assert!(evaluator.coverage().is_none());
+
+        // array masks cover both outcomes at once, constants are evaluated
+        let mut evaluator = Evaluator::new("where(v > 1, 1 + 1, 0)", &["v"]).unwrap();
+        evaluator.enable_coverage();
+        inputs.insert_owned("v".into(), vec![0.0, 2.0]);
+        evaluator.evaluate(&inputs).unwrap();
+        let coverage = evaluator.coverage().unwrap();
+        assert!(coverage.unexecuted().is_empty() && coverage.uncovered_branches().is_empty());
+    }
+}
@@ -0,0 +1,95 @@
+use std::io;
+use std::path::Path;
+use thiserror::Error;
+use crate::Float;
+use super::InputVars;
+
+
+#[derive(Error, Debug)]
+pub enum CsvError {
+    #[error("failed to read the CSV data: {0}")]
+    Read(#[from] csv::Error),
+    #[error("no column named `{0}` in the CSV header")]
+    MissingColumn(String),
+    #[error("invalid number `{value}` in column `{column}`, line {line}")]
+    InvalidNumber {
+        column: String,
+        line: u64,
+        value: String,
+    },
+}
+
+impl InputVars {
+    /// Read the CSV file at `path`, see `from_csv_reader()`
+    pub fn from_csv_path(path: impl AsRef<Path>, columns: &[&str]) -> Result<Self, CsvError> {
+        let reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_path(path)?;
+        Self::from_csv(reader, columns)
+    }
+
+    /// Array variables from the `columns` of CSV data with a header line, named
+    /// after the header (all columns if `columns` is empty):
+    /// ```ignore
+    /// let inputs = InputVars::from_csv_path("spectrum.csv", &["wavelength", "intensity"])?;
+    /// let mut evaluator = Evaluator::new("trapz(intensity, wavelength)", &inputs.names())?;
+    /// ```
+    /// Empty cells and `NA` are missing values, the columns with any are `Vec<Option<Float>>`.
+    pub fn from_csv_reader<R: io::Read>(reader: R, columns: &[&str]) -> Result<Self, CsvError> {
+        Self::from_csv(csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(reader), columns)
+    }
+
+    fn from_csv<R: io::Read>(mut reader: csv::Reader<R>, columns: &[&str]) -> Result<Self, CsvError> {
+        let header = reader.headers()?.clone();
+        let selected: Vec<(usize, &str)> = match columns.is_empty() {
+            true => header.iter().enumerate().collect(),
+            false => columns.iter().map(|name| match header.iter().position(|h| h == *name) {
+                Some(i) => Ok((i, *name)),
+                None => Err(CsvError::MissingColumn(name.to_string()))
+            }).collect::<Result<_, _>>()?
+        };
+        let mut values: Vec<Vec<Option<Float>>> = vec![Vec::new(); selected.len()];
+        for record in reader.records() {
+            let record = record?;
+            for ((i, name), column) in selected.iter().zip(values.iter_mut()) {
+                let cell = record.get(*i).unwrap_or("");
+                column.push(match cell {
+                    "" | "NA" => None,
+                    _ => Some(cell.parse().map_err(|_| CsvError::InvalidNumber {
+                        column: name.to_string(),
+                        line: record.position().map_or(0, |p| p.line()),
+                        value: cell.to_string()
+                    })?)
+                });
+            }
+        }
+        let mut inputs = Self::new();
+        for ((_, name), column) in selected.into_iter().zip(values) {
+            match column.iter().all(|x| x.is_some()) {
+                true => inputs.insert_owned(name.to_string(), column.into_iter().flatten().collect::<Vec<Float>>()),
+                false => inputs.insert_owned(name.to_string(), column)
+            }
+        }
+        Ok(inputs)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_csv_columns() {
+        let data = "t, temp, note\n0, 20.5, a\n1, 21, b\n2, , c\n";
+        let inputs = InputVars::from_csv_reader(data.as_bytes(), &["t", "temp"]).unwrap();
+        assert_eq!(inputs.get("t").unwrap().iterate().as_slice(), &[0.0, 1.0, 2.0]);
+        assert_eq!(inputs.get("temp").unwrap().downcast_ref::<Vec<Option<Float>>>().unwrap(), &vec![Some(20.5), Some(21.0), None]);
+        assert!(inputs.get("note").is_none());
+
+        let mut evaluator = Evaluator::new("max(coalesce(temp, 0)) + sum(t)", &inputs.names()).unwrap();
+        assert_eq!(evaluator.evaluate(&inputs).unwrap().as_number(), 24.0);
+
+        assert!(matches!(InputVars::from_csv_reader(data.as_bytes(), &["x"]), Err(CsvError::MissingColumn(_))));
+        let err = InputVars::from_csv_reader(data.as_bytes(), &[]).unwrap_err();
+        assert!(matches!(err, CsvError::InvalidNumber { line: 2, .. }), "{}", err);
+    }
+}
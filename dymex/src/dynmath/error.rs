@@ -1,5 +1,6 @@
+use std::time::Duration;
 use thiserror::Error;
-use crate::Float;
+use crate::{Diagnostic, Float, Severity};
 use super::*;
 
 #[derive(Error, Debug)]
@@ -33,13 +34,73 @@ pub enum EvaluationError {
     InvalidOperation {
         info: String,
     },
+    #[error("the intermediate results exceed the memory limit of {limit} bytes")]
+    MemoryLimitExceeded {
+        limit: usize,
+    },
+    #[error("the evaluation was cancelled")]
+    Cancelled,
+    #[error("the evaluation exceeded the time limit of {limit:?}")]
+    TimedOut {
+        limit: Duration,
+    },
     #[error("Missing expression for final result")]
     MissingFinalExpression,
+    #[error("the expression has no node with the id {id}")]
+    UnknownNode {
+        id: usize,
+    },
+
+    /// A result outside of the bounds of `ArithmeticMode::Checked`
+    #[error("the result {value} is outside of the range [{min}, {max}]")]
+    OutOfRange {
+        value: Float,
+        min: Float,
+        max: Float,
+    },
+
+    /// A variable outside of its range declared by `Evaluator::declare_range()`
+    #[error("`{}` is outside of its range [{}, {}]: {}", .0.variable, .0.min, .0.max, .0.value)]
+    OutOfBounds(BoundsViolation),
+
+    /// A reduction of an empty array, with `EmptyReduction::Error`
+    #[error("`{function}` of an empty array")]
+    EmptyReduction {
+        function: String,
+    },
 
     #[error("unknown error")]
     Unknown,
 }
 
+impl EvaluationError {
+    /// Stable code of the error, `E201` to `E299` for the evaluation errors
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidUnaryOperation { .. } => "E201",
+            Self::InvalidBinaryOperation { .. } => "E202",
+            Self::InvalidField { .. } => "E203",
+            Self::InvalidArguments { .. } => "E204",
+            Self::MissingInputVariable { .. } => "E205",
+            Self::InvalidOperation { .. } => "E206",
+            Self::MemoryLimitExceeded { .. } => "E207",
+            Self::Cancelled => "E208",
+            Self::TimedOut { .. } => "E209",
+            Self::MissingFinalExpression => "E210",
+            Self::UnknownNode { .. } => "E211",
+            Self::OutOfRange { .. } => "E212",
+            Self::OutOfBounds(_) => "E213",
+            Self::EmptyReduction { .. } => "E214",
+            Self::Unknown => "E299",
+        }
+    }
+
+    /// The error as a `Diagnostic`, without a span (the values have no position in the expression)
+    pub fn diagnostic(&self) -> Diagnostic {
+        Diagnostic { code: self.code(), span: None, severity: Severity::Error, message: self.to_string() }
+    }
+}
+
 /// Unimplemented unary function that returns a number (min, max, sum ...)
 pub(super) fn unimpl_unary<L>(data: &L, op: &str) -> Result<Float, EvaluationError>
 where
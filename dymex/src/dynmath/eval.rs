@@ -1,77 +1,564 @@
 
 use std::{collections::HashMap, collections::hash_map::Iter};
+use std::cell::RefCell;
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 // use crate::parser::{A};
 use crate::*;
+use crate::tokenizer::MAX_FUNC_ARGS;
+use crate::parser::named_argument;
+use super::lambda::Lambda;
+use super::missing::present_values;
+
+/// Placeholder for the unused slots of the argument buffer
+const NO_VALUE: Float = float::NAN;
 
 #[derive(Clone)]
 pub struct Evaluator {
     values: HashMap<u16, Rc<dyn DynMath>>,
     expressions: HashMap<u16, Evaluand>,
-    aliases: HashMap<String, u16>
+    /// `expressions` in evaluation order, the result is the last one
+    order: Vec<Step>,
+    aliases: HashMap<String, u16>,
+    case_sensitive: bool,
+    context: EvalContext,
+    timeout: Option<Duration>,
+    fingerprint: Fingerprint,
+    profile: Option<Profile>,
+    coverage: Option<Coverage>,
+    /// The parsed expression, for `evaluate_node()`
+    ast: Rc<AST>,
+    /// Ids of the values of the nodes, keyed by node id
+    nodes: HashMap<usize, u16>,
+    watches: Vec<Watched>,
+    /// The result of a constant expression, see `is_constant()`
+    constant: Option<Rc<dyn DynMath>>,
+    audit: Option<Rc<dyn AuditSink>>,
+    bounds: VariableBounds,
+}
+
+/// Settings of the evaluation, shared with the bodies of the anonymous functions
+#[derive(Clone, Default)]
+pub(crate) struct EvalContext {
+    pub(crate) missing: MissingPolicy,
+    pub(crate) units: Rc<UnitRegistry>,
+    /// Limit of the memory used by the intermediate results, in bytes
+    pub(crate) memory_limit: Option<usize>,
+    /// The evaluation fails after this instant, with the time limit
+    pub(crate) deadline: Option<(Instant, Duration)>,
+    pub(crate) cancel: Option<CancelHandle>,
+    /// Generator of the stochastic functions, shared with the anonymous
+    /// functions so they continue the same sequence
+    pub(crate) rng: Option<Rc<RefCell<SeededRng>>>,
+    pub(crate) arithmetic: ArithmeticMode,
+    pub(crate) empty_reduction: EmptyReduction,
+    pub(crate) summation: Summation,
+    /// Delta degrees of freedom of `std()`
+    pub(crate) ddof: u32,
+}
+
+impl EvalContext {
+    /// Error if the evaluation was cancelled or ran out of time
+    pub(crate) fn interrupted(&self) -> Option<EvaluationError> {
+        if self.cancel.as_ref().is_some_and(|c| c.is_cancelled()) {
+            return Some(EvaluationError::Cancelled);
+        }
+        match self.deadline {
+            Some((deadline, limit)) if Instant::now() > deadline => Some(EvaluationError::TimedOut { limit }),
+            _ => None
+        }
+    }
+}
+
+/// Options of `Evaluator::configured()`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct EvaluatorConfig {
+    pub policy: VariablePolicy,
+    pub tokenizer: TokenizerConfig,
+    /// Functions accepted in the expression, all of them by default
+    pub functions: FunctionFilter,
+    /// Bounds of the results of the operations, unbounded by default
+    pub arithmetic: ArithmeticMode,
 }
 
 
 //CONSIDER Is a self.update(...) method varranted? Or are we OK creating a new
 // Evaluator every time the expression or variables change?
 impl Evaluator {
+    /// Parse `expression`, every referenced variable has to be among `variables`
     pub fn new(expression: &str, variables: &[&str]) -> Result<Self, DymexError> {
-        let ts = match TokenStream::new(expression) {
+        Self::with_policy(expression, variables, VariablePolicy::Strict)
+    }
+
+    /// Parse `expression`, unknown variables are handled according to `policy`
+    pub fn with_policy(expression: &str, variables: &[&str], policy: VariablePolicy) -> Result<Self, DymexError> {
+        Self::with_config(expression, variables, policy, TokenizerConfig::default())
+    }
+
+    /// Parse `expression` with the given tokenizer options. With `Case::Insensitive`
+    /// the input variables are also matched case insensitively.
+    pub fn with_config(expression: &str, variables: &[&str], policy: VariablePolicy, config: TokenizerConfig)
+    -> Result<Self, DymexError> {
+        Self::configured(expression, variables, &EvaluatorConfig { policy, tokenizer: config, ..Default::default() })
+    }
+
+    /// Parse `expression` with all the options of `config`, the functions not
+    /// allowed by `config.functions` are rejected with `ParsingError::ForbiddenFunction`
+    /// and invalid bounds of `config.arithmetic` with `ParsingError::InvalidConfiguration`
+    pub fn configured(expression: &str, variables: &[&str], config: &EvaluatorConfig) -> Result<Self, DymexError> {
+        let EvaluatorConfig { policy, tokenizer: config, functions, arithmetic } = config;
+        let policy = *policy;
+        if let Err(details) = arithmetic.validate() {
+            return Err(DymexError::ParsingError(ParsingError::InvalidConfiguration(details)));
+        }
+        let ts = match TokenStream::with_config(expression, *config) {
             Ok(ts) => ts,
             Err(err) => return Err(DymexError::LexicalError(err))
         };
-        match AST::new(ts) {
+        let ast = match AST::new(ts) {
+            Err(err) => return Err(DymexError::ParsingError(err)),
+            Ok(ast) => ast
+        };
+        if let Err(err) = ast.check_functions(functions) {
+            return Err(DymexError::ParsingError(err));
+        }
+        let check = if config.case != Case::Insensitive {
+            ast.check_variables(variables, policy)
+        } else {
+            let lowercase: Vec<String> = variables.iter().map(|v| v.to_lowercase()).collect();
+            let lowercase: Vec<&str> = lowercase.iter().map(|v| v.as_str()).collect();
+            ast.check_variables(&lowercase, policy)
+        };
+        match check {
             Err(err) => Err(DymexError::ParsingError(err)),
-            Ok(ast) => Ok(Self::from_ast(ast))
+            Ok(()) => {
+                let mut evaluator = Self::from_ast(ast);
+                evaluator.case_sensitive = config.case != Case::Insensitive;
+                evaluator.context.arithmetic = *arithmetic;
+                Ok(evaluator)
+            }
         }
     }
 
     pub fn from_ast(ast: AST) -> Self {
-        let (val, aliases, expr) = flatten_tree(ast);
+        let fingerprint = ast.fingerprint();
+        let (val, aliases, expr, order, nodes) = flatten_tree(&ast);
         Self {
             values: val,
             expressions: expr,
-            aliases: aliases
+            order,
+            aliases: aliases,
+            case_sensitive: true,
+            context: EvalContext::default(),
+            timeout: None,
+            fingerprint,
+            profile: None,
+            coverage: None,
+            ast: Rc::new(ast),
+            nodes,
+            watches: Vec::new(),
+            constant: None,
+            audit: None,
+            bounds: VariableBounds::default(),
+        }
+    }
+
+    /// True if the result does not depend on the inputs: the expression has no
+    /// free variables (the local variables of `with()` and the parameters of
+    /// anonymous functions are bound) and does not call `now()`
+    pub fn is_constant(&self) -> bool {
+        self.aliases.is_empty()
+            && !DFSBranchIter::new(&self.ast.tree).any(|branch| matches!(branch.tc().token, Token::Func(Function::Now, _)))
+    }
+
+    /// The result of a constant expression (see `is_constant()`), computed once
+    /// by the first evaluation (under the limits set by then, see `set_timeout()`)
+    /// and kept until a setting of the evaluation changes, so the host does not
+    /// need to evaluate it for every sample. `evaluate()` also returns it without
+    /// evaluating again. `None` if the expression is not constant, or its
+    /// evaluation fails (`evaluate()` returns the error).
+    pub fn constant_value(&mut self) -> Option<Box<dyn DynMath>> {
+        if self.constant.is_none() && self.is_constant() {
+            let _ = self.evaluate_unaudited(&InputVars::new());
         }
+        self.constant.as_ref().map(|value| value.clone_boxed())
+    }
+
+    /// Measure the time spent in every operation from now on, accumulated over
+    /// the `evaluate()` calls. Profiling relies on `std::time::Instant`, which is
+    /// not available on every target (e.g. `wasm32-unknown-unknown`).
+    pub fn enable_profiling(&mut self) {
+        if self.profile.is_none() {
+            self.profile = Some(Profile::new());
+        }
+    }
+
+    /// Stop profiling and return the collected profile
+    pub fn disable_profiling(&mut self) -> Option<Profile> {
+        self.profile.take()
+    }
+
+    /// The profile collected since `enable_profiling()`
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Record which operations are evaluated and the outcomes of the
+    /// conditions from now on, accumulated over the `evaluate()` calls
+    pub fn enable_coverage(&mut self) {
+        if self.coverage.is_none() {
+            self.coverage = Some(Coverage::new(self.expressions.values().map(|e| &e.op)));
+        }
+    }
+
+    /// Stop recording the coverage and return it
+    pub fn disable_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take()
+    }
+
+    /// The coverage collected since `enable_coverage()`
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Handling of missing values (NA) in the reductions, see `MissingPolicy`
+    pub fn set_missing_policy(&mut self, policy: MissingPolicy) {
+        self.context.missing = policy;
+        self.constant = None;
+    }
+
+    /// Result of the reductions of empty arrays, an error by default, see `EmptyReduction`
+    pub fn set_empty_reduction(&mut self, empty: EmptyReduction) {
+        self.context.empty_reduction = empty;
+        self.constant = None;
+    }
+
+    /// Algorithm of the sums of arrays in `sum`, `avg` and `std`, see `Summation`
+    pub fn set_summation(&mut self, summation: Summation) {
+        self.context.summation = summation;
+        self.constant = None;
+    }
+
+    /// Delta degrees of freedom of `std()`: the squared deviations are divided
+    /// by `n - ddof`. `0` (default) gives the standard deviation of the population,
+    /// `1` the sample standard deviation. See `Welford` for the algorithm.
+    pub fn set_ddof(&mut self, ddof: u32) {
+        self.context.ddof = ddof;
+        self.constant = None;
+    }
+
+    /// Units of `convert(x, "eV", "nm")`, `UnitRegistry::default()` if not set
+    pub fn set_unit_registry(&mut self, units: UnitRegistry) {
+        self.context.units = Rc::new(units);
+        self.constant = None;
+    }
+
+    /// Bounds of the results of the operations, see `ArithmeticMode`. Invalid
+    /// bounds (see `ArithmeticMode::validate()`) are rejected, the mode is not changed.
+    pub fn set_arithmetic_mode(&mut self, mode: ArithmeticMode) -> Result<(), EvaluationError> {
+        if let Err(details) = mode.validate() {
+            return Err(EvaluationError::InvalidArguments { function: "set_arithmetic_mode".into(), details });
+        }
+        self.context.arithmetic = mode;
+        self.constant = None;
+        Ok(())
+    }
+
+    /// Limit the memory used by the intermediate results of an evaluation
+    /// (see `DynMath::memory_size()`), `evaluate()` fails with
+    /// `EvaluationError::MemoryLimitExceeded` instead of allocating more.
+    /// The inputs are not counted. `None` (default) means no limit.
+    pub fn set_memory_limit(&mut self, bytes: Option<usize>) {
+        self.context.memory_limit = bytes;
+    }
+
+    /// Limit the duration of each `evaluate()` call: when `timeout` has passed,
+    /// the evaluation stops after the current operation (or call of an anonymous
+    /// function) with `EvaluationError::TimedOut`. A single operation on an array
+    /// (`v * 2`, `sum(v)`) is not interrupted. Like profiling, it relies on `std::time::Instant`.
+    /// `None` (default) means no limit.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Abort the evaluations when `handle` is cancelled, see `CancelHandle`
+    pub fn set_cancel_handle(&mut self, handle: Option<CancelHandle>) {
+        self.context.cancel = handle;
+    }
+
+    /// Seed of the generator of the stochastic functions, see `SeededRng`.
+    /// The evaluations continue the sequence started by the seed, so the same
+    /// seed and the same `evaluate()` calls give the same results. `None`
+    /// (default) removes the generator.
+    pub fn set_seed(&mut self, seed: Option<u64>) {
+        self.context.rng = seed.map(|seed| Rc::new(RefCell::new(SeededRng::new(seed))));
+    }
+
+    /// Declare the valid range of an input (or field of an input, `s.x`) or
+    /// of the assigned result (`y` of `y = ...`). The values are checked in
+    /// every evaluation, outside of the range the evaluation fails (see
+    /// `set_bounds_action()`). Every element of arrays is checked.
+    pub fn declare_range(&mut self, name: &str, min: Float, max: Float) {
+        self.bounds.declare(name, min, max);
+    }
+
+    /// Fail or warn when a variable leaves its declared range, fail by default
+    pub fn set_bounds_action(&mut self, action: BoundsAction) {
+        self.bounds.action = action;
+    }
+
+    /// The variables outside of their ranges in the last evaluation, with `BoundsAction::Warn`
+    pub fn bounds_violations(&self) -> &[BoundsViolation] {
+        &self.bounds.violations
+    }
+
+    /// Record an `AuditEntry` of every `evaluate()` call (also of the failed
+    /// ones) in `sink`. `None` (default) disables the audit.
+    pub fn set_audit_sink(&mut self, sink: Option<Rc<dyn AuditSink>>) {
+        self.audit = sink;
+    }
+
+    /// Evaluate with the settings of the enclosing expression (for the body of lambdas)
+    pub(crate) fn set_context(&mut self, context: &EvalContext) {
+        self.context = context.clone();
+        self.constant = None;
+    }
+
+    /// Names of the input variables read by the expression, sorted
+    pub fn inputs(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.aliases.keys().map(|name| name.as_str()).collect();
+        names.sort();
+        names
+    }
+
+    /// Fingerprint of the parsed expression, see `AST::fingerprint()`
+    pub fn fingerprint(&self) -> Fingerprint {
+        self.fingerprint
     }
 
     pub fn evaluate(&mut self, inputs: &InputVars) -> Result<Box<dyn DynMath>, EvaluationError> {
+        let Some(sink) = self.audit.clone() else {
+            return self.evaluate_unaudited(inputs);
+        };
+        let start = Instant::now();
+        let result = self.evaluate_unaudited(inputs);
+        let mut used: Vec<String> = self.aliases.keys().cloned().collect();
+        used.sort();
+        sink.record(AuditEntry {
+            fingerprint: self.fingerprint,
+            inputs: used,
+            duration: start.elapsed(),
+            outcome: AuditOutcome::of(&result),
+        });
+        result
+    }
+
+    fn evaluate_unaudited(&mut self, inputs: &InputVars) -> Result<Box<dyn DynMath>, EvaluationError> {
+        let result = self.evaluate_checked(inputs)?;
+        if self.constant.is_none() && self.is_constant() {
+            self.constant = Some(Rc::from(result.clone_boxed()));
+            // only the result is kept, not the intermediate results
+            let expressions = &self.expressions;
+            self.values.retain(|id, _| !expressions.contains_key(id));
+        }
+        Ok(result)
+    }
+
+    /// Evaluate, with the checks of the declared ranges
+    fn evaluate_checked(&mut self, inputs: &InputVars) -> Result<Box<dyn DynMath>, EvaluationError> {
+        if self.bounds.is_empty() {
+            return self.run(inputs);
+        }
+        self.bounds.check_inputs(inputs)?;
+        let result = self.run(inputs)?;
+        if let Some(name) = &self.ast.assigned_to {
+            self.bounds.check(name, &*result)?;
+        }
+        Ok(result)
+    }
+
+    fn run(&mut self, inputs: &InputVars) -> Result<Box<dyn DynMath>, EvaluationError> {
+        if let Some(constant) = &self.constant
+        && self.watches.is_empty() && self.profile.is_none() && self.coverage.is_none() {
+            return Ok(constant.clone_boxed());
+        }
+        let lowercase_inputs;
+        let inputs = if self.case_sensitive {
+            inputs
+        } else {
+            lowercase_inputs = inputs.to_lowercase();
+            &lowercase_inputs
+        };
         for (varname, id) in &self.aliases {
-            if let Some(x) = inputs.as_hashmap().get(varname) {
-                self.values.insert(*id, x.clone());
-            } else {
-                return Err(EvaluationError::MissingInputVariable
-                    {varname: varname.to_string()}
-                );
+            match inputs.resolve(varname) {
+                Ok(x) => { self.values.insert(*id, x); },
+                Err(e) => return Err(e)
             }
         }
+        // inputs and literals are watched right away, the results when they are evaluated
+        for watch in &mut self.watches {
+            watch.value = match self.expressions.contains_key(&watch.value_id) {
+                true => None,
+                false => self.values.get(&watch.value_id).cloned()
+            };
+        }
 
-        let mut eval_order = self.expressions.keys()
-            .copied()
-            .collect::<Vec<u16>>();
-        eval_order.sort_by(|a, b| b.cmp(a));
-
-        if let Some(final_result_id) = eval_order.last() {
-            for expr_id in &eval_order {
-                let result = self.expressions[&expr_id].eval(&self.values);
-                match result {
-                    Err(e) => return Err(e),
-                    Ok(res) => {
-                        if expr_id == final_result_id {
-                            return Ok(Box::from(res))
+        if let Some(profile) = &mut self.profile {
+            profile.count_evaluation();
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage.count_evaluation();
+        }
+        if self.order.is_empty() {
+            // a single number, constant or input variable is the root value itself
+            return match self.values.get(&0) {
+                Some(value) => Ok(value.clone_boxed()),
+                // empty expression
+                None => Ok(Box::from(float::NAN))
+            }
+        }
+        if let Some(timeout) = self.timeout {
+            self.context.deadline = Some((Instant::now() + timeout, timeout));
+        }
+        let mut next = 0;
+        // memory used by the intermediate results
+        let mut used = 0;
+        while let Some(step) = self.order.get(next) {
+            if let Some(e) = self.context.interrupted() {
+                return Err(e);
+            }
+            next += 1;
+            let (expr_id, result) = match step {
+                Step::ShortCircuit { cond, value, id, end } => {
+                    let decided = &self.values[cond];
+                    if let Some(coverage) = &mut self.coverage {
+                        coverage.record_condition(&self.expressions[id].op, &**decided);
+                    }
+                    if decided.downcast_ref::<bool>() != Some(value) {
+                        continue;
+                    }
+                    // the result of `and` / `or` without evaluating its right side
+                    if let Some(coverage) = &mut self.coverage {
+                        coverage.record(&self.expressions[id].op);
+                    }
+                    next = *end;
+                    (id, Ok(decided.clone_boxed()))
+                }
+                Step::Eval(expr_id) => {
+                    let evaluand = &self.expressions[expr_id];
+                    if let Some(coverage) = &mut self.coverage {
+                        coverage.record(&evaluand.op);
+                        if let Token::Func(Function::Where, _) = evaluand.op.token
+                        && let Some(mask) = self.values.get(&evaluand.args[0]) {
+                            coverage.record_condition(&evaluand.op, &**mask);
+                        }
+                    }
+                    let result = match &mut self.profile {
+                        None => evaluand.eval(&self.values, &self.context),
+                        Some(profile) => {
+                            let start = Instant::now();
+                            let result = evaluand.eval(&self.values, &self.context);
+                            profile.record(&evaluand.op, start.elapsed());
+                            result
                         }
-                        self.values.insert(*expr_id, Rc::from(res));
+                    };
+                    (expr_id, result)
+                }
+            };
+            match result.and_then(|res| self.context.arithmetic.apply(res)) {
+                Err(e) => return Err(e),
+                Ok(res) => {
+                    if let Some(limit) = self.context.memory_limit {
+                        used += res.memory_size();
+                        if used > limit {
+                            return Err(EvaluationError::MemoryLimitExceeded { limit });
+                        }
+                    }
+                    let watched = self.watches.iter().any(|w| w.value_id == *expr_id);
+                    if next == self.order.len() && !watched {
+                        return Ok(res)
+                    }
+                    let res: Rc<dyn DynMath> = Rc::from(res);
+                    for watch in self.watches.iter_mut().filter(|w| w.value_id == *expr_id) {
+                        watch.value = Some(res.clone());
                     }
+                    if next == self.order.len() {
+                        return Ok(res.clone_boxed())
+                    }
+                    self.values.insert(*expr_id, res);
                 }
             }
-        } else {
-            // empty expression
-            return Ok(Box::from(float::NAN));
         }
 
         panic!("ERROR: end of evaluation chain")
         // Ok(self.values[final_result_id].clone())
     }
+
+    /// Record the value of the subexpression with the node id `id` (see
+    /// `AST::subexpression()`) in every evaluation, under `label`. The values
+    /// of the last `evaluate()` are returned by `watched()`, also when it failed,
+    /// to debug long expressions without splitting them. The nodes inside
+    /// anonymous functions cannot be watched.
+    pub fn watch(&mut self, id: usize, label: &str) -> Result<(), EvaluationError> {
+        let value_id = *self.nodes.get(&id).ok_or(EvaluationError::UnknownNode { id })?;
+        self.watches.push(Watched { label: label.to_string(), id, value: None, value_id });
+        Ok(())
+    }
+
+    /// Watch the value of the local variable `name` of a `with(name = ..., ...)`
+    /// (the first binding with that name), see `watch()`
+    pub fn watch_binding(&mut self, name: &str) -> Result<(), EvaluationError> {
+        let binding = DFSBranchIter::new(&self.ast.tree)
+            .filter(|branch| matches!(branch.tc().token, Token::Func(Function::With, _)))
+            .flat_map(|with| match with {
+                Branch::Expression(_, args) => args.iter().filter_map(named_argument).collect(),
+                Branch::Atom(_) => Vec::new()
+            })
+            .find(|(var, _)| var.token == Token::Var(name.to_string()));
+        match binding {
+            Some((_, value)) => self.watch(value.tc().at, name),
+            None => Err(EvaluationError::InvalidOperation { info: format!("no `with()` binding named `{}`", name) })
+        }
+    }
+
+    /// Stop watching every subexpression
+    pub fn clear_watches(&mut self) {
+        self.watches.clear();
+    }
+
+    /// The watched subexpressions with their values in the last evaluation, in the
+    /// order they were added
+    pub fn watched(&self) -> &[Watched] {
+        &self.watches
+    }
+
+    /// Evaluate only the subexpression with the node id `id` (see `AST::subexpression()`),
+    /// with the settings of this evaluator. For previews of the value of a part
+    /// of the expression, like the node under the cursor (`AST::node_at()`).
+    /// The variables bound by the enclosing lambdas and `with()`s are not
+    /// defined, unless they are also inputs.
+    pub fn evaluate_node(&self, id: usize, inputs: &InputVars) -> Result<Box<dyn DynMath>, EvaluationError> {
+        let ast = self.ast.subexpression(id).ok_or(EvaluationError::UnknownNode { id })?;
+        let mut evaluator = Self::from_ast(ast);
+        evaluator.case_sensitive = self.case_sensitive;
+        evaluator.context = self.context.clone();
+        evaluator.timeout = self.timeout;
+        evaluator.evaluate(inputs)
+    }
+
+    /// The parsed expression
+    pub(crate) fn ast(&self) -> &AST {
+        &self.ast
+    }
+
+    /// True if `evaluate()` only computes the value: no audit, declared ranges,
+    /// arithmetic mode, limits, cancel handle, watches, profile or coverage,
+    /// which an evaluation outside of the evaluator (`GpuEvaluator`) would skip
+    pub(crate) fn is_plain(&self) -> bool {
+        self.audit.is_none() && self.bounds.is_empty() && self.context.arithmetic == ArithmeticMode::default()
+            && self.context.memory_limit.is_none() && self.timeout.is_none() && self.context.cancel.is_none()
+            && self.watches.is_empty() && self.profile.is_none() && self.coverage.is_none()
+    }
 }
 
 
@@ -107,6 +594,39 @@ impl InputVars {
         self.0.get(key)
     }
 
+    /// Returns the value of the variable `path`. For dotted paths (`a.b.c`)
+    /// without an exact match, the longest prefix that is an input variable
+    /// is looked up and the remaining parts are accessed as its fields.
+    pub fn resolve(&self, path: &str) -> Result<Rc<dyn DynMath>, EvaluationError> {
+        if let Some(x) = self.0.get(path) {
+            return Ok(x.clone());
+        }
+        let mut end = path.len();
+        while let Some(dot) = path[..end].rfind('.') {
+            end = dot;
+            if let Some(x) = self.0.get(&path[..end]) {
+                let mut value = x.clone();
+                for field in path[end+1..].split('.') {
+                    value = Rc::from(value.get_field(field)?);
+                }
+                return Ok(value);
+            }
+        }
+        Err(EvaluationError::MissingInputVariable { varname: path.to_string() })
+    }
+
+    /// Copy of the inputs with lowercased names (for case insensitive lookup)
+    pub fn to_lowercase(&self) -> Self {
+        Self(self.0.iter().map(|(k, v)| (k.to_lowercase(), v.clone())).collect())
+    }
+
+    /// Collect the fields of a `DymexVars` struct (see `#[derive(DymexVars)]`)
+    pub fn from_vars<T: DymexVars + ?Sized>(vars: &T) -> Self {
+        let mut inputs = Self::new();
+        vars.insert_vars(&mut inputs, "");
+        inputs
+    }
+
 }
 
 struct IdGenerator {
@@ -122,109 +642,451 @@ impl IdGenerator {
 }
 
 
-pub(crate) fn flatten_tree(ast: AST)
+pub(crate) fn flatten_tree(ast: &AST)
     -> (HashMap<u16, Rc<dyn DynMath>>,
         HashMap<String, u16>,
-        HashMap<u16, Evaluand>) {
+        HashMap<u16, Evaluand>,
+        Vec<Step>,
+        HashMap<usize, u16>) {
 
-    let mut id_gen = IdGenerator::new();
+    let mut flat = FlatTree {
+        values: HashMap::new(),
+        aliases: HashMap::new(),
+        expressions: HashMap::new(),
+        order: Vec::new(),
+        literals: HashMap::new(),
+        nodes: HashMap::new(),
+        id_gen: IdGenerator::new(),
+    };
+    let id = flat.id_gen.get_id();
+    flat.recurse_tree(&ast.tree, &HashMap::new(), id);
+
+    (flat.values, flat.aliases, flat.expressions, flat.order, flat.nodes)
+}
 
+/// A step of the evaluation
+#[derive(Clone)]
+pub(crate) enum Step {
+    /// Evaluate the expression with the given id
+    Eval(u16),
+    /// Short-circuit of `a and b` (`a or b`): if `cond` is `value` (false for `and`,
+    /// true for `or`), it is also the result `id`, and the steps of `b` are skipped
+    /// by jumping to `end`
+    ShortCircuit { cond: u16, value: bool, id: u16, end: usize },
+}
+
+/// Value of a number, constant or string literal, the key of its shared id
+#[derive(PartialEq, Eq, Hash)]
+enum Literal {
+    /// The bits of the number, `0.5` and `pi` are deduplicated by value
+    Number(u64),
+    Str(String),
+}
+
+impl Literal {
+    fn of(branch: &Branch) -> Option<Self> {
+        match branch {
+            Branch::Atom(a) => match &a.token {
+                Token::Number(x) => Some(Self::Number(u64::from(x.to_bits()))),
+                Token::Const(c) => Some(Self::Number(u64::from(c.value().to_bits()))),
+                Token::Str(s) => Some(Self::Str(s.clone())),
+                _ => None
+            },
+            _ => None
+        }
+    }
+}
+
+/// Local variables of `with(t = x * 2, ...)` in scope, and the ids of their values
+type Scope = HashMap<String, u16>;
+
+/// Returns true if the variable `path` (`t` or `t.x`) refers to a local variable
+fn is_local(path: &str, scope: &Scope) -> bool {
+    scope.contains_key(path.split('.').next().unwrap_or(path))
+}
+
+struct FlatTree {
     // variables, constants and _evaluated_ results
-    let mut values: HashMap<u16, Rc<dyn DynMath>> = HashMap::new();
-    // mapping between variable name and id
-    let mut aliases: HashMap<String, u16> = HashMap::new();
+    values: HashMap<u16, Rc<dyn DynMath>>,
+    // mapping between input variable name and id
+    aliases: HashMap<String, u16>,
     // evaluands: only expressions!
-    let mut evaluands: HashMap<u16, Evaluand> = HashMap::new();
-
-    fn recurse_tree(tree: &Branch,
-        values: &mut HashMap<u16, Rc<dyn DynMath>>,
-        expressions: &mut HashMap<u16, Evaluand>,
-        aliases: &mut HashMap<String, u16>,
-        id_gen: &mut IdGenerator,
-        id: u16) {
+    expressions: HashMap<u16, Evaluand>,
+    // evaluands in evaluation order, the arguments before the operation
+    order: Vec<Step>,
+    // ids of the literals, every occurrence of the same value shares one id
+    literals: HashMap<Literal, u16>,
+    // ids of the values of the nodes, keyed by the position of their token
+    nodes: HashMap<usize, u16>,
+    id_gen: IdGenerator,
+}
+
+impl FlatTree {
+    fn insert(&mut self, id: u16, evaluand: Evaluand) {
+        self.expressions.insert(id, evaluand);
+        self.order.push(Step::Eval(id));
+    }
+
+    /// Id of the value of an operand: the id of a local or input variable
+    /// (registered right away, so `v * v` refers to the same id twice),
+    /// the id of an earlier occurrence of the same literal (`0.5` in
+    /// `x*0.5 + y*0.5`), or a new id for any other operand
+    fn operand_id(&mut self, arg: &Branch, scope: &Scope) -> u16 {
+        match arg.variable_path() {
+            Some(v) if let Some(id) = scope.get(&v) => *id,
+            // `t.x` of a local `t` is a field access
+            Some(v) if is_local(&v, scope) => self.id_gen.get_id(),
+            Some(v) => self.alias_id(v),
+            None => match Literal::of(arg) {
+                Some(literal) => *self.literals.entry(literal).or_insert_with(|| self.id_gen.get_id()),
+                None => self.id_gen.get_id()
+            }
+        }
+    }
+
+    fn alias_id(&mut self, varname: String) -> u16 {
+        match self.aliases.get(&varname) {
+            Some(id) => *id,
+            None => {
+                let id = self.id_gen.get_id();
+                self.aliases.insert(varname, id);
+                id
+            }
+        }
+    }
+
+    fn recurse_tree(&mut self, tree: &Branch, scope: &Scope, id: u16) {
+            self.nodes.insert(tree.tc().at, id);
             match tree {
                 Branch::Atom(a) => {
                     //RFO: here we insert a new value for every occurance of the same number/constant/var
                     match a.token.to_owned() {
                         Token::Const(c) => {
                             println!("Insert const {} {}", id, c.value()); // DEBUG
-                            values.insert(id,Rc::new(c.value()));
+                            self.values.insert(id,Rc::new(c.value()));
                         }
                         Token::Number(x) => {
                             println!("Insert number {} {}", id, x); // DEBUG
-                            values.insert(id,Rc::new(x));
+                            self.values.insert(id,Rc::new(x));
                         }
+                        Token::Str(s) => {
+                            self.values.insert(id,Rc::new(s));
+                        }
+                        // the value of a local variable is already evaluated under its id
+                        Token::Var(v) if scope.contains_key(&v) => {}
                         Token::Var(v) => {
                             println!("Insert var {} {}", id, v); // DEBUG
-                            aliases.insert( v, id);
+                            self.aliases.insert( v, id);
                         }
                         _ => panic!("Unexpected token in transform_tree(). This is likely a bug!")
                     };
                 },
-                Branch::Expression(exp, args) => {
-                    // this is not good: if one arg is a const/number/variable id already exists!
-                    // let arg_ids: Vec<u16> = args.iter().map(|_| id_gen.get_id()).collect();
-                    // instead this complicated mess:
-                    let mut arg_ids: Vec<u16> = Vec::new();
+                // `a.b.c` where `a` is a variable: resolved as a dotted input path
+                Branch::Expression(_, _) if let Some(path) = tree.variable_path()
+                && !is_local(&path, scope) => {
+                    self.aliases.insert(path, id);
+                }
+                // field access `obj.field`: the attribute is not a value, it becomes the
+                // operation itself with `obj` as its single argument
+                Branch::Expression(exp, args) if exp.token == Token::Dot
+                && let Some(Branch::Atom(attr)) = args.get(1)
+                && let Token::Attr(_) = &attr.token => {
+                    let obj = &args[0];
+                    let obj_id = self.operand_id(obj, scope);
+                    self.recurse_tree(obj, scope, obj_id);
+                    self.insert(id, Evaluand::new(attr.to_owned(), vec![obj_id]));
+                }
+                // `with(a = 1, b = a + 1, a * b)`: the bindings are evaluated in order,
+                // each one in the scope of the previous ones, then the body
+                Branch::Expression(exp, args) if matches!(exp.token, Token::Func(Function::With, _)) => {
+                    let mut scope = scope.clone();
+                    let mut arg_ids = Vec::new();
                     for arg in args {
-                        if let Branch::Atom(at) = arg
-                        && let Token::Var(v) = &at.token {
-                            match aliases.get(v) {
-                                None => arg_ids.push(id_gen.get_id()),
-                                Some(id)  => arg_ids.push(*id)
-                            }
-                        } else {
-                            arg_ids.push(id_gen.get_id());
+                        let (name, value) = match named_argument(arg) {
+                            Some((name, value)) => (Some(&name.token), value),
+                            None => (None, arg) // the body
+                        };
+                        let value_id = self.operand_id(value, &scope);
+                        self.recurse_tree(value, &scope, value_id);
+                        arg_ids.push(value_id);
+                        if let Some(Token::Var(name)) = name {
+                            scope.insert(name.clone(), value_id);
                         }
                     }
-
-                    let eval = Evaluand {
-                            op: exp.to_owned(),
-                            args: arg_ids.to_owned()
+                    self.insert(id, Evaluand::new(exp.to_owned(), arg_ids));
+                }
+                // `(lo = min(v), hi = max(v))`: the values of the elements, the names
+                // are kept by the evaluand
+                Branch::Expression(exp, elements) if exp.token == Token::LP => {
+                    let mut arg_ids = Vec::new();
+                    let mut names = Vec::new();
+                    for element in elements {
+                        let (name, value) = match named_argument(element) {
+                            Some((name, value)) => (Some(name.token.to_string()), value),
+                            None => (None, element)
                         };
-                    println!("Insert expr {} {}", id, exp.token); // DEBUG
-                    expressions.insert( id, eval);
+                        let value_id = self.operand_id(value, scope);
+                        self.recurse_tree(value, scope, value_id);
+                        arg_ids.push(value_id);
+                        names.push(name);
+                    }
+                    self.insert(id, Evaluand { op: exp.to_owned(), args: arg_ids.into(), lambda: None, names, power: None });
+                }
+                // `avg(m, axis = 0)`: the keyword arguments are evaluated like the
+                // positional ones, their names are kept by the evaluand
+                Branch::Expression(exp, args) if let Token::Func(function, _) = &exp.token
+                && !function.keyword_params().is_empty() && args.iter().any(|a| named_argument(a).is_some()) => {
+                    let mut arg_ids = Vec::new();
+                    let mut names = Vec::new();
+                    for arg in args {
+                        let (name, value) = match named_argument(arg) {
+                            Some((name, value)) => (Some(name.token.to_string()), value),
+                            None => (None, arg)
+                        };
+                        let value_id = self.operand_id(value, scope);
+                        self.recurse_tree(value, scope, value_id);
+                        arg_ids.push(value_id);
+                        names.push(name);
+                    }
+                    self.insert(id, Evaluand { op: exp.to_owned(), args: arg_ids.into(), lambda: None, names, power: None });
+                }
+                // `x != 0 and 1 / x > 2`: the rhs is only evaluated if the lhs does not decide the result
+                Branch::Expression(exp, args) if let Token::LogicOp(op) = &exp.token
+                && let [lhs, rhs] = args.as_slice() => {
+                    let lhs_id = self.operand_id(lhs, scope);
+                    self.recurse_tree(lhs, scope, lhs_id);
+                    let guard = self.order.len();
+                    self.order.push(Step::ShortCircuit {
+                        cond: lhs_id,
+                        value: *op == LogicalOperator::Or,
+                        id,
+                        end: 0
+                    });
+                    let rhs_id = self.operand_id(rhs, scope);
+                    self.recurse_tree(rhs, scope, rhs_id);
+                    self.insert(id, Evaluand::new(exp.to_owned(), vec![lhs_id, rhs_id]));
+                    let after = self.order.len();
+                    if let Step::ShortCircuit { end, .. } = &mut self.order[guard] {
+                        *end = after;
+                    }
+                }
+                Branch::Expression(exp, args) => {
+                    // the anonymous function of `map(v, x -> x * a)` is not evaluated as an argument
+                    let lambda = args.iter().find(|a| Lambda::is_lambda(a)).map(|l| Rc::new(Lambda::new(l, scope)));
+                    let args: Vec<&Branch> = args.iter().filter(|a| !Lambda::is_lambda(a)).collect();
+                    let mut arg_ids: Vec<u16> = args.iter().map(|arg| self.operand_id(arg, scope)).collect();
 
+                    // followed by the variables captured by the anonymous function
+                    for v in lambda.iter().flat_map(|l| l.captures()) {
+                        let capture_id = match scope.get(v) {
+                            Some(id) => *id,
+                            None => self.alias_id(v.clone())
+                        };
+                        arg_ids.push(capture_id);
+                    }
 
                     for (id, arg) in arg_ids.iter().zip(args.iter()) {
-                        recurse_tree(
-                            arg,
-                            values,
-                            expressions,
-                            aliases,
-                            id_gen,
-                            *id
-                        );
+                        self.recurse_tree(arg, scope, *id);
                     }
+
+                    // strength reduction: `x ** 2` is `x * x`
+                    let power = match (&exp.token, args.as_slice()) {
+                        (Token::ArOp(ArithmeticOperator::Pow), [_, rhs]) => SmallPower::of(rhs),
+                        _ => None
+                    };
+                    println!("Insert expr {} {}", id, exp.token); // DEBUG
+                    self.insert(id, Evaluand {
+                        op: exp.to_owned(),
+                        args: arg_ids.into(),
+                        lambda,
+                        names: Vec::new(),
+                        power
+                    });
                 }
             }
         }
+}
+
+/// The variadic reduction `fun` of `args`, with the missing values, empty
+/// arrays, summation and `ddof` of `context`
+fn reduce_values(fun: &Function, args: &[&dyn DynMath], context: &EvalContext) -> Result<Box<dyn DynMath>, EvaluationError> {
+    // missing values in the arguments of the reductions
+    let present;
+    let present_refs: Vec<&dyn DynMath>;
+    let args = match args.iter().any(|x| has_missing(*x)) {
+        false => args,
+        true if context.missing == MissingPolicy::Propagate => return Ok(Box::new(Missing)),
+        true => {
+            present = present_values(args);
+            present_refs = present.iter().map(|x| &**x).collect();
+            if present_refs.is_empty() {
+                return Ok(Box::new(Missing));
+            }
+            &present_refs[..]
+        }
+    };
 
+    if is_empty_array(args) {
+        return context.empty_reduction.result(fun).map(|f| Box::new(f) as Box<dyn DynMath>);
+    }
+    if *fun == Function::Std && context.ddof != 0 {
+        return std_with_ddof(args, context.ddof).map(|std| Box::new(std) as Box<dyn DynMath>);
+    }
+    if context.summation != Summation::Naive
+        && let [arg] = args
+        && arg.array_len().is_some()
+        && matches!(fun, Function::Sum | Function::Avg | Function::Std)
+    {
+        let values = float_values(*arg, fun)?;
+        if let Some(result) = context.summation.reduce(fun, &values) {
+            return Ok(Box::new(result));
+        }
+    }
+    let result = match fun {
+        Function::Min => dynmath_min(args),
+        Function::Max => dynmath_max(args),
+        Function::Avg => dynmath_avg(args),
+        Function::Std => dynmath_std(args),
+        Function::Sum => dynmath_sum(args),
+        Function::Prod => dynmath_prod(args),
+        Function::Range => dynmath_range(args),
+        _ => panic!("ERROR: {} is variadic!", fun)
+    };
+    match result {
+        Err(err) => Err(err),
+        Ok(f) => Ok(Box::new(f))
+    }
+}
 
-    let id = id_gen.get_id();
-    recurse_tree(
-        &ast.tree,
-        &mut values,
-        &mut evaluands,
-        &mut aliases,
-        &mut id_gen,
-        id
-    );
 
+/// Number of argument ids stored inline, enough for the operators and most functions
+const INLINE_ARGS: usize = 4;
 
-    (values, aliases, evaluands)
+/// Ids of the arguments of an `Evaluand`, kept inside the evaluand (no heap
+/// allocation) for up to `INLINE_ARGS` arguments
+#[derive(Clone)]
+pub(crate) enum ArgIds {
+    Inline { len: u8, ids: [u16; INLINE_ARGS] },
+    Heap(Vec<u16>),
 }
 
+impl From<Vec<u16>> for ArgIds {
+    fn from(args: Vec<u16>) -> Self {
+        match args.len() {
+            len if len <= INLINE_ARGS => {
+                let mut ids = [0; INLINE_ARGS];
+                ids[..len].copy_from_slice(&args);
+                Self::Inline { len: len as u8, ids }
+            }
+            _ => Self::Heap(args)
+        }
+    }
+}
+
+impl std::ops::Deref for ArgIds {
+    type Target = [u16];
+
+    fn deref(&self) -> &[u16] {
+        match self {
+            Self::Inline { len, ids } => &ids[..*len as usize],
+            Self::Heap(ids) => ids
+        }
+    }
+}
 
-// by limiting args, this could be kept on the stack
 #[derive(Clone)]
 pub(crate) struct Evaluand {
     op: TokenContext,
-    args: Vec<u16>
+    args: ArgIds,
+    /// Function argument of `map`, `filter` and `reduce`, its captured
+    /// variables are the last `args`
+    lambda: Option<Rc<Lambda>>,
+    /// Names of the elements of a tuple `(lo = min(v), hi = max(v))`
+    names: Vec<Option<String>>,
+    /// `x ** 2` with a literal exponent evaluated as `x * x`, see `SmallPower`
+    power: Option<SmallPower>,
+}
+
+/// Literal exponents of `x ** n` with a faster evaluation than `powf()`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum SmallPower {
+    Square,
+    Cube,
+    Fourth,
+}
+
+impl SmallPower {
+    /// The fast path of `lhs ** rhs`, if `rhs` is a literal 2, 3 or 4. Not `0.5`:
+    /// `sqrt()` differs from `powf()` for `-0` and `-inf`.
+    pub(crate) fn of(rhs: &Branch) -> Option<Self> {
+        match rhs {
+            Branch::Atom(TokenContext { token: Token::Number(n), .. }) => match *n {
+                2.0 => Some(Self::Square),
+                3.0 => Some(Self::Cube),
+                4.0 => Some(Self::Fourth),
+                _ => None
+            },
+            _ => None
+        }
+    }
 }
 
 impl Evaluand {
-    pub(crate) fn eval(&self, values: &HashMap<u16, Rc<dyn DynMath>>) -> Result<Box<dyn DynMath>, EvaluationError> {
+    pub(crate) fn new(op: TokenContext, args: Vec<u16>) -> Self {
+        Self { op, args: args.into(), lambda: None, names: Vec::new(), power: None }
+    }
+
+    /// The reductions with keyword arguments: `avg(m, axis = 0, keepdims = 1)`
+    /// along an axis of a single value (see `DynMath::reduce()`), `std(v, ddof = 1)`
+    fn eval_keywords(&self, fun: &Function, reduction: Reduction, values: &HashMap<u16, Rc<dyn DynMath>>, context: &EvalContext)
+    -> Result<Box<dyn DynMath>, EvaluationError> {
+        let invalid = |details: String| EvaluationError::InvalidArguments { function: reduction.name().into(), details };
+        // non-negative integers
+        let index = |name: &str, value: &dyn DynMath| {
+            let x = match value.category() {
+                Category::Number => value.as_number(),
+                _ => return Err(invalid(format!("`{}` should be a number, not `{}`", name, value.type_name())))
+            };
+            match x >= 0.0 && x.fract() == 0.0 {
+                true => Ok(x),
+                false => Err(invalid(format!("`{}` should be a non-negative integer, not {}", name, x)))
+            }
+        };
+        let mut positional = Vec::new();
+        let mut axis = None;
+        let mut keepdims = false;
+        let mut ddof = None;
+        for (id, name) in self.args.iter().zip(&self.names) {
+            let value = &*values[id];
+            match name.as_deref() {
+                None => positional.push(value),
+                Some("axis") => axis = Some(index("axis", value)? as usize),
+                Some("ddof") => ddof = Some(index("ddof", value)?.min(u32::MAX as Float) as u32),
+                Some("keepdims") => keepdims = match value.downcast_ref::<bool>() {
+                    Some(b) => *b,
+                    None if matches!(value.category(), Category::Number) => value.as_number() != 0.0,
+                    None => return Err(invalid(format!("`keepdims` should be a number or a bool, not `{}`", value.type_name())))
+                },
+                Some(other) => return Err(invalid(format!("unknown keyword argument `{}`", other)))
+            }
+        }
+        match (axis, ddof) {
+            (Some(_), Some(_)) => Err(invalid("`ddof` is not supported along an axis".into())),
+            (Some(axis), None) => match positional.as_slice() {
+                [arg] => arg.reduce(reduction, Axis::Along(axis), keepdims),
+                _ => Err(invalid("reduces a single value along an axis".into()))
+            },
+            // a number either way
+            (None, Some(ddof)) => {
+                let context = EvalContext { ddof, ..context.clone() };
+                reduce_values(fun, &positional, &context)
+            }
+            (None, None) => reduce_values(fun, &positional, context)
+        }
+    }
+
+    pub(crate) fn eval(&self, values: &HashMap<u16, Rc<dyn DynMath>>, context: &EvalContext)
+    -> Result<Box<dyn DynMath>, EvaluationError> {
         use ArithmeticOperator as AO;
 
         let get_val = |id| &*values[id];
@@ -251,7 +1113,16 @@ impl Evaluand {
                             AO::Minus => return lhs.sub(rhs),
                             AO::Mul => return lhs.mul(rhs),
                             AO::Div => return lhs.div(rhs),
-                            AO::Pow => return lhs.pow(rhs),
+                            // the fast paths of numbers and arrays, other values may handle powers differently
+                            AO::Pow => match self.power {
+                                Some(power) if matches!(lhs.category(), Category::Number | Category::Array) => match power {
+                                    SmallPower::Square => lhs.dyn_pow2(),
+                                    SmallPower::Cube => lhs.dyn_pow3(),
+                                    SmallPower::Fourth => lhs.dyn_pow4(),
+                                },
+                                _ => lhs.pow(rhs)
+                            },
+                            AO::Xor => return lhs.xor(rhs),
                             _ => Err(EvaluationError::InvalidBinaryOperation {
                                     operation: self.op.token.to_string(),
                                     lhs: lhs.type_name().into(),
@@ -264,9 +1135,48 @@ impl Evaluand {
                     })
                 }
             }
+            Token::LB => get_val(&self.args[0]).index(get_val(&self.args[1])),
+            Token::LP => {
+                let values = self.args.iter().map(|id| get_val(id).clone_boxed()).collect();
+                Ok(Box::new(Tuple::new(self.names.clone(), values)))
+            }
+            Token::RelOp(op) => get_val(&self.args[0]).compare(get_val(&self.args[1]), op),
+            // reached only if the lhs did not short-circuit, see `Step::ShortCircuit`
+            Token::LogicOp(op) => {
+                let lhs = get_val(&self.args[0]);
+                let rhs = get_val(&self.args[1]);
+                match op {
+                    LogicalOperator::And => lhs.and(rhs),
+                    LogicalOperator::Or => lhs.or(rhs),
+                }
+            }
+            // the bindings are already evaluated, the result is the body
+            Token::Func(Function::With, _) => match self.args.last() {
+                Some(body) => Ok(get_val(body).clone_boxed()),
+                None => Err(EvaluationError::MissingFinalExpression)
+            }
+            Token::Func(fun, _) if let Some(lambda) = &self.lambda => {
+                let n = self.args.len() - lambda.captures().len();
+                let args: Vec<&dyn DynMath> = self.args[..n].iter().map(get_val).collect();
+                let captured: Vec<Rc<dyn DynMath>> = self.args[n..].iter().map(|id| values[id].clone()).collect();
+                lambda.apply(fun, &args, &captured, context)
+            }
+            Token::Func(fun, _) if !self.names.is_empty() && let Some(reduction) = Reduction::from_function(fun) => {
+                self.eval_keywords(fun, reduction, values, context)
+            }
             Token::Func(fun, max_args) => {
-                debug_assert!(self.args.len() <= *max_args);
-                if *max_args == 1 {
+                if self.args.len() > *max_args {
+                    return Err(EvaluationError::InvalidArguments {
+                        function: fun.to_string(),
+                        details: format!("accepts at most {} arguments", max_args)
+                    });
+                }
+                if *max_args == 0 {
+                    match fun {
+                        Function::Now => dynmath_now().map(|t| Box::new(t) as Box<dyn DynMath>),
+                        _ => panic!("ERROR: {} should have parameters!", fun)
+                    }
+                } else if *max_args == 1 {
                     // DEBUG >
                     println!("{}", values.iter().map(|(k,_)| format!("{}", k)).collect::<Vec<String>>().join(", "));
                     println!("get id {}", &self.args[0]);
@@ -284,29 +1194,106 @@ impl Evaluand {
                         Function::Log2 => return arg.dyn_log2(),
                         Function::Log10 => return arg.dyn_log10(),
                         Function::Sqrt => return arg.dyn_sqrt(),
+                        Function::Days => return arg.dyn_days(),
+                        Function::Hours => return arg.dyn_hours(),
+                        Function::IsNa => return dynmath_isna(arg),
+                        Function::Argmin => return dynmath_argmin(arg),
+                        Function::Argmax => return dynmath_argmax(arg),
                         _ => panic!("ERROR: {} should have only a single parameter!", fun)
                     }
+                } else if *max_args == 2 {
+                    // functions with an operand and a scalar parameter
+                    if self.args.len() != 2 {
+                        return Err(EvaluationError::InvalidArguments {
+                            function: fun.to_string(),
+                            details: "expects exactly two arguments".into()
+                        });
+                    }
+                    let arg = get_val(&self.args[0]);
+                    let param = get_val(&self.args[1]);
+                    // functions of two arrays, and the elementwise rounding and bitwise functions
+                    match fun {
+                        Function::Trapz => return dynmath_trapz(arg, param),
+                        Function::Gradient => return dynmath_gradient(arg, param),
+                        Function::Polyval => return dynmath_polyval(arg, param),
+                        Function::RoundTo => return dynmath_round_to(arg, param, Rounding::HalfAwayFromZero, "round_to"),
+                        Function::RoundEven => return dynmath_round_to(arg, param, Rounding::HalfEven, "round_even"),
+                        Function::FloorTo => return dynmath_round_to(arg, param, Rounding::Down, "floor_to"),
+                        Function::CeilTo => return dynmath_round_to(arg, param, Rounding::Up, "ceil_to"),
+                        Function::BitAnd => return dynmath_bitwise(arg, param, Bitwise::And),
+                        Function::BitOr => return dynmath_bitwise(arg, param, Bitwise::Or),
+                        Function::BitXor => return dynmath_bitwise(arg, param, Bitwise::Xor),
+                        Function::Shl => return dynmath_bitwise(arg, param, Bitwise::Shl),
+                        Function::Shr => return dynmath_bitwise(arg, param, Bitwise::Shr),
+                        _ => {}
+                    }
+                    if !matches!(param.category(), Category::Number) {
+                        return Err(EvaluationError::InvalidArguments {
+                            function: fun.to_string(),
+                            details: "the second argument should be a number".into()
+                        });
+                    }
+                    match fun {
+                        Function::Resample => arg.resample(param.as_number()),
+                        Function::Shift if !param.as_number().is_finite() => Err(EvaluationError::InvalidArguments {
+                            function: fun.to_string(),
+                            details: format!("the shift should be a finite number, not {}", param.as_number())
+                        }),
+                        // saturated to the range of `isize`
+                        Function::Shift => arg.shift(param.as_number().round() as isize),
+                        Function::Find => dynmath_find(arg, param.as_number()),
+                        _ => panic!("ERROR: {} should have exactly two parameters!", fun)
+                    }
+                } else if *max_args == 3 {
+                    // functions with an operand and two parameters
+                    if self.args.len() != 3 {
+                        return Err(EvaluationError::InvalidArguments {
+                            function: fun.to_string(),
+                            details: "expects exactly three arguments".into()
+                        });
+                    }
+                    let arg = get_val(&self.args[0]);
+                    match fun {
+                        Function::Between => dynmath_between(arg, get_val(&self.args[1]), get_val(&self.args[2])),
+                        Function::Where => dynmath_where(arg, get_val(&self.args[1]), get_val(&self.args[2])),
+                        Function::Convert => dynmath_convert(arg, get_val(&self.args[1]), get_val(&self.args[2]), &context.units),
+                        #[cfg(feature = "polyfit")]
+                        Function::Polyfit => dynmath_polyfit(arg, get_val(&self.args[1]), get_val(&self.args[2])),
+                        #[cfg(not(feature = "polyfit"))]
+                        Function::Polyfit => Err(EvaluationError::InvalidOperation {
+                            info: "polyfit() is only available with the `polyfit` feature".into()
+                        }),
+                        _ => panic!("ERROR: {} should have exactly three parameters!", fun)
+                    }
                 } else {
-                    let args: Vec<Rc<dyn DynMath>> = self.args.iter().map(
-                            |id| values.get(id).unwrap().clone()
-                        ).collect();
-
-
-                    let result = match fun {
-                        Function::Min => dynmath_min(&args),
-                        Function::Max => dynmath_max(&args),
-                        Function::Avg => dynmath_avg(&args),
-                        Function::Std => dynmath_std(&args),
-                        Function::Sum => dynmath_sum(&args),
-                        Function::Range => dynmath_range(&args),
-                        _ => panic!("ERROR: {} is variadic!", fun)
-                    };
-                    match result {
-                        Err(err) => Err(err),
-                        Ok(f) => Ok(Box::new(f))
+                    // the arguments are collected on the stack, no allocation per call
+                    if self.args.len() > MAX_FUNC_ARGS {
+                        return Err(EvaluationError::InvalidArguments {
+                            function: fun.to_string(),
+                            details: format!("accepts at most {} arguments", MAX_FUNC_ARGS)
+                        });
+                    }
+                    let mut buffer = [&NO_VALUE as &dyn DynMath; MAX_FUNC_ARGS];
+                    for (arg, id) in buffer.iter_mut().zip(self.args.iter()) {
+                        *arg = get_val(id);
                     }
+                    let args = &buffer[..self.args.len()];
+                    match fun {
+                        Function::Coalesce => return dynmath_coalesce(args),
+                        Function::Format => return dynmath_format(args),
+                        _ => {}
+                    }
+
+                    reduce_values(fun, args, context)
                 }
             }
+            Token::Attr(field) => {
+                let obj = get_val(&self.args[0]);
+                obj.get_field(field)
+            }
+            Token::Dot => Err(EvaluationError::InvalidOperation {
+                info: "Only named fields can be accessed with `.`".into(),
+            }),
             _ => panic!("wtf")
         }
     }
@@ -322,52 +1309,150 @@ mod tests {
     #[test]
     fn test_addition() {
         let token = Token::ArOp(ArithmeticOperator::Plus);
-        let tc = TokenContext {token: token, at: 0, len: 0 };
+        let tc = TokenContext::dummy(token);
         let eval = Evaluand {
             op: tc,
-            args: vec![0,1]
+            args: vec![0,1].into(),
+            lambda: None,
+            names: Vec::new(),
+            power: None
         };
 
         let mut variables: HashMap<u16, Rc<dyn DynMath>> = HashMap::new();
         variables.insert(0, Rc::new(1.0));
         variables.insert(1, Rc::new(2.0));
 
-        let res = eval.eval(&variables).unwrap();
+        let res = eval.eval(&variables, &EvalContext::default()).unwrap();
         assert_eq!(res.as_number(), 3.0);
     }
 
+    #[test]
+    fn test_profiling() {
+        let mut evaluator = Evaluator::new("sin(x) * 2 + max(x, 1)", &["x"]).unwrap();
+        let mut inputs = InputVars::new();
+        inputs.insert_owned("x".into(), 0.5);
+        evaluator.evaluate(&inputs).unwrap();
+        assert!(evaluator.profile().is_none());
+
+        evaluator.enable_profiling();
+        for _ in 0..3 {
+            evaluator.evaluate(&inputs).unwrap();
+        }
+        let profile = evaluator.profile().unwrap();
+        assert_eq!(profile.evaluations(), 3);
+        assert_eq!(profile.nodes().len(), 4);
+        let sin = profile.get(0).unwrap();
+        assert_eq!((sin.operation.as_str(), sin.calls), ("Sin", 3));
+        assert_eq!(profile.get(11).unwrap().operation, "+");
+        assert!(profile.to_string().contains("Max"));
+
+        let profile = evaluator.disable_profiling().unwrap();
+        assert_eq!(profile.annotations().len(), 4);
+        assert!(evaluator.profile().is_none());
+    }
+
+    #[test]
+    fn test_small_powers() {
+        let evaluator = Evaluator::new("x ** 2 + x ** 3 + x ** 4 + x ** 0.5 + x ** 5", &["x"]).unwrap();
+        let mut powers: Vec<SmallPower> = evaluator.expressions.values().filter_map(|e| e.power).collect();
+        powers.sort_by_key(|p| *p as u8);
+        assert_eq!(powers, [SmallPower::Square, SmallPower::Cube, SmallPower::Fourth]);
+
+        let mut inputs = InputVars::new();
+        inputs.insert_owned("x".into(), 4.0);
+        inputs.insert_owned("v".into(), vec![1.0, 2.0, 9.0]);
+        inputs.insert_owned("na".into(), vec![Some(2.0), None]);
+        let evaluate = |expr: &str| Evaluator::new(expr, &inputs.names()).unwrap().evaluate(&inputs).unwrap();
+        assert_eq!(evaluate("x ** 2 + x ** 3 + x ** 4 + x ** 0.5").as_number(), 16.0 + 64.0 + 256.0 + 2.0);
+        assert_eq!(evaluate("v ** 2").iterate().as_slice(), &[1.0, 4.0, 81.0]);
+        assert_eq!(evaluate("v ** 0.5").iterate().as_slice(), &[1.0, float::consts::SQRT_2, 3.0]);
+        // as `powf()`, unlike `sqrt()`
+        assert_eq!(evaluate("(-x / 0) ** 0.5").as_number(), float::INFINITY);
+        let zero = evaluate("(-0 * x) ** 0.5").as_number();
+        assert!(zero == 0.0 && zero.is_sign_positive());
+        // other values are not changed
+        assert_eq!(evaluate("na ** 2").downcast_ref::<Vec<Option<Float>>>(), Some(&vec![Some(4.0), None]));
+    }
+
+    #[test]
+    fn test_shared_literals() {
+        let mut evaluator = Evaluator::new("x*0.5 + y*0.5 + z*0.5 + 2*pi - pi", &["x", "y", "z"]).unwrap();
+        // 0.5, 2 and pi
+        assert_eq!(evaluator.values.len(), 3);
+        let mut inputs = InputVars::new();
+        inputs.insert_owned("x".into(), 1.0);
+        inputs.insert_owned("y".into(), 2.0);
+        inputs.insert_owned("z".into(), 3.0);
+        assert_eq!(evaluator.evaluate(&inputs).unwrap().as_number(), 3.0 + float::consts::PI);
+
+        let evaluator = Evaluator::new("format(\"{}\", 1) == format(\"{}\", 1)", &[]).unwrap();
+        assert_eq!(evaluator.values.len(), 2);
+    }
+
     #[test]
     fn test_variadic_fnc1() {
         let token = Token::Func(Function::Max, 10);
-        let tc = TokenContext {token: token, at: 0, len: 0};
+        let tc = TokenContext::dummy(token);
         let eval = Evaluand {
             op: tc,
-            args: vec![0, 1, 3, 5, 6,]
+            args: vec![0, 1, 3, 5, 6,].into(),
+            lambda: None,
+            names: Vec::new(),
+            power: None
         };
         let mut variables: HashMap<u16, Rc<dyn DynMath>> = HashMap::new();
         let test_val = [3.4, 0.0, 99.0, 16.0, 3.0, 2.0, -99.0, 1.0, -1.0];
         for (i, x) in test_val.iter().enumerate() {
             variables.insert(i as u16, Rc::new(*x));
         }
-        let res = eval.eval(&variables).unwrap();
+        let res = eval.eval(&variables, &EvalContext::default()).unwrap();
         assert_eq!(res.as_number(), 16.0);
     }
 
 
+    #[test]
+    fn test_variadic_range() {
+        let eval = Evaluand {
+            op: TokenContext::dummy(Token::Func(Function::Range, 10)),
+            args: vec![0, 1, 2].into(),
+            lambda: None,
+            names: Vec::new(),
+            power: None
+        };
+        let mut variables: HashMap<u16, Rc<dyn DynMath>> = HashMap::new();
+        for (i, x) in [2.0, -1.0, 5.0].iter().enumerate() {
+            variables.insert(i as u16, Rc::new(*x));
+        }
+        let res = eval.eval(&variables, &EvalContext::default()).unwrap();
+        assert_eq!(res.as_number(), 6.0);
+
+        let eval = Evaluand {
+            op: TokenContext::dummy(Token::Func(Function::Sum, MAX_FUNC_ARGS)),
+            args: vec![0; MAX_FUNC_ARGS + 1].into(),
+            lambda: None,
+            names: Vec::new(),
+            power: None
+        };
+        assert!(matches!(eval.eval(&variables, &EvalContext::default()), Err(EvaluationError::InvalidArguments { .. })));
+    }
+
     #[test]
     fn test_variadic_fnc2() {
         let token = Token::Func(Function::Max, 10);
-        let tc = TokenContext {token: token, at: 0, len: 0};
+        let tc = TokenContext::dummy(token);
         let eval = Evaluand {
             op: tc,
-            args: vec![0]
+            args: vec![0].into(),
+            lambda: None,
+            names: Vec::new(),
+            power: None
         };
         let mut variables: HashMap<u16, Rc<dyn DynMath>> = HashMap::new();
         let vector = vec![-16.0, -4.0, 0.0, 4.0, 8.0];
 
         variables.insert(0, Rc::new(vector));
 
-        let res = eval.eval(&variables).unwrap();
+        let res = eval.eval(&variables, &EvalContext::default()).unwrap();
         assert_eq!(res.as_number(), 8.0);
     }
 }
\ No newline at end of file
@@ -0,0 +1,26 @@
+use super::{DynMath, EvaluationError};
+
+
+/// Named field access for composite host types, usually derived with
+/// `#[derive(DynFields)]`. `DynMath::get_field()` of such types can
+/// forward to `dyn_field()`.
+pub trait DynFields {
+    /// Names of the fields that are accessible from expressions
+    fn field_names(&self) -> &'static [&'static str];
+
+    /// Returns `field_name` field of self
+    fn dyn_field(&self, field_name: &str) -> Result<Box<dyn DynMath>, EvaluationError>;
+}
+
+/// Box a copy of a field value (helper for `get_field()` implementations)
+pub fn field_value<T: DynMath>(value: &T) -> Result<Box<dyn DynMath>, EvaluationError> {
+    Ok(value.clone_boxed())
+}
+
+/// Error for a `field_name` field that does not exist on `type_name`
+pub fn invalid_field(type_name: &'static str, field_name: &str) -> Result<Box<dyn DynMath>, EvaluationError> {
+    Err(EvaluationError::InvalidField {
+        type_name,
+        field: field_name.into()
+    })
+}
@@ -0,0 +1,197 @@
+use std::fmt;
+use std::rc::Rc;
+use crate::Float;
+use super::{DynMath, Category, Missing, Tuple};
+
+
+/// Notation of the numbers written by a `ResultFormatter`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Notation {
+    /// Fixed, except for small and large numbers: scientific if the exponent is
+    /// below -4, or at least the number of significant digits (16 by default)
+    #[default]
+    Auto,
+    /// `1234.5`
+    Fixed,
+    /// `1.2345e3`
+    Scientific,
+}
+
+/// Suffix of a value, see `ResultFormatter::with_unit_suffix()`
+pub type UnitSuffix = Rc<dyn Fn(&dyn DynMath) -> Option<String>>;
+
+/// Formatting of evaluation results for display, so that host applications
+/// show numbers consistently:
+/// ```ignore
+/// let formatter = ResultFormatter::new()
+///     .with_significant_digits(4)
+///     .with_thousands_separator(',')
+///     .with_unit_suffix(|_| Some("nm".into()));
+/// println!("{}", formatter.display(&*result)); // 1,234 nm
+/// ```
+/// Arrays are written as `[1, 2, 3]`, tuples as `(1, hi = 2)`, missing values as `NA`.
+/// Without any options numbers are written in their shortest exact form.
+#[derive(Clone, Default)]
+pub struct ResultFormatter {
+    significant_digits: Option<usize>,
+    notation: Notation,
+    thousands_separator: Option<char>,
+    unit_suffix: Option<UnitSuffix>,
+}
+
+impl ResultFormatter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Round numbers to `digits` significant digits (at least 1)
+    pub fn with_significant_digits(mut self, digits: usize) -> Self {
+        self.significant_digits = Some(digits.max(1));
+        self
+    }
+
+    pub fn with_notation(mut self, notation: Notation) -> Self {
+        self.notation = notation;
+        self
+    }
+
+    /// Group the digits of the integer part by three in fixed notation: `1,234,567.5`
+    pub fn with_thousands_separator(mut self, separator: char) -> Self {
+        self.thousands_separator = Some(separator);
+        self
+    }
+
+    /// Hook for the unit of a number or array result: the returned text is written
+    /// after the value, separated by a space. For tuples it is called for each element.
+    pub fn with_unit_suffix<F>(mut self, suffix: F) -> Self
+    where F: Fn(&dyn DynMath) -> Option<String> + 'static {
+        self.unit_suffix = Some(Rc::new(suffix));
+        self
+    }
+
+    /// `value` formatted, as a `Display` value
+    pub fn display<'a>(&'a self, value: &'a dyn DynMath) -> FormattedValue<'a> {
+        FormattedValue { formatter: self, value }
+    }
+
+    pub fn format(&self, value: &dyn DynMath) -> String {
+        self.display(value).to_string()
+    }
+
+    /// A single number
+    pub fn format_number(&self, x: Float) -> String {
+        if !x.is_finite() {
+            return x.to_string();
+        }
+        // the exponent after rounding to the significant digits: 9.99 may become 1.0e1
+        let scientific = match self.significant_digits {
+            Some(digits) => format!("{:.*e}", digits - 1, x),
+            None => format!("{:e}", x)
+        };
+        let (mantissa, exponent) = scientific.split_once('e').expect("`{:e}` has an exponent");
+        let exponent: i32 = exponent.parse().expect("`{:e}` has an integer exponent");
+        let notation = match self.notation {
+            Notation::Auto if exponent < -4 || exponent >= self.significant_digits.unwrap_or(16) as i32 => Notation::Scientific,
+            Notation::Auto => Notation::Fixed,
+            notation => notation
+        };
+        match notation {
+            Notation::Scientific => format!("{}e{}", mantissa, exponent),
+            _ => {
+                let fixed = match self.significant_digits {
+                    Some(digits) => {
+                        let rounded: Float = scientific.parse().expect("`{:e}` parses");
+                        let decimals = (digits as i32 - 1 - exponent).max(0) as usize;
+                        format!("{:.*}", decimals, rounded)
+                    }
+                    None => x.to_string()
+                };
+                match self.thousands_separator {
+                    Some(separator) => group_thousands(&fixed, separator),
+                    None => fixed
+                }
+            }
+        }
+    }
+
+    fn write_value(&self, f: &mut fmt::Formatter, x: &dyn DynMath) -> fmt::Result {
+        match x.category() {
+            Category::Number => f.write_str(&self.format_number(x.as_number()))?,
+            Category::Array => self.write_list(f, x.iterate().map(|x| self.format_number(*x)))?,
+            Category::Unqiue => if let Some(t) = x.downcast_ref::<Tuple>() {
+                f.write_str("(")?;
+                for (i, (name, element)) in t.names().iter().zip(t.iter()).enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    if let Some(name) = name {
+                        write!(f, "{} = ", name)?;
+                    }
+                    self.write_value(f, element)?;
+                }
+                // no suffix for the tuple itself
+                return f.write_str(")");
+            } else if let Some(s) = x.downcast_ref::<String>() {
+                f.write_str(s)?
+            } else if let Some(b) = x.downcast_ref::<bool>() {
+                write!(f, "{}", b)?
+            } else if x.is::<Missing>() {
+                f.write_str("NA")?
+            } else if let Some(v) = x.downcast_ref::<Vec<bool>>() {
+                self.write_list(f, v.iter().map(|b| b.to_string()))?
+            } else if let Some(v) = x.downcast_ref::<Vec<Option<Float>>>() {
+                self.write_list(f, v.iter().map(|x| x.map_or_else(|| "NA".into(), |x| self.format_number(x))))?
+            } else {
+                x.fmt_value(f)?
+            }
+        }
+        match self.unit_suffix.as_ref().and_then(|suffix| suffix(x)) {
+            Some(suffix) => write!(f, " {}", suffix),
+            None => Ok(())
+        }
+    }
+
+    fn write_list(&self, f: &mut fmt::Formatter, items: impl Iterator<Item = String>) -> fmt::Result {
+        f.write_str("[")?;
+        for (i, item) in items.enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            f.write_str(&item)?;
+        }
+        f.write_str("]")
+    }
+}
+
+/// `1234567.5` -> `1,234,567.5`
+fn group_thousands(fixed: &str, separator: char) -> String {
+    let (sign, digits) = match fixed.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", fixed)
+    };
+    let (integer, fraction) = match digits.find('.') {
+        Some(dot) => digits.split_at(dot),
+        None => (digits, "")
+    };
+    let mut res = String::from(sign);
+    for (i, c) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            res.push(separator);
+        }
+        res.push(c);
+    }
+    res.push_str(fraction);
+    res
+}
+
+/// A value written by a `ResultFormatter`, see `ResultFormatter::display()`
+pub struct FormattedValue<'a> {
+    formatter: &'a ResultFormatter,
+    value: &'a dyn DynMath,
+}
+
+impl fmt::Display for FormattedValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.formatter.write_value(f, self.value)
+    }
+}
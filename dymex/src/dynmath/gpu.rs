@@ -0,0 +1,423 @@
+//! Evaluation of elementwise expressions over big arrays on the GPU (wgpu).
+//! The expression is translated to a WGSL compute shader computing one element
+//! per invocation:
+//! ```ignore
+//! let mut evaluator = GpuEvaluator::new("sqrt(x ** 2 + y ** 2) * where(x > 0, 1, -1)", &["x", "y"])?;
+//! let r = evaluator.evaluate(&inputs)?;  // Vec<Float> of the length of `x` and `y`
+//! ```
+//! The subset of `parser/translate.rs` is translated, the shader computes with
+//! `f32`s, so the results are less precise than on the CPU.
+//!
+//! The expression is evaluated by an `Evaluator` on the CPU instead, if
+//! - it has other nodes (like reductions), see `GpuEvaluator::is_compiled()`
+//! - the inputs are not numbers and `Vec<Float>`s of the same length, at least `min_len()` long
+//! - there is no GPU, or the inputs exceed the size of its storage buffers
+//! - the CPU evaluator has an audit sink, declared ranges, an arithmetic mode,
+//!   limits or a cancel handle, see `GpuEvaluator::cpu_evaluator()`
+
+use std::fmt::Write;
+use std::sync::mpsc;
+use wgpu::util::DeviceExt;
+use crate::{ArithmeticOperator, Branch, DymexError, Emitter, Float, Function, LogicalOperator, RelationalOperator, translate};
+use super::{DynMath, EvaluationError, Evaluator, InputVars};
+use super::eval::SmallPower;
+
+const WORKGROUP_SIZE: u32 = 256;
+const MAX_WORKGROUPS: u32 = 65535;
+
+/// Start of the shader: all inputs are in `data`, `layout` has the offset
+/// and the stride (0 for numbers) of each variable
+const PRELUDE: &str = "\
+@group(0) @binding(0) var<storage, read> data: array<f32>;
+@group(0) @binding(1) var<storage, read> layout: array<u32>;
+@group(0) @binding(2) var<storage, read_write> result: array<f32>;
+
+fn v(k: u32, i: u32) -> f32 {
+    return data[layout[2u * k] + i * layout[2u * k + 1u]];
+}
+
+// `pow()` is undefined for negative bases
+fn power(x: f32, y: f32) -> f32 {
+    if (x >= 0.0 || y != floor(y)) {
+        return pow(x, y);
+    }
+    let p = pow(-x, y);
+    return select(p, -p, y % 2.0 != 0.0);
+}
+";
+
+
+/// Evaluator of elementwise expressions, on the GPU when it is worth it
+pub struct GpuEvaluator {
+    evaluator: Evaluator,
+    /// The WGSL source, if the expression is supported
+    shader: Option<String>,
+    /// The variables of the expression, `v(k, i)` in the shader is `variables[k][i]`
+    variables: Vec<String>,
+    min_len: usize,
+    gpu: GpuState,
+}
+
+enum GpuState {
+    /// Initialized by the first evaluation on the GPU
+    Uninitialized,
+    Ready(Gpu),
+    Unavailable,
+}
+
+struct Gpu {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl GpuEvaluator {
+    /// Parse `expression`, every referenced variable has to be among `variables`
+    pub fn new(expression: &str, variables: &[&str]) -> Result<Self, DymexError> {
+        let evaluator = Evaluator::new(expression, variables)?;
+        let mut wgsl = Wgsl { variables: Vec::new() };
+        let shader = translate(&evaluator.ast().tree, &mut wgsl).ok().map(|body| {
+            let mut shader = String::from(PRELUDE);
+            writeln!(shader, "\n@compute @workgroup_size({})", WORKGROUP_SIZE).unwrap(); // writing to a String does not fail
+            writeln!(shader, "fn main(@builtin(global_invocation_id) id: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>) {{").unwrap();
+            writeln!(shader, "    let i = id.x + id.y * groups.x * {}u;", WORKGROUP_SIZE).unwrap();
+            writeln!(shader, "    if (i >= arrayLength(&result)) {{\n        return;\n    }}").unwrap();
+            writeln!(shader, "    result[i] = {};\n}}", body).unwrap();
+            shader
+        });
+        Ok(Self { evaluator, shader, variables: wgsl.variables, min_len: 100_000, gpu: GpuState::Uninitialized })
+    }
+
+    /// Whether the expression can be evaluated on the GPU
+    pub fn is_compiled(&self) -> bool {
+        self.shader.is_some()
+    }
+
+    /// The WGSL source of the compute shader
+    pub fn shader(&self) -> Option<&str> {
+        self.shader.as_deref()
+    }
+
+    /// Shorter arrays are evaluated on the CPU, the transfers would take longer
+    pub fn min_len(&self) -> usize {
+        self.min_len
+    }
+
+    pub fn set_min_len(&mut self, min_len: usize) {
+        self.min_len = min_len;
+    }
+
+    /// The CPU evaluator, for its settings (missing policy, limits, ...)
+    pub fn cpu_evaluator(&mut self) -> &mut Evaluator {
+        &mut self.evaluator
+    }
+
+    /// Evaluate on the GPU if possible (the result is a `Vec<Float>`), otherwise on the CPU
+    pub fn evaluate(&mut self, inputs: &InputVars) -> Result<Box<dyn DynMath>, EvaluationError> {
+        if !self.evaluator.is_plain() {
+            return self.evaluator.evaluate(inputs);
+        }
+        match self.evaluate_on_gpu(inputs) {
+            Some(result) => Ok(Box::new(result)),
+            None => self.evaluator.evaluate(inputs)
+        }
+    }
+
+    fn evaluate_on_gpu(&mut self, inputs: &InputVars) -> Option<Vec<Float>> {
+        let shader = self.shader.as_ref()?;
+        let (data, layout, len) = pack(&self.variables, inputs)?;
+        if len < self.min_len {
+            return None;
+        }
+        if let GpuState::Uninitialized = self.gpu {
+            self.gpu = Gpu::new(shader).map_or(GpuState::Unavailable, GpuState::Ready);
+        }
+        match &self.gpu {
+            GpuState::Ready(gpu) => gpu.run(&data, &layout, len),
+            _ => None
+        }
+    }
+}
+
+/// The values of the variables in a single buffer with their layout (see `PRELUDE`)
+/// and the length of the arrays. `None` if the inputs are not numbers and
+/// arrays of the same length, or there are no arrays.
+fn pack(variables: &[String], inputs: &InputVars) -> Option<(Vec<f32>, Vec<u32>, usize)> {
+    let values: Vec<&dyn DynMath> = variables.iter()
+        .map(|name| inputs.get(name).map(|value| value.as_ref()))
+        .collect::<Option<_>>()?;
+    let mut len = None;
+    for value in &values {
+        if let Some(array) = value.downcast_ref::<Vec<Float>>() {
+            match len {
+                Some(len) if len != array.len() => return None,
+                _ => len = Some(array.len())
+            }
+        } else if value.downcast_ref::<Float>().is_none() {
+            return None;
+        }
+    }
+    let len = len?;
+    let mut data = Vec::new();
+    let mut layout = Vec::new();
+    for value in values {
+        layout.push(data.len() as u32);
+        match value.downcast_ref::<Vec<Float>>() {
+            Some(array) => {
+                layout.push(1);
+                data.extend(array.iter().map(|x| *x as f32));
+            }
+            None => {
+                layout.push(0);
+                data.push(value.as_number() as f32);
+            }
+        }
+    }
+    Some((data, layout, len))
+}
+
+impl Gpu {
+    fn new(shader: &str) -> Option<Self> {
+        let instance = wgpu::Instance::default();
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))?;
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None)).ok()?;
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("dymex"),
+            source: wgpu::ShaderSource::Wgsl(shader.into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("dymex"),
+            layout: None,
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        Some(Self { device, queue, pipeline })
+    }
+
+    /// `None` if the buffers would be empty (wgpu rejects the zero sized bindings) or too large
+    fn run(&self, data: &[f32], layout: &[u32], len: usize) -> Option<Vec<Float>> {
+        if len == 0 || data.is_empty() {
+            return None;
+        }
+        let limit = self.device.limits().max_storage_buffer_binding_size as usize;
+        if data.len() * size_of::<f32>() > limit || len * size_of::<f32>() > limit {
+            return None;
+        }
+        let storage = |contents: &[u8]| self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents,
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let data = storage(&data.iter().flat_map(|x| x.to_ne_bytes()).collect::<Vec<u8>>());
+        let layout = storage(&layout.iter().flat_map(|x| x.to_ne_bytes()).collect::<Vec<u8>>());
+        let size = (len * size_of::<f32>()) as u64;
+        let result = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: data.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: layout.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: result.as_entire_binding() },
+            ],
+        });
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            // the rows of workgroups are continued in the next row
+            let workgroups = (len as u32).div_ceil(WORKGROUP_SIZE);
+            pass.dispatch_workgroups(workgroups.min(MAX_WORKGROUPS), workgroups.div_ceil(MAX_WORKGROUPS), 1);
+        }
+        encoder.copy_buffer_to_buffer(&result, 0, &staging, 0, size);
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = staging.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |mapped| {
+            let _ = sender.send(mapped);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv().ok()?.ok()?;
+        let values = slice.get_mapped_range().chunks_exact(size_of::<f32>())
+            .map(|bytes| f32::from_ne_bytes(bytes.try_into().unwrap()) as Float)
+            .collect();
+        staging.unmap();
+        Some(values)
+    }
+}
+
+
+/// Emitter of WGSL expressions, `v(k, i)` is the element `i` of `variables[k]`.
+/// The variables are collected in the order of their first occurrence.
+struct Wgsl {
+    variables: Vec<String>,
+}
+
+impl Emitter for Wgsl {
+    type Number = String;
+    type Bool = String;
+    type Error = ();
+
+    fn unsupported(&mut self, _: &Branch) {}
+
+    fn number(&mut self, x: Float) -> Result<String, ()> {
+        literal(x).ok_or(())
+    }
+
+    fn variable(&mut self, name: &str, _: usize) -> Result<String, ()> {
+        let k = match self.variables.iter().position(|v| v == name) {
+            Some(k) => k,
+            None => {
+                self.variables.push(name.to_string());
+                self.variables.len() - 1
+            }
+        };
+        Ok(format!("v({}u, i)", k))
+    }
+
+    fn negate(&mut self, x: String) -> Result<String, ()> {
+        Ok(format!("(-{})", x))
+    }
+
+    fn arithmetic(&mut self, op: &ArithmeticOperator, a: String, b: String) -> Result<String, ()> {
+        match op {
+            ArithmeticOperator::Plus => Ok(format!("({} + {})", a, b)),
+            ArithmeticOperator::Minus => Ok(format!("({} - {})", a, b)),
+            ArithmeticOperator::Mul => Ok(format!("({} * {})", a, b)),
+            ArithmeticOperator::Div => Ok(format!("({} / {})", a, b)),
+            _ => Err(())
+        }
+    }
+
+    fn power(&mut self, a: String, b: String, rhs: &Branch) -> Result<String, ()> {
+        match SmallPower::of(rhs) {
+            Some(SmallPower::Square) => Ok(format!("({0} * {0})", a)),
+            _ => Ok(format!("power({}, {})", a, b)),
+        }
+    }
+
+    fn unary(&mut self, f: &Function, x: String) -> Result<String, ()> {
+        match f {
+            Function::Cot => Ok(format!("(1.0 / tan({}))", x)),
+            Function::Log => Ok(format!("log({})", x)),
+            Function::Log10 => Ok(format!("(log2({}) * {})", x, literal(std::f64::consts::LOG10_2 as Float).ok_or(())?)),
+            f => Ok(format!("{}({})", f.name(), x)),
+        }
+    }
+
+    fn extremum(&mut self, f: &Function, a: String, b: String) -> Result<String, ()> {
+        Ok(format!("{}({}, {})", f.name(), a, b))
+    }
+
+    fn select(&mut self, condition: String, a: String, b: String) -> Result<String, ()> {
+        Ok(format!("select({}, {}, {})", b, a, condition))
+    }
+
+    fn compare(&mut self, op: &RelationalOperator, a: String, b: String) -> Result<String, ()> {
+        let op = match op {
+            RelationalOperator::Eq => "==",
+            RelationalOperator::Neq => "!=",
+            RelationalOperator::Greater => ">",
+            RelationalOperator::Lesser => "<",
+            RelationalOperator::Geq => ">=",
+            RelationalOperator::Leq => "<=",
+        };
+        Ok(format!("({} {} {})", a, op, b))
+    }
+
+    fn logical(&mut self, op: &LogicalOperator, a: String, b: String) -> Result<String, ()> {
+        let op = match op {
+            LogicalOperator::And => "&&",
+            LogicalOperator::Or => "||",
+        };
+        Ok(format!("({} {} {})", a, op, b))
+    }
+}
+
+/// WGSL float literal, `-` is an operator. `None` out of the range of `f32`:
+/// WGSL has no literals of the infinities.
+fn literal(x: Float) -> Option<String> {
+    let x32 = x as f32;
+    if !x32.is_finite() {
+        return None;
+    }
+    let literal = format!("{:?}", x32);
+    let literal = match literal.contains(['.', 'e']) {
+        true => literal,
+        false => literal + ".0"
+    };
+    match x.is_sign_negative() {
+        true => Some(format!("({})", literal)),
+        false => Some(literal)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_gpu_shader() {
+        let evaluator = GpuEvaluator::new("where(x > 0 and y != 1, sqrt(x ** 2 + y ** 2), -2.5 * min(x, y, 1))", &["x", "y"]).unwrap();
+        let shader = evaluator.shader().unwrap();
+        assert!(shader.contains(
+            "result[i] = select(((-2.5) * min(min(v(0u, i), v(1u, i)), 1.0)), \
+            sqrt(((v(0u, i) * v(0u, i)) + (v(1u, i) * v(1u, i)))), ((v(0u, i) > 0.0) && (v(1u, i) != 1.0)));"
+        ), "{}", shader);
+        assert!(!GpuEvaluator::new("x - avg(x)", &["x"]).unwrap().is_compiled());
+        // out of the range of `f32`
+        assert!(!GpuEvaluator::new("x * 1e300", &["x"]).unwrap().is_compiled());
+        assert!(!GpuEvaluator::new("x - 1e39", &["x"]).unwrap().is_compiled());
+        assert!(GpuEvaluator::new("x * 1e-300", &["x"]).unwrap().is_compiled());
+    }
+
+    #[test]
+    fn test_gpu_evaluation() {
+        let x: Vec<Float> = (0..1000).map(|i| i as Float * 0.01 - 5.0).collect();
+        let mut inputs = InputVars::new();
+        inputs.insert_owned("x".into(), x.clone());
+        inputs.insert_owned("a".into(), 2.0);
+        // on the GPU if there is one, otherwise on the CPU: the results are the same
+        let mut evaluator = GpuEvaluator::new("a * x ** 3 - where(x < 0, exp(x), cos(x))", &["x", "a"]).unwrap();
+        evaluator.set_min_len(0);
+        let result = evaluator.evaluate(&inputs).unwrap();
+        let result = result.downcast_ref::<Vec<Float>>().unwrap();
+        for (x, y) in x.iter().zip(result) {
+            let expected = 2.0 * x.powi(3) - if *x < 0.0 { x.exp() } else { x.cos() };
+            assert!((y - expected).abs() < 1e-4 * expected.abs().max(1.0), "{} {} {}", x, y, expected);
+        }
+        // the settings of the CPU evaluator apply
+        evaluator.cpu_evaluator().declare_range("x", 0.0, 1.0);
+        assert!(evaluator.evaluate(&inputs).is_err());
+        evaluator.cpu_evaluator().declare_range("x", -10.0, 10.0);
+        evaluator.cpu_evaluator().set_arithmetic_mode(ArithmeticMode::Saturating { min: -1.0, max: 1.0 }).unwrap();
+        let result = evaluator.evaluate(&inputs).unwrap();
+        assert!(result.downcast_ref::<Vec<Float>>().unwrap().iter().all(|y| y.abs() <= 1.0));
+        // unsupported inputs are evaluated on the CPU
+        inputs.insert_owned("x".into(), vec![Some(1.0), None]);
+        assert!(evaluator.evaluate(&inputs).is_ok());
+
+        // so are the empty arrays, wgpu rejects empty buffers
+        let mut evaluator = GpuEvaluator::new("a * x ** 3 - where(x < 0, exp(x), cos(x))", &["x", "a"]).unwrap();
+        evaluator.set_min_len(0);
+        inputs.insert_owned("x".into(), Vec::<Float>::new());
+        assert_eq!(evaluator.evaluate(&inputs).unwrap().downcast_ref::<Vec<Float>>(), Some(&vec![]));
+    }
+}
@@ -0,0 +1,239 @@
+use std::any::Any;
+use std::cell::OnceCell;
+use std::fmt;
+use std::path::Path;
+use std::rc::Rc;
+use thiserror::Error;
+use crate::{Float, MAXDIM, RelationalOperator};
+use super::{DynMath, EvaluationError, Category, InputVars, Unary};
+
+
+#[derive(Error, Debug)]
+pub enum Hdf5Error {
+    #[error("HDF5 error: {0}")]
+    Hdf5(#[from] hdf5::Error),
+    #[error("the dataset `{name}` has the shape {shape:?}, not 1 dimension")]
+    Shape {
+        name: String,
+        shape: Vec<usize>,
+    },
+}
+
+/// Array input backed by a 1 dimensional dataset of an HDF5 file: the elements
+/// are read (converted to `Float`) when the dataset is first used by an
+/// expression, so binding every dataset of a file is cheap and only the ones
+/// the expressions refer to are loaded. The loaded elements are shared by the
+/// clones of the value.
+/// ```ignore
+/// let inputs = InputVars::from_hdf5("run_042.h5", &[
+///     ("spectra/wavelength", "wl"),
+///     ("spectra/intensity", "i"),
+/// ])?;
+/// let centroid = Evaluator::new("sum(i * wl) / sum(i)", &inputs.names())?.evaluate(&inputs)?;
+/// ```
+/// Operations work on the loaded `Vec<Float>`, errors of reading the dataset are
+/// `EvaluationError::InvalidOperation`s of the evaluation.
+#[derive(Clone)]
+pub struct Hdf5Dataset {
+    dataset: hdf5::Dataset,
+    values: Rc<OnceCell<Vec<Float>>>,
+}
+
+impl Hdf5Dataset {
+    /// Bind `dataset`, it must be 1 dimensional. Nothing is read until it is used.
+    pub fn new(dataset: hdf5::Dataset) -> Result<Self, Hdf5Error> {
+        let shape = dataset.shape();
+        if shape.len() != 1 {
+            return Err(Hdf5Error::Shape { name: dataset.name(), shape });
+        }
+        Ok(Self { dataset, values: Rc::new(OnceCell::new()) })
+    }
+
+    pub fn len(&self) -> usize {
+        self.dataset.size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the elements have been read from the file
+    pub fn is_loaded(&self) -> bool {
+        self.values.get().is_some()
+    }
+
+    /// The elements, read from the file on the first call
+    pub fn values(&self) -> Result<&Vec<Float>, EvaluationError> {
+        if let Some(values) = self.values.get() {
+            return Ok(values);
+        }
+        let values = self.dataset.read_raw::<Float>().map_err(|e| EvaluationError::InvalidOperation {
+            info: format!("failed to read the dataset `{}`: {}", self.dataset.name(), e)
+        })?;
+        Ok(self.values.get_or_init(|| values))
+    }
+}
+
+impl InputVars {
+    /// Bind datasets of `file` as variables, `datasets` maps the paths of the
+    /// datasets in the file to variable names. See `Hdf5Dataset`.
+    pub fn insert_hdf5(&mut self, file: &hdf5::File, datasets: &[(&str, &str)]) -> Result<(), Hdf5Error> {
+        for (path, name) in datasets {
+            let dataset = Hdf5Dataset::new(file.dataset(path)?)?;
+            self.insert_owned(name.to_string(), dataset);
+        }
+        Ok(())
+    }
+
+    /// Open the HDF5 file at `path` and bind `datasets` (dataset path, variable name) of it
+    pub fn from_hdf5(path: impl AsRef<Path>, datasets: &[(&str, &str)]) -> Result<Self, Hdf5Error> {
+        let file = hdf5::File::open(path)?;
+        let mut inputs = Self::new();
+        inputs.insert_hdf5(&file, datasets)?;
+        Ok(inputs)
+    }
+}
+
+impl fmt::Debug for Hdf5Dataset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Hdf5Dataset({}, loaded: {})", self.dataset.name(), self.is_loaded())
+    }
+}
+
+impl DynMath for Hdf5Dataset {
+
+    fn clone_boxed(&self) -> Box<dyn DynMath> { Box::new(self.clone()) }
+
+    /// Not `Category::Array`, the elements may not be read yet
+    fn category(&self) -> Category { Category::Unqiue }
+
+    fn array_len(&self) -> Option<usize> { Some(self.len()) }
+
+    fn shape(&self) -> [usize; MAXDIM] {
+        let mut shape = [0; MAXDIM];
+        shape[0] = self.len();
+        shape
+    }
+
+    fn type_name(&self) -> &'static str { "Hdf5Dataset" }
+
+    fn memory_size(&self) -> usize {
+        size_of::<Self>() + self.values.get().map_or(0, |values| values.memory_size())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn fmt_value(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<Hdf5Dataset {} of {} elements>", self.dataset.name(), self.len())
+    }
+
+    fn unary_dispatcher(&self, f: Unary, op: &str) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.values()?.unary_dispatcher(f, op)
+    }
+
+    fn elementwise(&self, f: Unary) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.values()?.elementwise(f)
+    }
+
+    fn add(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.values()?.add(other)
+    }
+    fn sub(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.values()?.sub(other)
+    }
+    fn sub_inv(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.values()?.sub_inv(other)
+    }
+    fn mul(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.values()?.mul(other)
+    }
+    fn div(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.values()?.div(other)
+    }
+    fn div_inv(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.values()?.div_inv(other)
+    }
+    fn pow(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.values()?.pow(other)
+    }
+    fn pow_inv(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.values()?.pow_inv(other)
+    }
+    fn xor(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.values()?.xor(other)
+    }
+
+    fn compare(&self, other: &dyn DynMath, op: &RelationalOperator) -> Result<Box<dyn DynMath>, EvaluationError> {
+        match other.downcast_ref::<Hdf5Dataset>() {
+            Some(other) => self.values()?.compare(other.values()?, op),
+            None => self.values()?.compare(other, op)
+        }
+    }
+
+    fn index(&self, index: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.values()?.index(index)
+    }
+
+    fn min(&self) -> Result<Float, EvaluationError> {
+        self.values()?.min()
+    }
+    fn max(&self) -> Result<Float, EvaluationError> {
+        self.values()?.max()
+    }
+    fn range(&self) -> Result<Float, EvaluationError> {
+        self.values()?.range()
+    }
+    fn sum(&self) -> Result<Float, EvaluationError> {
+        self.values()?.sum()
+    }
+    fn prod(&self) -> Result<Float, EvaluationError> {
+        self.values()?.prod()
+    }
+    fn avg(&self) -> Result<Float, EvaluationError> {
+        self.values()?.avg()
+    }
+    fn std(&self) -> Result<Float, EvaluationError> {
+        self.values()?.std()
+    }
+    fn l2_norm(&self) -> Result<Float, EvaluationError> {
+        self.values()?.l2_norm()
+    }
+    fn l1_norm(&self) -> Result<Float, EvaluationError> {
+        self.values()?.l1_norm()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_hdf5_datasets() {
+        let path = std::env::temp_dir().join(format!("dymex_test_{}.h5", std::process::id()));
+        {
+            let file = hdf5::File::create(&path).unwrap();
+            let spectra = file.create_group("spectra").unwrap();
+            spectra.new_dataset_builder().with_data(&[400.0, 500.0, 600.0]).create("wavelength").unwrap();
+            spectra.new_dataset_builder().with_data(&[1i32, 3, 2]).create("intensity").unwrap();
+            file.new_dataset_builder().with_data(&[[1.0, 2.0], [3.0, 4.0]]).create("image").unwrap();
+        }
+
+        let inputs = InputVars::from_hdf5(&path, &[("spectra/wavelength", "wl"), ("spectra/intensity", "i")]).unwrap();
+        let wl = inputs.get("wl").unwrap().downcast_ref::<Hdf5Dataset>().unwrap();
+        assert_eq!(wl.len(), 3);
+        assert!(!wl.is_loaded());
+        let mut evaluate = |expr: &str| Evaluator::new(expr, &["wl", "i"]).unwrap().evaluate(&inputs).unwrap();
+        assert_eq!(evaluate("sum(i)").as_number(), 6.0);
+        assert!(!inputs.get("wl").unwrap().downcast_ref::<Hdf5Dataset>().unwrap().is_loaded());
+        assert_eq!(evaluate("wl[i == max(i)]").iterate().as_slice(), &[500.0]);
+        assert_eq!(evaluate("wl / 100 + i").iterate().as_slice(), &[5.0, 8.0, 8.0]);
+        assert!(inputs.get("wl").unwrap().downcast_ref::<Hdf5Dataset>().unwrap().is_loaded());
+
+        assert!(matches!(InputVars::from_hdf5(&path, &[("image", "img")]), Err(Hdf5Error::Shape { .. })));
+        assert!(InputVars::from_hdf5(&path, &[("spectra/missing", "m")]).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,318 @@
+//! Cranelift JIT compilation of scalar expressions, for formulas evaluated with
+//! different inputs millions of times (like once per sample of a signal).
+//! The expression is compiled to a native function reading the inputs from a
+//! slice, in the order of the input names:
+//! ```ignore
+//! let mut f = JitEvaluator::new("a * sin(t) ** 2 + b", &["a", "b", "t"])?;
+//! for (t, y) in samples.iter().zip(&mut output) {
+//!     *y = f.evaluate(&[2.0, 0.5, *t])?;
+//! }
+//! ```
+//! The subset of `parser/translate.rs` is compiled, expressions with any other
+//! node are evaluated by an `Evaluator` instead, see `JitEvaluator::is_compiled()`.
+
+use cranelift_codegen::ir::{types, AbiParam, InstBuilder, MemFlags, Value};
+use cranelift_codegen::ir::condcodes::FloatCC;
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Linkage, Module};
+use crate::{ArithmeticOperator, Branch, DymexError, Emitter, Float, Function, LogicalOperator, RelationalOperator, translate};
+use super::{Category, EvaluationError, Evaluator, InputVars};
+use super::eval::SmallPower;
+
+/// The compiled expression, called with a pointer to the input values
+type Compiled = extern "C" fn(*const f64) -> f64;
+
+
+/// Evaluator of a scalar expression with the inputs given as a slice of numbers.
+/// Supported expressions are compiled to native code, the others are evaluated
+/// by the interpreter, so the result is the same either way.
+pub struct JitEvaluator {
+    inputs: Vec<String>,
+    backend: Backend,
+}
+
+enum Backend {
+    /// `module` owns the memory of the code of `function`
+    Native { function: Compiled, module: Option<JITModule> },
+    Interpreter { evaluator: Evaluator, vars: InputVars },
+}
+
+impl JitEvaluator {
+    /// Parse `expression` and compile it if possible, every referenced variable
+    /// has to be among `inputs`
+    pub fn new(expression: &str, inputs: &[&str]) -> Result<Self, DymexError> {
+        let evaluator = Evaluator::new(expression, inputs)?;
+        let inputs: Vec<String> = inputs.iter().map(|name| name.to_string()).collect();
+        let backend = match compile(&evaluator.ast().tree, &inputs) {
+            Some((function, module)) => Backend::Native { function, module: Some(module) },
+            None => Backend::Interpreter { evaluator, vars: InputVars::new() }
+        };
+        Ok(Self { inputs, backend })
+    }
+
+    /// Whether the expression is evaluated by native code (otherwise by the interpreter)
+    pub fn is_compiled(&self) -> bool {
+        matches!(self.backend, Backend::Native { .. })
+    }
+
+    pub fn input_names(&self) -> &[String] {
+        &self.inputs
+    }
+
+    /// Evaluate with `values` as the inputs, in the order of `input_names()`.
+    /// Fails if the interpreted expression fails or its result is not a number.
+    /// Panics if the number of values differs from the number of inputs.
+    pub fn evaluate(&mut self, values: &[Float]) -> Result<Float, EvaluationError> {
+        assert_eq!(values.len(), self.inputs.len(), "one value is needed for each input");
+        match &mut self.backend {
+            Backend::Native { function, .. } => Ok(function(values.as_ptr())),
+            Backend::Interpreter { evaluator, vars } => {
+                for (name, value) in self.inputs.iter().zip(values) {
+                    vars.insert_owned(name.clone(), *value);
+                }
+                let result = evaluator.evaluate(vars)?;
+                match result.category() {
+                    Category::Number => Ok(result.as_number()),
+                    _ => Err(EvaluationError::InvalidOperation {
+                        info: format!("the result is a `{}`, not a number", result.type_name())
+                    })
+                }
+            }
+        }
+    }
+}
+
+impl Drop for JitEvaluator {
+    fn drop(&mut self) {
+        if let Backend::Native { module, .. } = &mut self.backend
+            && let Some(module) = module.take() {
+            // SAFETY: `function` is not called after this
+            unsafe { module.free_memory() };
+        }
+    }
+}
+
+
+/// The functions called by the compiled code, with the symbol `dymex_{name}`
+const UNARY: [(Function, extern "C" fn(f64) -> f64); 8] = [
+    (Function::Sin, sin), (Function::Cos, cos), (Function::Tan, tan), (Function::Cot, cot),
+    (Function::Exp, exp), (Function::Log, log), (Function::Log2, log2), (Function::Log10, log10),
+];
+const BINARY: [(&str, extern "C" fn(f64, f64) -> f64); 3] = [
+    ("dymex_pow", pow), ("dymex_min", min), ("dymex_max", max),
+];
+
+// Same as the evaluation of numbers by the interpreter
+extern "C" fn sin(x: f64) -> f64 { x.sin() }
+extern "C" fn cos(x: f64) -> f64 { x.cos() }
+extern "C" fn tan(x: f64) -> f64 { x.tan() }
+extern "C" fn cot(x: f64) -> f64 { 1.0 / x.tan() }
+extern "C" fn exp(x: f64) -> f64 { x.exp() }
+extern "C" fn log(x: f64) -> f64 { x.ln() }
+extern "C" fn log2(x: f64) -> f64 { x.log2() }
+extern "C" fn log10(x: f64) -> f64 { x.log10() }
+extern "C" fn pow(x: f64, y: f64) -> f64 { x.powf(y) }
+extern "C" fn min(x: f64, y: f64) -> f64 { x.min(y) }
+extern "C" fn max(x: f64, y: f64) -> f64 { x.max(y) }
+
+fn unary_symbol(f: &Function) -> Option<String> {
+    UNARY.iter().any(|(g, _)| g == f).then(|| format!("dymex_{}", f.name()))
+}
+
+/// Compile `tree` to a native function, `None` if it has unsupported nodes
+fn compile(tree: &Branch, inputs: &[String]) -> Option<(Compiled, JITModule)> {
+    let mut flags = settings::builder();
+    flags.set("opt_level", "speed").ok()?;
+    let isa = cranelift_native::builder().ok()?.finish(settings::Flags::new(flags)).ok()?;
+    let mut builder = JITBuilder::with_isa(isa, default_libcall_names());
+    for (f, function) in UNARY {
+        builder.symbol(format!("dymex_{}", f.name()), function as *const u8);
+    }
+    for (name, function) in BINARY {
+        builder.symbol(name, function as *const u8);
+    }
+    let mut module = JITModule::new(builder);
+
+    let mut context = module.make_context();
+    context.func.signature.params.push(AbiParam::new(module.target_config().pointer_type()));
+    context.func.signature.returns.push(AbiParam::new(types::F64));
+    let mut builder_context = FunctionBuilderContext::new();
+    let mut builder = FunctionBuilder::new(&mut context.func, &mut builder_context);
+    let block = builder.create_block();
+    builder.append_block_params_for_function_params(block);
+    builder.switch_to_block(block);
+    builder.seal_block(block);
+    let values = builder.block_params(block)[0];
+    let mut codegen = Codegen { builder, module: &mut module, inputs, values };
+    let result = translate(tree, &mut codegen).ok()?;
+    codegen.builder.ins().return_(&[result]);
+    codegen.builder.finalize();
+
+    let id = module.declare_function("expression", Linkage::Export, &context.func.signature).ok()?;
+    module.define_function(id, &mut context).ok()?;
+    module.clear_context(&mut context);
+    module.finalize_definitions().ok()?;
+    // SAFETY: the function was defined with the signature of `Compiled`
+    let function = unsafe { std::mem::transmute::<*const u8, Compiled>(module.get_finalized_function(id)) };
+    Some((function, module))
+}
+
+struct Codegen<'a> {
+    builder: FunctionBuilder<'a>,
+    module: &'a mut JITModule,
+    inputs: &'a [String],
+    /// Pointer to the first input value
+    values: Value,
+}
+
+impl Emitter for Codegen<'_> {
+    type Number = Value;
+    type Bool = Value;
+    type Error = ();
+
+    fn unsupported(&mut self, _: &Branch) {}
+
+    fn number(&mut self, x: Float) -> Result<Value, ()> {
+        Ok(self.builder.ins().f64const(x))
+    }
+
+    fn variable(&mut self, name: &str, _: usize) -> Result<Value, ()> {
+        let i = self.inputs.iter().position(|input| input == name).ok_or(())?;
+        let offset = (i * size_of::<f64>()) as i32;
+        Ok(self.builder.ins().load(types::F64, MemFlags::trusted(), self.values, offset))
+    }
+
+    fn negate(&mut self, x: Value) -> Result<Value, ()> {
+        Ok(self.builder.ins().fneg(x))
+    }
+
+    fn arithmetic(&mut self, op: &ArithmeticOperator, a: Value, b: Value) -> Result<Value, ()> {
+        let ins = self.builder.ins();
+        match op {
+            ArithmeticOperator::Plus => Ok(ins.fadd(a, b)),
+            ArithmeticOperator::Minus => Ok(ins.fsub(a, b)),
+            ArithmeticOperator::Mul => Ok(ins.fmul(a, b)),
+            ArithmeticOperator::Div => Ok(ins.fdiv(a, b)),
+            _ => Err(())
+        }
+    }
+
+    /// Multiplications for the small literal exponents
+    fn power(&mut self, a: Value, b: Value, rhs: &Branch) -> Result<Value, ()> {
+        let ins = self.builder.ins();
+        match SmallPower::of(rhs) {
+            Some(SmallPower::Square) => Ok(ins.fmul(a, a)),
+            Some(SmallPower::Cube) => {
+                let square = ins.fmul(a, a);
+                Ok(self.builder.ins().fmul(square, a))
+            }
+            Some(SmallPower::Fourth) => {
+                let square = ins.fmul(a, a);
+                Ok(self.builder.ins().fmul(square, square))
+            }
+            None => self.call("dymex_pow", &[a, b])
+        }
+    }
+
+    fn unary(&mut self, f: &Function, x: Value) -> Result<Value, ()> {
+        match f {
+            Function::Abs => Ok(self.builder.ins().fabs(x)),
+            Function::Sqrt => Ok(self.builder.ins().sqrt(x)),
+            f => {
+                let symbol = unary_symbol(f).ok_or(())?;
+                self.call(&symbol, &[x])
+            }
+        }
+    }
+
+    fn extremum(&mut self, f: &Function, a: Value, b: Value) -> Result<Value, ()> {
+        match f {
+            Function::Min => self.call("dymex_min", &[a, b]),
+            _ => self.call("dymex_max", &[a, b]),
+        }
+    }
+
+    fn select(&mut self, condition: Value, a: Value, b: Value) -> Result<Value, ()> {
+        Ok(self.builder.ins().select(condition, a, b))
+    }
+
+    fn compare(&mut self, op: &RelationalOperator, a: Value, b: Value) -> Result<Value, ()> {
+        let cc = match op {
+            RelationalOperator::Eq => FloatCC::Equal,
+            RelationalOperator::Neq => FloatCC::NotEqual,
+            RelationalOperator::Greater => FloatCC::GreaterThan,
+            RelationalOperator::Lesser => FloatCC::LessThan,
+            RelationalOperator::Geq => FloatCC::GreaterThanOrEqual,
+            RelationalOperator::Leq => FloatCC::LessThanOrEqual,
+        };
+        Ok(self.builder.ins().fcmp(cc, a, b))
+    }
+
+    fn logical(&mut self, op: &LogicalOperator, a: Value, b: Value) -> Result<Value, ()> {
+        match op {
+            LogicalOperator::And => Ok(self.builder.ins().band(a, b)),
+            LogicalOperator::Or => Ok(self.builder.ins().bor(a, b)),
+        }
+    }
+}
+
+impl Codegen<'_> {
+    /// Call one of the functions of `UNARY` or `BINARY`
+    fn call(&mut self, symbol: &str, args: &[Value]) -> Result<Value, ()> {
+        let mut signature = self.module.make_signature();
+        signature.params.extend(args.iter().map(|_| AbiParam::new(types::F64)));
+        signature.returns.push(AbiParam::new(types::F64));
+        let id = self.module.declare_function(symbol, Linkage::Import, &signature).map_err(|_| ())?;
+        let function = self.module.declare_func_in_func(id, self.builder.func);
+        let call = self.builder.ins().call(function, args);
+        Ok(self.builder.inst_results(call)[0])
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_jit_evaluator() {
+        let expressions = [
+            "a * sin(t) ** 2 + b",
+            "-(a - b) / 2 ** t + sqrt(abs(t)) * pi",
+            "max(a, b, t) - min(a, log(t + 1))",
+            "where(a < b and t >= 0, a ** 3, exp(-t))",
+            "where(a == b or t != t, 1, 0)",
+        ];
+        for expression in expressions {
+            let mut jit = JitEvaluator::new(expression, &["a", "b", "t"]).unwrap();
+            assert!(jit.is_compiled(), "{}", expression);
+            let mut evaluator = Evaluator::new(expression, &["a", "b", "t"]).unwrap();
+            for values in [[2.0, 0.5, 0.25], [-1.0, 3.0, 2.0], [1.0, 1.0, -0.5]] {
+                let mut inputs = InputVars::new();
+                for (name, value) in ["a", "b", "t"].iter().zip(values) {
+                    inputs.insert_owned(name.to_string(), value);
+                }
+                let expected = evaluator.evaluate(&inputs).unwrap().as_number();
+                let result = jit.evaluate(&values).unwrap();
+                assert!(result == expected || result.is_nan() && expected.is_nan(), "{} {:?}", expression, values);
+            }
+        }
+        // `** 0.5` is not `sqrt()` for `-0` and `-inf`
+        let mut jit = JitEvaluator::new("a ** 0.5", &["a"]).unwrap();
+        assert_eq!(jit.evaluate(&[float::NEG_INFINITY]).unwrap(), float::INFINITY);
+        assert!(jit.evaluate(&[-0.0]).unwrap().is_sign_positive());
+    }
+
+    #[test]
+    fn test_jit_fallback() {
+        let mut jit = JitEvaluator::new("with(s = a + b, s * s)", &["a", "b"]).unwrap();
+        assert!(!jit.is_compiled());
+        assert_eq!(jit.evaluate(&[1.0, 2.0]).unwrap(), 9.0);
+
+        let mut jit = JitEvaluator::new("a < b", &["a", "b"]).unwrap();
+        assert!(jit.evaluate(&[1.0, 2.0]).is_err());
+        assert!(JitEvaluator::new("a + c", &["a", "b"]).is_err());
+    }
+}
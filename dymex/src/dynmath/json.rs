@@ -0,0 +1,180 @@
+//! Conversion of values from and to `serde_json::Value`, so results can be sent
+//! to clients as JSON and JSON inputs can be evaluated.
+//!
+//! | value                         | JSON                                    |
+//! |-------------------------------|-----------------------------------------|
+//! | number                        | number, `null` for NaN and infinities   |
+//! | array                         | array of numbers                        |
+//! | `bool`, `Vec<bool>`           | boolean, array of booleans              |
+//! | `String`                      | string                                  |
+//! | `NA`, array with NA           | `null`, array of numbers and `null`s    |
+//! | `TimeSeries`                  | `{"t": [...], "values": [...]}`         |
+//! | tuple                         | object if all elements are named, array otherwise |
+//! | other (host) types            | string, the value as displayed          |
+
+use serde_json::{Map, Value};
+use crate::Float;
+use super::{DynMath, Category, EvaluationError, InputVars, Missing, TimeSeries, Tuple};
+
+
+impl dyn DynMath {
+    /// The value as JSON, see the table in `dynmath/json.rs`
+    pub fn to_json_value(&self) -> Value {
+        match self.category() {
+            Category::Number => Value::from(self.as_number()),
+            Category::Array => Value::from(self.iterate().as_slice().to_vec()),
+            Category::Unqiue => if let Some(t) = self.downcast_ref::<Tuple>() {
+                match t.names().iter().all(|name| name.is_some()) {
+                    true => Value::Object(t.names().iter().flatten().cloned()
+                        .zip(t.iter().map(|x| x.to_json_value()))
+                        .collect()),
+                    false => Value::Array(t.iter().map(|x| x.to_json_value()).collect())
+                }
+            } else if let Some(b) = self.downcast_ref::<bool>() {
+                Value::from(*b)
+            } else if let Some(v) = self.downcast_ref::<Vec<bool>>() {
+                Value::from(v.clone())
+            } else if let Some(s) = self.downcast_ref::<String>() {
+                Value::from(s.as_str())
+            } else if self.is::<Missing>() {
+                Value::Null
+            } else if let Some(v) = self.downcast_ref::<Vec<Option<Float>>>() {
+                Value::Array(v.iter().map(|x| x.map_or(Value::Null, Value::from)).collect())
+            } else if let Some(ts) = self.downcast_ref::<TimeSeries>() {
+                let mut obj = Map::new();
+                obj.insert("t".into(), Value::from(ts.timestamps().to_vec()));
+                obj.insert("values".into(), Value::from(ts.values().to_vec()));
+                Value::Object(obj)
+            } else {
+                Value::from(self.to_string())
+            }
+        }
+    }
+
+    /// Serialize the value to JSON text
+    pub fn to_json(&self) -> String {
+        self.to_json_value().to_string()
+    }
+}
+
+/// An input value from JSON: numbers, booleans, strings and `null` (`NA`),
+/// arrays of numbers (with `null`s for missing elements) or booleans, and
+/// objects, which become tuples with named elements (accessed as `x.field`).
+/// Nested and mixed arrays are not supported.
+pub fn value_from_json(json: &Value) -> Result<Box<dyn DynMath>, EvaluationError> {
+    Ok(match json {
+        Value::Null => Box::new(Missing),
+        Value::Bool(b) => Box::new(*b),
+        Value::Number(n) => Box::new(n.as_f64().unwrap_or(f64::NAN) as Float),
+        Value::String(s) => Box::new(s.clone()),
+        Value::Array(items) => {
+            if items.iter().all(|x| x.is_boolean()) && !items.is_empty() {
+                Box::new(items.iter().filter_map(|x| x.as_bool()).collect::<Vec<bool>>())
+            } else if items.iter().all(|x| x.is_number()) {
+                Box::new(items.iter().filter_map(|x| x.as_f64().map(|x| x as Float)).collect::<Vec<Float>>())
+            } else if items.iter().all(|x| x.is_number() || x.is_null()) {
+                Box::new(items.iter().map(|x| x.as_f64().map(|x| x as Float)).collect::<Vec<Option<Float>>>())
+            } else {
+                return Err(EvaluationError::InvalidOperation {
+                    info: "JSON arrays should contain only numbers (and nulls) or only booleans".into()
+                });
+            }
+        }
+        Value::Object(obj) => {
+            let names = obj.keys().map(|name| Some(name.clone())).collect();
+            let values = obj.values().map(value_from_json).collect::<Result<_, _>>()?;
+            Box::new(Tuple::new(names, values))
+        }
+    })
+}
+
+impl InputVars {
+    /// Input variables from the fields of a JSON object. Nested objects are
+    /// records, their fields are accessed with dots:
+    /// ```ignore
+    /// let event = r#"{"payload": {"sensor": {"temp": 31.5, "id": "t-1"}}, "limit": 30}"#;
+    /// let inputs = InputVars::from_json(event)?;
+    /// let mut rule = Evaluator::new("payload.sensor.temp > limit", &inputs.names())?;
+    /// ```
+    /// See `value_from_json()` for the supported values.
+    pub fn from_json(json: &str) -> Result<Self, EvaluationError> {
+        match serde_json::from_str::<Value>(json) {
+            Ok(value) => Self::from_json_value(&value),
+            Err(e) => Err(EvaluationError::InvalidOperation { info: format!("invalid JSON: {}", e) })
+        }
+    }
+
+    pub fn from_json_value(json: &Value) -> Result<Self, EvaluationError> {
+        let obj = match json.as_object() {
+            Some(obj) => obj,
+            None => return Err(EvaluationError::InvalidOperation {
+                info: "the JSON document of the inputs should be an object".into()
+            })
+        };
+        let mut inputs = Self::new();
+        for (name, value) in obj {
+            inputs.insert_json(name.clone(), value)?;
+        }
+        Ok(inputs)
+    }
+
+    /// Insert a JSON value as the variable `name`, for example a whole event as `payload`
+    pub fn insert_json(&mut self, name: String, json: &Value) -> Result<(), EvaluationError> {
+        self.insert_ref(name, value_from_json(json)?.into());
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::*;
+
+    #[test]
+    fn test_results_to_json() {
+        let mut variables = InputVars::new();
+        variables.insert_owned("v".to_owned(), vec![1.0, 2.5]);
+        let mut evaluate = |expr: &str| Evaluator::new(expr, &variables.names()).unwrap().evaluate(&variables).unwrap();
+
+        assert_eq!(evaluate("max(v)").to_json_value(), json!(2.5));
+        assert_eq!(evaluate("v * 2").to_json_value(), json!([2.0, 5.0]));
+        assert_eq!(evaluate("v > 2").to_json_value(), json!([false, true]));
+        assert_eq!(evaluate("0 / 0").to_json_value(), json!(null));
+        assert_eq!(evaluate("(lo = min(v), hi = max(v))").to_json_value(), json!({"lo": 1.0, "hi": 2.5}));
+        assert_eq!(evaluate("(min(v), v)").to_json_value(), json!([1.0, [1.0, 2.5]]));
+        assert_eq!(evaluate("format(\"{}\", max(v))").to_json(), "\"2.5\"");
+    }
+
+    #[test]
+    fn test_inputs_from_json() {
+        let inputs = json!({"x": 2, "v": [1, null, 3], "flags": [true, false], "name": "a", "point": {"x": 1.5, "y": -1.0}});
+        let variables = InputVars::from_json_value(&inputs).unwrap();
+        let mut evaluate = |expr: &str| Evaluator::new(expr, &variables.names()).unwrap().evaluate(&variables).unwrap();
+        assert_eq!(evaluate("x * point.x").as_number(), 3.0);
+        assert_eq!(evaluate("coalesce(v, 0) + point.y").to_json_value(), json!([0.0, -1.0, 2.0]));
+        assert_eq!(evaluate("point").to_json_value(), inputs["point"]);
+        assert_eq!(evaluate("flags").to_json_value(), inputs["flags"]);
+        assert!(value_from_json(&json!([1, "a"])).is_err());
+        assert!(value_from_json(&json!([[1], [2]])).is_err());
+    }
+
+    #[test]
+    fn test_json_events() {
+        let event = r#"{"payload": {"sensor": {"temp": 31.5, "id": "t-1", "readings": [30, 31, 33.5]}}, "limit": 30}"#;
+        let inputs = InputVars::from_json(event).unwrap();
+        let mut rule = |expr: &str| Evaluator::new(expr, &inputs.names()).unwrap().evaluate(&inputs);
+        assert_eq!(rule("payload.sensor.temp > limit").unwrap().downcast_ref::<bool>(), Some(&true));
+        assert_eq!(rule("max(payload.sensor.readings) - limit").unwrap().as_number(), 3.5);
+        assert_eq!(rule("payload.sensor.id").unwrap().to_json(), "\"t-1\"");
+        assert!(matches!(rule("payload.sensor.humidity"), Err(EvaluationError::InvalidField { .. })));
+
+        let mut inputs = InputVars::new();
+        inputs.insert_json("event".into(), &serde_json::from_str(event).unwrap()).unwrap();
+        let mut evaluator = Evaluator::new("event.payload.sensor.temp - event.limit", &inputs.names()).unwrap();
+        assert_eq!(evaluator.evaluate(&inputs).unwrap().as_number(), 1.5);
+
+        assert!(InputVars::from_json("[1, 2]").is_err());
+        assert!(InputVars::from_json("{\"a\": ").is_err());
+    }
+}
@@ -0,0 +1,198 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::*;
+
+/// Maximal number of terms of `sum(i -> ..., first, last)` and `prod()`
+const MAX_TERMS: Float = 1e7;
+/// Maximal number of bisection steps of `solve()`, enough to reach the precision of `Float`
+const MAX_BISECTIONS: usize = 2100;
+
+/// Anonymous function argument of `map`, `filter` and `reduce`: `(a, b) -> a + b`.
+/// The body is evaluated by its own `Evaluator`, with the parameters and the
+/// captured variables of the enclosing expression as inputs.
+pub(crate) struct Lambda {
+    params: Vec<String>,
+    /// Variables of the enclosing expression referenced in the body
+    captures: Vec<String>,
+    body: RefCell<Evaluator>,
+}
+
+impl Lambda {
+    /// `branch` is a `Token::Arrow` expression: the parameters followed by the body.
+    /// `locals` are the local variables of the enclosing `with()` expressions.
+    pub(crate) fn new<T>(branch: &Branch, locals: &HashMap<String, T>) -> Self {
+        let (body, params) = match branch {
+            Branch::Expression(_, args) => args.split_last().expect("lambdas have a body"),
+            Branch::Atom(_) => panic!("ERROR: {} is not a lambda!", branch)
+        };
+        let params: Vec<String> = params.iter().filter_map(|p| p.variable_path()).collect();
+        let mut captures: Vec<String> = Vec::new();
+        for path in body.free_variables() {
+            // fields of local variables are accessed in the body
+            let root = path.split('.').next().unwrap_or(&path);
+            let capture = if locals.contains_key(root) { root.to_string() } else { path };
+            if !params.contains(&capture) && !captures.contains(&capture) {
+                captures.push(capture);
+            }
+        }
+        let body = Evaluator::from_ast(AST { tree: body.clone(), assigned_to: None });
+        Self { params, captures, body: RefCell::new(body) }
+    }
+
+    pub(crate) fn is_lambda(branch: &Branch) -> bool {
+        matches!(branch, Branch::Expression(tc, _) if tc.token == Token::Arrow)
+    }
+
+    pub(crate) fn captures(&self) -> &[String] {
+        &self.captures
+    }
+
+    /// Apply the higher order function `fun` to its arguments (`array` and the
+    /// initial value of `reduce`, the first and last index of `sum` and `prod`,
+    /// or the target and the bounds of `solve`) and the values of the captured variables
+    pub(crate) fn apply(&self, fun: &Function, args: &[&dyn DynMath], captured: &[Rc<dyn DynMath>],
+    context: &EvalContext) -> Result<Box<dyn DynMath>, EvaluationError> {
+        let invalid = |details: &str| EvaluationError::InvalidArguments {
+            function: fun.to_string(),
+            details: details.into()
+        };
+        self.body.borrow_mut().set_context(context);
+        let mut inputs = InputVars::new();
+        for (name, value) in self.captures.iter().zip(captured) {
+            inputs.insert_ref(name.clone(), value.clone());
+        }
+        if let Function::Sum | Function::Prod = fun {
+            let indices = index_range(args).map_err(|details| invalid(&details))?;
+            let mut terms = indices.map(|i| self.call(&[i], &mut inputs, fun, context));
+            return match fun {
+                Function::Sum => terms.try_fold(0.0, |acc, x| x.map(|x| acc + x)),
+                _ => terms.try_fold(1.0, |acc, x| x.map(|x| acc * x)),
+            }.map(|res| Box::new(res) as Box<dyn DynMath>);
+        }
+
+        if let Function::Solve = fun {
+            let (target, lo, hi) = match args {
+                [target, lo, hi] if super::all_scalars(args) => (target.as_number(), lo.as_number(), hi.as_number()),
+                _ => return Err(invalid("expects a function, the target value and the bounds"))
+            };
+            let f = |x| self.call(&[x], &mut inputs, fun, context).map(|y| y - target);
+            return bisect(f, lo, hi).map(|x| Box::new(x) as Box<dyn DynMath>);
+        }
+
+        let array = args[0];
+        let elements: Vec<Float> = match array.category() {
+            Category::Number => vec![array.as_number()],
+            Category::Array => array.iterate().copied().collect(),
+            Category::Unqiue => return Err(invalid("the first argument should be an array")),
+        };
+
+        match fun {
+            Function::Map => {
+                let mapped = elements.iter()
+                    .map(|x| self.call(&[*x], &mut inputs, fun, context))
+                    .collect::<Result<Vec<Float>, EvaluationError>>()?;
+                match array.category() {
+                    Category::Number => Ok(Box::new(mapped[0])),
+                    _ => Ok(Box::new(mapped))
+                }
+            }
+            Function::Filter => {
+                let mut kept = Vec::new();
+                for x in elements {
+                    let keep = self.call(&[x], &mut inputs, fun, context)?;
+                    if keep != 0.0 && !keep.is_nan() {
+                        kept.push(x);
+                    }
+                }
+                Ok(Box::new(kept))
+            }
+            Function::Reduce => {
+                let init = args.get(1).ok_or_else(|| invalid("expects an initial value"))?;
+                if !matches!(init.category(), Category::Number) {
+                    return Err(invalid("the initial value should be a number"));
+                }
+                let mut acc = init.as_number();
+                for x in elements {
+                    acc = self.call(&[acc, x], &mut inputs, fun, context)?;
+                }
+                Ok(Box::new(acc))
+            }
+            _ => panic!("ERROR: {} does not accept a function argument!", fun)
+        }
+    }
+
+    /// Evaluate the body with the parameters set to `args`, unless the
+    /// evaluation was cancelled or ran out of time
+    fn call(&self, args: &[Float], inputs: &mut InputVars, fun: &Function, context: &EvalContext)
+    -> Result<Float, EvaluationError> {
+        if let Some(e) = context.interrupted() {
+            return Err(e);
+        }
+        for (name, x) in self.params.iter().zip(args) {
+            inputs.insert_owned(name.clone(), *x);
+        }
+        let result = self.body.borrow_mut().evaluate(inputs)?;
+        match result.category() {
+            Category::Number => Ok(result.as_number()),
+            _ => Err(EvaluationError::InvalidArguments {
+                function: fun.to_string(),
+                details: format!("the function should return a number, not `{}`", result.type_name())
+            })
+        }
+    }
+}
+
+/// The indices `first, first + 1, ...` up to `last` (inclusive) of `sum(i -> ..., first, last)`
+fn index_range(args: &[&dyn DynMath]) -> Result<impl Iterator<Item = Float>, String> {
+    let (first, last) = match args {
+        [first, last] if super::all_scalars(args) => (first.as_number(), last.as_number()),
+        _ => return Err("expects a function of the index, the first and the last index".into())
+    };
+    if !first.is_finite() || !last.is_finite() {
+        return Err("the first and last index should be finite".into());
+    }
+    let n = (last - first).floor() + 1.0;
+    if n > MAX_TERMS {
+        return Err(format!("at most {} terms are supported", MAX_TERMS));
+    }
+    Ok((0..n.max(0.0) as usize).map(move |i| first + i as Float))
+}
+
+/// Root of `f` in `[lo, hi]` by bisection, `f` should change sign between the bounds
+fn bisect(mut f: impl FnMut(Float) -> Result<Float, EvaluationError>, mut lo: Float, mut hi: Float)
+-> Result<Float, EvaluationError> {
+    let invalid = |details: String| EvaluationError::InvalidArguments { function: Function::Solve.to_string(), details };
+    if !lo.is_finite() || !hi.is_finite() {
+        return Err(invalid("the bounds should be finite".into()));
+    }
+    let (mut f_lo, f_hi) = (f(lo)?, f(hi)?);
+    if f_lo == 0.0 {
+        return Ok(lo);
+    }
+    if f_hi == 0.0 {
+        return Ok(hi);
+    }
+    if f_lo.is_nan() || f_hi.is_nan() || f_lo.signum() == f_hi.signum() {
+        return Err(invalid(format!("the function does not cross the target between {} and {}", lo, hi)));
+    }
+    for _ in 0..MAX_BISECTIONS {
+        let mid = lo / 2.0 + hi / 2.0;
+        if mid == lo || mid == hi {
+            break;
+        }
+        let f_mid = f(mid)?;
+        if f_mid == 0.0 {
+            return Ok(mid);
+        }
+        if f_mid.is_nan() {
+            return Err(invalid(format!("the function is undefined at {}", mid)));
+        }
+        if f_mid.signum() == f_lo.signum() {
+            (lo, f_lo) = (mid, f_mid);
+        } else {
+            hi = mid;
+        }
+    }
+    Ok(lo / 2.0 + hi / 2.0)
+}
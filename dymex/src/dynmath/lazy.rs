@@ -0,0 +1,245 @@
+use std::any::Any;
+use std::cell::OnceCell;
+use std::fmt;
+use std::rc::Rc;
+use crate::{float, Float, MAXDIM, RelationalOperator};
+use super::{DynMath, EvaluationError, Category, Unary};
+
+type Elements = Box<dyn Iterator<Item = Float>>;
+
+
+/// Array input computed on demand: the elements are generated by a closure
+/// whenever they are needed, instead of being stored in a `Vec`. So expressions
+/// can run over generated sequences (or sequences read from a stream) without
+/// allocating them:
+/// ```ignore
+/// inputs.insert_owned("t".into(), LazyArray::from_fn(10_000_000, |i| i as Float * 1e-6));
+/// let energy = Evaluator::new("sum(sin(t) ** 2) * 1e-6", &["t"])?.evaluate(&inputs)?;
+/// ```
+/// Elementwise functions and arithmetic with numbers and other `LazyArray`s
+/// are lazy too, the reductions (`sum()`, `avg()`, `std()`, `min()`, ...)
+/// consume the elements one by one. Other operations (comparisons, indexing,
+/// arithmetic with arrays, ...) work on the elements collected into a `Vec<Float>`.
+/// The elements are generated for every pass over them (`std()` makes two), the
+/// generator should be cheap and return the same elements each time.
+#[derive(Clone)]
+pub struct LazyArray {
+    elements: Rc<dyn Fn() -> Elements>,
+    /// Counted on first use for arrays created from iterators
+    len: OnceCell<usize>,
+}
+
+impl LazyArray {
+    /// The array `[f(0), f(1), ..., f(len - 1)]`
+    pub fn from_fn<F>(len: usize, f: F) -> Self
+    where F: Fn(usize) -> Float + 'static {
+        let f = Rc::new(f);
+        Self {
+            elements: Rc::new(move || {
+                let f = f.clone();
+                Box::new((0..len).map(move |i| f(i)))
+            }),
+            len: OnceCell::from(len),
+        }
+    }
+
+    /// The elements of the iterators returned by `f`, it is called for every pass
+    pub fn from_iter_fn<F, I>(f: F) -> Self
+    where F: Fn() -> I + 'static, I: Iterator<Item = Float> + 'static {
+        Self { elements: Rc::new(move || Box::new(f())), len: OnceCell::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        *self.len.get_or_init(|| self.iter().count())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// A new pass over the elements
+    pub fn iter(&self) -> impl Iterator<Item = Float> {
+        (self.elements)()
+    }
+
+    /// The elements collected into a `Vec`
+    pub fn materialize(&self) -> Vec<Float> {
+        self.iter().collect()
+    }
+
+    /// `[f(x) for x in self]`, lazily
+    fn map<F>(&self, f: F) -> Self
+    where F: Fn(Float) -> Float + Clone + 'static {
+        let elements = self.elements.clone();
+        Self {
+            elements: Rc::new(move || Box::new(elements().map(f.clone()))),
+            len: self.len.clone(),
+        }
+    }
+
+    /// Elementwise operation with a number or another `LazyArray`, `None` for other values
+    fn binary(&self, other: &dyn DynMath, f: fn(Float, Float) -> Float) -> Option<Box<dyn DynMath>> {
+        if let Category::Number = other.category() {
+            let b = other.as_number();
+            return Some(Box::new(self.map(move |a| f(a, b))));
+        }
+        let other = other.downcast_ref::<LazyArray>()?;
+        let (lhs, rhs) = (self.elements.clone(), other.elements.clone());
+        Some(Box::new(Self {
+            elements: Rc::new(move || Box::new(lhs().zip(rhs()).map(move |(a, b)| f(a, b)))),
+            len: OnceCell::new(),
+        }))
+    }
+}
+
+impl fmt::Debug for LazyArray {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LazyArray(len: {:?})", self.len.get())
+    }
+}
+
+impl DynMath for LazyArray {
+
+    fn clone_boxed(&self) -> Box<dyn DynMath> { Box::new(self.clone()) }
+
+    /// Not `Category::Array`, there is no slice of the elements to `iterate()`
+    fn category(&self) -> Category { Category::Unqiue }
+
+    fn array_len(&self) -> Option<usize> { Some(self.len()) }
+
+    fn shape(&self) -> [usize; MAXDIM] {
+        let mut shape = [0; MAXDIM];
+        shape[0] = self.len();
+        shape
+    }
+
+    fn type_name(&self) -> &'static str { "LazyArray" }
+
+    /// The elements are not stored
+    fn memory_size(&self) -> usize { size_of::<Self>() }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn fmt_value(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<LazyArray of {} elements>", self.len())
+    }
+
+    fn unary_dispatcher(&self, f: Unary, _op: &str) -> Result<Box<dyn DynMath>, EvaluationError> {
+        Ok(Box::new(self.map(f)))
+    }
+
+    fn elementwise(&self, f: Unary) -> Result<Box<dyn DynMath>, EvaluationError> {
+        Ok(Box::new(self.map(f)))
+    }
+
+    fn add(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| a + b).map_or_else(|| self.materialize().add(other), Ok)
+    }
+    fn sub(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| a - b).map_or_else(|| self.materialize().sub(other), Ok)
+    }
+    fn sub_inv(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| b - a).map_or_else(|| self.materialize().sub_inv(other), Ok)
+    }
+    fn mul(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| a * b).map_or_else(|| self.materialize().mul(other), Ok)
+    }
+    fn div(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| a / b).map_or_else(|| self.materialize().div(other), Ok)
+    }
+    fn div_inv(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| b / a).map_or_else(|| self.materialize().div_inv(other), Ok)
+    }
+    fn pow(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| a.powf(b)).map_or_else(|| self.materialize().pow(other), Ok)
+    }
+    fn pow_inv(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| b.powf(a)).map_or_else(|| self.materialize().pow_inv(other), Ok)
+    }
+    fn xor(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.materialize().xor(other)
+    }
+
+    fn compare(&self, other: &dyn DynMath, op: &RelationalOperator) -> Result<Box<dyn DynMath>, EvaluationError> {
+        match other.downcast_ref::<LazyArray>() {
+            Some(other) => self.materialize().compare(&other.materialize(), op),
+            None => self.materialize().compare(other, op)
+        }
+    }
+
+    fn index(&self, index: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.materialize().index(index)
+    }
+
+    fn min(&self) -> Result<Float, EvaluationError> {
+        Ok(self.iter().fold(float::INFINITY, |a, b| a.min(b)))
+    }
+    fn max(&self) -> Result<Float, EvaluationError> {
+        Ok(self.iter().fold(float::NEG_INFINITY, |a, b| a.max(b)))
+    }
+    fn range(&self) -> Result<Float, EvaluationError> {
+        Ok(self.max()? - self.min()?)
+    }
+    fn sum(&self) -> Result<Float, EvaluationError> {
+        Ok(self.iter().sum::<Float>())
+    }
+    fn prod(&self) -> Result<Float, EvaluationError> {
+        Ok(self.iter().product::<Float>())
+    }
+    fn avg(&self) -> Result<Float, EvaluationError> {
+        let (sum, len) = self.iter().fold((0.0, 0), |(sum, len), x| (sum + x, len + 1));
+        let _ = self.len.set(len);
+        Ok(sum / (len as Float))
+    }
+    fn std(&self) -> Result<Float, EvaluationError> {
+        let avg = self.avg()?;
+        let sq_err = self.iter().map(|e| (e-avg)*(e-avg)).sum::<Float>();
+        Ok((sq_err / (self.len() as Float)).sqrt())
+    }
+    fn l2_norm(&self) -> Result<Float, EvaluationError> {
+        Ok(self.iter().map(|e| e*e).sum::<Float>().sqrt())
+    }
+    fn l1_norm(&self) -> Result<Float, EvaluationError> {
+        Ok(self.iter().map(|e| e.abs()).sum::<Float>())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use crate::*;
+
+    #[test]
+    fn test_lazy_arrays() {
+        let generated = Rc::new(Cell::new(0));
+        let counter = generated.clone();
+        let mut inputs = InputVars::new();
+        inputs.insert_owned("t".into(), LazyArray::from_fn(1000, move |i| {
+            counter.set(counter.get() + 1);
+            i as Float * 0.001
+        }));
+        inputs.insert_owned("n".into(), LazyArray::from_iter_fn(|| (1..=4).map(|i| i as Float)));
+        inputs.insert_owned("v".into(), vec![1.0, 2.0, 3.0, 4.0]);
+        let mut evaluate = |expr: &str| Evaluator::new(expr, &inputs.names()).unwrap().evaluate(&inputs).unwrap();
+
+        // the elementwise operations are lazy, the reduction generates every element once
+        let result = evaluate("sum((2 * t - 1) ** 2)");
+        let expected: Float = (0..1000).map(|i| (i as Float * 0.002 - 1.0).powi(2)).sum();
+        assert!(approx_eq(result.as_number(), expected));
+        assert_eq!(generated.get(), 1000);
+        assert!(evaluate("t / 2").is::<LazyArray>());
+
+        assert!(approx_eq(evaluate("avg(n) + max(n) + std(n) ** 2").as_number(), 2.5 + 4.0 + 1.25));
+        assert_eq!(evaluate("sum(n * n - n)").as_number(), 20.0);
+        assert_eq!(evaluate("1 / n").downcast_ref::<LazyArray>().unwrap().materialize(), [1.0, 0.5, 1.0 / 3.0, 0.25]);
+        // other operations on the materialized elements
+        assert_eq!(evaluate("n + v").iterate().as_slice(), &[2.0, 4.0, 6.0, 8.0]);
+        assert_eq!(evaluate("n[-1]").as_number(), 4.0);
+        assert_eq!(evaluate("n > 2").downcast_ref::<Vec<bool>>(), Some(&vec![false, false, true, true]));
+        assert_eq!(evaluate("t").to_string(), "<LazyArray of 1000 elements>");
+    }
+}
@@ -0,0 +1,248 @@
+use crate::{Float, MAXDIM, RelationalOperator};
+use super::{DynMath, ResultFormatter, EvaluationError, Category, Unary, unimpl_binary};
+use std::any::Any;
+use std::fmt;
+
+
+/// Missing value (NA), distinct from NaN: NaN is the result of an invalid
+/// operation, NA is the lack of a measurement. Operations with NA result in NA,
+/// reductions handle it according to the `MissingPolicy` of the `Evaluator`.
+/// Arrays with gaps are represented as `Vec<Option<Float>>`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Missing;
+
+/// Handling of missing values in the reductions (`avg`, `min`, `sum`, ...)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MissingPolicy {
+    /// The result is NA if any of the values is missing
+    #[default]
+    Propagate,
+    /// The missing values are ignored, the result is NA only if every value is missing
+    Skip,
+}
+
+/// Returns true for NA and for arrays with missing elements
+pub fn has_missing(x: &dyn DynMath) -> bool {
+    match x.downcast_ref::<Vec<Option<Float>>>() {
+        Some(v) => v.iter().any(Option::is_none),
+        None => x.is::<Missing>()
+    }
+}
+
+/// The arguments of a reduction without the missing values: NA is dropped, and
+/// arrays with gaps are replaced by their present elements
+pub(super) fn present_values(args: &[&dyn DynMath]) -> Vec<Box<dyn DynMath>> {
+    args.iter()
+        .filter(|x| !x.is::<Missing>())
+        .map(|x| match x.downcast_ref::<Vec<Option<Float>>>() {
+            Some(v) => Box::new(present(v)) as Box<dyn DynMath>,
+            None => x.clone_boxed()
+        })
+        .collect()
+}
+
+fn present(v: &[Option<Float>]) -> Vec<Float> {
+    v.iter().flatten().copied().collect()
+}
+
+/// `coalesce(a, b, ...)`: the missing values of `a` are replaced by the
+/// corresponding values of `b`, the values still missing by those of the next
+/// argument, and so on
+pub fn dynmath_coalesce(args: &[&dyn DynMath]) -> Result<Box<dyn DynMath>, EvaluationError> {
+    let (first, fallbacks) = match args.split_first() {
+        Some(split) => split,
+        None => return Ok(Box::new(Missing))
+    };
+    let mut result = first.clone_boxed();
+    for fallback in fallbacks {
+        if !has_missing(&*result) {
+            break;
+        }
+        result = match result.downcast_ref::<Vec<Option<Float>>>() {
+            Some(v) => fill_gaps(v, *fallback)?,
+            None => fallback.clone_boxed()
+        };
+    }
+    Ok(result)
+}
+
+fn fill_gaps(v: &[Option<Float>], fallback: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+    let filled: Vec<Option<Float>> = match fallback.category() {
+        Category::Number => v.iter().map(|x| x.or(Some(fallback.as_number()))).collect(),
+        Category::Array => v.iter().zip(fallback.iterate()).map(|(x, y)| x.or(Some(*y))).collect(),
+        Category::Unqiue => match fallback.downcast_ref::<Vec<Option<Float>>>() {
+            Some(w) => v.iter().zip(w).map(|(x, y)| x.or(*y)).collect(),
+            None if fallback.is::<Missing>() => v.to_vec(),
+            None => return unimpl_binary("Array with NA", fallback.type_name(), "coalesce")
+        }
+    };
+    // without gaps it is a plain array again
+    match filled.iter().all(Option::is_some) {
+        true => Ok(Box::new(present(&filled))),
+        false => Ok(Box::new(filled))
+    }
+}
+
+
+impl DynMath for Missing {
+
+    fn clone_boxed(&self) -> Box<dyn DynMath> { Box::new(*self) }
+
+    fn category(&self) -> Category { Category::Unqiue }
+
+    fn shape(&self) -> [usize; MAXDIM] { [0; MAXDIM] }
+
+    fn type_name(&self) -> &'static str { "NA" }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn fmt_value(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", ResultFormatter::new().display(self))
+    }
+
+    fn unary_dispatcher(&self, _f: Unary, _op: &str) -> Result<Box<dyn DynMath>, EvaluationError> {
+        Ok(Box::new(Missing))
+    }
+
+    fn add(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> { self.propagate(other) }
+    fn sub(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> { self.propagate(other) }
+    fn mul(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> { self.propagate(other) }
+    fn div(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> { self.propagate(other) }
+    fn pow(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> { self.propagate(other) }
+    fn sub_inv(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> { self.propagate(other) }
+    fn div_inv(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> { self.propagate(other) }
+    fn pow_inv(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> { self.propagate(other) }
+
+    fn compare(&self, _other: &dyn DynMath, _op: &RelationalOperator) -> Result<Box<dyn DynMath>, EvaluationError> {
+        Ok(Box::new(Missing))
+    }
+
+    /// `NA and false` is false, otherwise NA
+    fn and(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        match other.downcast_ref::<bool>() {
+            Some(false) => Ok(Box::new(false)),
+            _ => Ok(Box::new(Missing))
+        }
+    }
+
+    /// `NA or true` is true, otherwise NA
+    fn or(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        match other.downcast_ref::<bool>() {
+            Some(true) => Ok(Box::new(true)),
+            _ => Ok(Box::new(Missing))
+        }
+    }
+}
+
+impl Missing {
+    /// Result of a binary operation with NA, every element is missing for arrays
+    fn propagate(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        match other.category() {
+            Category::Array => Ok(Box::new(vec![None as Option<Float>; other.shape()[0]])),
+            _ => match other.downcast_ref::<Vec<Option<Float>>>() {
+                Some(v) => Ok(Box::new(vec![None as Option<Float>; v.len()])),
+                None => Ok(Box::new(Missing))
+            }
+        }
+    }
+}
+
+
+/// Array with missing elements
+impl DynMath for Vec<Option<Float>> {
+
+    fn clone_boxed(&self) -> Box<dyn DynMath> { Box::new(self.clone()) }
+
+    fn category(&self) -> Category { Category::Unqiue }
+
+    fn array_len(&self) -> Option<usize> { Some(self.len()) }
+
+    fn shape(&self) -> [usize; MAXDIM] {
+        let mut shape = [0; MAXDIM];
+        shape[0] = self.len();
+        shape
+    }
+
+    fn type_name(&self) -> &'static str { "Array with NA" }
+
+    fn memory_size(&self) -> usize { size_of::<Self>() + self.len() * size_of::<Option<Float>>() }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn fmt_value(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", ResultFormatter::new().display(self))
+    }
+
+    fn unary_dispatcher(&self, f: Unary, _op: &str) -> Result<Box<dyn DynMath>, EvaluationError> {
+        Ok(Box::new(self.iter().map(|x| x.map(f)).collect::<Vec<Option<Float>>>()))
+    }
+
+    fn add(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        zip_with(self, other, |a, b| a + b, "+")
+    }
+    fn sub(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        zip_with(self, other, |a, b| a - b, "-")
+    }
+    fn mul(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        zip_with(self, other, |a, b| a * b, "*")
+    }
+    fn div(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        zip_with(self, other, |a, b| a / b, "/")
+    }
+    fn pow(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        zip_with(self, other, |a, b| a.powf(b), "**")
+    }
+    fn sub_inv(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        zip_with(self, other, |a, b| b - a, "-")
+    }
+    fn div_inv(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        zip_with(self, other, |a, b| b / a, "/")
+    }
+    fn pow_inv(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        zip_with(self, other, |a, b| b.powf(a), "**")
+    }
+
+    // reductions of the present elements, see `MissingPolicy`
+    fn min(&self) -> Result<Float, EvaluationError> { DynMath::min(&present(self)) }
+    fn max(&self) -> Result<Float, EvaluationError> { DynMath::max(&present(self)) }
+    fn range(&self) -> Result<Float, EvaluationError> { DynMath::range(&present(self)) }
+    fn sum(&self) -> Result<Float, EvaluationError> { DynMath::sum(&present(self)) }
+    fn prod(&self) -> Result<Float, EvaluationError> { DynMath::prod(&present(self)) }
+    fn avg(&self) -> Result<Float, EvaluationError> { DynMath::avg(&present(self)) }
+    fn std(&self) -> Result<Float, EvaluationError> { DynMath::std(&present(self)) }
+    fn l2_norm(&self) -> Result<Float, EvaluationError> { DynMath::l2_norm(&present(self)) }
+    fn l1_norm(&self) -> Result<Float, EvaluationError> { DynMath::l1_norm(&present(self)) }
+}
+
+/// Elementwise `f(a, b)` of an array with gaps and `other`, missing where either is missing
+fn zip_with(v: &[Option<Float>], other: &dyn DynMath, f: impl Fn(Float, Float) -> Float, op: &str)
+-> Result<Box<dyn DynMath>, EvaluationError> {
+    let result: Vec<Option<Float>> = match other.category() {
+        Category::Number => {
+            let x = other.as_number();
+            v.iter().map(|a| a.map(|a| f(a, x))).collect()
+        }
+        Category::Array => v.iter().zip(other.iterate()).map(|(a, b)| a.map(|a| f(a, *b))).collect(),
+        Category::Unqiue => match other.downcast_ref::<Vec<Option<Float>>>() {
+            Some(w) => v.iter().zip(w).map(|(a, b)| a.zip(*b).map(|(a, b)| f(a, b))).collect(),
+            None if other.is::<Missing>() => vec![None; v.len()],
+            None => return unimpl_binary("Array with NA", other.type_name(), op)
+        }
+    };
+    Ok(Box::new(result))
+}
+
+/// `isna(x)`: true if `x` is NA, elementwise for arrays
+pub fn dynmath_isna(x: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+    if let Some(v) = x.downcast_ref::<Vec<Option<Float>>>() {
+        return Ok(Box::new(v.iter().map(Option::is_none).collect::<Vec<bool>>()));
+    }
+    match x.category() {
+        Category::Array => Ok(Box::new(vec![false; x.shape()[0]])),
+        _ => Ok(Box::new(x.is::<Missing>()))
+    }
+}
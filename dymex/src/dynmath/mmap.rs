@@ -0,0 +1,317 @@
+use std::any::Any;
+use std::fmt;
+use std::fs::File;
+use std::ops::Range;
+use std::path::Path;
+use std::slice::Iter;
+use std::sync::Arc;
+use std::io;
+use memmap2::Mmap;
+use thiserror::Error;
+use crate::{float, Float, MAXDIM, RelationalOperator};
+use super::{DynMath, ResultFormatter, EvaluationError, Category, InputVars, Unary};
+use super::boolean::compare_numbers;
+use super::vector::element;
+use super::npy_header::NpyHeader;
+
+type Binary = fn(Float, Float) -> Float;
+
+
+#[derive(Error, Debug)]
+pub enum MmapError {
+    #[error("failed to map the file: {0}")]
+    Io(#[from] io::Error),
+    #[error("the data has {0} bytes, not a whole number of `f64`s")]
+    Length(usize),
+    #[error("invalid npy file: {0}")]
+    Npy(String),
+}
+
+/// Array input backed by a memory mapped file of `f64`s: the elements are read
+/// in place (paged in by the OS on demand), not copied, so expressions can be
+/// evaluated over datasets larger than the memory. Results of operations are
+/// `Vec<Float>`s, so reductions (`sum(x)`, `max(x)`, ...) and indexing (`x[i]`)
+/// are what scale to such datasets. Cloning the array or the value is cheap.
+///
+/// The file must not be modified while it is mapped.
+#[derive(Clone)]
+pub struct MappedArray {
+    map: Arc<Mmap>,
+    /// Byte offset of the first element in the file
+    offset: usize,
+    /// Elements of this array (a slice of the file)
+    range: Range<usize>,
+}
+
+impl MappedArray {
+    /// Map a file of native endian `f64`s (like the output of `numpy.ndarray.tofile()`)
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, MmapError> {
+        Self::from_map(map(path.as_ref())?, 0)
+    }
+
+    /// Map the data of a 1 dimensional `.npy` file of `f64`s
+    pub fn open_npy(path: impl AsRef<Path>) -> Result<Self, MmapError> {
+        let map = map(path.as_ref())?;
+        let offset = npy_data_offset(&map)?;
+        Self::from_map(map, offset)
+    }
+
+    fn from_map(map: Mmap, offset: usize) -> Result<Self, MmapError> {
+        let bytes = map.len() - offset;
+        if bytes % size_of::<f64>() != 0 {
+            return Err(MmapError::Length(bytes));
+        }
+        // the maps are page aligned, the npy headers are padded to 16 bytes
+        if (map.as_ptr() as usize + offset) % align_of::<f64>() != 0 {
+            return Err(MmapError::Npy("the data is not aligned".into()));
+        }
+        Ok(Self { map: Arc::new(map), offset, range: 0..bytes / size_of::<f64>() })
+    }
+
+    pub fn values(&self) -> &[Float] {
+        let bytes = &self.map[self.offset..];
+        // SAFETY: the data is aligned and has a whole number of `f64`s (checked by `from_map()`),
+        // and every bit pattern is a valid `f64`
+        let all = unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const Float, bytes.len() / size_of::<Float>()) };
+        &all[self.range.clone()]
+    }
+
+    pub fn len(&self) -> usize {
+        self.range.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.range.is_empty()
+    }
+
+    /// The elements `range` of this array sharing the map, `None` if it is out of bounds
+    pub fn slice(&self, range: Range<usize>) -> Option<Self> {
+        if range.start > range.end || range.end > self.len() {
+            return None;
+        }
+        let start = self.range.start;
+        Some(Self { map: self.map.clone(), offset: self.offset, range: start + range.start..start + range.end })
+    }
+
+    /// Apply `f` elementwise with a number or another array, other values are
+    /// handled by `fallback` (called with a copy of the array)
+    fn binary<F>(&self, other: &dyn DynMath, f: Binary, fallback: F) -> Result<Box<dyn DynMath>, EvaluationError>
+    where F: FnOnce(&Vec<Float>) -> Result<Box<dyn DynMath>, EvaluationError> {
+        match other.category() {
+            Category::Number => Ok(Box::new(
+                self.values().iter().map(|a| f(*a, other.as_number())).collect::<Vec<Float>>()
+            )),
+            Category::Array => Ok(Box::new(
+                self.values().iter().zip(other.iterate()).map(|(a, b)| f(*a, *b)).collect::<Vec<Float>>()
+            )),
+            Category::Unqiue => fallback(&self.values().to_vec())
+        }
+    }
+}
+
+fn map(path: &Path) -> Result<Mmap, MmapError> {
+    let file = File::open(path)?;
+    // SAFETY: see the documentation of `MappedArray`, the file is not modified while mapped
+    Ok(unsafe { Mmap::map(&file)? })
+}
+
+/// The offset of the data in an npy file, after checking that it is a
+/// 1 dimensional array of native endian `f64`s
+fn npy_data_offset(file: &[u8]) -> Result<usize, MmapError> {
+    let header = NpyHeader::parse(file).map_err(MmapError::Npy)?;
+    let native = if cfg!(target_endian = "little") { "<f8" } else { ">f8" };
+    if header.descr != native {
+        return Err(MmapError::Npy(format!("the data type `{}` is not native endian `f64`", header.descr)));
+    }
+    if header.shape.len() != 1 {
+        return Err(MmapError::Npy(format!("the array has the shape {:?}, not 1 dimension", header.shape)));
+    }
+    let expected = header.len().checked_mul(size_of::<f64>())
+        .ok_or_else(|| MmapError::Npy(format!("too many bytes of data for {} elements", header.len())))?;
+    if file.len() - header.data_offset != expected {
+        return Err(MmapError::Npy(format!("{} bytes of data for {} elements", file.len() - header.data_offset, header.len())));
+    }
+    Ok(header.data_offset)
+}
+
+impl fmt::Debug for MappedArray {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MappedArray({:?})", self.range)
+    }
+}
+
+impl DynMath for MappedArray {
+
+    fn clone_boxed(&self) -> Box<dyn DynMath> { Box::new(self.clone()) }
+
+    fn category(&self) -> Category { Category::Array }
+
+    fn shape(&self) -> [usize; MAXDIM] {
+        let mut shape = [0; MAXDIM];
+        shape[0] = self.len();
+        shape
+    }
+
+    /// The file is mapped, it is not counted
+    fn memory_size(&self) -> usize { size_of::<Self>() }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn fmt_value(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", ResultFormatter::new().display(self))
+    }
+
+    fn iterate(&self) -> Iter<'_, Float> {
+        self.values().iter()
+    }
+
+    fn elementwise(&self, func: Unary) -> Result<Box<dyn DynMath>, EvaluationError> {
+        Ok(Box::new(self.values().iter().map(|x| func(*x)).collect::<Vec<Float>>()))
+    }
+
+    fn add(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| a + b, |v| v.add(other))
+    }
+    fn sub(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| a - b, |v| v.sub(other))
+    }
+    fn sub_inv(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| b - a, |v| v.sub_inv(other))
+    }
+    fn mul(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| a * b, |v| v.mul(other))
+    }
+    fn div(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| a / b, |v| v.div(other))
+    }
+    fn div_inv(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| b / a, |v| v.div_inv(other))
+    }
+    fn pow(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| a.powf(b), |v| v.pow(other))
+    }
+    fn pow_inv(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| b.powf(a), |v| v.pow_inv(other))
+    }
+
+    fn compare(&self, other: &dyn DynMath, op: &RelationalOperator) -> Result<Box<dyn DynMath>, EvaluationError> {
+        match other.category() {
+            Category::Number => Ok(Box::new(
+                self.values().iter().map(|a| compare_numbers(*a, other.as_number(), op)).collect::<Vec<bool>>()
+            )),
+            Category::Array => Ok(Box::new(
+                self.values().iter().zip(other.iterate()).map(|(a, b)| compare_numbers(*a, *b, op)).collect::<Vec<bool>>()
+            )),
+            Category::Unqiue => self.values().to_vec().compare(other, op)
+        }
+    }
+
+    /// Single elements are read in place (bounds checked), masks copy the selected elements
+    fn index(&self, index: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        match index.category() {
+            Category::Number => element(self.values(), index.as_number()).map(|x| Box::new(x) as Box<dyn DynMath>),
+            _ => match index.downcast_ref::<Vec<bool>>() {
+                Some(mask) if mask.len() == self.len() => Ok(Box::new(
+                    self.values().iter().zip(mask).filter(|(_, m)| **m).map(|(x, _)| *x).collect::<Vec<Float>>()
+                )),
+                _ => self.values().to_vec().index(index)
+            }
+        }
+    }
+
+    fn min(&self) -> Result<Float, EvaluationError> {
+        Ok(self.values().iter().fold(float::INFINITY, |a, &b| a.min(b)))
+    }
+    fn max(&self) -> Result<Float, EvaluationError> {
+        Ok(self.values().iter().fold(float::NEG_INFINITY, |a, &b| a.max(b)))
+    }
+    fn range(&self) -> Result<Float, EvaluationError> {
+        Ok(self.max()? - self.min()?)
+    }
+    fn sum(&self) -> Result<Float, EvaluationError> {
+        Ok(self.values().iter().sum::<Float>())
+    }
+    fn prod(&self) -> Result<Float, EvaluationError> {
+        Ok(self.values().iter().product::<Float>())
+    }
+    fn avg(&self) -> Result<Float, EvaluationError> {
+        Ok(self.sum()? / (self.len() as Float))
+    }
+    fn std(&self) -> Result<Float, EvaluationError> {
+        let avg = self.avg()?;
+        let sq_err = self.values().iter().map(|e| (e-avg)*(e-avg)).sum::<Float>();
+        Ok((sq_err / (self.len() as Float)).sqrt())
+    }
+    fn l2_norm(&self) -> Result<Float, EvaluationError> {
+        Ok(self.values().iter().map(|e| e*e).sum::<Float>().sqrt())
+    }
+    fn l1_norm(&self) -> Result<Float, EvaluationError> {
+        Ok(self.values().iter().map(|e| e.abs()).sum::<Float>())
+    }
+}
+
+
+impl InputVars {
+    /// Map the `.npy` file at `path` as the array variable `name`, see `MappedArray`
+    pub fn insert_npy(&mut self, name: String, path: impl AsRef<Path>) -> Result<(), MmapError> {
+        self.insert_owned(name, MappedArray::open_npy(path)?);
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use crate::*;
+
+    fn write_temp(name: &str, bytes: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("dymex-{}-{}", std::process::id(), name));
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn npy(values: &[f64], shape: &str) -> Vec<u8> {
+        let mut header = format!("{{'descr': '<f8', 'fortran_order': False, 'shape': {}, }}", shape);
+        // the data starts at a multiple of 64 bytes
+        while (10 + header.len() + 1) % 64 != 0 {
+            header.push(' ');
+        }
+        header.push('\n');
+        let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+        bytes.extend((header.len() as u16).to_le_bytes());
+        bytes.extend(header.as_bytes());
+        bytes.extend(values.iter().flat_map(|x| x.to_le_bytes()));
+        bytes
+    }
+
+    #[test]
+    fn test_mapped_arrays() {
+        let values: Vec<f64> = (0..1000).map(|i| i as f64).collect();
+        let npy_path = write_temp("x.npy", &npy(&values, "(1000,)"));
+        let raw_path = write_temp("y.bin", &values.iter().flat_map(|x| x.to_ne_bytes()).collect::<Vec<u8>>());
+
+        let mut inputs = InputVars::new();
+        inputs.insert_npy("x".into(), &npy_path).unwrap();
+        let y = MappedArray::open(&raw_path).unwrap();
+        assert_eq!(y.values(), values.as_slice());
+        inputs.insert_owned("y".into(), y.slice(10..20).unwrap());
+        assert!(y.slice(990..1001).is_none());
+
+        let mut evaluate = |expr: &str| Evaluator::new(expr, &inputs.names()).unwrap().evaluate(&inputs);
+        assert_eq!(evaluate("sum(x)").unwrap().as_number(), 499500.0);
+        assert_eq!(evaluate("x[-1] + y[0]").unwrap().as_number(), 1009.0);
+        assert_eq!(evaluate("max(y * 2)").unwrap().as_number(), 38.0);
+        assert!(evaluate("y[10]").is_err());
+
+        let mismatch = write_temp("z.npy", &npy(&values, "(10, 100)"));
+        assert!(matches!(MappedArray::open_npy(&mismatch), Err(MmapError::Npy(_))));
+        let overflow = write_temp("o.npy", &npy(&values, "(2305843009213693952,)"));
+        assert!(matches!(MappedArray::open_npy(&overflow), Err(MmapError::Npy(_))));
+        for path in [npy_path, raw_path, mismatch, overflow] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
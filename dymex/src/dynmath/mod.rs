@@ -1,16 +1,108 @@
 use std::any::Any;
-use std::rc::Rc;
+use std::fmt;
 use std::slice::Iter;
-use crate::{float, Float};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::{float, Float, RelationalOperator};
 
 pub const MAXDIM: usize = 3;
+const SECONDS_PER_HOUR: Float = 3600.0;
+const SECONDS_PER_DAY: Float = 86400.0;
 
 mod number;
+mod scalar;
+pub use scalar::*;
+mod boolean;
+pub use boolean::*;
+mod missing;
+pub use missing::*;
+mod poly;
+pub use poly::*;
+mod bitwise;
+pub use bitwise::*;
+mod cancel;
+pub use cancel::*;
+mod formatter;
+pub use formatter::*;
+#[cfg(feature = "json")]
+mod json;
+#[cfg(feature = "json")]
+pub use json::*;
+#[cfg(feature = "csv")]
+mod csv;
+#[cfg(feature = "csv")]
+pub use self::csv::*;
+// Arrow columns are shared as they are, which needs `Float` to be `f64`
+#[cfg(all(feature = "arrow", not(feature = "single_precision")))]
+mod arrow;
+#[cfg(all(feature = "arrow", not(feature = "single_precision")))]
+pub use self::arrow::*;
+// The compiled code reads the inputs as `f64`s
+#[cfg(all(feature = "jit", not(feature = "single_precision")))]
+mod jit;
+#[cfg(all(feature = "jit", not(feature = "single_precision")))]
+pub use self::jit::*;
+// The mapped files are read as `f64`s
+#[cfg(all(feature = "mmap", not(feature = "single_precision")))]
+mod mmap;
+#[cfg(all(feature = "mmap", not(feature = "single_precision")))]
+pub use self::mmap::*;
+#[cfg(feature = "npy")]
+mod npy;
+#[cfg(feature = "npy")]
+pub use self::npy::*;
+// The datasets are read as `Float`s, converted by the HDF5 library
+#[cfg(feature = "hdf5")]
+mod hdf5;
+#[cfg(feature = "hdf5")]
+pub use self::hdf5::*;
+#[cfg(any(feature = "npy", all(feature = "mmap", not(feature = "single_precision"))))]
+mod npy_header;
+#[cfg(feature = "gpu")]
+mod gpu;
+#[cfg(feature = "gpu")]
+pub use self::gpu::*;
+mod rounding;
+pub use rounding::*;
+mod string;
+pub use string::*;
+mod tuple;
+pub use tuple::*;
+mod units;
+pub use units::*;
 mod vector;
+mod lazy;
+pub use lazy::*;
+mod fields;
+pub use fields::*;
+mod vars;
+pub use vars::*;
+mod timeseries;
+pub use timeseries::*;
 mod eval;
 pub use eval::*;
+mod lambda;
 mod multi_eval;
 pub use multi_eval::*;
+mod pipeline;
+pub use pipeline::*;
+mod template;
+pub use template::*;
+mod profile;
+pub use profile::*;
+mod coverage;
+pub use coverage::*;
+mod watch;
+pub use watch::*;
+mod audit;
+pub use audit::*;
+mod random;
+pub use random::*;
+mod arithmetic;
+pub use arithmetic::*;
+mod bounds;
+pub use bounds::*;
+mod reduction;
+pub use reduction::*;
 mod error;
 pub use error::*;
 
@@ -41,14 +133,60 @@ impl Category {
 pub type Unary = fn(Float) -> Float;
 
 
+/// Dynamically typed value that can be used as an input of an expression.
+///
+/// Besides `Float` and `Vec<Float>`, host types can be made available to the
+/// evaluator by implementing this trait. Such types usually have the category
+/// `Category::Unqiue`, override `type_name()` (used in error messages) and
+/// expose their contents with `get_field()`, so `spectrum.x` evaluates to
+/// the `x` field of `spectrum`. `#[derive(DynFields)]` generates the field
+/// access, which `get_field()` can simply forward to:
+///
+/// ```ignore
+/// #[derive(Clone, DynFields)]
+/// struct Spectrum {
+///     x: Vec<Float>,
+///     y: Vec<Float>,
+/// }
+///
+/// impl DynMath for Spectrum {
+///     fn clone_boxed(&self) -> Box<dyn DynMath> { Box::new(self.clone()) }
+///     fn category(&self) -> Category { Category::Unqiue }
+///     fn shape(&self) -> [usize; MAXDIM] { [0; MAXDIM] }
+///     fn type_name(&self) -> &'static str { "Spectrum" }
+///     fn as_any(&self) -> &dyn Any { self }
+///     fn get_field(&self, field_name: &str) -> Result<Box<dyn DynMath>, EvaluationError> {
+///         self.dyn_field(field_name)
+///     }
+/// }
+/// ```
 pub trait DynMath : Any {
 
+    /// A deep copy of the value, also available as `Clone` for `Box<dyn DynMath>`
     fn clone_boxed(&self) -> Box<dyn DynMath>;
 
     fn category(&self) -> Category;
 
     fn shape(&self) -> [usize; MAXDIM];
 
+    /// Number of elements of the arrays, also of the array-like unique types
+    /// (`TimeSeries`, `LazyArray`, ...), `None` for numbers and other values
+    fn array_len(&self) -> Option<usize> {
+        match self.category() {
+            Category::Array => Some(self.shape()[0]),
+            _ => None
+        }
+    }
+
+    /// Number of dimensions: 0 for numbers and the unique types, the
+    /// number of non-zero lengths of `shape()` for arrays (at least 1)
+    fn ndim(&self) -> usize {
+        match self.category() {
+            Category::Array => self.shape().iter().take_while(|n| **n > 0).count().max(1),
+            _ => 0
+        }
+    }
+
     fn shape_matches(&self, other: &dyn DynMath) -> bool {
         match (self.category(), other.category()) {
             (Category::Number, _) => true,
@@ -71,6 +209,21 @@ pub trait DynMath : Any {
         panic!("Panic: `{}` is not a number.", self.type_name())
     }
 
+    /// Approximate memory used by the value in bytes, including its heap
+    /// allocations (for `Evaluator::set_memory_limit()`)
+    fn memory_size(&self) -> usize {
+        std::mem::size_of_val(self)
+    }
+
+    /// Write the value for display, used by the `Display` and `Debug` of
+    /// `dyn DynMath` (so `Box<dyn DynMath>` and `Rc<dyn DynMath>` can be printed)
+    /// and by `ResultFormatter` for host types. The default is `<TypeName>`.
+    fn fmt_value(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<{}>", self.type_name())
+    }
+
+    /// Upcast to `Any`, the implementation must return `self`.
+    /// Used for downcasting results, see `downcast_ref()`.
     fn as_any(&self) -> &dyn Any;
 
     /// Return an iterator if possible
@@ -79,11 +232,17 @@ pub trait DynMath : Any {
         panic!("Panic: trying to iterate non-iterable `{}`", self.type_name())
     }
 
-    /// Apply provided function for self elementwise
-    // Here it's ok to panic, this should be only called after matching on self.category()
-    #[allow(unused_variables)]
+    /// Apply provided function for self elementwise. By default the elements
+    /// of arrays are collected into a `Vec<Float>`, array types that should keep
+    /// their own type can override it.
     fn elementwise(&self, f: Unary) -> Result<Box<dyn DynMath>, EvaluationError> {
-        panic!("Panic: applying unary function to `{}` is not supported.", self.type_name())
+        match self.category() {
+            Category::Array => Ok(Box::new(self.iterate().map(|x| f(*x)).collect::<Vec<Float>>())),
+            _ => Err(EvaluationError::InvalidUnaryOperation {
+                operation: "elementwise function".into(),
+                operand: self.type_name().into(),
+            })
+        }
     }
 
     /// Helper function for unary functions, selects the appropriate method for the evaluation of `f`
@@ -134,6 +293,36 @@ pub trait DynMath : Any {
         unimpl_binary(self.type_name(), &other.type_name(), "**")
     }
 
+    /// Bitwise exclusive or
+    fn xor(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
+    {
+        unimpl_binary(self.type_name(), &other.type_name(), "xor")
+    }
+
+    /// Comparison `self op other`, the result is a `bool`
+    fn compare(&self, other: &dyn DynMath, op: &RelationalOperator) -> Result<Box<dyn DynMath>, EvaluationError>
+    {
+        unimpl_binary(self.type_name(), &other.type_name(), &op.to_string())
+    }
+
+    /// Indexing `self[index]`
+    fn index(&self, index: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
+    {
+        unimpl_binary(self.type_name(), &index.type_name(), "[]")
+    }
+
+    /// Logical and of `bool`s, evaluated by short-circuit, see `Evaluator`
+    fn and(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
+    {
+        unimpl_binary(self.type_name(), &other.type_name(), "and")
+    }
+
+    /// Logical or of `bool`s, evaluated by short-circuit, see `Evaluator`
+    fn or(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
+    {
+        unimpl_binary(self.type_name(), &other.type_name(), "or")
+    }
+
     /// Helpers for non-commutative binary operators
     /// Number + Array type would be tricky to implement, as it is not obvious how to
     /// reconstruct an Array from its iterator. Instead, we call Array + Number.
@@ -171,6 +360,9 @@ pub trait DynMath : Any {
     fn sum(&self) -> Result<Float, EvaluationError> {
         unimpl_unary(self, "sum()")
     }
+    fn prod(&self) -> Result<Float, EvaluationError> {
+        unimpl_unary(self, "prod()")
+    }
     fn range(&self) -> Result<Float, EvaluationError> {
         unimpl_unary(self, "range()")
     }
@@ -181,6 +373,46 @@ pub trait DynMath : Any {
         unimpl_unary(self, "l1_norm()")
     }
 
+    /// Reduction along `axis`, the reduced axis is removed from the shape. With
+    /// `keepdims` it is kept with length 1, so the result broadcasts against
+    /// `self`, as in `m - avg(m, axis=0)` of a matrix. The default handles the
+    /// numbers and 1-D arrays by the reductions above: their result is a number
+    /// either way, which broadcasts against any array. Types of more dimensions
+    /// should override it.
+    #[allow(unused_variables)]
+    fn reduce(&self, reduction: Reduction, axis: Axis, keepdims: bool) -> Result<Box<dyn DynMath>, EvaluationError> {
+        let ndim = self.ndim();
+        match axis {
+            Axis::All => reduction.apply(self).map(|x| Box::new(x) as Box<dyn DynMath>),
+            Axis::Along(0) if ndim == 1 => reduction.apply(self).map(|x| Box::new(x) as Box<dyn DynMath>),
+            Axis::Along(axis) if axis >= ndim => Err(EvaluationError::InvalidArguments {
+                function: reduction.name().into(),
+                details: format!("axis {} is out of range for `{}` of {} dimensions", axis, self.type_name(), ndim)
+            }),
+            Axis::Along(_) => Err(EvaluationError::InvalidOperation {
+                info: format!("`{}` has no reductions along an axis", self.type_name())
+            })
+        }
+    }
+
+    // Time series operations: Self, parameter -> Self
+    /// Resample with a regular `dt` time step
+    #[allow(unused_variables)]
+    fn resample(&self, dt: Float) -> Result<Box<dyn DynMath>, EvaluationError> {
+        Err(EvaluationError::InvalidUnaryOperation {
+            operation: "resample()".into(),
+            operand: self.type_name().into(),
+        })
+    }
+    /// Shift values by `n` samples
+    #[allow(unused_variables)]
+    fn shift(&self, n: isize) -> Result<Box<dyn DynMath>, EvaluationError> {
+        Err(EvaluationError::InvalidUnaryOperation {
+            operation: "shift()".into(),
+            operand: self.type_name().into(),
+        })
+    }
+
     // Unary operations: Self -> Self
     // todo: round, ceil, floor?
     fn dyn_abs(&self) -> Result<Box<dyn DynMath>, EvaluationError> {
@@ -222,25 +454,86 @@ pub trait DynMath : Any {
     fn dyn_sqrt(&self) -> Result<Box<dyn DynMath>, EvaluationError> {
         self.unary_dispatcher(|x|x.sqrt() , "sqrt()")
     }
+    /// Duration of `x` days in seconds
+    fn dyn_days(&self) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.unary_dispatcher(|x|x * SECONDS_PER_DAY , "days()")
+    }
+
+    /// Duration of `x` hours in seconds
+    fn dyn_hours(&self) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.unary_dispatcher(|x|x * SECONDS_PER_HOUR , "hours()")
+    }
+
     //TODO: add to parser
     fn dyn_cbrt(&self) -> Result<Box<dyn DynMath>, EvaluationError> {
         self.unary_dispatcher(|x|x.cbrt() , "cbrt()")
     }
-    //TODO: add to parser
+    /// `x ** 2` with a literal exponent, faster than `pow()`
     fn dyn_pow2(&self) -> Result<Box<dyn DynMath>, EvaluationError> {
         self.unary_dispatcher(|x|x*x , "^2")
     }
-    //TODO: add to parser
+    /// `x ** 3` with a literal exponent
     fn dyn_pow3(&self) -> Result<Box<dyn DynMath>, EvaluationError> {
         self.unary_dispatcher(|x|x*x*x , "^3")
     }
-    //TODO: add to parser
+    /// `x ** 4` with a literal exponent
     fn dyn_pow4(&self) -> Result<Box<dyn DynMath>, EvaluationError> {
         self.unary_dispatcher(|x|x*x*x*x , "^4")
     }
 
 }
 
+impl dyn DynMath {
+    /// Returns a reference to the concrete value if it is of type `T`
+    pub fn downcast_ref<T: DynMath>(&self) -> Option<&T> {
+        self.as_any().downcast_ref::<T>()
+    }
+
+    /// Returns true if the concrete value is of type `T`
+    pub fn is<T: DynMath>(&self) -> bool {
+        self.as_any().is::<T>()
+    }
+
+    /// Approximate equality of results (see `approx_eq()`): numbers, and arrays of
+    /// the same length elementwise, NaN equals NaN. The elements of tuples are
+    /// compared the same way, other values are equal if they are displayed the same.
+    pub fn approx_eq(&self, other: &dyn DynMath) -> bool {
+        let same = |x: Float, y: Float| (x.is_nan() && y.is_nan()) || x == y || crate::approx_eq(x, y);
+        match (self.category(), other.category()) {
+            (Category::Number, Category::Number) => same(self.as_number(), other.as_number()),
+            (Category::Array, Category::Array) => self.shape() == other.shape()
+                && self.iterate().zip(other.iterate()).all(|(x, y)| same(*x, *y)),
+            (Category::Unqiue, Category::Unqiue) => match (self.downcast_ref::<Tuple>(), other.downcast_ref::<Tuple>()) {
+                (Some(a), Some(b)) => a.names() == b.names() && a.iter().zip(b.iter()).all(|(x, y)| x.approx_eq(y)),
+                _ => self.type_name() == other.type_name() && self.to_string() == other.to_string()
+            },
+            _ => false
+        }
+    }
+}
+
+/// Deep copy with `clone_boxed()`, so results can be kept in `Clone` containers
+impl Clone for Box<dyn DynMath> {
+    fn clone(&self) -> Self {
+        self.clone_boxed()
+    }
+}
+
+impl fmt::Display for dyn DynMath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_value(f)
+    }
+}
+
+/// `Number(2.5)`, `Array([1, 2])`, the type name and the value
+impl fmt::Debug for dyn DynMath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}(", self.type_name())?;
+        self.fmt_value(f)?;
+        f.write_str(")")
+    }
+}
+
 fn invalid_args_err(func: &str, details: &str) -> Result<Float, EvaluationError> {
     Err(EvaluationError::InvalidArguments {
         function: func.into(),
@@ -248,82 +541,54 @@ fn invalid_args_err(func: &str, details: &str) -> Result<Float, EvaluationError>
     })
 }
 
-fn all_scalars(args: &[Rc<dyn DynMath>]) -> bool
+fn all_scalars(args: &[&dyn DynMath]) -> bool
 {
     args.iter().all(|e| matches!(e.category(), Category::Number))
 }
 
-// fn unbox_numbers(args: &[impl DynMath], func: &str) -> Result<Vec::<Float>, EvaluationError> {
-//     let mut res = Vec::new();
-//     for v in args {
-//         if let Ok(n) = v.number() {
-//             res.push(n);
-//         } else {
-//             return Err(EvaluationError::InvalidArguments {
-//                 function: func.into(),
-//                 details: "every argument should be a number".into()
-//             })
-//         }
-//     }
-//     return Ok(res);
-// }
-
-#[allow(unused_variables)]
-fn unbox_numbers(args: &[Rc<dyn DynMath>], func: &str) -> Result<Vec::<Float>, EvaluationError> {
-    let mut res = Vec::new();
-    for v in args {
-        res.push(v.as_number())
-    }
-    return Ok(res);
+/// The arguments as numbers, should be only called after `all_scalars()`
+fn numbers<'a>(args: &'a [&dyn DynMath]) -> impl Iterator<Item = Float> + 'a {
+    args.iter().map(|v| v.as_number())
 }
 
-// fn unary_dispatcher(obj: &dyn DynMath, f: Unary, op: &str) -> Result<Box<dyn DynMath>, EvaluationError> {
-//     match obj.category() {
-//         Category::Number => Ok(Box::new(f(obj.as_number()))),
-//         Category::Array => obj.elementwise(f),
-//         _ => Err(EvaluationError::InvalidUnaryOperation {
-//             operation: op.into(),
-//             operand: obj.type_name().into(),
-//         }),
-//     }
-// }
-
 
 const ZERO_ARGS_ERR: &str = "needs at least one argument";
 const MULTI_ARGS_ERR: &str = "accepts a single array or multiple scalar values";
 
-pub fn dynmath_min(args: &[Rc<dyn DynMath>]) -> Result<Float, EvaluationError>
+/// Current time as seconds since the unix epoch.
+/// Times are plain numbers in expressions: the difference of two times is a
+/// duration in seconds, comparable with `hours()` and `days()`.
+pub fn dynmath_now() -> Result<Float, EvaluationError> {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(t) => Ok(t.as_secs_f64() as Float),
+        Err(_) => Err(EvaluationError::InvalidOperation {
+            info: "system time is before the unix epoch".into()
+        })
+    }
+}
+
+pub fn dynmath_min(args: &[&dyn DynMath]) -> Result<Float, EvaluationError>
 {
     match args.len() {
         0 => invalid_args_err("min", ZERO_ARGS_ERR),
         1 => return args[0].min(),
         _ if !all_scalars(args) => invalid_args_err("min", MULTI_ARGS_ERR),
-        _ => match unbox_numbers(args, "min") {
-            Err(e) => Err(e),
-            Ok(v) => {
-                Ok(v.iter().fold(float::INFINITY, |a, &b| a.min(b)))
-            }
-        }
+        _ => Ok(numbers(args).fold(float::INFINITY, |a, b| a.min(b)))
     }
 }
 
 
-pub fn dynmath_max(args: &[Rc<dyn DynMath>]) -> Result<Float, EvaluationError>
+pub fn dynmath_max(args: &[&dyn DynMath]) -> Result<Float, EvaluationError>
 {
     match args.len() {
         0 => invalid_args_err("max", ZERO_ARGS_ERR),
         1 => return args[0].max(),
         _ if !all_scalars(args) => invalid_args_err("max", MULTI_ARGS_ERR),
-        _ => match unbox_numbers(args, "max") {
-            Err(e) => Err(e),
-            Ok(v) => {
-                Ok(v.iter().fold(float::NEG_INFINITY, |a, &b| a.max(b)))
-            }
-        }
+        _ => Ok(numbers(args).fold(float::NEG_INFINITY, |a, b| a.max(b)))
     }
 }
 
-pub fn dynmath_range(args: &[Rc<dyn DynMath>]) -> Result<Float, EvaluationError>
+pub fn dynmath_range(args: &[&dyn DynMath]) -> Result<Float, EvaluationError>
 {
     match args.len() {
         0 => invalid_args_err("range", ZERO_ARGS_ERR),
@@ -333,93 +598,179 @@ pub fn dynmath_range(args: &[Rc<dyn DynMath>]) -> Result<Float, EvaluationError>
             (Ok(_max), Ok(_min)) => Ok(_max - _min)
         }
         _ if !all_scalars(args) => invalid_args_err("range", MULTI_ARGS_ERR),
-        _ => match unbox_numbers(args, "range") {
-            Err(e) => Err(e),
-            Ok(v) => {
-                let _max = v.iter().fold(float::INFINITY, |a, &b| a.max(b));
-                let _min = v.iter().fold(float::INFINITY, |a, &b| a.min(b));
-                Ok(_max - _min)
-            }
+        _ => {
+            let _max = numbers(args).fold(float::NEG_INFINITY, |a, b| a.max(b));
+            let _min = numbers(args).fold(float::INFINITY, |a, b| a.min(b));
+            Ok(_max - _min)
         }
     }
 }
 
 
-pub fn dynmath_sum(args: &[Rc<dyn DynMath>]) -> Result<Float, EvaluationError>
+pub fn dynmath_sum(args: &[&dyn DynMath]) -> Result<Float, EvaluationError>
 {
     match args.len() {
         0 => invalid_args_err("sum", ZERO_ARGS_ERR),
-        1 => Ok(args[0].iterate().sum()),
+        1 => args[0].sum(),
         _ if !all_scalars(args) => invalid_args_err("sum", MULTI_ARGS_ERR),
-        _ => match unbox_numbers(args, "sum") {
-            Err(e) => Err(e),
-            Ok(v) => {
-                Ok(v.iter().sum::<Float>())
-            }
-        }
+        _ => Ok(numbers(args).sum::<Float>())
+    }
+}
+
+pub fn dynmath_prod(args: &[&dyn DynMath]) -> Result<Float, EvaluationError>
+{
+    match args.len() {
+        0 => invalid_args_err("prod", ZERO_ARGS_ERR),
+        1 => args[0].prod(),
+        _ if !all_scalars(args) => invalid_args_err("prod", MULTI_ARGS_ERR),
+        _ => Ok(numbers(args).product::<Float>())
     }
 }
 
-pub fn dynmath_avg(args: &[Rc<dyn DynMath>]) -> Result<Float, EvaluationError>
+pub fn dynmath_avg(args: &[&dyn DynMath]) -> Result<Float, EvaluationError>
 {
     match args.len() {
         0 => invalid_args_err("avg", ZERO_ARGS_ERR),
         1 => return args[0].avg(),
         _ if !all_scalars(args) => invalid_args_err("avg", MULTI_ARGS_ERR),
-        _ => match unbox_numbers(args, "avg") {
-            Err(e) => Err(e),
-            Ok(v) => {
-                Ok(v.iter().sum::<Float>() / (args.len() as Float))
-            }
-        }
+        _ => Ok(numbers(args).sum::<Float>() / (args.len() as Float))
     }
 }
 
-pub fn dynmath_std(args: &[Rc<dyn DynMath>]) -> Result<Float, EvaluationError>
+pub fn dynmath_std(args: &[&dyn DynMath]) -> Result<Float, EvaluationError>
 {
     match args.len() {
         0 => invalid_args_err("std", ZERO_ARGS_ERR),
         1 => return args[0].std(),
         _ if !all_scalars(args) => invalid_args_err("std", MULTI_ARGS_ERR),
-        _ => match unbox_numbers(args, "std") {
-            Err(e) => Err(e),
-            Ok(v) => {
-                let avg = v.iter().sum::<Float>() / (args.len() as Float);
-                let sq_err = v.iter().map(|e| (e-avg)*(e-avg)).sum::<Float>();
-                Ok((sq_err / (args.len() as Float)).sqrt())
-            }
+        _ => {
+            let avg = numbers(args).sum::<Float>() / (args.len() as Float);
+            let sq_err = numbers(args).map(|e| (e-avg)*(e-avg)).sum::<Float>();
+            Ok((sq_err / (args.len() as Float)).sqrt())
         }
     }
 }
 
-pub fn dynmath_l2(args: &[Rc<dyn DynMath>]) -> Result<Float, EvaluationError>
+pub fn dynmath_l2(args: &[&dyn DynMath]) -> Result<Float, EvaluationError>
 {
     match args.len() {
         0 => invalid_args_err("l2_norm", ZERO_ARGS_ERR),
         1 => return args[0].l2_norm(),
         _ if !all_scalars(args) => invalid_args_err("l2_norm", MULTI_ARGS_ERR),
-        _ => match unbox_numbers(args, "l2_norm") {
-            Err(e) => Err(e),
-            Ok(v) => {
-                let sq_sum = v.iter().map(|e| e*e).sum::<Float>();
-                Ok(sq_sum.sqrt())
-            }
-        }
+        _ => Ok(numbers(args).map(|e| e*e).sum::<Float>().sqrt())
     }
 }
 
-pub fn dynmath_l1(args: &[Rc<dyn DynMath>]) -> Result<Float, EvaluationError>
+pub fn dynmath_l1(args: &[&dyn DynMath]) -> Result<Float, EvaluationError>
 {
     match args.len() {
         0 => invalid_args_err("l1_norm", ZERO_ARGS_ERR),
         1 => return args[0].l1_norm(),
         _ if !all_scalars(args) => invalid_args_err("l1_norm", MULTI_ARGS_ERR),
-        _ => match unbox_numbers(args, "l1_norm") {
-            Err(e) => Err(e),
-            Ok(v) => {
-                let abs_sum = v.iter().map(|e| e.abs()).sum::<Float>();
-                Ok(abs_sum)
-            }
+        _ => Ok(numbers(args).map(|e| e.abs()).sum::<Float>())
+    }
+}
+
+/// `lo <= x and x <= hi`, the bounds are inclusive
+pub fn dynmath_between(x: &dyn DynMath, lo: &dyn DynMath, hi: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
+{
+    let above = x.compare(lo, &RelationalOperator::Geq)?;
+    let below = x.compare(hi, &RelationalOperator::Leq)?;
+    above.and(&*below)
+}
+
+/// Index of the smallest element of an array, NaN elements are ignored
+pub fn dynmath_argmin(x: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
+{
+    arg_extremum(x, "argmin", |a, b| a < b)
+}
+
+/// Index of the largest element of an array, NaN elements are ignored
+pub fn dynmath_argmax(x: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
+{
+    arg_extremum(x, "argmax", |a, b| a > b)
+}
+
+/// Index of the first element that is `better` than all the others, NA for
+/// arrays without numbers
+fn arg_extremum(x: &dyn DynMath, func: &str, better: fn(Float, Float) -> bool)
+-> Result<Box<dyn DynMath>, EvaluationError> {
+    let mut best: Option<(usize, Float)> = None;
+    for (i, y) in elements(x, func)?.enumerate().filter(|(_, y)| !y.is_nan()) {
+        if best.is_none_or(|(_, b)| better(y, b)) {
+            best = Some((i, y));
         }
     }
-}
\ No newline at end of file
+    match best {
+        Some((i, _)) => Ok(Box::new(i as Float)),
+        None => Ok(Box::new(Missing))
+    }
+}
+
+/// `find(v, x)`: index of the first element equal to `x`, NA if there is none
+pub fn dynmath_find(array: &dyn DynMath, value: Float) -> Result<Box<dyn DynMath>, EvaluationError>
+{
+    match elements(array, "find")?.position(|y| y == value) {
+        Some(i) => Ok(Box::new(i as Float)),
+        None => Ok(Box::new(Missing))
+    }
+}
+
+/// The elements of an array, a number is an array of length 1
+fn elements<'a>(x: &'a dyn DynMath, func: &str) -> Result<Box<dyn Iterator<Item = Float> + 'a>, EvaluationError> {
+    match x.category() {
+        Category::Number => Ok(Box::new(std::iter::once(x.as_number()))),
+        Category::Array => Ok(Box::new(x.iterate().copied())),
+        Category::Unqiue => Err(EvaluationError::InvalidArguments {
+            function: func.into(),
+            details: format!("expects an array, not `{}`", x.type_name())
+        })
+    }
+}
+
+/// `trapz(y, x)`: integral of the samples `y` taken at `x`, by the trapezoidal rule
+pub fn dynmath_trapz(y: &dyn DynMath, x: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
+{
+    let (y, x) = samples(y, x, "trapz")?;
+    let area = y.windows(2).zip(x.windows(2))
+        .map(|(y, x)| (x[1] - x[0]) * (y[0] + y[1]) / 2.0)
+        .sum::<Float>();
+    Ok(Box::new(area))
+}
+
+/// `gradient(y, x)`: derivative of the samples `y` taken at `x`. Second order
+/// central differences are used inside, one sided differences at the ends.
+pub fn dynmath_gradient(y: &dyn DynMath, x: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
+{
+    let (y, x) = samples(y, x, "gradient")?;
+    let n = y.len();
+    if n < 2 {
+        return Err(EvaluationError::InvalidArguments {
+            function: "gradient".into(),
+            details: "needs at least two samples".into()
+        });
+    }
+    let mut gradient = Vec::with_capacity(n);
+    gradient.push((y[1] - y[0]) / (x[1] - x[0]));
+    for i in 1..n-1 {
+        let (hs, hd) = (x[i] - x[i-1], x[i+1] - x[i]);
+        gradient.push((hs * hs * y[i+1] + (hd * hd - hs * hs) * y[i] - hd * hd * y[i-1]) / (hs * hd * (hd + hs)));
+    }
+    gradient.push((y[n-1] - y[n-2]) / (x[n-1] - x[n-2]));
+    Ok(Box::new(gradient))
+}
+
+/// The values `y` and the sampling points `x`, two arrays of the same length
+fn samples<'a>(y: &'a dyn DynMath, x: &'a dyn DynMath, func: &str) -> Result<(&'a [Float], &'a [Float]), EvaluationError> {
+    match (y.category(), x.category()) {
+        (Category::Array, Category::Array) if y.shape() == x.shape() => Ok((y.iterate().as_slice(), x.iterate().as_slice())),
+        (Category::Array, Category::Array) => Err(EvaluationError::InvalidArguments {
+            function: func.into(),
+            details: format!("the values and the sampling points have different lengths ({} and {})", y.shape()[0], x.shape()[0])
+        }),
+        _ => Err(EvaluationError::InvalidArguments {
+            function: func.into(),
+            details: format!("expects two arrays, not `{}` and `{}`", y.type_name(), x.type_name())
+        })
+    }
+}
@@ -1,37 +1,76 @@
+use std::collections::HashMap;
 use std::rc::Rc;
 // use crate::parser::{A};
 use crate::*;
 
 const FINAL_RESULT_ALIAS: &str = "RESULT";
 
+/// Evaluator of a formula of statements, separated by new lines or `;`:
+/// assignments (`x = a * b`), the final result, and named outputs
+/// (`out mean = avg(v); out spread = std(v)`), see `evaluate_outputs()`.
+///
+/// The statements whose value is not used by the final result or an output
+/// are not evaluated (see `statement_count()`), so their evaluation errors
+/// are not reported either: `m = v * "a"; m = max(v); m` does not fail.
+/// The assignments of the variables with a declared range are always evaluated.
 #[derive(Clone)]
 pub struct MultiExpEvaluator {
     expressions: Vec<Evaluator>,
     temporaries: Vec<String>,
+    /// The statements that are evaluated, see `live_statements()`
+    live: Vec<bool>,
+    outputs: Vec<String>,
+    bounds: VariableBounds,
 }
 
 
 impl MultiExpEvaluator {
+    /// Parse the statements of `expression`, every referenced variable has to be
+    /// among `variables` or assigned in a previous statement
     pub fn new(expression: &str, variables: &[&str]) -> Result<Self, DymexError> {
+        Self::with_policy(expression, variables, VariablePolicy::Strict)
+    }
+
+    /// Parse the statements of `expression`, unknown variables are handled according to `policy`
+    pub fn with_policy(expression: &str, variables: &[&str], policy: VariablePolicy) -> Result<Self, DymexError> {
         let mut var = variables.iter().map(|s| s.to_string()).collect::<Vec<String>>();
-        let mut evaluators = Vec::new();
+        let mut statements = Vec::new();
         let mut temp_variables = Vec::new();
-        for line in expression.lines() {
+        let mut outputs = Vec::new();
+        let expression = match blank_comments(expression) {
+            Ok(exp) => exp,
+            Err(err) => return Err(DymexError::LexicalError(err))
+        };
+        for line in split_statements(&expression) {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (output, line) = match output_statement(line) {
+                Some(statement) => (true, statement),
+                None => (false, line)
+            };
             match split_assignement(line) {
+                (None, _) if output => {
+                    let details = "outputs are named: `out name = value`".to_string();
+                    return Err(DymexError::ParsingError(ParsingError::InvalidAssignment(details, 0)));
+                }
                 (None, Some(exp)) => {
-                    match parse_expr(&exp, &var) {
+                    match parse_expr(&exp, &var, policy) {
                         Ok(ast) => {
-                            evaluators.push(Evaluator::from_ast(ast));
+                            statements.push(ast);
                             temp_variables.push(FINAL_RESULT_ALIAS.to_string());
                         },
                         Err(e) => return Err(e)
                     }
                 },
                 (Some(var_id), Some(exp)) => {
-                    match parse_expr(&exp, &var) {
+                    match parse_expr(&exp, &var, policy) {
                         Ok(ast) => {
-                            evaluators.push(Evaluator::from_ast(ast));
+                            statements.push(ast);
                             var.push(var_id.clone());
+                            if output {
+                                outputs.push(var_id.clone());
+                            }
                             temp_variables.push(var_id);
                         },
                         Err(e) => return Err(e)
@@ -43,54 +82,187 @@ impl MultiExpEvaluator {
             }
         }
 
+        let expressions: Vec<Evaluator> = statements.into_iter().map(Evaluator::from_ast).collect();
+        let bounds = VariableBounds::default();
         Ok(Self {
-            expressions: evaluators,
-            temporaries: temp_variables
+            live: live_statements(&expressions, &temp_variables, &outputs, &bounds),
+            expressions,
+            temporaries: temp_variables,
+            outputs,
+            bounds,
         })
     }
 
+    /// Number of statements evaluated, after removing the unused ones
+    pub fn statement_count(&self) -> usize {
+        self.live.iter().filter(|live| **live).count()
+    }
+
+    /// Names of the outputs (`out name = value`), in order
+    pub fn outputs(&self) -> &[String] {
+        &self.outputs
+    }
 
+
+    /// The result of the final expression. A formula of outputs only
+    /// (`out lo = min(v); out hi = max(v)`) results in the `Tuple` of the outputs.
     pub fn evaluate(&mut self, inputs: &InputVars) -> Result<Box<dyn DynMath>, EvaluationError> {
+        let values = self.evaluate_statements(inputs)?;
+        match values.get(FINAL_RESULT_ALIAS) {
+            Some(res) => Ok(res.clone_boxed()),
+            None if !self.outputs.is_empty() => {
+                let names = self.outputs.iter().cloned().map(Some).collect();
+                let outputs = self.outputs.iter().map(|name| values.as_hashmap()[name].clone_boxed()).collect();
+                Ok(Box::new(Tuple::new(names, outputs)))
+            }
+            None => Err(EvaluationError::MissingFinalExpression)
+        }
+    }
+
+    /// The values of the outputs (`out name = value`) by name
+    pub fn evaluate_outputs(&mut self, inputs: &InputVars) -> Result<HashMap<String, Box<dyn DynMath>>, EvaluationError> {
+        let values = self.evaluate_statements(inputs)?;
+        Ok(self.outputs.iter().map(|name| (name.clone(), values.as_hashmap()[name].clone_boxed())).collect())
+    }
+
+    /// Declare the valid range of an input or assigned variable, see `Evaluator::declare_range()`.
+    /// The assignments of `name` are evaluated (and checked) even if their value is not used.
+    pub fn declare_range(&mut self, name: &str, min: Float, max: Float) {
+        self.bounds.declare(name, min, max);
+        self.live = live_statements(&self.expressions, &self.temporaries, &self.outputs, &self.bounds);
+    }
+
+    pub fn set_bounds_action(&mut self, action: BoundsAction) {
+        self.bounds.action = action;
+    }
+
+    /// The variables outside of their ranges in the last evaluation, with `BoundsAction::Warn`
+    pub fn bounds_violations(&self) -> &[BoundsViolation] {
+        &self.bounds.violations
+    }
+
+    /// The inputs extended by the values of the statements
+    fn evaluate_statements(&mut self, inputs: &InputVars) -> Result<InputVars, EvaluationError> {
+        self.bounds.check_inputs(inputs)?;
         let mut inputs = inputs.clone();
-        for (var_id, exp) in self.temporaries.iter().zip(self.expressions.iter_mut()) {
+        let statements = self.temporaries.iter().zip(self.expressions.iter_mut()).zip(&self.live);
+        for ((var_id, exp), _) in statements.filter(|(_, live)| **live) {
             match exp.evaluate(&inputs) {
                 Ok(result) => {
+                    self.bounds.check(var_id, &*result)?;
                     // intentionally not returning early here, even if `var_id == FINAL_RESULT_ALIAS
                     inputs.insert_ref(var_id.clone(), Rc::from(result));
                 },
                 Err(e) => return Err(e)
             }
         }
-        return match inputs.get(FINAL_RESULT_ALIAS) {
-            Some(res) => Ok(res.clone_boxed()),
-            None => Err(EvaluationError::MissingFinalExpression)
+        Ok(inputs)
+    }
+}
+
+/// Dead code elimination: true for the statements whose value is used by the
+/// final result or an output, directly or through other statements, and for the
+/// assignments of the variables with a declared range (they are checked). The
+/// others (assignments that are never read, or overwritten before being read,
+/// and results of statements before the final one) are not evaluated.
+fn live_statements(statements: &[Evaluator], assigned: &[String], outputs: &[String], bounds: &VariableBounds) -> Vec<bool> {
+    let mut needed: Vec<String> = outputs.to_vec();
+    needed.push(FINAL_RESULT_ALIAS.to_string());
+    let mut live = vec![false; statements.len()];
+    for (i, (statement, name)) in statements.iter().zip(assigned).enumerate().rev() {
+        match needed.iter().position(|n| n == name) {
+            // earlier assignments of the same name are overwritten by this one
+            Some(position) => { needed.swap_remove(position); },
+            None if bounds.is_declared(name) => {},
+            None => continue
+        }
+        live[i] = true;
+        for path in statement.inputs() {
+            let root = path.split('.').next().unwrap_or(path).to_string();
+            if !needed.contains(&root) {
+                needed.push(root);
+            }
         }
     }
+    live
 }
 
+/// The statements of `expression`, separated by new lines or `;` (except in
+/// strings and escaped identifiers)
+fn split_statements(expression: &str) -> Vec<&str> {
+    let mut statements = Vec::new();
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in expression.char_indices() {
+        match (quote, c) {
+            (None, '"' | '`') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '\n' | ';') => {
+                statements.push(&expression[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    statements.push(&expression[start..]);
+    statements
+}
 
+/// The assignment of an output statement `out name = value`
+fn output_statement(statement: &str) -> Option<&str> {
+    let rest = statement.trim_start().strip_prefix("out")?;
+    // `out = 1` assigns the variable `out`
+    match rest.starts_with(char::is_whitespace) && !rest.trim_start().starts_with('=') {
+        true => Some(rest),
+        false => None
+    }
+}
+
+
+/// Split `name = value` at the assignment outside of any parentheses, so the
+/// `=` of `with(t = x, ...)`, of named arguments and of `==` stays in the value.
+/// A statement that does not tokenize is returned whole, for the parser to
+/// report the error.
 fn split_assignement(exp: &str) -> (Option<String>, Option<String>) {
-    if !exp.contains("=") {
+    let Ok(ts) = TokenStream::new(exp) else {
         return (None, Some(exp.to_string()));
-    }
-    let mut tmp = exp.splitn( 2, "=");
-    let key = match tmp.next() {
-        Some(s) => Some(s.trim().to_string()),
-        None => None,
     };
-    let value = tmp.next().map(str::to_string);
-    (key, value)
+    let mut depth = 0;
+    let mut at = None;
+    for tc in ts.tokens() {
+        match tc.token {
+            Token::LP | Token::LB => depth += 1,
+            Token::RP | Token::RB => depth -= 1,
+            Token::AssignOp(AssignmentOperator::Assign) if depth == 0 => {
+                at = Some(tc.at);
+                break;
+            }
+            _ => {}
+        }
+    }
+    match at {
+        Some(at) => {
+            let key: String = exp.chars().take(at).collect();
+            let value: String = exp.chars().skip(at + 1).collect();
+            (Some(key.trim().to_string()), Some(value))
+        }
+        None => (None, Some(exp.to_string()))
+    }
 }
 
-fn parse_expr(expression: &str, variables: &[String]) -> Result<AST, DymexError> {
+fn parse_expr(expression: &str, variables: &[String], policy: VariablePolicy) -> Result<AST, DymexError> {
     let v = &variables.iter().map(|s| s.as_ref()).collect::<Vec<&str>>();
     let ts = match TokenStream::new(expression) {
         Ok(ts) => ts,
         Err(err) => return Err(DymexError::LexicalError(err))
     };
 
-    match AST::new(ts) {
-        Ok(ast) => Ok(ast),
+    let ast = match AST::new(ts) {
+        Ok(ast) => ast,
+        Err(err) => return Err(DymexError::ParsingError(err))
+    };
+    match ast.check_variables(v, policy) {
+        Ok(()) => Ok(ast),
         Err(err) => Err(DymexError::ParsingError(err))
     }
 }
\ No newline at end of file
@@ -0,0 +1,175 @@
+use std::fs::File;
+use std::io::{self, Read, Seek};
+use std::path::Path;
+use std::rc::Rc;
+use thiserror::Error;
+use crate::Float;
+use super::{DynMath, InputVars};
+use super::npy_header::NpyHeader;
+
+
+#[derive(Error, Debug)]
+pub enum NpyError {
+    #[error("failed to read the file: {0}")]
+    Io(#[from] io::Error),
+    #[error("failed to read the npz archive: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("invalid npy data `{name}`: {details}")]
+    Format {
+        name: String,
+        details: String,
+    },
+}
+
+/// The value of the contents of an `.npy` file: 1 dimensional arrays are
+/// `Vec<Float>`s and 0 dimensional ones numbers (`Vec<bool>` and `bool` for
+/// booleans). The floating point, signed and unsigned integer types are supported,
+/// of both byte orders.
+pub fn value_from_npy(file: &[u8]) -> Result<Box<dyn DynMath>, String> {
+    let header = NpyHeader::parse(file)?;
+    let data = &file[header.data_offset..];
+    let mut descr = header.descr.chars();
+    let little = match descr.next() {
+        Some('<') => true,
+        Some('>') => false,
+        _ => cfg!(target_endian = "little")
+    };
+    let kind = descr.next();
+    let size: usize = descr.as_str().parse().map_err(|_| format!("unknown data type `{}`", header.descr))?;
+    let expected = header.len().checked_mul(size)
+        .ok_or_else(|| format!("too many bytes of data for {} elements of {} bytes", header.len(), size))?;
+    if data.len() != expected {
+        return Err(format!("{} bytes of data for {} elements of {} bytes", data.len(), header.len(), size));
+    }
+
+    macro_rules! numbers {
+        ($t:ty) => {
+            data.chunks_exact(size_of::<$t>()).map(|bytes| {
+                let bytes = bytes.try_into().unwrap(); // the chunks have the size of the type
+                (if little { <$t>::from_le_bytes(bytes) } else { <$t>::from_be_bytes(bytes) }) as Float
+            }).collect::<Vec<Float>>()
+        };
+    }
+    let values = match (kind, size) {
+        (Some('b'), 1) => {
+            let values: Vec<bool> = data.iter().map(|b| *b != 0).collect();
+            return match header.shape.len() {
+                0 => Ok(Box::new(values[0])),
+                1 => Ok(Box::new(values)),
+                n => Err(format!("{} dimensional arrays are not supported", n))
+            };
+        }
+        (Some('f'), 8) => numbers!(f64),
+        (Some('f'), 4) => numbers!(f32),
+        (Some('i'), 8) => numbers!(i64),
+        (Some('i'), 4) => numbers!(i32),
+        (Some('i'), 2) => numbers!(i16),
+        (Some('i'), 1) => numbers!(i8),
+        (Some('u'), 8) => numbers!(u64),
+        (Some('u'), 4) => numbers!(u32),
+        (Some('u'), 2) => numbers!(u16),
+        (Some('u'), 1) => numbers!(u8),
+        _ => return Err(format!("unsupported data type `{}`", header.descr))
+    };
+    match header.shape.len() {
+        0 => Ok(Box::new(values[0])),
+        1 => Ok(Box::new(values)),
+        n => Err(format!("{} dimensional arrays are not supported", n))
+    }
+}
+
+impl InputVars {
+    /// Read the `.npy` file at `path` as the variable `name`, see `value_from_npy()`
+    pub fn load_npy(&mut self, name: String, path: impl AsRef<Path>) -> Result<(), NpyError> {
+        let file = std::fs::read(path)?;
+        let value = value_from_npy(&file).map_err(|details| NpyError::Format { name: name.clone(), details })?;
+        self.insert_ref(name, Rc::from(value));
+        Ok(())
+    }
+
+    /// Read the `.npz` archive at `path`, see `from_npz_reader()`
+    pub fn from_npz_path(path: impl AsRef<Path>) -> Result<Self, NpyError> {
+        Self::from_npz_reader(File::open(path)?)
+    }
+
+    /// The arrays of an `.npz` archive (`numpy.savez()`, compressed or not) as
+    /// variables named after their keys:
+    /// ```ignore
+    /// // numpy.savez("spectrum.npz", wavelength=wl, intensity=i)
+    /// let inputs = InputVars::from_npz_path("spectrum.npz")?;
+    /// let mut evaluator = Evaluator::new("trapz(intensity, wavelength)", &inputs.names())?;
+    /// ```
+    pub fn from_npz_reader<R: Read + Seek>(reader: R) -> Result<Self, NpyError> {
+        let mut archive = zip::ZipArchive::new(reader)?;
+        let mut inputs = Self::new();
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i)?;
+            if entry.is_dir() {
+                continue;
+            }
+            let name = entry.name().trim_end_matches(".npy").to_string();
+            let mut file = Vec::new();
+            entry.read_to_end(&mut file)?;
+            let value = value_from_npy(&file).map_err(|details| NpyError::Format { name: name.clone(), details })?;
+            inputs.insert_ref(name, Rc::from(value));
+        }
+        Ok(inputs)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+    use crate::*;
+
+    fn npy(descr: &str, shape: &str, data: &[u8]) -> Vec<u8> {
+        let mut header = format!("{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}", descr, shape);
+        while (10 + header.len() + 1) % 64 != 0 {
+            header.push(' ');
+        }
+        header.push('\n');
+        let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+        bytes.extend((header.len() as u16).to_le_bytes());
+        bytes.extend(header.as_bytes());
+        bytes.extend(data);
+        bytes
+    }
+
+    #[test]
+    fn test_npy_values() {
+        let floats: Vec<u8> = [1.5f64, -2.0, 4.0].iter().flat_map(|x| x.to_le_bytes()).collect();
+        let value = value_from_npy(&npy("<f8", "(3,)", &floats)).unwrap();
+        assert_eq!(value.iterate().as_slice(), &[1.5, -2.0, 4.0]);
+        let ints: Vec<u8> = [7i32, -1].iter().flat_map(|x| x.to_be_bytes()).collect();
+        let value = value_from_npy(&npy(">i4", "(2,)", &ints)).unwrap();
+        assert_eq!(value.iterate().as_slice(), &[7.0, -1.0]);
+        assert_eq!(value_from_npy(&npy("<f4", "()", &2.5f32.to_le_bytes())).unwrap().as_number(), 2.5);
+        let mask = value_from_npy(&npy("|b1", "(3,)", &[1, 0, 1])).unwrap();
+        assert_eq!(mask.downcast_ref::<Vec<bool>>(), Some(&vec![true, false, true]));
+
+        assert!(value_from_npy(&npy("<f8", "(3, 1)", &floats)).unwrap_err().contains("2 dimensional"));
+        assert!(value_from_npy(&npy("<f8", "(4,)", &floats)).is_err());
+        assert!(value_from_npy(&npy("<c16", "(1,)", &[0; 16])).is_err());
+        // the number of elements or bytes would overflow
+        assert!(value_from_npy(&npy("<f8", "(4611686018427387904, 4)", &floats)).unwrap_err().contains("too many elements"));
+        assert!(value_from_npy(&npy("<f8", "(2305843009213693952,)", &floats)).unwrap_err().contains("too many bytes"));
+        assert!(value_from_npy(b"not an npy file").is_err());
+    }
+
+    #[test]
+    fn test_npz_archive() {
+        let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        let x: Vec<u8> = [1.0f64, 2.0, 3.0].iter().flat_map(|x| x.to_le_bytes()).collect();
+        archive.start_file("x.npy", options).unwrap();
+        archive.write_all(&npy("<f8", "(3,)", &x)).unwrap();
+        archive.start_file("n.npy", options).unwrap();
+        archive.write_all(&npy("<i8", "()", &10i64.to_le_bytes())).unwrap();
+        let archive = archive.finish().unwrap();
+
+        let inputs = InputVars::from_npz_reader(Cursor::new(archive.into_inner())).unwrap();
+        let result = Evaluator::new("sum(x) * n", &inputs.names()).unwrap().evaluate(&inputs).unwrap();
+        assert_eq!(result.as_number(), 60.0);
+    }
+}
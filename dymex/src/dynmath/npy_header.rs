@@ -0,0 +1,54 @@
+//! The header of `.npy` files, see the format in the numpy documentation (`numpy.lib.format`)
+
+pub(super) struct NpyHeader {
+    /// The data type, like `<f8`
+    pub descr: String,
+    pub fortran_order: bool,
+    pub shape: Vec<usize>,
+    /// Offset of the data in the file
+    pub data_offset: usize,
+}
+
+impl NpyHeader {
+    /// Parse the header at the start of `file`
+    pub fn parse(file: &[u8]) -> Result<Self, String> {
+        if !file.starts_with(b"\x93NUMPY") || file.len() < 10 {
+            return Err("missing magic string".into());
+        }
+        let (header_start, header_len) = match file[6] {
+            1 => (10usize, u16::from_le_bytes([file[8], file[9]]) as usize),
+            2 | 3 if file.len() >= 12 => (12, u32::from_le_bytes([file[8], file[9], file[10], file[11]]) as usize),
+            version => return Err(format!("unknown version {}", version))
+        };
+        let header = header_start.checked_add(header_len)
+            .and_then(|header_end| file.get(header_start..header_end))
+            .and_then(|header| std::str::from_utf8(header).ok())
+            .ok_or("truncated header")?;
+        let descr = value(header, "descr")
+            .and_then(|descr| descr.strip_prefix('\''))
+            .and_then(|descr| descr.split('\'').next())
+            .ok_or("missing data type")?;
+        let fortran_order = value(header, "fortran_order").is_some_and(|order| order.starts_with("True"));
+        let shape = value(header, "shape")
+            .and_then(|shape| shape.strip_prefix('('))
+            .and_then(|shape| shape.split(')').next())
+            .ok_or("missing shape")?;
+        let shape = shape.split(',').map(|d| d.trim()).filter(|d| !d.is_empty())
+            .map(|d| d.parse::<usize>().map_err(|_| format!("invalid shape ({})", shape)))
+            .collect::<Result<Vec<usize>, String>>()?;
+        if shape.iter().try_fold(1usize, |len, d| len.checked_mul(*d)).is_none() {
+            return Err(format!("too many elements ({})", shape.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(", ")));
+        }
+        Ok(Self { descr: descr.to_string(), fortran_order, shape, data_offset: header_start + header_len })
+    }
+
+    /// The number of elements, `parse()` checks that it fits in a `usize`
+    pub fn len(&self) -> usize {
+        self.shape.iter().product()
+    }
+}
+
+/// The text after `'key':` in the header dictionary
+fn value<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+    header.split(&format!("'{}':", key)).nth(1).map(|value| value.trim_start())
+}
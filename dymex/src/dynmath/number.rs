@@ -1,6 +1,18 @@
-use crate::{Float, MAXDIM};
-use super::{DynMath, EvaluationError, Category, unimpl_binary};
+use crate::{Float, MAXDIM, RelationalOperator};
+use super::{DynMath, ResultFormatter, EvaluationError, Category, Missing, unimpl_binary};
+use super::scalar::{tolerance_of, with_tolerance_of};
 use std::any::Any;
+use std::fmt;
+
+
+/// The number as an integer, if it has no fractional part
+pub(super) fn as_integer(x: Float) -> Option<i64> {
+    if x.fract() == 0.0 && x.abs() < i64::MAX as Float {
+        Some(x as i64)
+    } else {
+        None
+    }
+}
 
 
 impl DynMath for Float {
@@ -19,40 +31,40 @@ impl DynMath for Float {
         self
     }
 
+    fn fmt_value(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", ResultFormatter::new().display(self))
+    }
+
 
     fn add(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
     {
         match other.category() {
-            Category::Number => Ok(Box::new(*self + other.as_number())),
-            Category::Array => (*other).add(self),
-            _ => unimpl_binary(self.type_name(), other.type_name(), "+")
+            Category::Number => Ok(with_tolerance_of(other, *self + other.as_number())),
+            Category::Array | Category::Unqiue => (*other).add(self),
         }
     }
 
     fn sub(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
     {
         match other.category() {
-            Category::Number => Ok(Box::new(*self - other.as_number())),
-            Category::Array => (*other).sub_inv(self),
-            _ => unimpl_binary(self.type_name(), other.type_name(), "-")
+            Category::Number => Ok(with_tolerance_of(other, *self - other.as_number())),
+            Category::Array | Category::Unqiue => (*other).sub_inv(self),
         }
     }
 
     fn mul(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
     {
         match other.category() {
-            Category::Number => Ok(Box::new(*self * other.as_number())),
-            Category::Array => (*other).mul(self),
-            _ => unimpl_binary(self.type_name(), other.type_name(), "+")
+            Category::Number => Ok(with_tolerance_of(other, *self * other.as_number())),
+            Category::Array | Category::Unqiue => (*other).mul(self),
         }
     }
 
     fn div(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
     {
         match other.category() {
-            Category::Number => Ok(Box::new(*self / other.as_number())),
-            Category::Array => (*other).div_inv(self),
-            _ => unimpl_binary(self.type_name(), other.type_name(), "+")
+            Category::Number => Ok(with_tolerance_of(other, *self / other.as_number())),
+            Category::Array | Category::Unqiue => (*other).div_inv(self),
         }
     }
 
@@ -60,11 +72,44 @@ impl DynMath for Float {
     {
         match other.category() {
 
-            Category::Number => Ok(Box::new(self.powf(other.as_number()))),
-            Category::Array => (*other).pow_inv(self),
-            _ => unimpl_binary(self.type_name(), other.type_name(), "+")
+            Category::Number => Ok(with_tolerance_of(other, self.powf(other.as_number()))),
+            Category::Array | Category::Unqiue => (*other).pow_inv(self),
+        }
+    }
+
+    fn xor(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
+    {
+        match other.category() {
+            Category::Number => match (as_integer(*self), as_integer(other.as_number())) {
+                (Some(a), Some(b)) => Ok(Box::new((a ^ b) as Float)),
+                _ => Err(EvaluationError::InvalidOperation {
+                    info: "xor is only defined for integers".into()
+                })
+            },
+            _ => unimpl_binary(self.type_name(), other.type_name(), "xor")
+        }
+    }
+
+    fn compare(&self, other: &dyn DynMath, op: &RelationalOperator) -> Result<Box<dyn DynMath>, EvaluationError>
+    {
+        match other.category() {
+            Category::Number => Ok(Box::new(tolerance_of(other).compare(*self, other.as_number(), op))),
+            Category::Array => other.compare(self, &op.reversed()),
+            _ if other.is::<Missing>() => Ok(Box::new(Missing)),
+            _ => unimpl_binary(self.type_name(), other.type_name(), &op.to_string())
         }
     }
 
+    // reductions of a single number, as if it were an array of length 1
+    fn min(&self) -> Result<Float, EvaluationError> { Ok(*self) }
+    fn max(&self) -> Result<Float, EvaluationError> { Ok(*self) }
+    fn range(&self) -> Result<Float, EvaluationError> { Ok(0.0) }
+    fn sum(&self) -> Result<Float, EvaluationError> { Ok(*self) }
+    fn prod(&self) -> Result<Float, EvaluationError> { Ok(*self) }
+    fn avg(&self) -> Result<Float, EvaluationError> { Ok(*self) }
+    fn std(&self) -> Result<Float, EvaluationError> { Ok(0.0) }
+    fn l2_norm(&self) -> Result<Float, EvaluationError> { Ok(self.abs()) }
+    fn l1_norm(&self) -> Result<Float, EvaluationError> { Ok(self.abs()) }
+
 
 }
\ No newline at end of file
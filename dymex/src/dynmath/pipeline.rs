@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use thiserror::Error;
+use crate::*;
+
+
+#[derive(Error, Debug)]
+pub enum PipelineError {
+    #[error("stage `{stage}`: {error:?}")]
+    InvalidExpression {
+        stage: String,
+        error: DymexError,
+    },
+    #[error("stage `{stage}` uses the undefined variable `{variable}`")]
+    MissingVariable {
+        stage: String,
+        variable: String,
+    },
+    #[error("stage `{stage}` uses `{variable}`, which is computed by a later stage")]
+    LaterStage {
+        stage: String,
+        variable: String,
+    },
+    #[error("`{0}` is the name of more than one stage or input")]
+    DuplicateName(String),
+    #[error("the pipeline has no stages")]
+    Empty,
+    #[error("stage `{stage}` failed: {error}")]
+    Evaluation {
+        stage: String,
+        error: EvaluationError,
+    },
+}
+
+/// Evaluator of named stages in order, the value of a stage is an input of the
+/// later ones:
+/// ```
+/// # use dymex::*;
+/// let mut pipeline = Pipeline::new(&["v"], &[
+///     ("mu", "avg(v)"),
+///     ("centered", "v - mu"),
+///     ("rms", "sqrt(avg(centered ** 2))"),
+/// ]).unwrap();
+/// let mut inputs = InputVars::new();
+/// inputs.insert_owned("v".into(), vec![1.0, 3.0]);
+/// assert_eq!(pipeline.evaluate(&inputs).unwrap().as_number(), 1.0);
+/// ```
+/// The stages are checked together when the pipeline is created: every
+/// variable has to be an input or an earlier stage, see `PipelineError`. The
+/// result is the value of the last stage, the inputs and stages nothing
+/// depends on are reported by `unused_inputs()` and `unused_stages()`.
+#[derive(Clone)]
+pub struct Pipeline {
+    inputs: Vec<String>,
+    stages: Vec<Stage>,
+}
+
+#[derive(Clone)]
+struct Stage {
+    name: String,
+    evaluator: Evaluator,
+    /// The inputs and stages used by the stage
+    dependencies: Vec<String>,
+}
+
+impl Pipeline {
+    /// Parse the `(name, expression)` pairs of `stages`, the expressions can
+    /// use the `inputs` and the names of the earlier stages
+    pub fn new(inputs: &[&str], stages: &[(&str, &str)]) -> Result<Self, PipelineError> {
+        if stages.is_empty() {
+            return Err(PipelineError::Empty);
+        }
+        let mut known: Vec<&str> = Vec::new();
+        for name in inputs.iter().chain(stages.iter().map(|(name, _)| name)) {
+            if known.contains(name) {
+                return Err(PipelineError::DuplicateName(name.to_string()));
+            }
+            known.push(name);
+        }
+
+        let mut compiled = Vec::new();
+        for (i, (name, expression)) in stages.iter().enumerate() {
+            let invalid = |error| PipelineError::InvalidExpression { stage: name.to_string(), error };
+            let ts = TokenStream::new(expression).map_err(|e| invalid(DymexError::LexicalError(e)))?;
+            let ast = AST::new(ts).map_err(|e| invalid(DymexError::ParsingError(e)))?;
+            let mut dependencies = Vec::new();
+            for path in ast.tree.free_variables() {
+                let root = path.split('.').next().unwrap_or(&path).to_string();
+                let position = known.iter().position(|k| *k == root);
+                match position {
+                    Some(p) if p < inputs.len() + i => {},
+                    Some(_) => return Err(PipelineError::LaterStage { stage: name.to_string(), variable: root }),
+                    None => return Err(PipelineError::MissingVariable { stage: name.to_string(), variable: root })
+                }
+                if !dependencies.contains(&root) {
+                    dependencies.push(root);
+                }
+            }
+            compiled.push(Stage { name: name.to_string(), evaluator: Evaluator::from_ast(ast), dependencies });
+        }
+        Ok(Self { inputs: inputs.iter().map(|s| s.to_string()).collect(), stages: compiled })
+    }
+
+    /// Names of the stages, in evaluation order
+    pub fn stage_names(&self) -> Vec<&str> {
+        self.stages.iter().map(|stage| stage.name.as_str()).collect()
+    }
+
+    /// The inputs and earlier stages used by the stage `name`
+    pub fn dependencies(&self, name: &str) -> Option<&[String]> {
+        self.stages.iter().find(|stage| stage.name == name).map(|stage| stage.dependencies.as_slice())
+    }
+
+    /// Inputs not used by any stage
+    pub fn unused_inputs(&self) -> Vec<&str> {
+        self.inputs.iter()
+            .filter(|input| !self.stages.iter().any(|stage| stage.dependencies.contains(input)))
+            .map(|input| input.as_str())
+            .collect()
+    }
+
+    /// Stages whose value is not used by a later stage, except the last one (the result)
+    pub fn unused_stages(&self) -> Vec<&str> {
+        let (_, earlier) = self.stages.split_last().unwrap(); // not empty, checked by `new()`
+        earlier.iter().enumerate()
+            .filter(|(i, stage)| !self.stages[i + 1..].iter().any(|later| later.dependencies.contains(&stage.name)))
+            .map(|(_, stage)| stage.name.as_str())
+            .collect()
+    }
+
+    /// The value of the last stage
+    pub fn evaluate(&mut self, inputs: &InputVars) -> Result<Box<dyn DynMath>, PipelineError> {
+        let values = self.evaluate_stages(inputs)?;
+        let last = &self.stages[self.stages.len() - 1].name;
+        Ok(values.as_hashmap()[last].clone_boxed())
+    }
+
+    /// The values of every stage by name
+    pub fn evaluate_all(&mut self, inputs: &InputVars) -> Result<HashMap<String, Box<dyn DynMath>>, PipelineError> {
+        let values = self.evaluate_stages(inputs)?;
+        Ok(self.stages.iter().map(|stage| (stage.name.clone(), values.as_hashmap()[&stage.name].clone_boxed())).collect())
+    }
+
+    /// The inputs extended by the values of the stages
+    fn evaluate_stages(&mut self, inputs: &InputVars) -> Result<InputVars, PipelineError> {
+        let mut values = inputs.clone();
+        for stage in self.stages.iter_mut() {
+            let result = stage.evaluator.evaluate(&values)
+                .map_err(|error| PipelineError::Evaluation { stage: stage.name.clone(), error })?;
+            values.insert_ref(stage.name.clone(), Rc::from(result));
+        }
+        Ok(values)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_pipeline() {
+        let mut pipeline = Pipeline::new(&["v", "scale", "offset"], &[
+            ("scaled", "v * scale"),
+            ("debug", "max(scaled)"),
+            ("level", "avg(scaled)"),
+            ("spread", "std(scaled) / level"),
+        ]).unwrap();
+        assert_eq!(pipeline.stage_names(), ["scaled", "debug", "level", "spread"]);
+        assert_eq!(pipeline.dependencies("spread").unwrap(), ["scaled", "level"]);
+        assert_eq!(pipeline.unused_inputs(), ["offset"]);
+        assert_eq!(pipeline.unused_stages(), ["debug"]);
+
+        let mut inputs = InputVars::new();
+        inputs.insert_owned("v".into(), vec![1.0, 2.0, 3.0]);
+        inputs.insert_owned("scale".into(), 2.0);
+        inputs.insert_owned("offset".into(), 0.0);
+        let expected = (8.0 as Float / 3.0).sqrt() / 4.0;
+        assert!(approx_eq(pipeline.evaluate(&inputs).unwrap().as_number(), expected));
+        let values = pipeline.evaluate_all(&inputs).unwrap();
+        assert_eq!(values["debug"].as_number(), 6.0);
+        assert_eq!(values["scaled"].iterate().as_slice(), &[2.0, 4.0, 6.0]);
+
+        let mut missing = InputVars::new();
+        missing.insert_owned("v".into(), vec![1.0]);
+        assert!(matches!(pipeline.evaluate(&missing), Err(PipelineError::Evaluation { stage, .. }) if stage == "scaled"));
+    }
+
+    #[test]
+    fn test_pipeline_errors() {
+        let missing = Pipeline::new(&["a"], &[("b", "a + 1"), ("c", "b * d")]).err().unwrap();
+        assert!(matches!(missing, PipelineError::MissingVariable { stage, variable } if stage == "c" && variable == "d"));
+        let later = Pipeline::new(&["a"], &[("b", "c + 1"), ("c", "a * 2")]).err().unwrap();
+        assert!(matches!(later, PipelineError::LaterStage { stage, variable } if stage == "b" && variable == "c"));
+        let recursive = Pipeline::new(&["a"], &[("b", "b + a")]).err().unwrap();
+        assert!(matches!(recursive, PipelineError::LaterStage { .. }));
+        assert!(matches!(Pipeline::new(&["a"], &[("a", "1")]), Err(PipelineError::DuplicateName(name)) if name == "a"));
+        assert!(matches!(Pipeline::new(&["a"], &[("b", "a +")]), Err(PipelineError::InvalidExpression { .. })));
+        assert!(matches!(Pipeline::new(&["a"], &[]), Err(PipelineError::Empty)));
+    }
+}
@@ -0,0 +1,93 @@
+use crate::Float;
+use super::{DynMath, EvaluationError, Category};
+
+
+/// `polyval(coeffs, x)`: the polynomial `coeffs[0] * x^n + ... + coeffs[n]`
+/// (highest power first) at `x`, elementwise for arrays
+pub fn dynmath_polyval(coeffs: &dyn DynMath, x: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
+{
+    let coeffs: Vec<Float> = match coeffs.category() {
+        Category::Number => vec![coeffs.as_number()],
+        Category::Array => coeffs.iterate().copied().collect(),
+        Category::Unqiue => return invalid_args("polyval", format!("the coefficients should be an array, not `{}`", coeffs.type_name()))
+    };
+    let horner = |x: Float| coeffs.iter().fold(0.0, |acc, c| acc * x + c);
+    match x.category() {
+        Category::Number => Ok(Box::new(horner(x.as_number()))),
+        Category::Array => Ok(Box::new(x.iterate().map(|x| horner(*x)).collect::<Vec<Float>>())),
+        Category::Unqiue => invalid_args("polyval", format!("expects a number or an array, not `{}`", x.type_name()))
+    }
+}
+
+/// `polyfit(x, y, deg)`: coefficients of the least squares polynomial of degree
+/// `deg` through the points `(x, y)`, highest power first (as `polyval()` expects)
+#[cfg(feature = "polyfit")]
+pub fn dynmath_polyfit(x: &dyn DynMath, y: &dyn DynMath, deg: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
+{
+    let (x, y) = match (x.category(), y.category()) {
+        (Category::Array, Category::Array) if x.shape() == y.shape() => (x.iterate().as_slice(), y.iterate().as_slice()),
+        _ => return invalid_args("polyfit", "expects two arrays of the same length".into())
+    };
+    let deg = match deg.category() {
+        Category::Number if deg.as_number() >= 0.0 && deg.as_number().fract() == 0.0 => deg.as_number() as usize,
+        _ => return invalid_args("polyfit", "the degree should be a non-negative integer".into())
+    };
+    if x.len() <= deg {
+        return invalid_args("polyfit", format!("a polynomial of degree {} needs at least {} points", deg, deg + 1));
+    }
+
+    // normal equations `A^T A c = A^T y` of the Vandermonde matrix `A`, lowest power first
+    let n = deg + 1;
+    let mut power_sums = vec![0.0; 2 * n - 1];
+    let mut rhs = vec![0.0; n];
+    for (x, y) in x.iter().zip(y) {
+        let mut p = 1.0;
+        for k in 0..2 * n - 1 {
+            power_sums[k] += p;
+            if k < n {
+                rhs[k] += p * y;
+            }
+            p *= x;
+        }
+    }
+    let mut matrix: Vec<Vec<Float>> = (0..n).map(|i| power_sums[i..i + n].to_vec()).collect();
+    let mut coeffs = match solve_linear(&mut matrix, &mut rhs) {
+        Some(coeffs) => coeffs,
+        None => return invalid_args("polyfit", "the points do not determine the polynomial".into())
+    };
+    coeffs.reverse();
+    Ok(Box::new(coeffs))
+}
+
+/// Gaussian elimination with partial pivoting, `None` for singular matrices
+#[cfg(feature = "polyfit")]
+fn solve_linear(a: &mut [Vec<Float>], b: &mut [Float]) -> Option<Vec<Float>> {
+    let n = b.len();
+    let scale = a.iter().flatten().fold(0.0, |max: Float, x| max.max(x.abs()));
+    for col in 0..n {
+        let pivot = (col..n).max_by(|i, j| a[*i][col].abs().total_cmp(&a[*j][col].abs()))?;
+        if a[pivot][col].abs() <= scale * Float::EPSILON * n as Float {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        for row in col + 1..n {
+            let factor = a[row][col] / a[col][col];
+            let (upper, lower) = a.split_at_mut(row);
+            for (x, p) in lower[0][col..].iter_mut().zip(&upper[col][col..]) {
+                *x -= factor * p;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum: Float = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+fn invalid_args(function: &str, details: String) -> Result<Box<dyn DynMath>, EvaluationError> {
+    Err(EvaluationError::InvalidArguments { function: function.into(), details })
+}
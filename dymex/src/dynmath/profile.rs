@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+use crate::TokenContext;
+
+
+/// Accumulated evaluation time of a single operation
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeProfile {
+    /// The operator, function or field access
+    pub operation: String,
+    /// Position of the operation in the expression
+    pub at: usize,
+    pub calls: u64,
+    pub total: Duration,
+}
+
+impl NodeProfile {
+    pub fn mean(&self) -> Duration {
+        if self.calls == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(self.total.as_secs_f64() / self.calls as f64)
+    }
+}
+
+/// Time spent in the operations of an expression over many evaluations, see
+/// `Evaluator::enable_profiling()`. The time of an operation does not include
+/// the evaluation of its arguments. Operations are identified by their position
+/// in the expression, like the nodes of the AST.
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    nodes: HashMap<usize, NodeProfile>,
+    evaluations: u64,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of `evaluate()` calls
+    pub fn evaluations(&self) -> u64 {
+        self.evaluations
+    }
+
+    /// Time spent in every operation
+    pub fn total(&self) -> Duration {
+        self.nodes.values().map(|n| n.total).sum()
+    }
+
+    /// Profile of the operation at position `at` in the expression
+    pub fn get(&self, at: usize) -> Option<&NodeProfile> {
+        self.nodes.get(&at)
+    }
+
+    /// Every profiled operation, the most expensive first
+    pub fn nodes(&self) -> Vec<&NodeProfile> {
+        let mut nodes: Vec<&NodeProfile> = self.nodes.values().collect();
+        nodes.sort_by(|a, b| b.total.cmp(&a.total).then(a.at.cmp(&b.at)));
+        nodes
+    }
+
+    /// Short summary of the timings for every operation, keyed by position
+    /// (e.g. for `MermaidGraph::with_annotations()`)
+    pub fn annotations(&self) -> HashMap<usize, String> {
+        let total = self.total().as_secs_f64().max(f64::MIN_POSITIVE);
+        self.nodes.iter().map(|(at, n)| {
+            let share = 100.0 * n.total.as_secs_f64() / total;
+            (*at, format!("{} calls, {:.1?} ({:.0}%)", n.calls, n.total, share))
+        }).collect()
+    }
+
+    pub(crate) fn record(&mut self, op: &TokenContext, elapsed: Duration) {
+        let node = self.nodes.entry(op.at).or_insert_with(|| NodeProfile {
+            operation: op.token.to_string(),
+            at: op.at,
+            calls: 0,
+            total: Duration::ZERO,
+        });
+        node.calls += 1;
+        node.total += elapsed;
+    }
+
+    pub(crate) fn count_evaluation(&mut self) {
+        self.evaluations += 1;
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total = self.total().as_secs_f64().max(f64::MIN_POSITIVE);
+        writeln!(f, "{} evaluations, {:.1?} in total", self.evaluations, self.total())?;
+        writeln!(f, "{:>5}  {:<12} {:>8} {:>12} {:>12} {:>6}", "at", "operation", "calls", "total", "mean", "%")?;
+        for n in self.nodes() {
+            let share = 100.0 * n.total.as_secs_f64() / total;
+            writeln!(f, "{:>5}  {:<12} {:>8} {:>12} {:>12} {:>6.1}", n.at, n.operation, n.calls,
+                format!("{:.1?}", n.total), format!("{:.1?}", n.mean()), share)?;
+        }
+        Ok(())
+    }
+}
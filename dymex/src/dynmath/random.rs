@@ -0,0 +1,85 @@
+use crate::Float;
+
+
+/// Pseudorandom generator of the stochastic functions, part of the evaluation
+/// context (see `Evaluator::set_seed()`), so Monte Carlo evaluations are
+/// reproducible. There are no stochastic built in functions yet, the planned
+/// `rand()` and `randn()` draw from it. The algorithm is fixed, the same seed
+/// gives the same numbers in every version on every platform:
+/// - the state is four `u64` words, filled by SplitMix64 from the seed
+/// - the numbers are generated by xoshiro256** (Blackman and Vigna, 2018)
+/// - uniform numbers in `[0, 1)` are the upper 53 bits (24 bits with
+///   `single_precision`) of the next `u64`, divided by `2^53`
+/// - normally distributed numbers use the Box–Muller transform of two uniform
+///   numbers. `ln()`, `cos()` are not correctly rounded by every platform's libm,
+///   so these may differ in the last bits between platforms.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeededRng {
+    state: [u64; 4],
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        let mut x = seed;
+        let mut splitmix = || {
+            x = x.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = x;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^ (z >> 31)
+        };
+        Self { state: [splitmix(), splitmix(), splitmix(), splitmix()] }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+
+    /// Uniformly distributed number in `[0, 1)`
+    pub fn uniform(&mut self) -> Float {
+        let bits = Float::MANTISSA_DIGITS;
+        (self.next_u64() >> (64 - bits)) as Float / (1u64 << bits) as Float
+    }
+
+    /// Number of the standard normal distribution
+    pub fn normal(&mut self) -> Float {
+        // 1 - u is in (0, 1], its logarithm is finite
+        let u = 1.0 - self.uniform();
+        let v = self.uniform();
+        (-2.0 * u.ln()).sqrt() * (crate::float::consts::TAU * v).cos()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_seeded_rng() {
+        let mut rng = SeededRng::new(42);
+        let first: Vec<u64> = (0..3).map(|_| rng.next_u64()).collect();
+        // reference values of xoshiro256** seeded by SplitMix64
+        assert_eq!(first, [1546998764402558742, 6990951692964543102, 12544586762248559009]);
+        assert_eq!(SeededRng::new(42).next_u64(), first[0]);
+        assert_ne!(SeededRng::new(43).next_u64(), first[0]);
+
+        let mut rng = SeededRng::new(7);
+        let uniform: Vec<Float> = (0..10_000).map(|_| rng.uniform()).collect();
+        assert!(uniform.iter().all(|x| (0.0..1.0).contains(x)));
+        assert!((uniform.iter().sum::<Float>() / 10_000.0 - 0.5).abs() < 0.01);
+        let normal: Vec<Float> = (0..10_000).map(|_| rng.normal()).collect();
+        let mean = normal.iter().sum::<Float>() / 10_000.0;
+        let variance = normal.iter().map(|x| (x - mean) * (x - mean)).sum::<Float>() / 10_000.0;
+        assert!(mean.abs() < 0.05 && (variance - 1.0).abs() < 0.05);
+    }
+}
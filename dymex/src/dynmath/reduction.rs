@@ -0,0 +1,526 @@
+use std::borrow::Cow;
+use crate::{Float, Function, float};
+use super::{Category, DynMath, EvaluationError, LazyArray, TimeSeries};
+
+
+/// The reductions of `DynMath::reduce()`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reduction {
+    Min,
+    Max,
+    Avg,
+    Std,
+    Sum,
+    Prod,
+    Range,
+}
+
+impl Reduction {
+    pub const ALL: [Reduction; 7] = [Self::Min, Self::Max, Self::Avg, Self::Std, Self::Sum, Self::Prod, Self::Range];
+
+    /// The reduction computed by `function`, if it is one
+    pub fn from_function(function: &Function) -> Option<Self> {
+        match function {
+            Function::Min => Some(Self::Min),
+            Function::Max => Some(Self::Max),
+            Function::Avg => Some(Self::Avg),
+            Function::Std => Some(Self::Std),
+            Function::Sum => Some(Self::Sum),
+            Function::Prod => Some(Self::Prod),
+            Function::Range => Some(Self::Range),
+            _ => None
+        }
+    }
+
+    /// Name of the function of the reduction in expressions
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Min => "min",
+            Self::Max => "max",
+            Self::Avg => "avg",
+            Self::Std => "std",
+            Self::Sum => "sum",
+            Self::Prod => "prod",
+            Self::Range => "range",
+        }
+    }
+
+    /// The reduction of every element of `x`, by the scalar reductions of `DynMath` (`min()`, `sum()`, ...)
+    pub fn apply<T: DynMath + ?Sized>(&self, x: &T) -> Result<Float, EvaluationError> {
+        match self {
+            Self::Min => x.min(),
+            Self::Max => x.max(),
+            Self::Avg => x.avg(),
+            Self::Std => x.std(),
+            Self::Sum => x.sum(),
+            Self::Prod => x.prod(),
+            Self::Range => x.range(),
+        }
+    }
+}
+
+/// The elements reduced by `DynMath::reduce()`, numpy's `axis`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Axis {
+    /// Every element, the result is a number
+    #[default]
+    All,
+    /// Along the axis, which is removed from the shape: along `0` the rows of a
+    /// matrix are reduced to one row
+    Along(usize),
+}
+
+/// Result of the reductions (`sum`, `min`, `avg`, ...) of an empty array,
+/// for example of an empty measurement window, see `Evaluator::set_empty_reduction()`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum EmptyReduction {
+    /// The evaluation fails with `EvaluationError::EmptyReduction`
+    #[default]
+    Error,
+    /// The identity of the reduction: `0` for `sum`, `1` for `prod`, `inf`
+    /// for `min` and `-inf` for `max`. The others have no identity, they give NaN.
+    Identity,
+    /// NaN for every reduction
+    NaN,
+}
+
+impl EmptyReduction {
+    /// Result of the reduction `function` of an empty array
+    pub fn result(&self, function: &Function) -> Result<Float, EvaluationError> {
+        match (self, function) {
+            (Self::Error, _) => Err(EvaluationError::EmptyReduction { function: function.to_string() }),
+            (Self::Identity, Function::Sum) => Ok(0.0),
+            (Self::Identity, Function::Prod) => Ok(1.0),
+            (Self::Identity, Function::Min) => Ok(float::INFINITY),
+            (Self::Identity, Function::Max) => Ok(float::NEG_INFINITY),
+            (Self::Identity | Self::NaN, _) => Ok(float::NAN),
+        }
+    }
+}
+
+/// Algorithm of the sums in `sum`, `avg` and `std` of arrays, see `Evaluator::set_summation()`.
+/// The naive sum loses precision on long arrays and on terms of mixed magnitude.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Summation {
+    /// Adding the elements one by one, the error grows with the length of the array
+    #[default]
+    Naive,
+    /// Compensated summation (Kahan–Babuška, in Neumaier's variant): the
+    /// rounding error of every addition is carried in a second sum. Nearly
+    /// exact, also if large terms cancel out, about 4 times slower than `Naive`.
+    Kahan,
+    /// Summing the halves of the array recursively, the error grows with the
+    /// logarithm of the length. About as fast as `Naive`.
+    Pairwise,
+}
+
+impl Summation {
+    /// Sum of `values`
+    pub fn sum(&self, values: &[Float]) -> Float {
+        match self {
+            Self::Naive => values.iter().sum(),
+            Self::Kahan => {
+                let mut sum = 0.0;
+                let mut compensation = 0.0;
+                for &x in values {
+                    let t = sum + x;
+                    compensation += match sum.abs() >= x.abs() {
+                        true => (sum - t) + x,
+                        false => (x - t) + sum,
+                    };
+                    sum = t;
+                }
+                sum + compensation
+            }
+            Self::Pairwise => match values.len() {
+                // blocks are summed naively, as numpy does
+                0..=128 => values.iter().sum(),
+                n => self.sum(&values[..n / 2]) + self.sum(&values[n / 2..])
+            }
+        }
+    }
+
+    /// The reduction `function` of the array `values` with this summation,
+    /// `None` if the reduction has no sum
+    pub(crate) fn reduce(&self, function: &Function, values: &[Float]) -> Option<Float> {
+        let n = values.len() as Float;
+        match function {
+            Function::Sum => Some(self.sum(values)),
+            Function::Avg => Some(self.sum(values) / n),
+            Function::Std => {
+                let avg = self.sum(values) / n;
+                let sq_err: Vec<Float> = values.iter().map(|x| (x - avg) * (x - avg)).collect();
+                Some((self.sum(&sq_err) / n).sqrt())
+            }
+            _ => None
+        }
+    }
+}
+
+/// Running mean and variance (Welford's algorithm), for data that arrives in
+/// chunks or as a stream: every value is added once, nothing is kept.
+/// ```
+/// # use dymex::*;
+/// let mut stats = Welford::new();
+/// stats.extend([2.0, 4.0, 4.0, 4.0]);
+/// let mut chunk = Welford::new();
+/// chunk.extend([5.0, 5.0, 7.0, 9.0]);
+/// stats.merge(&chunk);
+/// assert_eq!(stats.mean(), 5.0);
+/// assert_eq!(stats.variance(0), 4.0);
+/// assert_eq!(stats.variance(1), 32.0 / 7.0);
+/// ```
+/// More stable than the sum of squares when the mean is large compared to the
+/// deviations, as with timestamps or sensor readings with an offset.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Welford {
+    count: u64,
+    mean: Float,
+    /// Sum of the squared deviations from the mean
+    m2: Float,
+}
+
+impl Welford {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, x: Float) {
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as Float;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    pub fn extend(&mut self, values: impl IntoIterator<Item = Float>) {
+        values.into_iter().for_each(|x| self.push(x));
+    }
+
+    /// Add the values of `other`, the statistics of another chunk (Chan et al.)
+    pub fn merge(&mut self, other: &Welford) {
+        if other.count == 0 {
+            return;
+        }
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        self.mean += delta * (other.count as Float / count as Float);
+        self.m2 += other.m2 + delta * delta * (self.count as Float * other.count as Float / count as Float);
+        self.count = count;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// NaN without values
+    pub fn mean(&self) -> Float {
+        match self.count {
+            0 => float::NAN,
+            _ => self.mean
+        }
+    }
+
+    /// Variance with `ddof` delta degrees of freedom: the sum of the squared
+    /// deviations is divided by `count - ddof`. `0` gives the variance of the
+    /// population, `1` the unbiased estimate from a sample. NaN if there are
+    /// not more values than `ddof`.
+    pub fn variance(&self, ddof: u32) -> Float {
+        match self.count.checked_sub(ddof as u64) {
+            Some(n) if n > 0 => self.m2 / n as Float,
+            _ => float::NAN
+        }
+    }
+
+    /// Standard deviation with `ddof` delta degrees of freedom, see `variance()`
+    pub fn std(&self, ddof: u32) -> Float {
+        self.variance(ddof).sqrt()
+    }
+}
+
+/// `std()` of `args` with `ddof` delta degrees of freedom: of the elements of
+/// an array or of numbers
+pub(crate) fn std_with_ddof(args: &[&dyn DynMath], ddof: u32) -> Result<Float, EvaluationError> {
+    let mut stats = Welford::new();
+    match args {
+        [arg] if arg.array_len().is_some() => stats.extend(float_values(*arg, &Function::Std)?.iter().copied()),
+        _ if args.iter().all(|x| matches!(x.category(), Category::Number)) => stats.extend(args.iter().map(|x| x.as_number())),
+        _ => return Err(EvaluationError::InvalidArguments {
+            function: "std".into(),
+            details: "the standard deviation with `ddof` is of an array or of numbers".into()
+        })
+    }
+    Ok(stats.std(ddof))
+}
+
+/// The elements of an array of numbers (see `DynMath::array_len()`), for the
+/// reductions `function` computed here, like `Summation` and `Welford`
+pub(crate) fn float_values<'a>(arg: &'a dyn DynMath, function: &Function) -> Result<Cow<'a, [Float]>, EvaluationError> {
+    if matches!(arg.category(), Category::Array) {
+        return Ok(Cow::Borrowed(arg.iterate().as_slice()));
+    }
+    if let Some(ts) = arg.downcast_ref::<TimeSeries>() {
+        return Ok(Cow::Borrowed(ts.values()));
+    }
+    if let Some(lazy) = arg.downcast_ref::<LazyArray>() {
+        return Ok(Cow::Owned(lazy.materialize()));
+    }
+    // the missing values are skipped, as by the reductions of the type
+    if let Some(v) = arg.downcast_ref::<Vec<Option<Float>>>() {
+        return Ok(Cow::Owned(v.iter().flatten().copied().collect()));
+    }
+    #[cfg(feature = "hdf5")]
+    if let Some(dataset) = arg.downcast_ref::<super::Hdf5Dataset>() {
+        return dataset.values().map(|v| Cow::Borrowed(v.as_slice()));
+    }
+    Err(EvaluationError::InvalidArguments {
+        function: function.to_string(),
+        details: format!("the elements of `{}` are not accessible", arg.type_name())
+    })
+}
+
+/// True if the arguments of a reduction are a single empty array, see `DynMath::array_len()`
+pub(crate) fn is_empty_array(args: &[&dyn DynMath]) -> bool {
+    match args {
+        [arg] => arg.array_len() == Some(0),
+        _ => false
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn evaluate(expression: &str, empty: EmptyReduction) -> Result<Box<dyn DynMath>, EvaluationError> {
+        let mut inputs = InputVars::new();
+        inputs.insert_owned("window".into(), Vec::<Float>::new());
+        inputs.insert_owned("x".into(), vec![1.0, 2.0]);
+        inputs.insert_owned("series".into(), TimeSeries::new(vec![], vec![]));
+        inputs.insert_owned("lazy".into(), LazyArray::from_fn(0, |i| i as Float));
+        inputs.insert_owned("gaps".into(), Vec::<Option<Float>>::new());
+        let mut evaluator = Evaluator::new(expression, &["window", "x", "series", "lazy", "gaps"]).unwrap();
+        evaluator.set_empty_reduction(empty);
+        evaluator.evaluate(&inputs)
+    }
+
+    #[test]
+    fn test_empty_reductions() {
+        for f in ["sum", "prod", "min", "max", "range", "avg", "std"] {
+            for array in ["window", "series", "lazy", "gaps"] {
+                let err = evaluate(&format!("{}({})", f, array), EmptyReduction::Error).err().unwrap();
+                assert_eq!(err.code(), "E214");
+                assert!(evaluate(&format!("{}({})", f, array), EmptyReduction::NaN).unwrap().as_number().is_nan());
+            }
+        }
+        let identity = |f: &str| evaluate(&format!("{}(window)", f), EmptyReduction::Identity).unwrap().as_number();
+        assert_eq!(identity("sum"), 0.0);
+        assert_eq!(identity("prod"), 1.0);
+        assert_eq!(identity("min"), float::INFINITY);
+        assert_eq!(identity("max"), float::NEG_INFINITY);
+        assert!(identity("avg").is_nan() && identity("std").is_nan() && identity("range").is_nan());
+
+        // arrays with elements and several arguments are not affected
+        assert_eq!(evaluate("sum(x) + max(1, 2)", EmptyReduction::Error).unwrap().as_number(), 5.0);
+        // elementwise operations of empty arrays are not reductions
+        assert!(evaluate("window * 2", EmptyReduction::Error).is_ok());
+    }
+
+    #[test]
+    fn test_summation_accuracy() {
+        // 0.1 is 0.1000000000000000055511151231257827 as a double, a million
+        // of them add up to 100000.0000000000055511151231257827, 100000 rounded
+        let tenths = vec![0.1; 1_000_000];
+        let error = |summation: Summation| (summation.sum(&tenths) - 100_000.0).abs();
+        assert!(error(Summation::Naive) > 1e-6);
+        assert_eq!(error(Summation::Kahan), 0.0);
+        assert!(error(Summation::Pairwise) < 1e-9);
+
+        // large terms cancelling out, the exact sum is 4
+        let mixed = [1.0, 1e100, 1.0, -1e100, 2.0];
+        assert_eq!(Summation::Naive.sum(&mixed), 2.0);
+        assert_eq!(Summation::Kahan.sum(&mixed), 4.0);
+        assert_eq!(Summation::Kahan.sum(&[]), 0.0);
+
+        // in the expressions, for sum, avg and std
+        let mut inputs = InputVars::new();
+        inputs.insert_owned("v".into(), tenths);
+        inputs.insert_owned("t".into(), TimeSeries::new(vec![0.0, 1.0, 2.0], vec![1e100, 1.0, -1e100]));
+        let evaluate = |expression: &str, summation: Summation| {
+            let mut evaluator = Evaluator::new(expression, &["v", "t"]).unwrap();
+            evaluator.set_summation(summation);
+            evaluator.evaluate(&inputs).unwrap().as_number()
+        };
+        assert_eq!(evaluate("sum(v)", Summation::Kahan), 100_000.0);
+        assert_eq!(evaluate("avg(v)", Summation::Kahan), 0.1);
+        assert_eq!(evaluate("std(v)", Summation::Kahan), 0.0);
+        assert_eq!(evaluate("sum(t)", Summation::Kahan), 1.0);
+        assert_eq!(evaluate("sum(t)", Summation::Naive), 0.0);
+        assert_ne!(evaluate("avg(v)", Summation::Naive), 0.1);
+    }
+
+    /// Row-major matrix, to check that the reductions along an axis broadcast
+    #[derive(Debug, Clone, PartialEq)]
+    struct Matrix {
+        rows: usize,
+        data: Vec<Float>,
+    }
+
+    impl Matrix {
+        fn cols(&self) -> usize {
+            self.data.len() / self.rows
+        }
+    }
+
+    impl DynMath for Matrix {
+        fn clone_boxed(&self) -> Box<dyn DynMath> { Box::new(self.clone()) }
+        fn category(&self) -> Category { Category::Array }
+        fn shape(&self) -> [usize; MAXDIM] { [self.rows, self.cols(), 0] }
+        fn as_any(&self) -> &dyn std::any::Any { self }
+        fn iterate(&self) -> std::slice::Iter<'_, Float> { self.data.iter() }
+        fn avg(&self) -> Result<Float, EvaluationError> { DynMath::avg(&self.data) }
+
+        fn reduce(&self, reduction: Reduction, axis: Axis, keepdims: bool) -> Result<Box<dyn DynMath>, EvaluationError> {
+            let (rows, cols) = (self.rows, self.cols());
+            let (lanes, reduced): (Vec<Vec<Float>>, Matrix) = match axis {
+                Axis::All => return reduction.apply(self).map(|x| Box::new(x) as Box<dyn DynMath>),
+                Axis::Along(0) => ((0..cols).map(|c| (0..rows).map(|r| self.data[r * cols + c]).collect()).collect(),
+                    Matrix { rows: 1, data: vec![] }),
+                Axis::Along(1) => (self.data.chunks(cols).map(<[Float]>::to_vec).collect(),
+                    Matrix { rows, data: vec![] }),
+                Axis::Along(_) => return Err(EvaluationError::InvalidOperation { info: "axis out of range".into() })
+            };
+            let data = lanes.iter().map(|lane| reduction.apply(lane)).collect::<Result<Vec<Float>, _>>()?;
+            match keepdims {
+                true => Ok(Box::new(Matrix { data, ..reduced })),
+                false => Ok(Box::new(data))
+            }
+        }
+
+        // broadcasts the axes of length 1, an array is a row
+        fn sub(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+            let other = match other.downcast_ref::<Vec<Float>>() {
+                Some(row) => &Matrix { rows: 1, data: row.clone() },
+                None => other.downcast_ref::<Matrix>().unwrap()
+            };
+            let cols = self.cols();
+            let data = (0..self.data.len()).map(|i| {
+                let (r, c) = (i / cols, i % cols);
+                let (r, c) = (r.min(other.rows - 1), c.min(other.cols() - 1));
+                self.data[i] - other.data[r * other.cols() + c]
+            }).collect();
+            Ok(Box::new(Matrix { rows: self.rows, data }))
+        }
+    }
+
+    #[test]
+    fn test_reduce_along_axis() {
+        let v = vec![1.0, 4.0, 2.0];
+        let reduce = |x: &dyn DynMath, reduction, axis, keepdims| x.reduce(reduction, axis, keepdims).unwrap().as_number();
+        for reduction in Reduction::ALL {
+            let expected = reduction.apply(&v).unwrap();
+            assert_eq!(reduce(&v, reduction, Axis::All, false), expected);
+            assert_eq!(reduce(&v, reduction, Axis::Along(0), true), expected);
+            let function = Function::from_name(reduction.name()).unwrap();
+            assert_eq!(Reduction::from_function(&function), Some(reduction));
+        }
+        assert_eq!(reduce(&2.5, Reduction::Sum, Axis::All, false), 2.5);
+        assert_eq!(v.ndim(), 1);
+        assert_eq!(2.5.ndim(), 0);
+        let err = v.reduce(Reduction::Sum, Axis::Along(1), false).err().unwrap();
+        assert!(matches!(err, EvaluationError::InvalidArguments { .. }));
+        assert!(2.5.reduce(Reduction::Sum, Axis::Along(0), false).is_err());
+
+        // the columns of a 2 x 3 matrix centered
+        let m = Matrix { rows: 2, data: vec![1.0, 2.0, 3.0, 3.0, 6.0, 9.0] };
+        assert_eq!(m.ndim(), 2);
+        let means = m.reduce(Reduction::Avg, Axis::Along(0), true).unwrap();
+        assert_eq!(means.shape(), [1, 3, 0]);
+        let centered = m.sub(&*means).unwrap();
+        assert_eq!(centered.downcast_ref::<Matrix>().unwrap().data, [-1.0, -2.0, -3.0, 1.0, 2.0, 3.0]);
+        let row_means = m.reduce(Reduction::Avg, Axis::Along(1), false).unwrap();
+        assert_eq!(row_means.downcast_ref::<Vec<Float>>(), Some(&vec![2.0, 6.0]));
+        assert_eq!(m.reduce(Reduction::Avg, Axis::All, false).unwrap().as_number(), 4.0);
+    }
+
+    #[test]
+    fn test_evaluate_along_axis() {
+        let mut inputs = InputVars::new();
+        inputs.insert_owned("m".into(), Matrix { rows: 2, data: vec![1.0, 2.0, 3.0, 3.0, 6.0, 9.0] });
+        inputs.insert_owned("v".into(), vec![1.0, 4.0, 2.0]);
+        let evaluate = |expression: &str| Evaluator::new(expression, &["m", "v"]).unwrap().evaluate(&inputs);
+        let centered = [-1.0, -2.0, -3.0, 1.0, 2.0, 3.0];
+        for expression in ["m - avg(m, axis=0)", "m - avg(m, axis = 0, keepdims = 1)"] {
+            let result = evaluate(expression).unwrap();
+            assert_eq!(result.downcast_ref::<Matrix>().unwrap().data, centered, "{}", expression);
+        }
+        let sums = evaluate("sum(m, axis = 1)").unwrap();
+        assert_eq!(sums.downcast_ref::<Vec<Float>>(), Some(&vec![6.0, 18.0]));
+        assert_eq!(evaluate("avg(m, axis = 0 + 0 * 1, keepdims = 0)").unwrap().shape(), [3, 0, 0]);
+        assert_eq!(evaluate("max(v, axis = 0)").unwrap().as_number(), 4.0);
+        assert_eq!(evaluate("range(v, keepdims = 1)").unwrap().as_number(), 3.0);
+        for expression in ["sum(v, axis = 1)", "sum(v, axis = -1)", "sum(v, axis = 0.5)", "sum(v, v, axis = 0)"] {
+            let err = evaluate(expression).err().unwrap();
+            assert!(matches!(err, EvaluationError::InvalidArguments { .. }), "{}", expression);
+        }
+    }
+
+    #[test]
+    fn test_welford() {
+        let values = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        let mut stats = Welford::new();
+        stats.extend(values);
+        assert_eq!((stats.count(), stats.mean(), stats.std(0)), (8, 5.0, 2.0));
+        assert_eq!(stats.variance(1), 32.0 / 7.0);
+        assert_eq!(DynMath::std(&values.to_vec()).unwrap(), stats.std(0));
+
+        // chunks give the same statistics as the whole
+        let mut chunked = Welford::new();
+        for chunk in values.chunks(3) {
+            let mut part = Welford::new();
+            part.extend(chunk.iter().copied());
+            chunked.merge(&part);
+        }
+        assert!((chunked.variance(1) - stats.variance(1)).abs() < 1e-12);
+        chunked.merge(&Welford::new());
+        assert_eq!(chunked.count(), 8);
+
+        // a large offset, the sum of squares would lose every digit
+        let mut offset = Welford::new();
+        offset.extend([4.0, 7.0, 13.0, 16.0].map(|x| x + 1e9));
+        assert_eq!(offset.variance(1), 30.0);
+        assert!(Welford::new().mean().is_nan());
+        assert!(offset.variance(4).is_nan() && offset.variance(5).is_nan());
+
+        // the sample standard deviation in the expressions
+        let mut inputs = InputVars::new();
+        inputs.insert_owned("v".into(), values.to_vec());
+        let std = |expression: &str, ddof: u32| {
+            let mut evaluator = Evaluator::new(expression, &["v"]).unwrap();
+            evaluator.set_ddof(ddof);
+            evaluator.evaluate(&inputs).unwrap().as_number()
+        };
+        assert_eq!(std("std(v)", 0), 2.0);
+        assert_eq!(std("std(v)", 1), (32.0 as Float / 7.0).sqrt());
+        assert_eq!(std("std(1, 3)", 1), (2.0 as Float).sqrt());
+        assert!(std("std(3)", 1).is_nan());
+        assert_eq!(std("std(v, ddof = 1)", 0), (32.0 as Float / 7.0).sqrt());
+        assert_eq!(std("std(v, ddof = 0)", 1), 2.0);
+
+        // every array type, also the ones without a slice of their elements
+        let sample = (32.0 as Float / 7.0).sqrt();
+        let lazy = values.to_vec();
+        inputs.insert_owned("lazy".into(), LazyArray::from_fn(values.len(), move |i| lazy[i]));
+        inputs.insert_owned("series".into(), TimeSeries::new((0..values.len()).map(|t| t as Float).collect(), values.to_vec()));
+        inputs.insert_owned("gaps".into(), values.iter().copied().map(Some).chain([None]).collect::<Vec<Option<Float>>>());
+        let mut evaluator = Evaluator::new("(std(lazy), std(series), std(gaps), sum(lazy), avg(gaps))", &["lazy", "series", "gaps"]).unwrap();
+        evaluator.set_missing_policy(MissingPolicy::Skip);
+        evaluator.set_ddof(1);
+        evaluator.set_summation(Summation::Kahan);
+        let result = evaluator.evaluate(&inputs).unwrap();
+        let result = result.downcast_ref::<Tuple>().unwrap();
+        let numbers: Vec<Float> = (0..5).map(|i| result.get(i).unwrap().as_number()).collect();
+        assert_eq!(numbers, [sample, sample, sample, 40.0, 5.0]);
+        let err = Evaluator::new("std(v, axis = 0, ddof = 1)", &["v"]).unwrap().evaluate(&inputs).err().unwrap();
+        assert!(matches!(err, EvaluationError::InvalidArguments { .. }));
+    }
+}
@@ -0,0 +1,72 @@
+use crate::Float;
+use super::{DynMath, EvaluationError, Category, Missing};
+
+
+/// Rounding mode of `round_to()`, `floor_to()`, `ceil_to()` and `round_even()`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rounding {
+    /// To the nearest multiple, halves away from zero (`round_to`)
+    HalfAwayFromZero,
+    /// To the nearest multiple, halves to the even multiple (`round_even`, banker's rounding)
+    HalfEven,
+    /// To the multiple below (`floor_to`)
+    Down,
+    /// To the multiple above (`ceil_to`)
+    Up,
+}
+
+/// `round_to(x, increment)` and the other rounding functions: `x` rounded to a
+/// multiple of `increment` (`0.05`, `100`, ...), elementwise for arrays.
+/// Quotients within a few ulps of a half or a whole multiple are treated as
+/// exact, so `round_to(2.675, 0.01)` is 2.68 even though 2.675 is stored as
+/// 2.67499999..., and the result is the closest `Float` to the decimal multiple.
+pub fn dynmath_round_to(x: &dyn DynMath, increment: &dyn DynMath, mode: Rounding, function: &str)
+-> Result<Box<dyn DynMath>, EvaluationError> {
+    let invalid = |details: String| EvaluationError::InvalidArguments { function: function.into(), details };
+    let increment = match increment.category() {
+        Category::Number if increment.as_number() > 0.0 && increment.as_number().is_finite() => increment.as_number(),
+        _ => return Err(invalid("the increment should be a positive number".into()))
+    };
+    let round = |x: Float| round_to(x, increment, mode);
+    match x.category() {
+        Category::Number => Ok(Box::new(round(x.as_number()))),
+        Category::Array => Ok(Box::new(x.iterate().map(|x| round(*x)).collect::<Vec<Float>>())),
+        Category::Unqiue => match x.downcast_ref::<Vec<Option<Float>>>() {
+            Some(v) => Ok(Box::new(v.iter().map(|x| x.map(round)).collect::<Vec<Option<Float>>>())),
+            None if x.is::<Missing>() => Ok(Box::new(Missing)),
+            None => Err(invalid(format!("expects a number or an array, not `{}`", x.type_name())))
+        }
+    }
+}
+
+fn round_to(x: Float, increment: Float, mode: Rounding) -> Float {
+    if !x.is_finite() {
+        return x;
+    }
+    // divide by `1 / increment` if it is an integer, `0.3 / 0.1` is not 3, `0.3 * 10` is
+    let inverse = (1.0 / increment).round();
+    let exact_inverse = inverse > 1.0 && (1.0 / increment - inverse).abs() <= tolerance(inverse);
+    let mut q = match exact_inverse {
+        true => x * inverse,
+        false => x / increment
+    };
+    let half = (2.0 * q).round() / 2.0;
+    if (q - half).abs() <= tolerance(q) {
+        q = half;
+    }
+    let q = match mode {
+        Rounding::HalfAwayFromZero => q.round(),
+        Rounding::HalfEven => q.round_ties_even(),
+        Rounding::Down => q.floor(),
+        Rounding::Up => q.ceil(),
+    };
+    match exact_inverse {
+        true => q / inverse,
+        false => q * increment
+    }
+}
+
+/// Difference of a few ulps around `x`
+fn tolerance(x: Float) -> Float {
+    8.0 * Float::EPSILON * x.abs().max(1.0)
+}
@@ -0,0 +1,226 @@
+use crate::{Float, MAXDIM, RelationalOperator};
+use super::{DynMath, ResultFormatter, EvaluationError, Category, Missing};
+use std::any::Any;
+use std::fmt;
+
+
+/// Tolerance of the comparisons of a `Scalar`: `a` and `b` are equal if
+/// `|a - b| <= max(absolute, relative * max(|a|, |b|))`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Tolerance {
+    pub absolute: Float,
+    pub relative: Float,
+}
+
+impl Tolerance {
+    pub fn absolute(epsilon: Float) -> Self {
+        Self { absolute: epsilon, relative: 0.0 }
+    }
+
+    pub fn relative(epsilon: Float) -> Self {
+        Self { absolute: 0.0, relative: epsilon }
+    }
+
+    /// The larger of the tolerances, for comparing two `Scalar`s
+    pub fn max(self, other: Self) -> Self {
+        Self { absolute: self.absolute.max(other.absolute), relative: self.relative.max(other.relative) }
+    }
+
+    pub fn is_close(&self, a: Float, b: Float) -> bool {
+        a == b || (a - b).abs() <= self.absolute.max(self.relative * a.abs().max(b.abs()))
+    }
+
+    /// `a op b`, where the close numbers are equal: `a < b` only if they are
+    /// not close, `a <= b` also if `a` is slightly larger. Without tolerance
+    /// (the default) the same as the comparison of numbers.
+    pub fn compare(&self, a: Float, b: Float, op: &RelationalOperator) -> bool {
+        match op {
+            RelationalOperator::Eq => self.is_close(a, b),
+            RelationalOperator::Neq => !self.is_close(a, b),
+            RelationalOperator::Lesser => a < b && !self.is_close(a, b),
+            RelationalOperator::Greater => a > b && !self.is_close(a, b),
+            RelationalOperator::Leq => a <= b || self.is_close(a, b),
+            RelationalOperator::Geq => a >= b || self.is_close(a, b),
+        }
+    }
+}
+
+/// Number compared with a tolerance in the expressions, since the exact
+/// equality of floating point results is rarely what a formula means:
+/// ```
+/// # use dymex::*;
+/// let mut inputs = InputVars::new();
+/// inputs.insert_owned("x".into(), Scalar::new(0.1, Tolerance::absolute(1e-9)));
+/// let mut evaluator = Evaluator::new("x * 3 == 0.3", &["x"]).unwrap();
+/// assert_eq!(evaluator.evaluate(&inputs).unwrap().downcast_ref::<bool>(), Some(&true));
+/// ```
+/// The arithmetic operators with numbers keep the tolerance (on either side),
+/// the functions (`sin(x)`) and operations with arrays give plain numbers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scalar {
+    pub value: Float,
+    pub tolerance: Tolerance,
+}
+
+impl Scalar {
+    pub fn new(value: Float, tolerance: Tolerance) -> Self {
+        Self { value, tolerance }
+    }
+
+    fn with_value(&self, value: Float) -> Box<dyn DynMath> {
+        Box::new(Self { value, tolerance: self.tolerance })
+    }
+}
+
+/// Tolerance of the comparisons with `value`, none unless it is a `Scalar`
+pub(super) fn tolerance_of(value: &dyn DynMath) -> Tolerance {
+    value.downcast_ref::<Scalar>().map(|s| s.tolerance).unwrap_or_default()
+}
+
+/// The result `x` of an operation of a number and `other`, a `Scalar` if `other` is one
+pub(super) fn with_tolerance_of(other: &dyn DynMath, x: Float) -> Box<dyn DynMath> {
+    match other.downcast_ref::<Scalar>() {
+        Some(scalar) => scalar.with_value(x),
+        None => Box::new(x)
+    }
+}
+
+impl DynMath for Scalar {
+
+    fn clone_boxed(&self) -> Box<dyn DynMath> { Box::new(*self) }
+
+    fn category(&self) -> Category { Category::Number }
+
+    fn shape(&self) -> [usize; MAXDIM] { [0; MAXDIM] }
+
+    fn type_name(&self) -> &'static str { "Scalar" }
+
+    fn as_number(&self) -> Float {
+        self.value
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn fmt_value(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", ResultFormatter::new().format_number(self.value))
+    }
+
+    fn add(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
+    {
+        match other.category() {
+            Category::Number => Ok(self.with_value(self.value + other.as_number())),
+            _ => self.value.add(other)
+        }
+    }
+
+    fn sub(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
+    {
+        match other.category() {
+            Category::Number => Ok(self.with_value(self.value - other.as_number())),
+            _ => self.value.sub(other)
+        }
+    }
+
+    fn mul(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
+    {
+        match other.category() {
+            Category::Number => Ok(self.with_value(self.value * other.as_number())),
+            _ => self.value.mul(other)
+        }
+    }
+
+    fn div(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
+    {
+        match other.category() {
+            Category::Number => Ok(self.with_value(self.value / other.as_number())),
+            _ => self.value.div(other)
+        }
+    }
+
+    fn pow(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
+    {
+        match other.category() {
+            Category::Number => Ok(self.with_value(self.value.powf(other.as_number()))),
+            _ => self.value.pow(other)
+        }
+    }
+
+    // `x ** 2` with a literal exponent is the operator, not a function
+    fn dyn_pow2(&self) -> Result<Box<dyn DynMath>, EvaluationError> {
+        Ok(self.with_value(self.value * self.value))
+    }
+    fn dyn_pow3(&self) -> Result<Box<dyn DynMath>, EvaluationError> {
+        Ok(self.with_value(self.value * self.value * self.value))
+    }
+    fn dyn_pow4(&self) -> Result<Box<dyn DynMath>, EvaluationError> {
+        Ok(self.with_value(self.value * self.value * self.value * self.value))
+    }
+
+    fn compare(&self, other: &dyn DynMath, op: &RelationalOperator) -> Result<Box<dyn DynMath>, EvaluationError>
+    {
+        match other.category() {
+            Category::Number => {
+                let tolerance = self.tolerance.max(tolerance_of(other));
+                Ok(Box::new(tolerance.compare(self.value, other.as_number(), op)))
+            }
+            Category::Array => other.compare(self, &op.reversed()),
+            _ if other.is::<Missing>() => Ok(Box::new(Missing)),
+            _ => self.value.compare(other, op)
+        }
+    }
+
+    fn min(&self) -> Result<Float, EvaluationError> { Ok(self.value) }
+    fn max(&self) -> Result<Float, EvaluationError> { Ok(self.value) }
+    fn range(&self) -> Result<Float, EvaluationError> { Ok(0.0) }
+    fn sum(&self) -> Result<Float, EvaluationError> { Ok(self.value) }
+    fn prod(&self) -> Result<Float, EvaluationError> { Ok(self.value) }
+    fn avg(&self) -> Result<Float, EvaluationError> { Ok(self.value) }
+    fn std(&self) -> Result<Float, EvaluationError> { Ok(0.0) }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn evaluate(expression: &str, x: Scalar) -> Box<dyn DynMath> {
+        let mut inputs = InputVars::new();
+        inputs.insert_owned("x".into(), x);
+        inputs.insert_owned("v".into(), vec![0.1, 0.2, 0.3]);
+        Evaluator::new(expression, &["x", "v"]).unwrap().evaluate(&inputs).unwrap()
+    }
+
+    fn is_true(expression: &str, x: Scalar) -> bool {
+        evaluate(expression, x).downcast_ref::<bool>() == Some(&true)
+    }
+
+    #[test]
+    fn test_tolerant_comparisons() {
+        let x = Scalar::new(0.1, Tolerance::absolute(1e-9));
+        assert!(is_true("x * 3 == 0.3", x));
+        assert!(is_true("0.3 == 3 * x", x));
+        assert!(is_true("0.1 + 0.2 == x * 3", x));
+        assert!(!is_true("x * 3 < 0.3", x));
+        assert!(is_true("x * 3 <= 0.3 and 0.3 >= x * 3", x));
+        assert!(is_true("x != 0.1000001", x));
+        assert!(!is_true("x * 3 == 0.3", Scalar::new(0.1, Tolerance::default())));
+        assert_eq!(evaluate("v * 2 == x * 4", x).downcast_ref::<Vec<bool>>(), Some(&vec![false, true, false]));
+        assert_eq!(evaluate("x * 3 - 0.3 <= 0", x).type_name(), "Bool");
+
+        let large = Scalar::new(1e12, Tolerance::relative(1e-9));
+        assert!(is_true("x + 1 == x", large));
+        assert!(is_true("x + 10000 > x", large));
+        // functions give plain numbers
+        assert_eq!(evaluate("x * 2", x).type_name(), "Scalar");
+        assert_eq!(evaluate("abs(x)", x).type_name(), "Number");
+        assert!(!is_true("abs(x) * 3 == 0.3", x));
+
+        // also with the literal exponents of the fast path
+        let s = Scalar::new(2.0 + 1e-12, Tolerance::absolute(1e-9));
+        assert!(is_true("x ** 2 == 4", s));
+        assert!(is_true("x ** 3 == 8 and x ** 4 == 16 and x ** 5 == 32", s));
+        assert_eq!(evaluate("x ** 2", s).type_name(), "Scalar");
+    }
+}
@@ -0,0 +1,118 @@
+use crate::{Float, MAXDIM, RelationalOperator};
+use super::{DynMath, ResultFormatter, EvaluationError, Category, Missing, unimpl_binary};
+use std::any::Any;
+use std::fmt;
+
+
+/// Text value of string literals (`"eV"`), used as the arguments of functions
+/// such as `convert(x, "eV", "nm")`, and the result of `format()`.
+/// Strings can only be compared for equality.
+impl DynMath for String {
+
+    fn clone_boxed(&self) -> Box<dyn DynMath> { Box::new(self.clone()) }
+
+    fn category(&self) -> Category { Category::Unqiue }
+
+    fn shape(&self) -> [usize; MAXDIM] { [0; MAXDIM] }
+
+    fn type_name(&self) -> &'static str { "String" }
+
+    fn memory_size(&self) -> usize { size_of::<Self>() + self.len() }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn fmt_value(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", ResultFormatter::new().display(self))
+    }
+
+    fn compare(&self, other: &dyn DynMath, op: &RelationalOperator) -> Result<Box<dyn DynMath>, EvaluationError>
+    {
+        match (other.downcast_ref::<String>(), op) {
+            (Some(other), RelationalOperator::Eq) => Ok(Box::new(self == other)),
+            (Some(other), RelationalOperator::Neq) => Ok(Box::new(self != other)),
+            _ => unimpl_binary(self.type_name(), other.type_name(), &op.to_string())
+        }
+    }
+}
+
+
+/// `format("peak at {:.1} nm", x)`: the template with its `{}` placeholders
+/// replaced by the arguments in order. `{:.N}` writes numbers with `N` decimals,
+/// `{{` and `}}` are literal braces. Arrays are written as `[1, 2, 3]`,
+/// missing values as `NA`.
+pub fn dynmath_format(args: &[&dyn DynMath]) -> Result<Box<dyn DynMath>, EvaluationError> {
+    let invalid = |details: String| EvaluationError::InvalidArguments { function: "format".into(), details };
+    let (template, mut values) = match args.split_first() {
+        Some((template, values)) if let Some(template) = template.downcast_ref::<String>() => (template, values.iter()),
+        _ => return Err(invalid("the first argument should be a string: format(\"x = {}\", x)".into()))
+    };
+    let mut res = String::with_capacity(template.len());
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.as_str().starts_with('{') => {
+                chars.next();
+                res.push('{');
+            }
+            '}' if chars.as_str().starts_with('}') => {
+                chars.next();
+                res.push('}');
+            }
+            '{' => {
+                let rest = chars.as_str();
+                let end = match rest.find('}') {
+                    Some(end) => end,
+                    None => return Err(invalid("unmatched `{`, write `{{` for a literal brace".into()))
+                };
+                let spec = &rest[..end];
+                chars = rest[end + 1..].chars();
+                let precision = match spec {
+                    "" => None,
+                    _ => match spec.strip_prefix(":.").and_then(|n| n.parse::<usize>().ok()) {
+                        Some(n) => Some(n),
+                        None => return Err(invalid(format!("invalid placeholder `{{{}}}`, expected `{{}}` or `{{:.2}}`", spec)))
+                    }
+                };
+                match values.next() {
+                    Some(value) => write_value(&mut res, *value, precision).map_err(invalid)?,
+                    None => return Err(invalid("more placeholders than values".into()))
+                }
+            }
+            '}' => return Err(invalid("unmatched `}`, write `}}` for a literal brace".into())),
+            c => res.push(c)
+        }
+    }
+    match values.len() {
+        0 => Ok(Box::new(res)),
+        _ => Err(invalid("more values than placeholders".into()))
+    }
+}
+
+fn write_value(res: &mut String, x: &dyn DynMath, precision: Option<usize>) -> Result<(), String> {
+    let number = |x: Float| match precision {
+        Some(n) => format!("{:.*}", n, x),
+        None => x.to_string()
+    };
+    let list = |items: Vec<String>| format!("[{}]", items.join(", "));
+    let text = match x.category() {
+        Category::Number => number(x.as_number()),
+        Category::Array => list(x.iterate().map(|x| number(*x)).collect()),
+        Category::Unqiue => if let Some(s) = x.downcast_ref::<String>() {
+            s.clone()
+        } else if let Some(b) = x.downcast_ref::<bool>() {
+            b.to_string()
+        } else if x.is::<Missing>() {
+            "NA".into()
+        } else if let Some(v) = x.downcast_ref::<Vec<bool>>() {
+            list(v.iter().map(|b| b.to_string()).collect())
+        } else if let Some(v) = x.downcast_ref::<Vec<Option<Float>>>() {
+            list(v.iter().map(|x| x.map_or_else(|| "NA".into(), number)).collect())
+        } else {
+            return Err(format!("cannot format `{}`", x.type_name()));
+        }
+    };
+    res.push_str(&text);
+    Ok(())
+}
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use crate::*;
+
+
+/// Formula with placeholders for literal values, `{name}`:
+/// ```
+/// # use dymex::*;
+/// # use std::collections::HashMap;
+/// let template = Template::new("(max(v) - {offset}) * {gain}", &["v"]).unwrap();
+/// let params = HashMap::from([("offset".to_string(), 2.0), ("gain".to_string(), 0.5)]);
+/// let mut evaluator = template.with_params(&params).unwrap();
+/// # let mut inputs = InputVars::new();
+/// # inputs.insert_owned("v".into(), vec![1.0, 2.0, 3.0, 4.0]);
+/// # assert_eq!(evaluator.evaluate(&inputs).unwrap().as_number(), 1.0);
+/// ```
+/// The template is parsed and its variables are checked once, `with_params()`
+/// only replaces the placeholders with the values as number literals (so the
+/// parts of the formula depending only on them are folded into constants).
+/// Placeholders in strings (`"{x} eV"`) are not replaced, those are format strings.
+#[derive(Debug, Clone)]
+pub struct Template {
+    ast: AST,
+    /// Name and position of the placeholders, in order of appearance
+    placeholders: Vec<(String, usize)>,
+}
+
+impl Template {
+    /// Parse `template`, every referenced variable (except the placeholders) has to be among `variables`
+    pub fn new(template: &str, variables: &[&str]) -> Result<Self, DymexError> {
+        let (expression, placeholders) = escape_placeholders(template);
+        let ts = match TokenStream::new(&expression) {
+            Ok(ts) => ts,
+            Err(err) => return Err(DymexError::LexicalError(err))
+        };
+        let ast = match AST::new(ts) {
+            Ok(ast) => ast,
+            Err(err) => return Err(DymexError::ParsingError(err))
+        };
+        let mut known = variables.to_vec();
+        known.extend(placeholders.iter().map(|(name, _)| name.as_str()));
+        if let Err(err) = ast.check_variables(&known, VariablePolicy::Strict) {
+            return Err(DymexError::ParsingError(err));
+        }
+        // placeholders are values, not attributes (`s.{field}`) or assigned names
+        for (_, at) in &placeholders {
+            if !is_variable_at(&ast.tree, *at) {
+                return Err(DymexError::ParsingError(ParsingError::UnexpectedToken(*at)));
+            }
+        }
+        Ok(Self { ast, placeholders })
+    }
+
+    /// Names of the placeholders, in order of first appearance
+    pub fn placeholders(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for (name, _) in &self.placeholders {
+            if !names.contains(&name.as_str()) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    /// The formula with the placeholders replaced by the values of `params`,
+    /// every placeholder needs a value (unused parameters are ignored)
+    pub fn substitute(&self, params: &HashMap<String, Float>) -> Result<AST, ParsingError> {
+        if let Some((name, at)) = self.placeholders.iter().find(|(name, _)| !params.contains_key(name)) {
+            return Err(ParsingError::MissingParameter(name.clone(), *at));
+        }
+        let mut ast = self.ast.clone();
+        replace_placeholders(&mut ast.tree, &self.placeholders, params);
+        Ok(ast)
+    }
+
+    /// Evaluator of the formula with the placeholders replaced by the values of `params`
+    pub fn with_params(&self, params: &HashMap<String, Float>) -> Result<Evaluator, ParsingError> {
+        self.substitute(params).map(Evaluator::from_ast)
+    }
+}
+
+/// The template with the placeholders written as escaped identifiers
+/// (`` `name` ``, so the positions are kept) and the placeholders
+fn escape_placeholders(template: &str) -> (String, Vec<(String, usize)>) {
+    let chars: Vec<char> = template.chars().collect();
+    let mut expression = String::with_capacity(template.len());
+    let mut placeholders = Vec::new();
+    let mut quote = None;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match (quote, c) {
+            (None, '"' | '`') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '{') => {
+                let name_len = chars[i + 1..].iter().take_while(|c| c.is_alphanumeric() || **c == '_').count();
+                if name_len > 0 && chars.get(i + 1 + name_len) == Some(&'}') {
+                    let name: String = chars[i + 1..i + 1 + name_len].iter().collect();
+                    expression.push('`');
+                    expression.push_str(&name);
+                    expression.push('`');
+                    placeholders.push((name, i));
+                    i += name_len + 2;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        expression.push(c);
+        i += 1;
+    }
+    (expression, placeholders)
+}
+
+fn is_variable_at(branch: &Branch, at: usize) -> bool {
+    match branch {
+        Branch::Atom(tc) => tc.at == at && matches!(tc.token, Token::Var(_)),
+        Branch::Expression(_, args) => args.iter().any(|arg| is_variable_at(arg, at))
+    }
+}
+
+fn replace_placeholders(branch: &mut Branch, placeholders: &[(String, usize)], params: &HashMap<String, Float>) {
+    match branch {
+        Branch::Atom(tc) => {
+            if let Some((name, _)) = placeholders.iter().find(|(_, at)| *at == tc.at)
+            && let Token::Var(_) = tc.token {
+                tc.token = Token::Number(params[name]);
+                tc.literal = None;
+            }
+        }
+        Branch::Expression(_, args) => {
+            for arg in args {
+                replace_placeholders(arg, placeholders, params);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::*;
+
+    #[test]
+    fn test_templates() {
+        let template = Template::new("where(v > {threshold}, v * {scale}, {threshold})", &["v"]).unwrap();
+        assert_eq!(template.placeholders(), ["threshold", "scale"]);
+        let mut inputs = InputVars::new();
+        inputs.insert_owned("v".into(), 3.0);
+
+        let params = HashMap::from([("threshold".to_string(), 2.0), ("scale".to_string(), 10.0)]);
+        assert_eq!(template.with_params(&params).unwrap().evaluate(&inputs).unwrap().as_number(), 30.0);
+        let params = HashMap::from([("threshold".to_string(), 5.0), ("scale".to_string(), 10.0)]);
+        assert_eq!(template.with_params(&params).unwrap().evaluate(&inputs).unwrap().as_number(), 5.0);
+
+        // only the placeholders are values, the variables are inputs
+        let params = HashMap::from([("threshold".to_string(), 1.0)]);
+        assert!(matches!(template.with_params(&params), Err(ParsingError::MissingParameter(name, 27)) if name == "scale"));
+        let params = HashMap::from([("x".to_string(), 2.0)]);
+        let mut evaluator = Template::new("{x} ** 2 + 1", &[]).unwrap().with_params(&params).unwrap();
+        assert_eq!(evaluator.constant_value().unwrap().as_number(), 5.0);
+
+        // strings are format strings, errors point into the template
+        assert_eq!(Template::new("format(\"{x}\", {x})", &[]).unwrap().placeholders(), ["x"]);
+        assert!(matches!(Template::new("{limit} + w", &["v"]),
+            Err(DymexError::ParsingError(ParsingError::UndefinedVariable(v, 10, _))) if v == "w"));
+        assert!(matches!(Template::new("s.{field}", &["s"]), Err(DymexError::ParsingError(ParsingError::UnexpectedToken(2)))));
+    }
+}
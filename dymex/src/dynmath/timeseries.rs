@@ -0,0 +1,271 @@
+use crate::{float, Float, MAXDIM};
+use super::{DynMath, EvaluationError, Category, Unary, unimpl_binary, invalid_field};
+use std::slice::Iter;
+use std::any::Any;
+
+type Binary = fn(Float, Float) -> Float;
+
+/// Maximal number of samples of `resample()`
+const MAX_SAMPLES: Float = 1e7;
+
+
+/// Values sampled at (sorted) timestamps.
+///
+/// Binary operations between two series are aligned on the timestamps: only
+/// the timestamps present in both series are kept (inner join), so series
+/// with different sampling never get silently mixed up elementwise.
+/// Timestamps are compared exactly, `resample()` can be used to bring
+/// series to a common grid. Operations with numbers are applied to the values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeSeries {
+    t: Vec<Float>,
+    values: Vec<Float>,
+}
+
+impl TimeSeries {
+    /// Create a series from timestamps and values, sorted by the timestamps.
+    /// Panics if the lengths of `t` and `values` differ.
+    pub fn new(t: Vec<Float>, values: Vec<Float>) -> Self {
+        assert_eq!(t.len(), values.len(), "TimeSeries needs exactly one value for every timestamp");
+        let mut pairs: Vec<(Float, Float)> = t.into_iter().zip(values).collect();
+        pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let (t, values) = pairs.into_iter().unzip();
+        Self { t, values }
+    }
+
+    pub fn timestamps(&self) -> &[Float] {
+        &self.t
+    }
+
+    pub fn values(&self) -> &[Float] {
+        &self.values
+    }
+
+    pub fn len(&self) -> usize {
+        self.t.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.t.is_empty()
+    }
+
+    fn map_values(&self, f: impl Fn(Float) -> Float) -> Self {
+        Self { t: self.t.clone(), values: self.values.iter().map(|y| f(*y)).collect() }
+    }
+
+    /// Apply `f` to the values of the common timestamps of self and other (inner join)
+    fn zip_aligned(&self, other: &TimeSeries, f: Binary) -> Self {
+        let mut t = Vec::new();
+        let mut values = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.len() && j < other.len() {
+            if self.t[i] < other.t[j] {
+                i += 1;
+            } else if self.t[i] > other.t[j] {
+                j += 1;
+            } else {
+                t.push(self.t[i]);
+                values.push(f(self.values[i], other.values[j]));
+                i += 1;
+                j += 1;
+            }
+        }
+        Self { t, values }
+    }
+
+    /// Helper for binary operations where self is the left hand side
+    fn binary(&self, other: &dyn DynMath, f: Binary, op: &str) -> Result<Box<dyn DynMath>, EvaluationError> {
+        match other.category() {
+            Category::Number => {
+                let x = other.as_number();
+                Ok(Box::new(self.map_values(|y| f(y, x))))
+            }
+            _ => match other.as_any().downcast_ref::<TimeSeries>() {
+                Some(ts) => Ok(Box::new(self.zip_aligned(ts, f))),
+                None => unimpl_binary(self.type_name(), other.type_name(), op)
+            }
+        }
+    }
+
+    /// Helper for binary operations where self is the right hand side
+    fn binary_inv(&self, other: &dyn DynMath, f: Binary, op: &str) -> Result<Box<dyn DynMath>, EvaluationError> {
+        match other.category() {
+            Category::Number => {
+                let x = other.as_number();
+                Ok(Box::new(self.map_values(|y| f(x, y))))
+            }
+            _ => unimpl_binary(other.type_name(), self.type_name(), op)
+        }
+    }
+
+    /// Linear interpolation of the values at `t`
+    fn interpolate(&self, t: Float) -> Float {
+        let i = self.t.partition_point(|x| *x < t);
+        if i == 0 {
+            return self.values[0];
+        }
+        if i == self.len() {
+            return self.values[self.len() - 1];
+        }
+        let (t0, t1) = (self.t[i-1], self.t[i]);
+        let (y0, y1) = (self.values[i-1], self.values[i]);
+        y0 + (y1 - y0) * (t - t0) / (t1 - t0)
+    }
+}
+
+
+impl DynMath for TimeSeries {
+
+    fn clone_boxed(&self) -> Box<dyn DynMath> { Box::new(self.clone()) }
+
+    fn category(&self) -> Category { Category::Unqiue }
+
+    fn array_len(&self) -> Option<usize> { Some(self.len()) }
+
+    fn shape(&self) -> [usize; MAXDIM] {
+        let mut shape = [0; MAXDIM];
+        shape[0] = self.len();
+        shape
+    }
+
+    fn type_name(&self) -> &'static str { "TimeSeries" }
+
+    fn memory_size(&self) -> usize { size_of::<Self>() + 2 * self.len() * size_of::<Float>() }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn iterate(&self) -> Iter<'_, Float> {
+        self.values.iter()
+    }
+
+    fn unary_dispatcher(&self, f: Unary, _op: &str) -> Result<Box<dyn DynMath>, EvaluationError> {
+        Ok(Box::new(self.map_values(f)))
+    }
+
+    fn get_field(&self, field_name: &str) -> Result<Box<dyn DynMath>, EvaluationError> {
+        match field_name {
+            "t" => Ok(Box::new(self.t.clone())),
+            "values" => Ok(Box::new(self.values.clone())),
+            _ => invalid_field(self.type_name(), field_name)
+        }
+    }
+
+    fn add(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| a + b, "+")
+    }
+    fn sub(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| a - b, "-")
+    }
+    fn mul(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| a * b, "*")
+    }
+    fn div(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| a / b, "/")
+    }
+    fn pow(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary(other, |a, b| a.powf(b), "**")
+    }
+    fn sub_inv(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary_inv(other, |a, b| a - b, "-")
+    }
+    fn div_inv(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary_inv(other, |a, b| a / b, "/")
+    }
+    fn pow_inv(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.binary_inv(other, |a, b| a.powf(b), "**")
+    }
+
+    fn min(&self) -> Result<Float, EvaluationError> { DynMath::min(&self.values) }
+    fn max(&self) -> Result<Float, EvaluationError> { DynMath::max(&self.values) }
+    fn range(&self) -> Result<Float, EvaluationError> { DynMath::range(&self.values) }
+    fn sum(&self) -> Result<Float, EvaluationError> { DynMath::sum(&self.values) }
+    fn prod(&self) -> Result<Float, EvaluationError> { DynMath::prod(&self.values) }
+    fn avg(&self) -> Result<Float, EvaluationError> { DynMath::avg(&self.values) }
+    fn std(&self) -> Result<Float, EvaluationError> { DynMath::std(&self.values) }
+    fn l2_norm(&self) -> Result<Float, EvaluationError> { DynMath::l2_norm(&self.values) }
+    fn l1_norm(&self) -> Result<Float, EvaluationError> { DynMath::l1_norm(&self.values) }
+
+    /// Linearly interpolated values on a regular grid with `dt` spacing,
+    /// starting at the first timestamp
+    fn resample(&self, dt: Float) -> Result<Box<dyn DynMath>, EvaluationError> {
+        if !(dt > 0.0) {
+            return Err(EvaluationError::InvalidArguments {
+                function: "resample".into(),
+                details: "the time step should be positive".into()
+            });
+        }
+        if self.is_empty() {
+            return Ok(self.clone_boxed());
+        }
+        let (t0, t1) = (self.t[0], self.t[self.len() - 1]);
+        let n = ((t1 - t0) / dt).floor() + 1.0;
+        if !n.is_finite() || n > MAX_SAMPLES {
+            return Err(EvaluationError::InvalidArguments {
+                function: "resample".into(),
+                details: format!("the time step {} gives more than {} samples", dt, MAX_SAMPLES)
+            });
+        }
+        let n = n as usize;
+        let t: Vec<Float> = (0..n).map(|i| t0 + (i as Float) * dt).collect();
+        let values = t.iter().map(|t| self.interpolate(*t)).collect();
+        Ok(Box::new(Self { t, values }))
+    }
+
+    /// Shift the values by `n` samples (a lag for positive `n`), keeping the
+    /// timestamps. Samples without a value are set to NaN.
+    fn shift(&self, n: isize) -> Result<Box<dyn DynMath>, EvaluationError> {
+        let len = self.len() as isize;
+        // longer shifts give only NaNs as well
+        let n = n.clamp(-len, len);
+        let values = (0..len).map(|i| match i.checked_sub(n) {
+            Some(j) if j >= 0 && j < len => self.values[j as usize],
+            _ => float::NAN
+        }).collect();
+        Ok(Box::new(Self { t: self.t.clone(), values }))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inner_join() {
+        let a = TimeSeries::new(vec![0.0, 1.0, 2.0, 3.0], vec![1.0, 2.0, 3.0, 4.0]);
+        let b = TimeSeries::new(vec![3.0, 1.0, 5.0], vec![10.0, 20.0, 30.0]);
+        let res = a.add(&b).unwrap();
+        let res = res.downcast_ref::<TimeSeries>().unwrap();
+        assert_eq!(res.timestamps(), &[1.0, 3.0]);
+        assert_eq!(res.values(), &[22.0, 14.0]);
+    }
+
+    #[test]
+    fn test_resample() {
+        let a = TimeSeries::new(vec![0.0, 2.0, 3.0], vec![0.0, 4.0, 2.0]);
+        let res = a.resample(0.5).unwrap();
+        let res = res.downcast_ref::<TimeSeries>().unwrap();
+        assert_eq!(res.timestamps(), &[0.0, 0.5, 1.0, 1.5, 2.0, 2.5, 3.0]);
+        assert_eq!(res.values(), &[0.0, 1.0, 2.0, 3.0, 4.0, 3.0, 2.0]);
+        // the number of samples is bounded
+        assert!(a.resample(1e-300).is_err());
+        assert!(a.resample(float::MIN_POSITIVE).is_err());
+        let wide = TimeSeries::new(vec![0.0, float::INFINITY], vec![0.0, 1.0]);
+        assert!(wide.resample(1.0).is_err());
+    }
+
+    #[test]
+    fn test_shift() {
+        let a = TimeSeries::new(vec![0.0, 1.0, 2.0], vec![1.0, 2.0, 3.0]);
+        let res = a.shift(1).unwrap();
+        let res = res.downcast_ref::<TimeSeries>().unwrap();
+        assert!(res.values()[0].is_nan());
+        assert_eq!(&res.values()[1..], &[1.0, 2.0]);
+        for n in [isize::MIN, isize::MAX, 3, -3] {
+            let res = a.shift(n).unwrap();
+            assert!(res.downcast_ref::<TimeSeries>().unwrap().values().iter().all(|x| x.is_nan()), "{}", n);
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use crate::MAXDIM;
+use super::{DynMath, ResultFormatter, EvaluationError, Category};
+use std::any::Any;
+use std::fmt;
+
+
+/// Result of a tuple expression: `(min(v), max(v), avg(v))`, or with named
+/// elements `(lo = min(v), hi = max(v))`. The elements are accessible by
+/// position (`get(0)`) and by name (`field("lo")`, or `result.lo` as an input).
+#[derive(Clone)]
+pub struct Tuple {
+    names: Vec<Option<String>>,
+    values: Vec<Box<dyn DynMath>>,
+}
+
+impl Tuple {
+    /// Tuple of `values`, `names` are the names of the elements (if any)
+    pub fn new(names: Vec<Option<String>>, values: Vec<Box<dyn DynMath>>) -> Self {
+        debug_assert_eq!(names.len(), values.len());
+        Self { names, values }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// The element at `position`
+    pub fn get(&self, position: usize) -> Option<&dyn DynMath> {
+        self.values.get(position).map(|v| &**v)
+    }
+
+    /// The element named `name`
+    pub fn field(&self, name: &str) -> Option<&dyn DynMath> {
+        let position = self.names.iter().position(|n| n.as_deref() == Some(name))?;
+        self.get(position)
+    }
+
+    /// Names of the elements, `None` for the unnamed ones
+    pub fn names(&self) -> &[Option<String>] {
+        &self.names
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn DynMath> {
+        self.values.iter().map(|v| &**v)
+    }
+}
+
+impl DynMath for Tuple {
+
+    fn clone_boxed(&self) -> Box<dyn DynMath> { Box::new(self.clone()) }
+
+    fn category(&self) -> Category { Category::Unqiue }
+
+    fn shape(&self) -> [usize; MAXDIM] { [0; MAXDIM] }
+
+    fn type_name(&self) -> &'static str { "Tuple" }
+
+    fn memory_size(&self) -> usize {
+        let names: usize = self.names.iter().flatten().map(|n| n.len()).sum();
+        size_of::<Self>() + names + self.iter().map(|v| size_of::<Box<dyn DynMath>>() + v.memory_size()).sum::<usize>()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn fmt_value(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", ResultFormatter::new().display(self))
+    }
+
+    /// `t.lo` for named elements, `t.0`, `t.1`, ... by position
+    fn get_field(&self, field_name: &str) -> Result<Box<dyn DynMath>, EvaluationError> {
+        let element = match field_name.parse::<usize>() {
+            Ok(position) => self.get(position),
+            Err(_) => self.field(field_name)
+        };
+        match element {
+            Some(element) => Ok(element.clone_boxed()),
+            None => Err(EvaluationError::InvalidField { type_name: self.type_name(), field: field_name.into() })
+        }
+    }
+}
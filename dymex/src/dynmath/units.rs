@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use crate::{float, Float};
+use super::{DynMath, EvaluationError, Category, Missing};
+
+
+/// Planck constant [J s]
+const PLANCK: Float = 6.626_070_15e-34;
+/// Speed of light [m / s]
+const SPEED_OF_LIGHT: Float = 299_792_458.0;
+
+/// Quantities of photons, convertible into each other by `E = h f = h c / λ = h c ν̃`
+const SPECTRAL: [&str; 4] = ["energy", "frequency", "length", "wavenumber"];
+
+
+/// A unit of measurement: the value `x` in this unit is `x * factor + offset`
+/// in the base unit of its quantity (the SI unit, e.g. `m`, `J` or `K`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unit {
+    pub quantity: String,
+    pub factor: Float,
+    pub offset: Float,
+}
+
+/// Units known to `convert(x, "eV", "nm")`, by name. The default registry
+/// contains the common units of length, mass, time, temperature, energy,
+/// frequency, wavenumber, angle, pressure, power, volume and speed.
+/// Additional units can be registered and set with `Evaluator::set_unit_registry()`:
+/// ```ignore
+/// let mut units = UnitRegistry::default();
+/// units.register("furlong", "length", 201.168);
+/// evaluator.set_unit_registry(units);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnitRegistry {
+    units: HashMap<String, Unit>,
+}
+
+impl UnitRegistry {
+    /// Registry without any units
+    pub fn empty() -> Self {
+        Self { units: HashMap::new() }
+    }
+
+    /// Add (or replace) the unit `name` of `quantity`, one of which is `factor`
+    /// times the base unit of `quantity`
+    pub fn register(&mut self, name: &str, quantity: &str, factor: Float) {
+        self.register_affine(name, quantity, factor, 0.0);
+    }
+
+    /// Add (or replace) a unit with a shifted zero point, such as `°C`:
+    /// `x` in this unit is `x * factor + offset` in the base unit of `quantity`
+    pub fn register_affine(&mut self, name: &str, quantity: &str, factor: Float, offset: Float) {
+        self.units.insert(name.into(), Unit { quantity: quantity.into(), factor, offset });
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Unit> {
+        self.units.get(name)
+    }
+
+    /// Names of the registered units
+    pub fn names(&self) -> Vec<&str> {
+        self.units.keys().map(|k| k.as_str()).collect()
+    }
+
+    /// Convert `x` from the unit `from` to the unit `to`. Units of the same
+    /// quantity are converted linearly, energy, frequency, wavelength (length)
+    /// and wavenumber are converted as the quantities of a photon (`eV` to `nm`).
+    pub fn convert(&self, x: Float, from: &str, to: &str) -> Result<Float, String> {
+        let unknown = |name: &str| format!("unknown unit `{}`", name);
+        let source = self.get(from).ok_or_else(|| unknown(from))?;
+        let target = self.get(to).ok_or_else(|| unknown(to))?;
+        let base = x * source.factor + source.offset;
+        let base = if source.quantity == target.quantity {
+            base
+        } else if SPECTRAL.contains(&source.quantity.as_str()) && SPECTRAL.contains(&target.quantity.as_str()) {
+            from_energy(to_energy(base, &source.quantity), &target.quantity)
+        } else {
+            return Err(format!("cannot convert {} (`{}`) to {} (`{}`)", source.quantity, from, target.quantity, to));
+        };
+        Ok((base - target.offset) / target.factor)
+    }
+}
+
+/// Photon energy [J] of the spectral quantity `x` in SI units
+fn to_energy(x: Float, quantity: &str) -> Float {
+    match quantity {
+        "frequency" => PLANCK * x,
+        "length" => PLANCK * SPEED_OF_LIGHT / x,
+        "wavenumber" => PLANCK * SPEED_OF_LIGHT * x,
+        _ => x
+    }
+}
+
+/// Inverse of `to_energy()`
+fn from_energy(energy: Float, quantity: &str) -> Float {
+    match quantity {
+        "frequency" => energy / PLANCK,
+        "length" => PLANCK * SPEED_OF_LIGHT / energy,
+        "wavenumber" => energy / (PLANCK * SPEED_OF_LIGHT),
+        _ => energy
+    }
+}
+
+impl Default for UnitRegistry {
+    fn default() -> Self {
+        let mut units = Self::empty();
+        let table: &[(&str, &[(&str, Float)])] = &[
+            ("length", &[("m", 1.0), ("km", 1e3), ("cm", 1e-2), ("mm", 1e-3), ("um", 1e-6), ("µm", 1e-6),
+                ("nm", 1e-9), ("pm", 1e-12), ("Å", 1e-10), ("in", 0.0254), ("ft", 0.3048), ("yd", 0.9144),
+                ("mi", 1609.344), ("au", 1.495_978_707e11), ("ly", 9.460_730_472_580_8e15), ("pc", 3.085_677_581_491_367e16)]),
+            ("mass", &[("kg", 1.0), ("g", 1e-3), ("mg", 1e-6), ("ug", 1e-9), ("µg", 1e-9), ("t", 1e3),
+                ("lb", 0.453_592_37), ("oz", 0.028_349_523_125), ("u", 1.660_539_066_6e-27)]),
+            ("time", &[("s", 1.0), ("ms", 1e-3), ("us", 1e-6), ("µs", 1e-6), ("ns", 1e-9), ("min", 60.0),
+                ("h", 3600.0), ("d", 86400.0)]),
+            ("temperature", &[("K", 1.0)]),
+            ("energy", &[("J", 1.0), ("kJ", 1e3), ("MJ", 1e6), ("eV", 1.602_176_634e-19), ("meV", 1.602_176_634e-22),
+                ("keV", 1.602_176_634e-16), ("MeV", 1.602_176_634e-13), ("cal", 4.184), ("kcal", 4184.0),
+                ("Wh", 3600.0), ("kWh", 3.6e6), ("erg", 1e-7), ("Ha", 4.359_744_722_207_1e-18)]),
+            ("frequency", &[("Hz", 1.0), ("kHz", 1e3), ("MHz", 1e6), ("GHz", 1e9), ("THz", 1e12)]),
+            ("wavenumber", &[("1/m", 1.0), ("1/cm", 100.0), ("cm^-1", 100.0)]),
+            ("angle", &[("rad", 1.0), ("deg", float::consts::PI / 180.0), ("°", float::consts::PI / 180.0),
+                ("rev", 2.0 * float::consts::PI)]),
+            ("pressure", &[("Pa", 1.0), ("hPa", 1e2), ("kPa", 1e3), ("MPa", 1e6), ("bar", 1e5), ("mbar", 1e2),
+                ("atm", 101_325.0), ("Torr", 101_325.0 / 760.0), ("mmHg", 133.322_387_415), ("psi", 6_894.757_293_168)]),
+            ("power", &[("W", 1.0), ("mW", 1e-3), ("kW", 1e3), ("MW", 1e6), ("hp", 745.699_871_582_27)]),
+            ("volume", &[("m^3", 1.0), ("L", 1e-3), ("l", 1e-3), ("mL", 1e-6), ("ml", 1e-6), ("gal", 3.785_411_784e-3)]),
+            ("speed", &[("m/s", 1.0), ("km/h", 1.0 / 3.6), ("mph", 0.447_04), ("kn", 1852.0 / 3600.0)]),
+        ];
+        for (quantity, names) in table {
+            for (name, factor) in *names {
+                units.register(name, quantity, *factor);
+            }
+        }
+        units.register_affine("°C", "temperature", 1.0, 273.15);
+        units.register_affine("degC", "temperature", 1.0, 273.15);
+        units.register_affine("°F", "temperature", 5.0 / 9.0, 273.15 - 32.0 * 5.0 / 9.0);
+        units.register_affine("degF", "temperature", 5.0 / 9.0, 273.15 - 32.0 * 5.0 / 9.0);
+        units
+    }
+}
+
+
+/// `convert(x, from, to)`: `x` converted from the unit `from` to the unit `to`
+/// of `units`, elementwise for arrays
+pub fn dynmath_convert(x: &dyn DynMath, from: &dyn DynMath, to: &dyn DynMath, units: &UnitRegistry)
+-> Result<Box<dyn DynMath>, EvaluationError> {
+    let invalid = |details: String| EvaluationError::InvalidArguments { function: "convert".into(), details };
+    let (from, to) = match (from.downcast_ref::<String>(), to.downcast_ref::<String>()) {
+        (Some(from), Some(to)) => (from, to),
+        _ => return Err(invalid("the units should be strings: convert(x, \"eV\", \"nm\")".into()))
+    };
+    let convert = |x: Float| units.convert(x, from, to).map_err(invalid);
+    match x.category() {
+        Category::Number => Ok(Box::new(convert(x.as_number())?)),
+        Category::Array => Ok(Box::new(x.iterate().map(|x| convert(*x)).collect::<Result<Vec<Float>, _>>()?)),
+        Category::Unqiue => match x.downcast_ref::<Vec<Option<Float>>>() {
+            Some(v) => Ok(Box::new(v.iter().map(|x| x.map(convert).transpose()).collect::<Result<Vec<Option<Float>>, _>>()?)),
+            // NA of a known unit is still NA
+            None if x.is::<Missing>() => convert(0.0).map(|_| Box::new(Missing) as Box<dyn DynMath>),
+            None => Err(invalid(format!("expects a number or an array, not `{}`", x.type_name())))
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use crate::Float;
+use super::InputVars;
+
+
+/// Types that can be turned into input variables, usually derived with
+/// `#[derive(DymexVars)]`. Struct fields become variables named after the
+/// field, fields of nested structs become dotted paths (`outer.inner.x`).
+pub trait DymexVars {
+    /// Insert self into `vars`, with variable names prefixed by `prefix`
+    fn insert_vars(&self, vars: &mut InputVars, prefix: &str);
+
+    fn to_input_vars(&self) -> InputVars {
+        InputVars::from_vars(self)
+    }
+}
+
+impl DymexVars for Float {
+    fn insert_vars(&self, vars: &mut InputVars, prefix: &str) {
+        vars.insert_owned(prefix.to_string(), *self);
+    }
+}
+
+impl DymexVars for Vec<Float> {
+    fn insert_vars(&self, vars: &mut InputVars, prefix: &str) {
+        vars.insert_owned(prefix.to_string(), self.clone());
+    }
+}
+
+/// Name of the `field` variable under `prefix` (helper for `#[derive(DymexVars)]`)
+pub fn var_path(prefix: &str, field: &str) -> String {
+    if prefix.is_empty() {
+        field.to_string()
+    } else {
+        format!("{}.{}", prefix, field)
+    }
+}
@@ -1,7 +1,11 @@
-use crate::{float, Float, MAXDIM};
-use super::{DynMath, EvaluationError, Category, Unary, unimpl_binary};
+use crate::{float, Float, MAXDIM, RelationalOperator};
+use super::{DynMath, ResultFormatter, EvaluationError, Category, Missing, Unary, unimpl_binary};
+use super::boolean::compare_numbers;
+use super::number::as_integer;
+use super::scalar::tolerance_of;
 use std::slice::Iter;
 use std::any::Any;
+use std::fmt;
 
 impl DynMath for Vec<Float> {
 
@@ -15,10 +19,16 @@ impl DynMath for Vec<Float> {
         shape
     }
 
+    fn memory_size(&self) -> usize { size_of::<Self>() + self.len() * size_of::<Float>() }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
 
+    fn fmt_value(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", ResultFormatter::new().display(self))
+    }
+
     fn iterate(&self) -> Iter<'_, Float> {
         self.iter()
     }
@@ -37,7 +47,7 @@ impl DynMath for Vec<Float> {
             Category::Array => Ok(Box::new(
                 self.iter().zip(other.iterate()).map(|(a, b)| a + b).collect::<Vec<Float>>()
             )),
-            _ => unimpl_binary(self.type_name(), other.type_name(), "+")
+            Category::Unqiue => (*other).add(self),
         }
     }
 
@@ -50,7 +60,7 @@ impl DynMath for Vec<Float> {
             Category::Array => Ok(Box::new(
                 self.iter().zip(other.iterate()).map(|(a, b)| a - b).collect::<Vec<Float>>()
             )),
-            _ => unimpl_binary(self.type_name(), other.type_name(), "-")
+            Category::Unqiue => (*other).sub_inv(self),
         }
     }
     fn sub_inv(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
@@ -77,7 +87,7 @@ impl DynMath for Vec<Float> {
             Category::Array => Ok(Box::new(
                 self.iter().zip(other.iterate()).map(|(a, b)| a * b).collect::<Vec<Float>>()
             )),
-            _ => unimpl_binary(self.type_name(), other.type_name(), "*")
+            Category::Unqiue => (*other).mul(self),
         }
     }
 
@@ -90,7 +100,7 @@ impl DynMath for Vec<Float> {
             Category::Array => Ok(Box::new(
                 self.iter().zip(other.iterate()).map(|(a, b)| a / b).collect::<Vec<Float>>()
             )),
-            _ => unimpl_binary(self.type_name(), other.type_name(), "/")
+            Category::Unqiue => (*other).div_inv(self),
         }
     }
     fn div_inv(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
@@ -112,7 +122,7 @@ impl DynMath for Vec<Float> {
             Category::Array => Ok(Box::new(
                 self.iter().zip(other.iterate()).map(|(a, b)| a.powf(*b)).collect::<Vec<Float>>()
             )),
-            _ => unimpl_binary(self.type_name(), other.type_name(), "^")
+            Category::Unqiue => (*other).pow_inv(self),
         }
     }
     fn pow_inv(&self, other: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
@@ -125,6 +135,41 @@ impl DynMath for Vec<Float> {
         }
     }
 
+    fn compare(&self, other: &dyn DynMath, op: &RelationalOperator) -> Result<Box<dyn DynMath>, EvaluationError>
+    {
+        match other.category() {
+            Category::Number => {
+                let tolerance = tolerance_of(other);
+                Ok(Box::new(self.iter().map(|a| tolerance.compare(*a, other.as_number(), op)).collect::<Vec<bool>>()))
+            }
+            Category::Array => Ok(Box::new(
+                self.iter().zip(other.iterate()).map(|(a, b)| compare_numbers(*a, *b, op)).collect::<Vec<bool>>()
+            )),
+            _ => unimpl_binary(self.type_name(), other.type_name(), &op.to_string())
+        }
+    }
+
+    /// Element at a (0 based) index, negative indices count from the end: `v[-1]`
+    /// is the last element. Boolean indexing: the elements where the mask is true.
+    fn index(&self, index: &dyn DynMath) -> Result<Box<dyn DynMath>, EvaluationError>
+    {
+        if let Category::Number = index.category() {
+            return element(self, index.as_number()).map(|x| Box::new(x) as Box<dyn DynMath>);
+        }
+        if index.is::<Missing>() {
+            return Ok(Box::new(Missing));
+        }
+        match index.downcast_ref::<Vec<bool>>() {
+            Some(mask) if mask.len() == self.len() => Ok(Box::new(
+                self.iter().zip(mask).filter(|(_, m)| **m).map(|(x, _)| *x).collect::<Vec<Float>>()
+            )),
+            Some(mask) => Err(EvaluationError::InvalidOperation {
+                info: format!("the mask has {} elements instead of {}", mask.len(), self.len())
+            }),
+            None => unimpl_binary(self.type_name(), index.type_name(), "[]")
+        }
+    }
+
     fn min(&self) -> Result<Float, EvaluationError> {
         Ok(self.iter().fold(float::INFINITY, |a, &b| a.min(b)))
     }
@@ -137,6 +182,9 @@ impl DynMath for Vec<Float> {
     fn sum(&self) -> Result<Float, EvaluationError> {
         Ok(self.iter().sum::<Float>())
     }
+    fn prod(&self) -> Result<Float, EvaluationError> {
+        Ok(self.iter().product::<Float>())
+    }
     fn avg(&self) -> Result<Float, EvaluationError> {
         Ok(self.iter().sum::<Float>() / (self.len() as Float))
     }
@@ -156,6 +204,20 @@ impl DynMath for Vec<Float> {
 }
 
 
+pub(super) fn element(v: &[Float], index: Float) -> Result<Float, EvaluationError> {
+    let len = v.len() as i64;
+    match as_integer(index) {
+        Some(i) if (0..len).contains(&i) => Ok(v[i as usize]),
+        Some(i) if (-len..0).contains(&i) => Ok(v[(len + i) as usize]),
+        Some(_) => Err(EvaluationError::InvalidOperation {
+            info: format!("index {} is out of range for {} elements", index, len)
+        }),
+        None => Err(EvaluationError::InvalidOperation {
+            info: format!("index {} is not an integer", index)
+        })
+    }
+}
+
 //TODO: maybe delete this:
 fn elementwise<T>(array: &[f64], func: T) -> Result<Vec<Float>, EvaluationError>
 where
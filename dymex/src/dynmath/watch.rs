@@ -0,0 +1,26 @@
+use std::fmt;
+use std::rc::Rc;
+use super::DynMath;
+
+
+/// A watched subexpression and its value in the last evaluation, see `Evaluator::watch()`
+#[derive(Clone)]
+pub struct Watched {
+    pub label: String,
+    /// Node id of the subexpression, the position of its token
+    pub id: usize,
+    /// `None` if the subexpression was not evaluated: it was skipped by `and`/`or`
+    /// or the evaluation failed before reaching it
+    pub value: Option<Rc<dyn DynMath>>,
+    /// Id of the value in the evaluator
+    pub(crate) value_id: u16,
+}
+
+impl fmt::Debug for Watched {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.value {
+            Some(value) => write!(f, "{} = {}", self.label, value),
+            None => write!(f, "{} = (not evaluated)", self.label)
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use std::fmt::{Display, Formatter, Result, Write};
-use crate::{ParsingError, TokenizerError};
+use std::ops::Range;
+use crate::{MessageCatalog, MessageId, ParsingError, TokenizerError, line_column};
+use crate::catalog::fill_template;
 
 
 #[derive(Debug, Clone, PartialEq)]
@@ -9,19 +11,54 @@ pub enum DymexError {
 
 }
 impl DymexError {
-    fn user_message(&self) -> UserMessage {
+    pub fn user_message(&self) -> UserMessage {
         match self {
             Self::LexicalError(e) => e.user_message(),
             Self::ParsingError(e) => e.user_message(),
         }
     }
+
+    /// Stable code of the error, see `TokenizerError::code()` and `ParsingError::code()`
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::LexicalError(e) => e.code(),
+            Self::ParsingError(e) => e.code(),
+        }
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        self.user_message().diagnostic()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Machine readable form of an error for tooling: match on `code` (`E108`),
+/// not on the text of `message`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    /// Characters (not bytes) of the expression the error points to
+    pub span: Option<Range<usize>>,
+    pub severity: Severity,
+    pub message: String,
 }
 
+#[derive(Debug, Clone)]
 pub struct UserMessage {
     msg: String,
     cursor: Option<usize>,
-    hint: Option<&'static str>,
-    examples: Option<&'static str>
+    hint: Option<String>,
+    examples: Option<&'static str>,
+    /// Stable code of the error, see `Diagnostic`
+    code: Option<&'static str>,
+    /// Identifier and arguments of the message, for `translate()`
+    id: Option<MessageId>,
+    args: Vec<(&'static str, String)>,
 }
 impl UserMessage {
     pub fn new(message: impl Into<String>, 
@@ -32,28 +69,199 @@ impl UserMessage {
         Self {
             msg: message.into(),
             cursor: cursor,
-            hint: hint,
-            examples: examples
+            hint: hint.map(str::to_string),
+            examples: examples,
+            code: None,
+            id: None,
+            args: Vec::new(),
+        }
+    }
+
+    pub fn with_id(mut self, id: MessageId) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    /// Add the argument `name` of the message, `{name}` in the translations
+    pub fn with_arg(mut self, name: &'static str, value: impl ToString) -> Self {
+        self.args.push((name, value.to_string()));
+        self
+    }
+
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn code(&self) -> Option<&'static str> {
+        self.code
+    }
+
+    /// The message as an error `Diagnostic`, its span is the character at the cursor.
+    /// The code is `E000` for messages without a code.
+    pub fn diagnostic(&self) -> Diagnostic {
+        Diagnostic {
+            code: self.code.unwrap_or("E000"),
+            span: self.cursor.map(|at| at..at + 1),
+            severity: Severity::Error,
+            message: self.msg.trim_end_matches(':').to_string(),
         }
     }
 
+    pub fn id(&self) -> Option<MessageId> {
+        self.id
+    }
+
+    pub fn args(&self) -> &[(&'static str, String)] {
+        &self.args
+    }
+
+    pub fn message(&self) -> &str {
+        &self.msg
+    }
+
+    pub fn hint(&self) -> Option<&str> {
+        self.hint.as_deref()
+    }
+
+    /// The message in the language of `catalog`, the parts without a
+    /// translation are kept (and messages without a hint get none)
+    pub fn translate(&self, catalog: &dyn MessageCatalog) -> Self {
+        let mut translated = self.clone();
+        if let Some(id) = self.id {
+            if let Some(message) = catalog.message(id) {
+                translated.msg = fill_template(message, &self.args);
+            }
+            if let Some(hint) = catalog.hint(id)
+            && self.hint.is_some() {
+                translated.hint = Some(fill_template(hint, &self.args));
+            }
+        }
+        translated
+    }
+
+    /// The message with the expression and a caret under the position of the
+    /// error. For multi-line expressions only the erroneous line (and the one
+    /// before it) is shown, prefixed with the line numbers.
     pub fn full_message(&self, expression: &str) -> String {
         let mut msg = format!("{}\n", self.msg);
-        msg.push_str(expression);
-        msg.push_str("\n");
-        if let Some(cursor) = self.cursor {
-            for _ in 0..cursor { msg.push(' '); };
-            msg.push_str("^\n");
+        match self.cursor {
+            Some(cursor) if expression.contains('\n') => {
+                let (line, column) = line_column(expression, cursor);
+                writeln!(msg, "--> line {}, column {}", line + 1, column + 1).unwrap();
+                let lines: Vec<&str> = expression.lines().collect();
+                for i in line.saturating_sub(1)..=line {
+                    writeln!(msg, "{:>4} | {}", i + 1, lines.get(i).unwrap_or(&"")).unwrap();
+                }
+                msg.push_str("     | ");
+                msg.push_str(&caret_padding(lines.get(line).unwrap_or(&""), column));
+                msg.push_str("^\n");
+            }
+            Some(cursor) => {
+                msg.push_str(expression);
+                msg.push_str("\n");
+                msg.push_str(&caret_padding(expression, cursor));
+                msg.push_str("^\n");
+            }
+            None => {
+                msg.push_str(expression);
+                msg.push_str("\n");
+            }
         }
-        if let Some(hint) = self.hint {
+        if let Some(hint) = &self.hint {
             writeln!(msg, "{}", hint).unwrap();
         }
         msg
     }
 }
 
+/// Whitespace that puts a caret under the `column`th character (not byte) of
+/// `line` in a terminal: tabs are kept, wide (CJK, emoji) characters take two
+/// columns and combining marks take none.
+fn caret_padding(line: &str, column: usize) -> String {
+    let mut padding = String::new();
+    for c in line.chars().take(column) {
+        match c {
+            '\t' => padding.push('\t'),
+            c if is_zero_width(c) => {},
+            c if is_wide(c) => padding.push_str("  "),
+            _ => padding.push(' ')
+        }
+    }
+    padding
+}
+
+fn is_zero_width(c: char) -> bool {
+    matches!(c as u32, 0x0300..=0x036F | 0x1AB0..=0x1AFF | 0x1DC0..=0x1DFF | 0x200B..=0x200D
+        | 0x20D0..=0x20FF | 0xFE00..=0xFE0F | 0xFE20..=0xFE2F)
+}
+
+fn is_wide(c: char) -> bool {
+    matches!(c as u32, 0x1100..=0x115F | 0x2E80..=0x303E | 0x3041..=0x33FF | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF | 0xA000..=0xA4CF | 0xAC00..=0xD7A3 | 0xF900..=0xFAFF | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60 | 0xFFE0..=0xFFE6 | 0x1F300..=0x1F64F | 0x1F900..=0x1F9FF | 0x20000..=0x3FFFD)
+}
+
 impl Display for UserMessage {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         write!(f, "{}\n", self.msg)
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multiline_caret() {
+        let msg = UserMessage::new("Invalid character: ?", Some(10), None, None);
+        let full = msg.full_message("x = 1\ny = x ? 2\nx + y");
+        assert_eq!(full, "Invalid character: ?\n--> line 2, column 5\n   1 | x = 1\n   2 | y = x ? 2\n     |     ^\n");
+
+        let full = msg.full_message("a + b + c +?");
+        assert_eq!(full, "Invalid character: ?\na + b + c +?\n          ^\n");
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let err = crate::validate("x + yy", &["x"]).unwrap_err();
+        assert_eq!(err.code(), "E108");
+        assert_eq!(err.user_message().code(), Some("E108"));
+        assert_eq!(err.diagnostic(), Diagnostic {
+            code: "E108",
+            span: Some(4..5),
+            severity: Severity::Error,
+            message: "Undefined variable: `yy`".into()
+        });
+        let err = crate::AST::from_expression("(1 + `a").unwrap_err();
+        assert_eq!(err.code(), "E004");
+        assert_eq!(err.user_message().diagnostic().code, "E004");
+
+        let codes = [
+            crate::EvaluationError::Cancelled.code(),
+            crate::EvaluationError::MissingInputVariable { varname: "x".into() }.code(),
+            crate::TokenizerError::SyntaxError(0).code(),
+            crate::ParsingError::MissingRP(0).code(),
+        ];
+        assert_eq!(codes, ["E208", "E205", "E001", "E103"]);
+        assert_eq!(crate::EvaluationError::Cancelled.diagnostic().span, None);
+    }
+
+    #[test]
+    fn test_unicode_caret() {
+        let expression = "λ * π + ϕ0 $";
+        let err = crate::TokenStream::new(expression).unwrap_err();
+        let full = err.user_message().full_message(expression);
+        assert!(full.contains("λ * π + ϕ0 $\n           ^\n"));
+
+        assert_eq!(caret_padding("\tx", 2), "\t ");
+        assert_eq!(caret_padding("長さ + x", 4), "      ");
+        assert_eq!(caret_padding("e\u{0301} + x", 3), "  ");
+    }
+}
@@ -22,11 +22,20 @@ pub use crate::parser::*;
 mod error;
 pub use crate::error::*;
 
+mod catalog;
+pub use crate::catalog::*;
+
+mod builtins;
+pub use crate::builtins::*;
+
 mod dynmath;
 pub use crate::dynmath::*;
 
-mod helpers;
-pub use crate::helpers::*;
+pub mod testing;
+pub use crate::testing::{approx_eq, dyn_eq, same_num_tokens};
+
+#[cfg(feature = "derive")]
+pub use dymex_derive::*;
 
 
 pub trait Latex {
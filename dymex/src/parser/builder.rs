@@ -0,0 +1,240 @@
+use std::ops;
+use crate::{ArithmeticOperator, Constant, DymexError, Evaluator, Float, Function, Token,
+    TokenContext, AssignmentOperator};
+use crate::tokenizer::parse_function;
+use super::{AST, Branch, ParsingError, check_calls};
+
+
+/// Build expressions programmatically, without going through text:
+///
+/// ```ignore
+/// let expr = ExprBuilder::var("x").mul(2.0).add(ExprBuilder::func(Function::Sin, [ExprBuilder::var("y")]));
+/// let mut evaluator = expr.build_evaluator()?;
+/// ```
+///
+/// Operators are also available with the `+ - * / -x` syntax.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExprBuilder {
+    branch: Branch,
+    assigned_to: Option<String>,
+}
+
+impl ExprBuilder {
+    fn atom(token: Token) -> Self {
+        Self { branch: Branch::Atom(TokenContext::dummy(token)), assigned_to: None }
+    }
+
+    fn expression(token: Token, args: Vec<ExprBuilder>) -> Self {
+        let args = args.into_iter().map(|a| a.branch).collect();
+        Self { branch: Branch::Expression(TokenContext::dummy(token), args), assigned_to: None }
+    }
+
+    fn binary(self, op: ArithmeticOperator, rhs: impl Into<ExprBuilder>) -> Self {
+        Self::expression(Token::ArOp(op), vec![self, rhs.into()])
+    }
+
+    /// Input variable
+    pub fn var(name: &str) -> Self {
+        Self::atom(Token::Var(name.to_string()))
+    }
+
+    /// Number literal
+    pub fn num(x: Float) -> Self {
+        Self::atom(Token::Number(x))
+    }
+
+    /// Built in constant
+    pub fn constant(c: Constant) -> Self {
+        Self::atom(Token::Const(c))
+    }
+
+    /// Call of a built in function
+    pub fn func(function: Function, args: impl IntoIterator<Item = ExprBuilder>) -> Self {
+        let max_args = function.max_args();
+        Self::expression(Token::Func(function, max_args), args.into_iter().collect())
+    }
+
+    /// Call of a built in function by its name, as it would be written in an expression
+    pub fn call(name: &str, args: impl IntoIterator<Item = ExprBuilder>) -> Result<Self, ParsingError> {
+        match parse_function(name) {
+            Some(token) => Ok(Self::expression(token, args.into_iter().collect())),
+            None => Err(ParsingError::NotImplemented(format!("Function `{}`", name)))
+        }
+    }
+
+    pub fn add(self, rhs: impl Into<ExprBuilder>) -> Self {
+        self.binary(ArithmeticOperator::Plus, rhs)
+    }
+
+    pub fn sub(self, rhs: impl Into<ExprBuilder>) -> Self {
+        self.binary(ArithmeticOperator::Minus, rhs)
+    }
+
+    pub fn mul(self, rhs: impl Into<ExprBuilder>) -> Self {
+        self.binary(ArithmeticOperator::Mul, rhs)
+    }
+
+    pub fn div(self, rhs: impl Into<ExprBuilder>) -> Self {
+        self.binary(ArithmeticOperator::Div, rhs)
+    }
+
+    pub fn pow(self, rhs: impl Into<ExprBuilder>) -> Self {
+        self.binary(ArithmeticOperator::Pow, rhs)
+    }
+
+    pub fn neg(self) -> Self {
+        Self::expression(Token::ArOp(ArithmeticOperator::Minus), vec![self])
+    }
+
+    /// Field access: `self.name`
+    pub fn field(self, name: &str) -> Self {
+        Self::expression(Token::Dot, vec![self, Self::atom(Token::Attr(name.to_string()))])
+    }
+
+    /// Assign the result to `varname` (`varname = self`)
+    pub fn assign_to(mut self, varname: &str) -> Self {
+        self.assigned_to = Some(varname.to_string());
+        self
+    }
+
+    /// Check the arguments of the function calls as the parser does and return
+    /// the AST. Every node gets a unique position, as positions are used as node ids.
+    pub fn build(self) -> Result<AST, ParsingError> {
+        let mut tree = self.branch;
+        let mut position = 0;
+        number_nodes(&mut tree, &mut position);
+        check_calls(&tree)?;
+
+        let tree = match self.assigned_to {
+            None => tree,
+            Some(varname) => {
+                let lhs = Branch::Atom(TokenContext::new(Token::Var(varname), position, 0));
+                let op = TokenContext::new(Token::AssignOp(AssignmentOperator::Assign), position + 1, 0);
+                Branch::Expression(op, vec![lhs, tree])
+            }
+        };
+        AST { tree, assigned_to: None }.check_assigment()
+    }
+
+    pub fn build_evaluator(self) -> Result<Evaluator, DymexError> {
+        match self.build() {
+            Ok(ast) => Ok(Evaluator::from_ast(ast)),
+            Err(err) => Err(DymexError::ParsingError(err))
+        }
+    }
+}
+
+/// Set the position of every node in depth first order
+fn number_nodes(branch: &mut Branch, position: &mut usize) {
+    match branch {
+        Branch::Atom(tc) => {
+            tc.at = *position;
+            *position += 1;
+        }
+        Branch::Expression(tc, args) => {
+            tc.at = *position;
+            *position += 1;
+            for arg in args {
+                number_nodes(arg, position);
+            }
+        }
+    }
+}
+
+
+impl From<Float> for ExprBuilder {
+    fn from(x: Float) -> Self {
+        Self::num(x)
+    }
+}
+
+impl From<Constant> for ExprBuilder {
+    fn from(c: Constant) -> Self {
+        Self::constant(c)
+    }
+}
+
+impl<T: Into<ExprBuilder>> ops::Add<T> for ExprBuilder {
+    type Output = ExprBuilder;
+    fn add(self, rhs: T) -> ExprBuilder { ExprBuilder::add(self, rhs) }
+}
+impl<T: Into<ExprBuilder>> ops::Sub<T> for ExprBuilder {
+    type Output = ExprBuilder;
+    fn sub(self, rhs: T) -> ExprBuilder { ExprBuilder::sub(self, rhs) }
+}
+impl<T: Into<ExprBuilder>> ops::Mul<T> for ExprBuilder {
+    type Output = ExprBuilder;
+    fn mul(self, rhs: T) -> ExprBuilder { ExprBuilder::mul(self, rhs) }
+}
+impl<T: Into<ExprBuilder>> ops::Div<T> for ExprBuilder {
+    type Output = ExprBuilder;
+    fn div(self, rhs: T) -> ExprBuilder { ExprBuilder::div(self, rhs) }
+}
+impl ops::Neg for ExprBuilder {
+    type Output = ExprBuilder;
+    fn neg(self) -> ExprBuilder { ExprBuilder::neg(self) }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{assert_matches, collections::HashSet};
+    use crate::*;
+    use super::ExprBuilder as E;
+
+    #[test]
+    fn test_same_as_parsed() {
+        let built = E::var("x").mul(2.0).add(E::func(Function::Sin, [E::var("y")])).build().unwrap();
+        let parsed = AST::from_expression("x*2 + sin(y)").unwrap();
+        assert_eq!(built.rpn_repr(), parsed.rpn_repr());
+
+        let built = (-(E::var("s").field("x") / E::constant(Constant::Pi))).assign_to("z").build().unwrap();
+        let parsed = AST::from_expression("z = -(s.x / pi)").unwrap();
+        assert_eq!(built.rpn_repr(), parsed.rpn_repr());
+        assert_eq!(built.assigned_to, Some("z".to_string()));
+    }
+
+    #[test]
+    fn test_unique_positions() {
+        let ast = E::func(Function::Max, [E::var("x"), E::num(1.0), E::var("x") * 2.0]).build().unwrap();
+        let positions: HashSet<usize> = ast.tree.iter_dfs().map(|b| b.tc().at).collect();
+        assert_eq!(positions.len(), 6);
+    }
+
+    #[test]
+    fn test_arguments() {
+        let res = E::func(Function::Sin, [E::var("x"), E::var("y")]).build();
+        assert_matches!(res, Err(ParsingError::TooManyArguments(_)));
+        let res = E::func(Function::Max, []).build();
+        assert_matches!(res, Err(ParsingError::MissingArgument(_)));
+        assert!(E::call("now", []).unwrap().build().is_ok());
+        assert!(E::call("foo", [E::var("x")]).is_err());
+    }
+
+    #[test]
+    fn test_function_arguments() {
+        // the function arguments have to be anonymous functions, which can't be built
+        for function in [Function::Map, Function::Filter, Function::Reduce, Function::Solve] {
+            let args = (0..function.max_args()).map(|_| E::var("v"));
+            let res = E::func(function, args).build_evaluator();
+            assert!(matches!(res, Err(DymexError::ParsingError(ParsingError::InvalidArgument(..)))));
+        }
+        // the local variables of `with()` are `name = value`
+        let res = E::func(Function::With, [E::var("v"), E::var("x")]).build_evaluator();
+        assert!(matches!(res, Err(DymexError::ParsingError(ParsingError::InvalidArgument(..)))));
+        let res = E::func(Function::With, []).build();
+        assert_matches!(res, Err(ParsingError::MissingArgument(_)));
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let mut evaluator = E::call("sqrt", [E::var("a").pow(2.0) + E::var("b").pow(2.0)])
+            .unwrap()
+            .build_evaluator()
+            .unwrap();
+        let mut inputs = InputVars::new();
+        inputs.insert_owned("a".into(), 3.0);
+        inputs.insert_owned("b".into(), 4.0);
+        assert_eq!(evaluator.evaluate(&inputs).unwrap().as_number(), 5.0);
+    }
+}
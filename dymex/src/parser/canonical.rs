@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use crate::{ArithmeticOperator, DynMath, Category, EvalContext, Evaluand, Float, Function, Token, TokenContext};
+use super::{AST, Branch};
+
+
+impl AST {
+    /// Canonical form of the expression tree, used to compare computations:
+    /// - token positions are cleared,
+    /// - constants are replaced by their values and operations with only
+    ///   number operands are evaluated (`2 * pi` -> `6.283...`),
+    /// - chains of `+` and `*` are flattened, their numbers folded into one and
+    ///   the operands sorted (`1 + b*a + 2` -> `a*b + 3`),
+    /// - the arguments of `min`, `max`, `sum`, ... are sorted.
+    ///
+    /// The canonical tree is a valid expression, but evaluating it may give
+    /// slightly different results due to the reordered floating point operations.
+    pub fn canonical(&self) -> Branch {
+        canonicalize(&self.tree)
+    }
+
+    /// Returns true if the expressions are the same computation up to the
+    /// reordering of commutative operands and constant folding, see `canonical()`.
+    /// The variable the result is assigned to is not compared.
+    pub fn equivalent_to(&self, other: &AST) -> bool {
+        self.canonical() == other.canonical()
+    }
+
+    /// Content hash of the canonical form: equivalent expressions (see
+    /// `equivalent_to()`) have the same fingerprint, regardless of spacing,
+    /// comments or operand order. Unlike `std::hash`, the value is stable across
+    /// processes and platforms, so it can be used as a persistent cache key.
+    pub fn fingerprint(&self) -> Fingerprint {
+        Fingerprint::of(&self.canonical().as_rpn_str())
+    }
+}
+
+/// Stable 64 bit content hash of an expression, see `AST::fingerprint()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Fingerprint(pub u64);
+
+impl Fingerprint {
+    const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+
+    /// FNV-1a hash of `s`
+    fn of(s: &str) -> Self {
+        let hash = s.bytes().fold(Self::FNV_OFFSET, |h, b| (h ^ b as u64).wrapping_mul(Self::FNV_PRIME));
+        Self(hash)
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// Functions whose result does not depend on the order of the arguments
+fn is_commutative(f: &Function) -> bool {
+    matches!(f, Function::Min | Function::Max | Function::Avg | Function::Std
+        | Function::Sum | Function::Prod | Function::Range)
+}
+
+fn number(x: Float) -> Branch {
+    Branch::Atom(TokenContext::dummy(Token::Number(x)))
+}
+
+fn as_number(branch: &Branch) -> Option<Float> {
+    match branch {
+        Branch::Atom(tc) => match tc.token {
+            Token::Number(x) => Some(x),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+fn canonicalize(branch: &Branch) -> Branch {
+    match branch {
+        Branch::Atom(tc) => match &tc.token {
+            Token::Const(c) => number(c.value()),
+            token => Branch::Atom(TokenContext::dummy(token.clone()))
+        },
+        Branch::Expression(tc, args) => {
+            let mut args: Vec<Branch> = args.iter().map(canonicalize).collect();
+            match &tc.token {
+                Token::ArOp(op @ (ArithmeticOperator::Plus | ArithmeticOperator::Mul)) if args.len() == 2 => {
+                    return commutative_chain(*op, args);
+                }
+                // `sum(i -> i, 1, 10)` is not commutative
+                Token::Func(f, _) if is_commutative(f) && !args.iter().any(|a| a.tc().token == Token::Arrow) => {
+                    args.sort_by_cached_key(|a| a.as_rpn_str());
+                }
+                _ => {}
+            }
+            let tc = TokenContext::dummy(tc.token.clone());
+            match fold_constants(&tc, &args) {
+                Some(x) => number(x),
+                None => Branch::Expression(tc, args)
+            }
+        }
+    }
+}
+
+/// Evaluate `op` if every argument is a number, with the same semantics as the evaluator
+fn fold_constants(op: &TokenContext, args: &[Branch]) -> Option<Float> {
+    match &op.token {
+        Token::ArOp(_) => {},
+        Token::Func(f, _) if *f != Function::Now => {},
+        _ => return None
+    }
+    let mut values: HashMap<u16, Rc<dyn DynMath>> = HashMap::new();
+    for (id, arg) in args.iter().enumerate() {
+        values.insert(id as u16, Rc::new(as_number(arg)?));
+    }
+    let ids = (0..args.len() as u16).collect();
+    match Evaluand::new(op.clone(), ids).eval(&values, &EvalContext::default()) {
+        Ok(res) if matches!(res.category(), Category::Number) => Some(res.as_number()),
+        _ => None
+    }
+}
+
+/// Collect the operands of a (canonical) chain of `op`
+fn chain_operands(op: ArithmeticOperator, branch: Branch, operands: &mut Vec<Branch>) {
+    match branch {
+        Branch::Expression(tc, args) if tc.token == Token::ArOp(op) && args.len() == 2 => {
+            for arg in args {
+                chain_operands(op, arg, operands);
+            }
+        }
+        other => operands.push(other)
+    }
+}
+
+/// Flatten, fold and sort `lhs op rhs` for commutative `op`, and rebuild it as
+/// a left associative chain: `(a op b) op c ...`
+fn commutative_chain(op: ArithmeticOperator, args: Vec<Branch>) -> Branch {
+    let mut operands = Vec::new();
+    for arg in args {
+        chain_operands(op, arg, &mut operands);
+    }
+    let (identity, combine): (Float, fn(Float, Float) -> Float) = match op {
+        ArithmeticOperator::Mul => (1.0, |a, b| a * b),
+        _ => (0.0, |a, b| a + b),
+    };
+    let mut folded = identity;
+    operands.retain(|o| match as_number(o) {
+        Some(x) => { folded = combine(folded, x); false },
+        None => true
+    });
+    operands.sort_by_cached_key(|o| o.as_rpn_str());
+    if folded != identity || operands.is_empty() {
+        operands.push(number(folded));
+    }
+
+    let mut operands = operands.into_iter();
+    let first = operands.next().unwrap();
+    operands.fold(first, |lhs, rhs| {
+        Branch::Expression(TokenContext::dummy(Token::ArOp(op)), vec![lhs, rhs])
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn equivalent(a: &str, b: &str) -> bool {
+        AST::from_expression(a).unwrap().equivalent_to(&AST::from_expression(b).unwrap())
+    }
+
+    #[test]
+    fn test_equivalent() {
+        assert!(equivalent("a*b+1", "1+b*a"));
+        assert!(equivalent("(a + b) + c", "c + (b + a)"));
+        assert!(equivalent("2 * pi * r", "r * 6.283185307179586"));
+        assert!(equivalent("x + 1 + 2", "3 + x"));
+        assert!(equivalent("max(a, b, 2*3)", "max(6, b, a)"));
+        assert!(equivalent("y = sin(0) + x", "x"));
+        assert!(equivalent("s.x * 2", "2 * s.x"));
+    }
+
+    #[test]
+    fn test_not_equivalent() {
+        assert!(!equivalent("a - b", "b - a"));
+        assert!(!equivalent("a / b", "b / a"));
+        assert!(!equivalent("a * (b + c)", "a * b + a * c"));
+        assert!(!equivalent("now() - now()", "0"));
+        assert!(!equivalent("s.x", "s.y"));
+    }
+
+    #[test]
+    fn test_canonical_form() {
+        let ast = AST::from_expression("1 + b*a + z + 2").unwrap();
+        assert_eq!(ast.canonical().as_rpn_str(), "(+: (+: (*: a, b), z), 3)");
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let fingerprint = |expr: &str| AST::from_expression(expr).unwrap().fingerprint();
+        assert_eq!(fingerprint("a*b + 1"), fingerprint("1+b *a # comment"));
+        assert_ne!(fingerprint("a - b"), fingerprint("b - a"));
+        // stable across processes and versions of the standard library
+        assert_eq!(fingerprint("x").to_string(), "af63f54c86021707");
+
+        let evaluator = Evaluator::new("max(x, 2) * y", &["x", "y"]).unwrap();
+        assert_eq!(evaluator.fingerprint(), fingerprint("y * max(2, x)"));
+    }
+}
@@ -0,0 +1,310 @@
+//! Translation of expressions into the source of standalone Rust functions over
+//! `f64` slices, so validated formulas can be compiled into a binary (e.g. by a
+//! build script) instead of being evaluated at runtime:
+//! ```ignore
+//! let ast = AST::from_expression("y = a * x ** 2 + b")?;
+//! std::fs::write(out_dir.join("formulas.rs"), ast.to_rust("quadratic", &["a", "b"])?)?;
+//! ```
+//! generates
+//! ```ignore
+//! /// y = a * x ** 2 + b
+//! pub fn quadratic(a: f64, x: &[f64], b: f64, out: &mut [f64]) {
+//!     assert_eq!(x.len(), out.len());
+//!     for (out, &x) in out.iter_mut().zip(x) {
+//!         *out = a * x.powi(2) + b;
+//!     }
+//! }
+//! ```
+//! The loop has no bounds checks, so LLVM vectorizes the arithmetic. The subset
+//! of `parser/translate.rs` is translated, other constructs are errors.
+
+use std::fmt::Write;
+use crate::{ArithmeticOperator, Constant, Float, Function, LogicalOperator, RelationalOperator};
+use super::{AST, Branch, Emitter, ParsingError, translate};
+
+/// Binding strength of the translated (sub)expressions, operands of weaker
+/// ones are parenthesized
+const IF: u8 = 0;
+const OR: u8 = 1;
+const AND: u8 = 2;
+const COMPARISON: u8 = 3;
+const SUM: u8 = 4;
+const PRODUCT: u8 = 5;
+const SIGN: u8 = 6;
+const POSTFIX: u8 = 7;
+
+/// The keywords of Rust (2024 edition), the identifiers of the same name are raw (`r#type`)
+const KEYWORDS: [&str; 52] = ["as", "break", "const", "continue", "crate", "else", "enum", "extern",
+"false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+"return", "self", "Self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use", "where",
+"while", "async", "await", "dyn", "abstract", "become", "box", "do", "final", "macro", "override", "priv",
+"typeof", "unsized", "virtual", "yield", "try", "gen"];
+
+
+impl AST {
+    /// The source of `pub fn {name}(...)` computing the expression elementwise
+    /// into the `out` slice. The parameters are the variables in the order of
+    /// their first occurrence, the ones in `scalars` are `f64`s, the others `&[f64]`s
+    /// of the length of `out` (the function panics for other lengths). The
+    /// variables named as Rust keywords are raw identifiers (`r#type`), `name`
+    /// and the variables have to be identifiers.
+    pub fn to_rust(&self, name: &str, scalars: &[&str]) -> Result<String, ParsingError> {
+        let name = identifier(name, 0)?;
+        let mut emitter = RustEmitter { variables: Vec::new() };
+        let body = translate(&self.tree, &mut emitter)?.source;
+        let variables = emitter.variables;
+        // the result is named `out`, unless that is a variable
+        let mut out = String::from("out");
+        while variables.contains(&out) {
+            out.push('_');
+        }
+        let mut arrays = Vec::new();
+        let mut parameters = String::new();
+        for v in &variables {
+            let parameter = identifier(v, 0)?;
+            match scalars.contains(&v.as_str()) {
+                true => write!(parameters, "{}: f64, ", parameter).unwrap(), // writing to a String does not fail
+                false => {
+                    write!(parameters, "{}: &[f64], ", parameter).unwrap();
+                    arrays.push(parameter);
+                }
+            }
+        }
+
+        let mut s = String::new();
+        writeln!(s, "/// {}", self.to_expression()).unwrap();
+        writeln!(s, "pub fn {}({}{}: &mut [f64]) {{", name, parameters, out).unwrap();
+        let (mut iterator, mut pattern) = (format!("{}.iter_mut()", out), out.clone());
+        for array in arrays {
+            writeln!(s, "    assert_eq!({}.len(), {}.len());", array, out).unwrap();
+            iterator = format!("{}.zip({})", iterator, array);
+            pattern = format!("({}, &{})", pattern, array);
+        }
+        writeln!(s, "    for {} in {} {{", pattern, iterator).unwrap();
+        writeln!(s, "        *{} = {};", out, body).unwrap();
+        writeln!(s, "    }}\n}}").unwrap();
+        Ok(s)
+    }
+}
+
+/// `name` as a Rust identifier, raw for the keywords
+fn identifier(name: &str, at: usize) -> Result<String, ParsingError> {
+    let mut chars = name.chars();
+    let valid = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+        && name != "_";
+    match (valid, KEYWORDS.contains(&name)) {
+        // no raw identifiers of the path segments
+        (true, true) if matches!(name, "crate" | "self" | "Self" | "super") => Err(not_identifier(name, at)),
+        (true, true) => Ok(format!("r#{}", name)),
+        (true, false) => Ok(name.to_string()),
+        (false, _) => Err(not_identifier(name, at))
+    }
+}
+
+fn not_identifier(name: &str, at: usize) -> ParsingError {
+    ParsingError::InvalidOperation(at, format!("`{}` is not a Rust identifier", name))
+}
+
+/// A Rust expression with its binding strength, and its value if it is a number literal
+struct Rust {
+    source: String,
+    binds: u8,
+    literal: Option<Float>,
+}
+
+impl Rust {
+    fn new(source: String, binds: u8) -> Self {
+        Self { source, binds, literal: None }
+    }
+}
+
+/// Emitter of Rust expressions, the variables are collected in the order of
+/// their first occurrence
+struct RustEmitter {
+    variables: Vec<String>,
+}
+
+impl Emitter for RustEmitter {
+    type Number = Rust;
+    type Bool = Rust;
+    type Error = ParsingError;
+
+    fn unsupported(&mut self, branch: &Branch) -> ParsingError {
+        ParsingError::InvalidOperation(branch.tc().at, format!("`{}` can not be translated to Rust", branch.tc().token))
+    }
+
+    fn number(&mut self, x: Float) -> Result<Rust, ParsingError> {
+        let literal = x.is_finite().then_some(x);
+        Ok(Rust { literal, ..Rust::from(x) })
+    }
+
+    fn constant(&mut self, c: &Constant) -> Result<Rust, ParsingError> {
+        // the literal values of the named constants are flagged by clippy
+        let name = match c {
+            Constant::Pi => "PI",
+            Constant::PiTimes2 => "TAU",
+            Constant::Sqrt2 => "SQRT_2",
+            Constant::Euler => "E",
+            c => return self.number(c.value())
+        };
+        Ok(Rust::new(format!("std::f64::consts::{}", name), POSTFIX))
+    }
+
+    fn variable(&mut self, name: &str, at: usize) -> Result<Rust, ParsingError> {
+        let ident = identifier(name, at)?;
+        if !self.variables.iter().any(|v| v == name) {
+            self.variables.push(name.to_string());
+        }
+        Ok(Rust::new(ident, POSTFIX))
+    }
+
+    fn negate(&mut self, x: Rust) -> Result<Rust, ParsingError> {
+        // `- -x` would be a double negation
+        Ok(Rust::new(format!("-{}", operand(x, SIGN + 1)), SIGN))
+    }
+
+    fn arithmetic(&mut self, op: &ArithmeticOperator, a: Rust, b: Rust) -> Result<Rust, ParsingError> {
+        let (op, strength) = match op {
+            ArithmeticOperator::Plus => ("+", SUM),
+            ArithmeticOperator::Minus => ("-", SUM),
+            ArithmeticOperator::Mul => ("*", PRODUCT),
+            ArithmeticOperator::Div => ("/", PRODUCT),
+            op => unreachable!("`{}` is not translated", op)
+        };
+        Ok(binary(a, op, b, strength))
+    }
+
+    fn power(&mut self, base: Rust, exponent: Rust, _: &Branch) -> Result<Rust, ParsingError> {
+        let base = receiver(base);
+        match exponent.literal {
+            Some(n) if n.fract() == 0.0 && n.abs() <= i32::MAX as Float => Ok(Rust::new(format!("{}.powi({})", base, n as i32), POSTFIX)),
+            _ => Ok(Rust::new(format!("{}.powf({})", base, exponent.source), POSTFIX))
+        }
+    }
+
+    fn unary(&mut self, f: &Function, x: Rust) -> Result<Rust, ParsingError> {
+        let method = match f {
+            Function::Cot => return Ok(Rust::new(format!("1.0 / {}.tan()", receiver(x)), PRODUCT)),
+            Function::Log => "ln",
+            f => f.name()
+        };
+        Ok(Rust::new(format!("{}.{}()", receiver(x), method), POSTFIX))
+    }
+
+    fn extremum(&mut self, f: &Function, a: Rust, b: Rust) -> Result<Rust, ParsingError> {
+        Ok(Rust::new(format!("{}.{}({})", receiver(a), f.name(), b.source), POSTFIX))
+    }
+
+    fn select(&mut self, condition: Rust, a: Rust, b: Rust) -> Result<Rust, ParsingError> {
+        Ok(Rust::new(format!("if {} {{ {} }} else {{ {} }}", condition.source, a.source, b.source), IF))
+    }
+
+    fn compare(&mut self, op: &RelationalOperator, a: Rust, b: Rust) -> Result<Rust, ParsingError> {
+        let op = match op {
+            RelationalOperator::Eq => "==",
+            RelationalOperator::Neq => "!=",
+            RelationalOperator::Greater => ">",
+            RelationalOperator::Lesser => "<",
+            RelationalOperator::Geq => ">=",
+            RelationalOperator::Leq => "<=",
+        };
+        // comparisons can not be chained
+        Ok(Rust::new(format!("{} {} {}", operand(a, COMPARISON + 1), op, operand(b, COMPARISON + 1)), COMPARISON))
+    }
+
+    fn logical(&mut self, op: &LogicalOperator, a: Rust, b: Rust) -> Result<Rust, ParsingError> {
+        Ok(match op {
+            LogicalOperator::And => binary(a, "&&", b, AND),
+            LogicalOperator::Or => binary(a, "||", b, OR),
+        })
+    }
+}
+
+/// `lhs op rhs` of a left associative operator
+fn binary(lhs: Rust, op: &str, rhs: Rust, strength: u8) -> Rust {
+    Rust::new(format!("{} {} {}", operand(lhs, strength), op, operand(rhs, strength + 1)), strength)
+}
+
+/// Parenthesized if it binds weaker than `strength`
+fn operand(x: Rust, strength: u8) -> String {
+    match x.binds < strength {
+        true => format!("({})", x.source),
+        false => x.source
+    }
+}
+
+/// The receiver of a method call, the type of numeric literals is not inferred there
+fn receiver(x: Rust) -> String {
+    match x.literal {
+        Some(x) if x.is_sign_negative() => format!("(-{:?}_f64)", -x),
+        Some(x) => format!("{:?}_f64", x),
+        None => operand(x, POSTFIX)
+    }
+}
+
+impl From<Float> for Rust {
+    fn from(x: Float) -> Self {
+        match x {
+            x if x.is_nan() => Self::new("f64::NAN".to_string(), POSTFIX),
+            x if x.is_infinite() && x > 0.0 => Self::new("f64::INFINITY".to_string(), POSTFIX),
+            x if x.is_infinite() => Self::new("f64::NEG_INFINITY".to_string(), POSTFIX),
+            x if x.is_sign_negative() => Self::new(format!("-{:?}", -x), SIGN),
+            x => Self::new(format!("{:?}", x), POSTFIX)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn body(expression: &str) -> String {
+        let source = AST::from_expression(expression).unwrap().to_rust("f", &[]).unwrap();
+        let line = source.lines().find(|line| line.trim_start().starts_with("*out = ")).unwrap();
+        line.trim_start().trim_start_matches("*out = ").trim_end_matches(';').to_string()
+    }
+
+    #[test]
+    fn test_to_rust() {
+        let ast = AST::from_expression("y = a * x ** 2 + b").unwrap();
+        assert_eq!(ast.to_rust("quadratic", &["a", "b"]).unwrap(), concat!(
+            "/// y = a * x ** 2 + b\n",
+            "pub fn quadratic(a: f64, x: &[f64], b: f64, out: &mut [f64]) {\n",
+            "    assert_eq!(x.len(), out.len());\n",
+            "    for (out, &x) in out.iter_mut().zip(x) {\n",
+            "        *out = a * x.powi(2) + b;\n",
+            "    }\n",
+            "}\n",
+        ));
+        let source = AST::from_expression("out * x").unwrap().to_rust("g", &[]).unwrap();
+        assert!(source.contains("pub fn g(out: &[f64], x: &[f64], out_: &mut [f64]) {"), "{}", source);
+        assert!(source.contains("for ((out_, &out), &x) in out_.iter_mut().zip(out).zip(x) {"), "{}", source);
+
+        assert_eq!(body("(a - b) - (c - d) / -z * e"), "a - b - (c - d) / -z * std::f64::consts::E");
+        assert_eq!(body("-(a + b) ** 0.5 - -2 ** x"), "-(a + b).powf(0.5) - (-2.0_f64).powf(x)");
+        assert_eq!(body("2 ** 3 * sin(x) + cotan(a + 1) * pi"), "2.0_f64.powi(3) * x.sin() + 1.0 / (a + 1.0).tan() * std::f64::consts::PI");
+        assert_eq!(body("where(a < b and (c > 0 or c == d), min(a, b, 1), ln(c))"),
+            "if a < b && (c > 0.0 || c == d) { a.min(b).min(1.0) } else { c.ln() }");
+        assert_eq!(body("1 + where(a > 0, a, -a)"), "1.0 + (if a > 0.0 { a } else { -a })");
+        assert_eq!(body("sin(sqrt3) + sqrt2 ** a"), "1.73205080757_f64.sin() + std::f64::consts::SQRT_2.powf(a)");
+        assert!(AST::from_expression("sum(x)").unwrap().to_rust("f", &[]).is_err());
+        assert!(AST::from_expression("a < b").unwrap().to_rust("f", &[]).is_err());
+    }
+
+    #[test]
+    fn test_identifiers() {
+        let source = AST::from_expression("type * fn + x").unwrap().to_rust("match", &["fn"]).unwrap();
+        assert!(source.contains("pub fn r#match(r#type: &[f64], r#fn: f64, x: &[f64], out: &mut [f64]) {"), "{}", source);
+        assert!(source.contains("for ((out, &r#type), &x) in out.iter_mut().zip(r#type).zip(x) {"), "{}", source);
+        assert!(source.contains("*out = r#type * r#fn + x;"), "{}", source);
+
+        let invalid = |expression: &str, name: &str| AST::from_expression(expression).unwrap().to_rust(name, &[]);
+        assert!(matches!(invalid("`my var` * 2", "f"), Err(ParsingError::InvalidOperation(0, _))));
+        assert!(matches!(invalid("2 * self", "f"), Err(ParsingError::InvalidOperation(4, _))));
+        for name in ["", "f(x: f64) {} fn g", "1f", "self", "_"] {
+            assert!(matches!(invalid("x", name), Err(ParsingError::InvalidOperation(..))), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::fmt;
+use crate::Token;
+use super::{AST, Branch};
+
+
+/// A difference between two versions of an AST, see `ast_diff()`.
+///
+/// Nodes are identified by their path: the indices of the children leading
+/// to the node from the root (`[]` is the root, `[1, 0]` the first argument
+/// of the second argument). Paths of removed nodes refer to the old tree,
+/// every other path to the new tree.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AstEdit {
+    /// Subtree only present in the new AST
+    Inserted { path: Vec<usize>, node: Branch },
+    /// Subtree only present in the old AST
+    Removed { path: Vec<usize>, node: Branch },
+    /// The operation, function or value of a node changed, its arguments are
+    /// compared separately
+    Changed { path: Vec<usize>, from: Token, to: Token },
+    /// The variable the result is assigned to changed
+    Assignment { from: Option<String>, to: Option<String> },
+}
+
+impl fmt::Display for AstEdit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AstEdit::Inserted { path, node } => write!(f, "+ {:?} {}", path, node.as_rpn_str()),
+            AstEdit::Removed { path, node } => write!(f, "- {:?} {}", path, node.as_rpn_str()),
+            AstEdit::Changed { path, from, to } => write!(f, "~ {:?} {} -> {}", path, from, to),
+            AstEdit::Assignment { from, to } => write!(f, "~ = {} -> {}",
+                from.as_deref().unwrap_or("_"), to.as_deref().unwrap_or("_")),
+        }
+    }
+}
+
+/// Structural differences between two ASTs, e.g. between saved revisions of a
+/// formula. Token positions are ignored, so reformatting an expression gives
+/// no edits. Arguments are matched by their longest common subsequence, so
+/// `max(a, b, c)` -> `max(a, c)` is reported as the removal of `b`.
+pub fn ast_diff(a: &AST, b: &AST) -> Vec<AstEdit> {
+    let mut edits = Vec::new();
+    if a.assigned_to != b.assigned_to {
+        edits.push(AstEdit::Assignment { from: a.assigned_to.clone(), to: b.assigned_to.clone() });
+    }
+    diff_branch(&a.tree, &b.tree, &mut Vec::new(), &mut edits);
+    edits
+}
+
+impl Branch {
+    /// Returns true if the (sub)trees are the same, regardless of the positions
+    /// of the tokens in the expression
+    pub fn same_structure(&self, other: &Branch) -> bool {
+        match (self, other) {
+            (Branch::Atom(a), Branch::Atom(b)) => a.token == b.token,
+            (Branch::Expression(a, a_args), Branch::Expression(b, b_args)) => {
+                a.token == b.token
+                && a_args.len() == b_args.len()
+                && a_args.iter().zip(b_args).all(|(x, y)| x.same_structure(y))
+            }
+            _ => false
+        }
+    }
+}
+
+fn children(branch: &Branch) -> &[Branch] {
+    match branch {
+        Branch::Atom(_) => &[],
+        Branch::Expression(_, args) => args
+    }
+}
+
+fn child_path(path: &[usize], i: usize) -> Vec<usize> {
+    let mut p = path.to_vec();
+    p.push(i);
+    p
+}
+
+fn diff_branch(a: &Branch, b: &Branch, path: &mut Vec<usize>, edits: &mut Vec<AstEdit>) {
+    if a.tc().token != b.tc().token {
+        edits.push(AstEdit::Changed { path: path.clone(), from: a.tc().token.clone(), to: b.tc().token.clone() });
+    }
+    diff_children(children(a), children(b), path, edits);
+}
+
+/// Match the arguments by their longest common subsequence, then diff the
+/// unmatched arguments between two matches pairwise
+fn diff_children(a: &[Branch], b: &[Branch], path: &mut Vec<usize>, edits: &mut Vec<AstEdit>) {
+    // lcs[i][j]: length of the LCS of a[i..] and b[j..]
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i].same_structure(&b[j]) {
+                lcs[i+1][j+1] + 1
+            } else {
+                lcs[i+1][j].max(lcs[i][j+1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let (mut gap_a, mut gap_b) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i].same_structure(&b[j]) {
+            diff_gap(a, b, gap_a..i, gap_b..j, path, edits);
+            i += 1;
+            j += 1;
+            gap_a = i;
+            gap_b = j;
+        } else if j == b.len() || (i < a.len() && lcs[i+1][j] >= lcs[i][j+1]) {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    diff_gap(a, b, gap_a..a.len(), gap_b..b.len(), path, edits);
+}
+
+fn diff_gap(a: &[Branch], b: &[Branch], range_a: std::ops::Range<usize>, range_b: std::ops::Range<usize>,
+    path: &mut Vec<usize>, edits: &mut Vec<AstEdit>) {
+    let paired = range_a.len().min(range_b.len());
+    for (i, j) in range_a.clone().zip(range_b.clone()) {
+        path.push(j);
+        diff_branch(&a[i], &b[j], path, edits);
+        path.pop();
+    }
+    for i in range_a.skip(paired) {
+        edits.push(AstEdit::Removed { path: child_path(path, i), node: a[i].clone() });
+    }
+    for j in range_b.skip(paired) {
+        edits.push(AstEdit::Inserted { path: child_path(path, j), node: b[j].clone() });
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn diff(a: &str, b: &str) -> Vec<String> {
+        let a = AST::from_expression(a).unwrap();
+        let b = AST::from_expression(b).unwrap();
+        ast_diff(&a, &b).iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn test_no_changes() {
+        assert!(diff("y = 2*x + sin(x)", "y=2 * x+sin( x )").is_empty());
+    }
+
+    #[test]
+    fn test_changes() {
+        assert_eq!(diff("a * b + 1", "a * b - 2"), vec!["~ [] + -> -", "~ [1] 1 -> 2"]);
+        assert_eq!(diff("max(a, b, c)", "max(a, c)"), vec!["- [1] b"]);
+        assert_eq!(diff("max(a, c)", "max(a, b * 2, c)"), vec!["+ [1] (*: b, 2)"]);
+        assert_eq!(diff("y = x", "z = x"), vec!["~ = y -> z"]);
+        assert_eq!(diff("sin(x)", "x + 1"), vec!["~ [] Sin -> +", "+ [1] 1"]);
+    }
+
+    #[test]
+    fn test_paths() {
+        let a = AST::from_expression("(x + 1) * 2").unwrap();
+        let b = AST::from_expression("(x + 3) * 2").unwrap();
+        assert_eq!(ast_diff(&a, &b), vec![AstEdit::Changed {
+            path: vec![0, 1],
+            from: Token::Number(1.0),
+            to: Token::Number(3.0),
+        }]);
+    }
+}
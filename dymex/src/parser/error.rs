@@ -1,4 +1,5 @@
-use crate::{UserMessage, TokenizerError};
+use crate::{MessageId, UserMessage, TokenizerError};
+use super::suggest::function_list;
 
 
 /// An error reported by the parser.
@@ -11,59 +12,173 @@ pub enum ParsingError {
     TooManyArguments(usize),
     InvalidOperation(usize, String),
     NotImplemented(String),
-    UndefinedVariable(String, usize),
+    /// The variable, its position and the suggested name (a close variable,
+    /// function or constant name)
+    UndefinedVariable(String, usize, Option<String>),
     InvalidAssignment(String, usize),
+    /// Invalid named argument (`f(x, name = value)`)
+    InvalidArgument(String, usize),
+    InvalidJson(String),
+    /// Call of an identifier that is not a function (`sgrt(x)`), its position
+    /// and the closest function name
+    UnknownFunction(String, usize, Option<String>),
+    /// No value for the placeholder `{name}` of a `Template`
+    MissingParameter(String, usize),
+    /// Call of a function excluded by the `FunctionFilter` of the evaluator, its position
+    ForbiddenFunction(String, usize),
+    /// Invalid option of the `EvaluatorConfig` (e.g. the bounds of the `ArithmeticMode`), the reason
+    InvalidConfiguration(String),
     LexingError(TokenizerError)
 }
 impl ParsingError {
-    pub fn user_message(&self) -> UserMessage {
+    /// Stable code of the error, `E101` to `E199` for the parser errors (the
+    /// code of the tokenizer error for `LexingError`)
+    pub fn code(&self) -> &'static str {
         match self {
+            Self::UnexpectedToken(_) => "E101",
+            Self::UnexpectedLP(_) => "E102",
+            Self::MissingRP(_) => "E103",
+            Self::MissingArgument(_) => "E104",
+            Self::TooManyArguments(_) => "E105",
+            Self::InvalidOperation(..) => "E106",
+            Self::NotImplemented(_) => "E107",
+            Self::UndefinedVariable(..) => "E108",
+            Self::InvalidAssignment(..) => "E109",
+            Self::InvalidArgument(..) => "E110",
+            Self::InvalidJson(_) => "E111",
+            Self::MissingParameter(..) => "E112",
+            Self::UnknownFunction(..) => "E113",
+            Self::ForbiddenFunction(..) => "E114",
+            Self::InvalidConfiguration(_) => "E115",
+            Self::LexingError(err) => err.code(),
+        }
+    }
+
+    pub fn user_message(&self) -> UserMessage {
+        let message = match self {
             Self::UnexpectedToken(i) => UserMessage::new(
                     format!("Unexpected token:"),
                     Some(*i),
                     None,
-                    None),
+                    None)
+                .with_id(MessageId::UnexpectedToken),
             Self::UnexpectedLP(i) => UserMessage::new(
                     format!("Unexpected ("),
                     Some(*i),
                     None,
-                    None),
+                    None)
+                .with_id(MessageId::UnexpectedLP),
             Self::MissingRP(i) => UserMessage::new(
                     format!("Missing )"),
                     Some(*i as usize),
                     None,
-                    None),
+                    None)
+                .with_id(MessageId::MissingRP),
             Self::MissingArgument(i) => UserMessage::new(
                     format!("Missing argument:"),
                     Some(*i as usize),
                     None,
-                    None),
+                    None)
+                .with_id(MessageId::MissingArgument),
             Self::TooManyArguments(i) => UserMessage::new(
                     format!("Too many arguments:"),
                     Some(*i as usize),
                     None,
-                    None),
+                    None)
+                .with_id(MessageId::TooManyArguments),
             Self::InvalidOperation(i, op) => UserMessage::new(
                     format!("Invalid operation: {}", op),
                     Some(*i as usize),
                     None,
-                    None),
+                    None)
+                .with_id(MessageId::InvalidOperation)
+                .with_arg("operation", op),
             Self::NotImplemented(feature) => UserMessage::new(
                     format!("{} is not yet implemented.", feature),
                     Some(0),  //TODO
                     None,
-                    None),
-            Self::UndefinedVariable(varname, i) => UserMessage::new(
+                    None)
+                .with_id(MessageId::NotImplemented)
+                .with_arg("feature", feature),
+            Self::UndefinedVariable(varname, i, suggestion) => {
+                let message = UserMessage::new(
                     format!("Undefined variable: `{}`", varname),
                     Some(*i),
                     None,
-                    None),
+                    None)
+                .with_id(MessageId::UndefinedVariable)
+                .with_arg("name", varname);
+                match suggestion {
+                    Some(suggestion) => message
+                        .with_hint(format!("Did you mean `{}`?", suggestion))
+                        .with_arg("suggestion", suggestion),
+                    None => message
+                }
+            }
             Self::InvalidAssignment(details, i) => UserMessage::new(
                     format!("Invalid assignment: `{}`", details),
                     Some(*i),
                     None,
-                    None),
+                    None)
+                .with_id(MessageId::InvalidAssignment)
+                .with_arg("details", details),
+            Self::InvalidArgument(details, i) => UserMessage::new(
+                    format!("Invalid argument: {}", details),
+                    Some(*i),
+                    Some("Named arguments follow the positional ones: `resample(v, dt = 60)`"),
+                    None)
+                .with_id(MessageId::InvalidArgument)
+                .with_arg("details", details),
+            Self::InvalidJson(details) => UserMessage::new(
+                    format!("Invalid JSON representation: {}", details),
+                    None,
+                    None,
+                    None)
+                .with_id(MessageId::InvalidJson)
+                .with_arg("details", details),
+            Self::MissingParameter(name, i) => UserMessage::new(
+                    format!("Missing value of the placeholder `{{{}}}`", name),
+                    Some(*i),
+                    None,
+                    None)
+                .with_id(MessageId::MissingParameter)
+                .with_arg("name", name),
+            Self::UnknownFunction(name, i, suggestion) => {
+                let functions = function_list();
+                let hint = match suggestion {
+                    Some(suggestion) => format!("Did you mean `{}`? The functions are: {}", suggestion, functions),
+                    None => format!("The functions are: {}", functions)
+                };
+                let message = UserMessage::new(
+                    format!("Unknown function: `{}`", name),
+                    Some(*i),
+                    None,
+                    None)
+                .with_id(MessageId::UnknownFunction)
+                .with_arg("name", name)
+                .with_hint(hint)
+                .with_arg("functions", functions);
+                match suggestion {
+                    Some(suggestion) => message.with_arg("suggestion", suggestion),
+                    None => message
+                }
+            }
+            Self::ForbiddenFunction(name, i) => UserMessage::new(
+                    format!("The function `{}` is not allowed:", name),
+                    Some(*i),
+                    None,
+                    None)
+                .with_id(MessageId::ForbiddenFunction)
+                .with_arg("name", name),
+            Self::InvalidConfiguration(details) => UserMessage::new(
+                    format!("Invalid configuration: {}", details),
+                    None,
+                    None,
+                    None)
+                .with_id(MessageId::InvalidConfiguration)
+                .with_arg("details", details),
             Self::LexingError(err) => err.user_message()
-        }
+        };
+        message.with_code(self.code())
     }
 }
\ No newline at end of file
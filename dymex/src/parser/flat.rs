@@ -0,0 +1,294 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::ops::Range;
+use crate::{Token, TokenContext};
+use super::{AST, Branch};
+
+
+/// Index of a node in a `FlatAst`, the root is 0
+pub type NodeId = u32;
+
+/// Non-recursive representation of the AST: the nodes are stored in a single
+/// `Vec` in breadth first order, so the children of a node are consecutive and
+/// a node refers to them by a range of indices. Building, traversing and
+/// dropping a `FlatAst` needs no recursion and a single allocation, which
+/// matters for large (machine generated) expressions.
+/// The nodes are visited through `FlatBranch`, with the traversal API of `Branch`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlatAst {
+    nodes: Vec<FlatNode>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct FlatNode {
+    tc: TokenContext,
+    /// `Branch::Atom` (an `Expression` can also have no children, like `now()`)
+    atom: bool,
+    parent: NodeId,
+    /// The children are the nodes `first_child..first_child + children`
+    first_child: NodeId,
+    children: NodeId,
+}
+
+impl FlatAst {
+    /// Flatten `tree`. The branches are moved into the nodes one by one,
+    /// so deep trees are not dropped recursively either.
+    pub fn from_branch(tree: Branch) -> Self {
+        let mut nodes = Vec::new();
+        let mut queue = VecDeque::from([(tree, 0)]);
+        while let Some((branch, parent)) = queue.pop_front() {
+            let id = nodes.len() as NodeId;
+            // the nodes before have already queued their children
+            let first_child = (nodes.len() + 1 + queue.len()) as NodeId;
+            match branch {
+                Branch::Atom(tc) => nodes.push(FlatNode { tc, atom: true, parent, first_child, children: 0 }),
+                Branch::Expression(tc, children) => {
+                    nodes.push(FlatNode { tc, atom: false, parent, first_child, children: children.len() as NodeId });
+                    queue.extend(children.into_iter().map(|child| (child, id)));
+                }
+            }
+        }
+        Self { nodes }
+    }
+
+    /// The equivalent recursive tree
+    pub fn to_branch(&self) -> Branch {
+        let mut built: Vec<Option<Branch>> = Vec::new();
+        built.resize_with(self.nodes.len(), || None);
+        // the children have larger ids than their parent, they are built first
+        for (id, node) in self.nodes.iter().enumerate().rev() {
+            let branch = match node.atom {
+                true => Branch::Atom(node.tc.clone()),
+                false => Branch::Expression(node.tc.clone(), built[node.range()].iter_mut()
+                    .map(|child| child.take().expect("children are built before their parent"))
+                    .collect())
+            };
+            built[id] = Some(branch);
+        }
+        built[0].take().expect("a FlatAst has a root")
+    }
+
+    pub fn root(&self) -> FlatBranch<'_> {
+        FlatBranch { ast: self, id: 0 }
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<FlatBranch<'_>> {
+        match (id as usize) < self.nodes.len() {
+            true => Some(FlatBranch { ast: self, id }),
+            false => None
+        }
+    }
+
+    /// Number of nodes
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// The nodes in breadth first order
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = FlatBranch<'_>> {
+        (0..self.nodes.len() as NodeId).map(|id| FlatBranch { ast: self, id })
+    }
+
+    /// The nodes in depth first (pre-)order, the order of `Branch::iter_dfs()`
+    pub fn iter_dfs(&self) -> impl Iterator<Item = FlatBranch<'_>> {
+        let mut stack = vec![self.root()];
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.children().rev());
+            Some(node)
+        })
+    }
+}
+
+impl FlatNode {
+    fn range(&self) -> Range<usize> {
+        self.first_child as usize..(self.first_child + self.children) as usize
+    }
+}
+
+impl From<Branch> for FlatAst {
+    fn from(tree: Branch) -> Self {
+        Self::from_branch(tree)
+    }
+}
+
+impl From<&Branch> for FlatAst {
+    fn from(tree: &Branch) -> Self {
+        let mut nodes = Vec::new();
+        let mut queue = VecDeque::from([(tree, 0)]);
+        while let Some((branch, parent)) = queue.pop_front() {
+            let id = nodes.len() as NodeId;
+            let first_child = (nodes.len() + 1 + queue.len()) as NodeId;
+            let (atom, children) = match branch {
+                Branch::Atom(_) => (true, &[] as &[Branch]),
+                Branch::Expression(_, children) => (false, children.as_slice())
+            };
+            nodes.push(FlatNode { tc: branch.tc().clone(), atom, parent, first_child, children: children.len() as NodeId });
+            queue.extend(children.iter().map(|child| (child, id)));
+        }
+        Self { nodes }
+    }
+}
+
+impl AST {
+    /// The tree of the AST as a `FlatAst`, see also `into_flat_ast()`
+    pub fn flatten_ast(&self) -> FlatAst {
+        FlatAst::from(&self.tree)
+    }
+
+    /// Move the tree of the AST into a `FlatAst` without cloning the tokens
+    pub fn into_flat_ast(self) -> FlatAst {
+        FlatAst::from_branch(self.tree)
+    }
+}
+
+
+/// A node of a `FlatAst` and its subtree, the counterpart of `&Branch`
+#[derive(Debug, Clone, Copy)]
+pub struct FlatBranch<'a> {
+    ast: &'a FlatAst,
+    id: NodeId,
+}
+
+impl<'a> FlatBranch<'a> {
+    fn node(&self) -> &'a FlatNode {
+        &self.ast.nodes[self.id as usize]
+    }
+
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+
+    pub fn tc(&self) -> &'a TokenContext {
+        &self.node().tc
+    }
+
+    /// True for the counterparts of `Branch::Atom`
+    pub fn is_atom(&self) -> bool {
+        self.node().atom
+    }
+
+    /// The operands of an expression, none for an atom
+    pub fn children(&self) -> impl DoubleEndedIterator<Item = FlatBranch<'a>> + ExactSizeIterator {
+        let ast = self.ast;
+        self.node().range().map(move |id| FlatBranch { ast, id: id as NodeId })
+    }
+
+    pub fn child(&self, i: usize) -> Option<FlatBranch<'a>> {
+        self.children().nth(i)
+    }
+
+    /// The expression this node is an operand of, `None` for the root
+    pub fn parent(&self) -> Option<FlatBranch<'a>> {
+        match self.id {
+            0 => None,
+            _ => Some(FlatBranch { ast: self.ast, id: self.node().parent })
+        }
+    }
+
+    /// See `Branch::variable_path()`
+    pub fn variable_path(&self) -> Option<String> {
+        match &self.tc().token {
+            Token::Var(v) if self.is_atom() => Some(v.clone()),
+            Token::Dot if !self.is_atom() => match (self.child(0), self.child(1)) {
+                (Some(obj), Some(attr)) if attr.is_atom()
+                && let Token::Attr(field) = &attr.tc().token => obj.variable_path().map(|p| format!("{}.{}", p, field)),
+                _ => None
+            },
+            _ => None
+        }
+    }
+
+    /// See `Branch::as_rpn_str()`
+    pub fn as_rpn_str(&self) -> String {
+        let mut s = String::new();
+        self.write_rpn(&mut s).unwrap();
+        s
+    }
+
+    /// See `Branch::write_rpn()`
+    pub fn write_rpn<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        enum Step<'a> {
+            Node(FlatBranch<'a>),
+            Separator,
+            Close,
+        }
+        let mut stack = vec![Step::Node(*self)];
+        while let Some(step) = stack.pop() {
+            match step {
+                Step::Node(node) if node.is_atom() => write!(w, "{}", node.tc().token)?,
+                Step::Node(node) if node.children().len() == 0 => write!(w, "({})", node.tc().token)?,
+                Step::Node(node) => {
+                    write!(w, "({}: ", node.tc().token)?;
+                    stack.push(Step::Close);
+                    for (i, child) in node.children().enumerate().rev() {
+                        stack.push(Step::Node(child));
+                        if i > 0 {
+                            stack.push(Step::Separator);
+                        }
+                    }
+                }
+                Step::Separator => w.write_str(", ")?,
+                Step::Close => w.write_str(")")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reverse polish notation, the same as that of the `Branch`
+impl fmt::Display for FlatBranch<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_rpn(f)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_flat_ast() {
+        let ast = AST::from_expression("max(a.b, 2) * sin(x) - now()").unwrap();
+        let flat = ast.flatten_ast();
+        assert_eq!(flat.root().as_rpn_str(), ast.rpn_repr());
+        assert_eq!(flat.to_branch(), ast.tree);
+        assert_eq!(flat.len(), ast.tree.iter_dfs().count());
+
+        // breadth first: the operands of the root follow it
+        let root = flat.root();
+        assert_eq!(root.tc().token, Token::ArOp(ArithmeticOperator::Minus));
+        assert_eq!(root.children().map(|c| c.id()).collect::<Vec<_>>(), vec![1, 2]);
+        let now = root.child(1).unwrap();
+        assert!(!now.is_atom() && now.children().len() == 0);
+        assert_eq!(now.parent().unwrap().id(), 0);
+        assert!(root.parent().is_none());
+
+        let paths: Vec<String> = flat.iter_dfs().filter_map(|n| n.variable_path()).collect();
+        assert_eq!(paths, vec!["a.b", "a", "x"]);
+        let dfs: Vec<String> = flat.iter_dfs().map(|n| n.tc().token.to_string()).collect();
+        let expected: Vec<String> = ast.tree.iter_dfs().map(|b| b.tc().token.to_string()).collect();
+        assert_eq!(dfs, expected);
+
+        assert_eq!(ast.clone().into_flat_ast(), flat);
+    }
+
+    #[test]
+    fn test_deep_flat_ast() {
+        // deep enough to overflow the stack if dropped or rebuilt recursively
+        let mut tree = Branch::Atom(TokenContext::dummy(Token::Number(1.0)));
+        for _ in 0..200_000 {
+            tree = Branch::Expression(TokenContext::dummy(Token::ArOp(ArithmeticOperator::Minus)), vec![tree]);
+        }
+        let flat = FlatAst::from_branch(tree);
+        assert_eq!(flat.len(), 200_001);
+        assert_eq!(flat.iter_dfs().count(), 200_001);
+        assert!(flat.get(200_000).unwrap().is_atom());
+        assert_eq!(flat.get(200_000).unwrap().parent().unwrap().id(), 199_999);
+    }
+}
@@ -0,0 +1,212 @@
+use crate::{DymexError, Function, Token, TokenStream};
+use super::{AST, Branch, ParsingError, Warning};
+use super::suggest::suggest_identifier;
+
+
+/// Metadata of a parsed expression
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpressionInfo {
+    /// Referenced variables in order of appearance, fields of variables
+    /// as dotted paths (`s.x`)
+    pub variables: Vec<String>,
+    /// Called functions in order of appearance
+    pub functions: Vec<Function>,
+    /// Depth of the AST, 1 for a single number or variable
+    pub depth: usize,
+    /// Variable the result is assigned to
+    pub assigned_to: Option<String>,
+    /// Suspicious parts of the expression, filled by `validate()`
+    pub warnings: Vec<Warning>,
+}
+
+impl AST {
+    pub fn info(&self) -> ExpressionInfo {
+        let mut info = ExpressionInfo {
+            variables: Vec::new(),
+            functions: Vec::new(),
+            depth: 0,
+            assigned_to: self.assigned_to.clone(),
+            warnings: Vec::new(),
+        };
+        collect_info(&self.tree, &mut info, 1);
+        info.variables = self.tree.free_variables();
+        info
+    }
+}
+
+fn collect_info(branch: &Branch, info: &mut ExpressionInfo, depth: usize) {
+    info.depth = info.depth.max(depth);
+    if branch.variable_path().is_some() {
+        return;
+    }
+    if let Branch::Expression(tc, args) = branch {
+        if let Token::Func(f, _) = &tc.token
+        && !info.functions.contains(f) {
+            info.functions.push(f.clone());
+        }
+        for arg in args {
+            collect_info(arg, info, depth + 1);
+        }
+    }
+}
+
+/// Returns true if the variable `path` is one of the `inputs`, or a field of one
+fn is_defined(path: &str, inputs: &[&str]) -> bool {
+    inputs.iter().any(|input| {
+        path == *input
+        || (path.starts_with(input) && path[input.len()..].starts_with('.'))
+    })
+}
+
+/// How identifiers that are not among the known variables are treated
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum VariablePolicy {
+    /// Unknown identifiers are rejected when the expression is parsed
+    #[default]
+    Strict,
+    /// Unknown identifiers are late-bound variables, evaluation fails with
+    /// `EvaluationError::MissingInputVariable` if they are not provided.
+    /// Useful to parse formulas before the data schema is known.
+    Permissive,
+}
+
+/// Functions accepted in the expressions, e.g. to forbid `now()` in an
+/// embedded rules engine. Checked when the expression is parsed, see `EvaluatorConfig`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum FunctionFilter {
+    #[default]
+    All,
+    /// Whitelist
+    Only(Vec<Function>),
+    /// Blacklist
+    Except(Vec<Function>),
+}
+
+impl FunctionFilter {
+    pub fn allows(&self, function: &Function) -> bool {
+        match self {
+            Self::All => true,
+            Self::Only(functions) => functions.contains(function),
+            Self::Except(functions) => !functions.contains(function),
+        }
+    }
+}
+
+impl AST {
+    /// Error for the first call of a function not allowed by `filter`
+    pub fn check_functions(&self, filter: &FunctionFilter) -> Result<(), ParsingError> {
+        for branch in self.tree.iter_dfs() {
+            if let Token::Func(function, _) = &branch.tc().token
+            && !filter.allows(function) {
+                return Err(ParsingError::ForbiddenFunction(function.name().into(), branch.tc().at));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the referenced variables against `variables` according to `policy`.
+    /// Fields of variables (`s.x`) are accepted if `s` or `s.x` is a variable.
+    pub fn check_variables(&self, variables: &[&str], policy: VariablePolicy) -> Result<(), ParsingError> {
+        if policy == VariablePolicy::Permissive {
+            return Ok(());
+        }
+        for path in self.info().variables {
+            if !is_defined(&path, variables) {
+                let at = self.tree.iter_dfs()
+                    .find(|b| b.variable_path().as_ref() == Some(&path))
+                    .map(|b| b.tc().at)
+                    .unwrap_or(0);
+                let suggestion = suggest_identifier(&path, variables);
+                return Err(ParsingError::UndefinedVariable(path, at, suggestion));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Tokenize and parse `expression` and check that every referenced variable is
+/// among `variables`, without constructing an evaluator. This is a cheap way to
+/// validate user input, and returns the metadata of the expression (with the warnings).
+pub fn validate(expression: &str, variables: &[&str]) -> Result<ExpressionInfo, DymexError> {
+    let ts = match TokenStream::new(expression) {
+        Ok(ts) => ts,
+        Err(err) => return Err(DymexError::LexicalError(err))
+    };
+    let ast = match AST::new(ts) {
+        Ok(ast) => ast,
+        Err(err) => return Err(DymexError::ParsingError(err))
+    };
+    match ast.check_variables(variables, VariablePolicy::Strict) {
+        Ok(()) => {
+            let mut info = ast.info();
+            info.warnings = ast.warnings(variables);
+            Ok(info)
+        }
+        Err(err) => Err(DymexError::ParsingError(err))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_info() {
+        let info = validate("y = max(x, s.x) * sin(x) - sqrt(max(1, s.x))", &["x", "s"]).unwrap();
+        assert_eq!(info.variables, vec!["x".to_string(), "s.x".to_string()]);
+        assert_eq!(info.functions, vec![Function::Max, Function::Sin, Function::Sqrt]);
+        assert_eq!(info.depth, 4);
+        assert_eq!(info.assigned_to, Some("y".to_string()));
+
+        assert_eq!(validate("1", &[]).unwrap().depth, 1);
+        assert!(validate("detector.gain * 2", &["detector.gain"]).is_ok());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(matches!(validate("x + y", &["x"]),
+            Err(DymexError::ParsingError(ParsingError::UndefinedVariable(v, 4, _))) if v == "y"));
+        assert!(matches!(validate("x + (1", &["x"]), Err(DymexError::ParsingError(_))));
+        assert!(matches!(validate("x $ 1", &["x"]), Err(DymexError::LexicalError(_))));
+        assert!(matches!(validate("sx.a", &["s"]), Err(DymexError::ParsingError(_))));
+    }
+
+    #[test]
+    fn test_variable_policy() {
+        let res = Evaluator::new("x + y", &["x"]);
+        assert!(matches!(res, Err(DymexError::ParsingError(ParsingError::UndefinedVariable(v, 4, _))) if v == "y"));
+
+        let mut evaluator = Evaluator::with_policy("x + y", &["x"], VariablePolicy::Permissive).unwrap();
+        let mut inputs = InputVars::new();
+        inputs.insert_owned("x".into(), 1.0);
+        assert!(matches!(evaluator.evaluate(&inputs), Err(EvaluationError::MissingInputVariable { .. })));
+        inputs.insert_owned("y".into(), 2.0);
+        assert_eq!(evaluator.evaluate(&inputs).unwrap().as_number(), 3.0);
+
+        assert!(MultiExpEvaluator::new("z = 2*x\nz + y", &["x"]).is_err());
+        assert!(MultiExpEvaluator::new("z = 2*x\nz + x", &["x"]).is_ok());
+        assert!(MultiExpEvaluator::with_policy("z = 2*x\nz + y", &["x"], VariablePolicy::Permissive).is_ok());
+    }
+
+    #[test]
+    fn test_function_filter() {
+        let config = EvaluatorConfig { functions: FunctionFilter::Except(vec![Function::Now]), ..Default::default() };
+        let res = Evaluator::configured("x - now() + days(1)", &["x"], &config);
+        assert!(matches!(res, Err(DymexError::ParsingError(ParsingError::ForbiddenFunction(f, 4))) if f == "now"));
+        assert!(Evaluator::configured("x + days(1)", &["x"], &config).is_ok());
+
+        let config = EvaluatorConfig {
+            functions: FunctionFilter::Only(vec![Function::Min, Function::Max]),
+            tokenizer: TokenizerConfig { syntax: Syntax::Compat, ..Default::default() },
+            ..Default::default()
+        };
+        assert!(Evaluator::configured("max(x, 0) + pow(x, 2)", &["x"], &config).is_ok());
+        let Err(err) = Evaluator::configured("max(x, abs(y))", &["x", "y"], &config) else { panic!() };
+        assert_eq!(err.user_message().code(), Some("E114"));
+        assert_eq!(err.user_message().to_string(), "The function `abs` is not allowed:\n");
+        // checked before the variables
+        assert!(matches!(Evaluator::configured("sin(z)", &[], &config),
+            Err(DymexError::ParsingError(ParsingError::ForbiddenFunction(..)))));
+    }
+}
@@ -0,0 +1,444 @@
+//! JSON representation of the AST, so that other tools can inspect or construct
+//! expressions without going through text.
+//!
+//! Schema (version 2):
+//! ```text
+//! ast   = { "version": 2, "assigned_to": string | null, "tree": node }
+//! node  = { "node": "atom" | "expression",
+//!           "token": token,
+//!           "span": [at, len],              (optional, defaults to [0, 0])
+//!           "literal": string,              (optional, number literals as written)
+//!           "children": [node, ...] }       (expressions only, the operands)
+//! token = { "type": type, "value": value }  (value only where listed)
+//! ```
+//!
+//! | type        | value                                         |
+//! |-------------|-----------------------------------------------|
+//! | `Number`    | number                                        |
+//! | `Var`       | variable name                                 |
+//! | `Attr`      | field name (rhs of `Dot`)                     |
+//! | `Const`     | constant name: `Pi`, `Euler`, `Sqrt2`, ...    |
+//! | `Func`      | function name as in expressions: `max`, `sin`, ... (`"max_args"` is optional, it has to be the function's) |
+//! | `ArOp`      | `+`, `-`, `*`, `/`, `**`, `%`, `~`, `xor`     |
+//! | `RelOp`     | `>`, `<`, `==`, `≠`, `≤`, `≥`                 |
+//! | `LogicOp`   | `and`, `or`                                   |
+//! | `AssignOp`  | `=`, `+=`, `-=`, `*=`, `/=`                   |
+//! | `Dot`, `Colon`, `LB`, ... | -                               |
+//! | `Error`     | - (invalid part of a partially parsed expression) |
+//!
+//! `span` is the position and length of the token in the original expression.
+//! `literal` is the source text of a number if it differs from the printed value
+//! (`1e-9`, `2.50`), see `TokenContext::literal`.
+//!
+//! Documents of older versions are upgraded when they are read, see `AST::upgrade_json()`:
+//! - version 1 named the functions by their Rust names (`Cot`, `Avg`), which changed
+//!   whenever a variant was renamed. Version 2 uses the names of the expressions
+//!   (`cotan`, `avg`), the aliases of `Function::from_name()` (`mean`) also load.
+
+use std::fmt::Display;
+use serde_json::{Map, Value};
+use crate::{ArithmeticOperator, AssignmentOperator, Constant, Function, LogicalOperator,
+    RelationalOperator, Token, TokenContext};
+use super::{AST, Branch, ParsingError, check_calls};
+
+const SCHEMA_VERSION: u64 = 2;
+
+/// Upgrades of the older schema versions, `UPGRADES[n]` converts version `n + 1` to `n + 2`
+const UPGRADES: [fn(&mut Value) -> Result<(), ParsingError>; 1] = [upgrade_v1];
+
+const ARITHMETIC_OPS: [ArithmeticOperator; 8] = [ArithmeticOperator::Plus, ArithmeticOperator::Minus,
+    ArithmeticOperator::Mul, ArithmeticOperator::Div, ArithmeticOperator::Rem,
+    ArithmeticOperator::Pow, ArithmeticOperator::Negate, ArithmeticOperator::Xor];
+const RELATIONAL_OPS: [RelationalOperator; 6] = [RelationalOperator::Greater, RelationalOperator::Lesser,
+    RelationalOperator::Eq, RelationalOperator::Neq, RelationalOperator::Leq, RelationalOperator::Geq];
+const LOGICAL_OPS: [LogicalOperator; 2] = [LogicalOperator::And, LogicalOperator::Or];
+const ASSIGNMENT_OPS: [AssignmentOperator; 5] = [AssignmentOperator::Assign, AssignmentOperator::PlusEq,
+    AssignmentOperator::MinusEq, AssignmentOperator::TimesEq, AssignmentOperator::DivideEq];
+
+
+impl AST {
+    /// Serialize the AST to JSON, see the schema in `parser/json.rs`
+    pub fn to_json(&self) -> String {
+        self.to_json_value().to_string()
+    }
+
+    pub fn to_json_value(&self) -> Value {
+        let mut obj = Map::new();
+        obj.insert("version".into(), Value::from(SCHEMA_VERSION));
+        obj.insert("assigned_to".into(), match &self.assigned_to {
+            Some(var) => Value::from(var.as_str()),
+            None => Value::Null
+        });
+        obj.insert("tree".into(), branch_to_json(&self.tree));
+        Value::Object(obj)
+    }
+
+    /// Construct an AST from its JSON representation
+    pub fn from_json(json: &str) -> Result<Self, ParsingError> {
+        match serde_json::from_str::<Value>(json) {
+            Ok(value) => Self::from_json_value(&value),
+            Err(e) => Err(ParsingError::InvalidJson(e.to_string()))
+        }
+    }
+
+    pub fn from_json_value(value: &Value) -> Result<Self, ParsingError> {
+        let upgraded;
+        let value = match schema_version(value)? {
+            SCHEMA_VERSION => value,
+            _ => {
+                upgraded = Self::upgrade_json(value)?;
+                &upgraded
+            }
+        };
+        let tree = match value.get("tree") {
+            Some(tree) => branch_from_json(tree)?,
+            None => return Err(invalid("missing `tree`"))
+        };
+        let assigned_to = match value.get("assigned_to") {
+            None | Some(Value::Null) => None,
+            Some(Value::String(var)) => Some(var.clone()),
+            Some(_) => return Err(invalid("`assigned_to` should be a string or null"))
+        };
+        check_calls(&tree)?;
+        let ast = AST { tree, assigned_to: None }.check_assigment()?;
+        match assigned_to {
+            Some(var) => Ok(AST { assigned_to: Some(var), ..ast }),
+            None => Ok(ast)
+        }
+    }
+
+    /// Convert a document saved by an older version to the current schema,
+    /// so archives can be rewritten once instead of upgraded on every read.
+    /// Documents of a newer version than this one are rejected.
+    pub fn upgrade_json(value: &Value) -> Result<Value, ParsingError> {
+        let version = schema_version(value)?;
+        let mut value = value.clone();
+        for upgrade in &UPGRADES[version as usize - 1..] {
+            upgrade(&mut value)?;
+        }
+        value["version"] = Value::from(SCHEMA_VERSION);
+        Ok(value)
+    }
+}
+
+
+fn invalid(details: impl Into<String>) -> ParsingError {
+    ParsingError::InvalidJson(details.into())
+}
+
+fn schema_version(value: &Value) -> Result<u64, ParsingError> {
+    match value.get("version").and_then(|v| v.as_u64()) {
+        Some(v @ 1..=SCHEMA_VERSION) => Ok(v),
+        Some(v) if v > SCHEMA_VERSION => Err(invalid(format!(
+            "schema version {} is newer than the supported version {}", v, SCHEMA_VERSION))),
+        Some(v) => Err(invalid(format!("unsupported schema version {}", v))),
+        None => Err(invalid("missing schema version"))
+    }
+}
+
+/// Version 1 to 2: the Rust names of the functions to their names in expressions
+fn upgrade_v1(value: &mut Value) -> Result<(), ParsingError> {
+    fn upgrade_node(node: &mut Value) -> Result<(), ParsingError> {
+        if let Some(token) = node.get_mut("token")
+            && token.get("type").and_then(|t| t.as_str()) == Some("Func")
+        {
+            let name = token.get("value").and_then(|v| v.as_str()).unwrap_or_default();
+            let function = find_by_debug(Function::ALL, name)
+                .ok_or_else(|| invalid(format!("unknown function `{}`", name)))?;
+            token["value"] = Value::from(function.name());
+        }
+        if let Some(children) = node.get_mut("children").and_then(|c| c.as_array_mut()) {
+            children.iter_mut().try_for_each(upgrade_node)?;
+        }
+        Ok(())
+    }
+    match value.get_mut("tree") {
+        Some(tree) => upgrade_node(tree),
+        None => Err(invalid("missing `tree`"))
+    }
+}
+
+fn branch_to_json(branch: &Branch) -> Value {
+    let mut obj = Map::new();
+    let tc = branch.tc();
+    obj.insert("node".into(), Value::from(match branch {
+        Branch::Atom(_) => "atom",
+        Branch::Expression(_, _) => "expression"
+    }));
+    obj.insert("token".into(), token_to_json(&tc.token));
+    obj.insert("span".into(), Value::from(vec![tc.at, tc.len]));
+    if let Some(literal) = &tc.literal {
+        obj.insert("literal".into(), Value::from(literal.as_str()));
+    }
+    if let Branch::Expression(_, children) = branch {
+        obj.insert("children".into(), Value::Array(children.iter().map(branch_to_json).collect()));
+    }
+    Value::Object(obj)
+}
+
+fn branch_from_json(value: &Value) -> Result<Branch, ParsingError> {
+    let token = match value.get("token") {
+        Some(t) => token_from_json(t)?,
+        None => return Err(invalid("missing `token`"))
+    };
+    let (at, len) = match value.get("span").and_then(|s| s.as_array()) {
+        None => (0, 0),
+        Some(span) => match (span.get(0).and_then(|x| x.as_u64()), span.get(1).and_then(|x| x.as_u64())) {
+            (Some(at), Some(len)) if span.len() == 2 => (at as usize, len as usize),
+            _ => return Err(invalid("`span` should be [at, len]"))
+        }
+    };
+    let mut tc = TokenContext::new(token, at, len);
+    tc.literal = value.get("literal").and_then(|l| l.as_str()).map(str::to_string);
+
+    match value.get("node").and_then(|n| n.as_str()) {
+        Some("atom") => match tc.token {
+            Token::Var(_) | Token::Attr(_) | Token::Const(_) | Token::Number(_) | Token::Str(_) | Token::Error => Ok(Branch::Atom(tc)),
+            _ => Err(invalid(format!("`{}` cannot be an atom", tc.token)))
+        },
+        Some("expression") => {
+            let children = match value.get("children").and_then(|c| c.as_array()) {
+                Some(children) => children.iter()
+                    .map(branch_from_json)
+                    .collect::<Result<Vec<Branch>, ParsingError>>()?,
+                None => return Err(invalid("expressions need `children`"))
+            };
+            check_operands(&tc.token, children.len())?;
+            Ok(Branch::Expression(tc, children))
+        }
+        _ => Err(invalid("`node` should be \"atom\" or \"expression\""))
+    }
+}
+
+/// The number of children of the expression nodes, as the parser builds them.
+/// The arguments of the functions are checked by `check_calls()`.
+fn check_operands(token: &Token, n: usize) -> Result<(), ParsingError> {
+    let valid = match token {
+        Token::ArOp(ArithmeticOperator::Plus | ArithmeticOperator::Minus) => n == 1 || n == 2,
+        Token::ArOp(ArithmeticOperator::Negate) => n == 1,
+        Token::ArOp(_) | Token::RelOp(_) | Token::LogicOp(_) | Token::AssignOp(_)
+            | Token::LB | Token::Dot | Token::Colon => n == 2,
+        // the parameters and the body
+        Token::Arrow => n >= 1,
+        // a tuple
+        Token::LP => n >= 2,
+        Token::Func(_, _) => true,
+        _ => return Err(invalid(format!("`{}` cannot be an expression", token)))
+    };
+    match valid {
+        true => Ok(()),
+        false => Err(invalid(format!("`{}` cannot have {} operand(s)", token, n)))
+    }
+}
+
+fn token_to_json(token: &Token) -> Value {
+    let mut obj = Map::new();
+    let (kind, value) = match token {
+        Token::ArOp(o) => ("ArOp", Value::from(o.to_string())),
+        Token::RelOp(o) => ("RelOp", Value::from(o.to_string())),
+        Token::LogicOp(o) => ("LogicOp", Value::from(o.to_string())),
+        Token::AssignOp(o) => ("AssignOp", Value::from(o.to_string())),
+        Token::Number(x) => ("Number", Value::from(*x)),
+        Token::Str(s) => ("Str", Value::from(s.as_str())),
+        Token::Const(c) => ("Const", Value::from(format!("{:?}", c))),
+        Token::Var(v) => ("Var", Value::from(v.as_str())),
+        Token::Attr(a) => ("Attr", Value::from(a.as_str())),
+        Token::Func(f, max_args) => {
+            obj.insert("max_args".into(), Value::from(*max_args));
+            ("Func", Value::from(f.name()))
+        }
+        Token::LP => ("LP", Value::Null),
+        Token::RP => ("RP", Value::Null),
+        Token::LB => ("LB", Value::Null),
+        Token::RB => ("RB", Value::Null),
+        Token::Comma => ("Comma", Value::Null),
+        Token::Semicolon => ("Semicolon", Value::Null),
+        Token::Dot => ("Dot", Value::Null),
+        Token::Colon => ("Colon", Value::Null),
+        Token::Arrow => ("Arrow", Value::Null),
+        Token::Newline => ("Newline", Value::Null),
+        Token::Eof => ("Eof", Value::Null),
+        Token::Error => ("Error", Value::Null),
+    };
+    obj.insert("type".into(), Value::from(kind));
+    if !value.is_null() {
+        obj.insert("value".into(), value);
+    }
+    Value::Object(obj)
+}
+
+fn token_from_json(value: &Value) -> Result<Token, ParsingError> {
+    let kind = match value.get("type").and_then(|t| t.as_str()) {
+        Some(kind) => kind,
+        None => return Err(invalid("tokens need a `type`"))
+    };
+    let text = value.get("value").and_then(|v| v.as_str());
+    let unknown = || invalid(format!("invalid value for `{}` token", kind));
+
+    let token = match (kind, text) {
+        ("ArOp", Some(s)) => Token::ArOp(find_by_display(&ARITHMETIC_OPS, s).ok_or_else(unknown)?),
+        ("RelOp", Some(s)) => Token::RelOp(find_by_display(&RELATIONAL_OPS, s).ok_or_else(unknown)?),
+        ("LogicOp", Some(s)) => Token::LogicOp(find_by_display(&LOGICAL_OPS, s).ok_or_else(unknown)?),
+        ("AssignOp", Some(s)) => Token::AssignOp(find_by_display(&ASSIGNMENT_OPS, s).ok_or_else(unknown)?),
+        ("Var", Some(s)) => Token::Var(s.to_string()),
+        ("Attr", Some(s)) => Token::Attr(s.to_string()),
+        ("Str", Some(s)) => Token::Str(s.to_string()),
+        ("Const", Some(s)) => Token::Const(find_by_debug(Constant::ALL, s).ok_or_else(unknown)?),
+        ("Func", Some(s)) => {
+            let function = Function::from_name(s).ok_or_else(unknown)?;
+            let max_args = function.max_args();
+            match value.get("max_args").map(|n| n.as_u64()) {
+                None => {},
+                Some(Some(n)) if n == max_args as u64 => {},
+                Some(_) => return Err(invalid(format!("`{}` accepts at most {} arguments", function.name(), max_args)))
+            }
+            Token::Func(function, max_args)
+        }
+        ("Number", _) => match value.get("value").and_then(|v| v.as_f64()) {
+            Some(x) => Token::Number(x),
+            None => return Err(unknown())
+        },
+        ("LP", _) => Token::LP,
+        ("RP", _) => Token::RP,
+        ("LB", _) => Token::LB,
+        ("RB", _) => Token::RB,
+        ("Comma", _) => Token::Comma,
+        ("Semicolon", _) => Token::Semicolon,
+        ("Dot", _) => Token::Dot,
+        ("Colon", _) => Token::Colon,
+        ("Arrow", _) => Token::Arrow,
+        ("Newline", _) => Token::Newline,
+        ("Eof", _) => Token::Eof,
+        ("Error", _) => Token::Error,
+        _ => return Err(invalid(format!("unknown token `{}`", value)))
+    };
+    Ok(token)
+}
+
+fn find_by_display<T: Display + Clone>(options: &[T], s: &str) -> Option<T> {
+    options.iter().find(|o| o.to_string() == s).cloned()
+}
+
+fn find_by_debug<T: std::fmt::Debug + Clone>(options: &[T], s: &str) -> Option<T> {
+    options.iter().find(|o| format!("{:?}", o) == s).cloned()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_round_trip() {
+        for expr in ["(1 + x) * 3", "max(0, sqrt(min(1,2,3,4))) - pi", "y = s.x ** 2 / 4.5", "now() - t"] {
+            let ast = AST::from_expression(expr).unwrap();
+            let json = ast.to_json();
+            let parsed = AST::from_json(&json).unwrap();
+            assert_eq!(parsed.tree, ast.tree);
+            assert_eq!(parsed.assigned_to, ast.assigned_to);
+        }
+    }
+
+    #[test]
+    fn test_construct() {
+        let json = r#"{"version": 2, "assigned_to": null, "tree": {
+            "node": "expression", "token": {"type": "ArOp", "value": "*"}, "children": [
+                {"node": "atom", "token": {"type": "Number", "value": 2}},
+                {"node": "expression", "token": {"type": "Func", "value": "sin", "max_args": 1}, "children": [
+                    {"node": "atom", "token": {"type": "Var", "value": "x"}}
+                ]}
+            ]}}"#;
+        let ast = AST::from_json(json).unwrap();
+        assert_eq!(ast.rpn_repr(), "(*: 2, (Sin: x))");
+    }
+
+    #[test]
+    fn test_invalid() {
+        let json = r#"{"version": 2, "tree": {"node": "atom", "token": {"type": "ArOp", "value": "+"}}}"#;
+        assert!(matches!(AST::from_json(json), Err(ParsingError::InvalidJson(_))));
+        let json = r#"{"version": 2, "tree": {"node": "atom", "token": {"type": "Const", "value": "Tau"}}}"#;
+        assert!(matches!(AST::from_json(json), Err(ParsingError::InvalidJson(_))));
+        assert!(matches!(AST::from_json("{"), Err(ParsingError::InvalidJson(_))));
+        let json = r#"{"version": 3, "tree": {"node": "atom", "token": {"type": "Var", "value": "x"}}}"#;
+        assert!(matches!(AST::from_json(json), Err(ParsingError::InvalidJson(_))));
+    }
+
+    #[test]
+    fn test_upgrade() {
+        // saved by a version 1 writer, with the Rust names of the functions
+        let json = r#"{"version": 1, "assigned_to": "y", "tree": {
+            "node": "expression", "token": {"type": "Func", "value": "Cot", "max_args": 1}, "children": [
+                {"node": "expression", "token": {"type": "Func", "value": "Avg", "max_args": 64}, "children": [
+                    {"node": "atom", "token": {"type": "Var", "value": "x"}}
+                ]}
+            ]}}"#;
+        let ast = AST::from_json(json).unwrap();
+        assert_eq!(ast.rpn_repr(), AST::from_expression("cotan(avg(x))").unwrap().rpn_repr());
+        assert_eq!(ast.assigned_to.as_deref(), Some("y"));
+        let value: serde_json::Value = serde_json::from_str(json).unwrap();
+        let upgraded = AST::upgrade_json(&value).unwrap();
+        assert_eq!(upgraded["version"], 2);
+        assert_eq!(upgraded["tree"]["token"]["value"], "cotan");
+        assert_eq!(AST::from_json_value(&upgraded).unwrap().tree, ast.tree);
+
+        let json = r#"{"version": 1, "tree": {"node": "expression",
+            "token": {"type": "Func", "value": "Tanh", "max_args": 1}, "children": []}}"#;
+        assert!(matches!(AST::from_json(json), Err(ParsingError::InvalidJson(_))));
+        // aliases of the functions
+        let json = r#"{"version": 2, "tree": {"node": "expression",
+            "token": {"type": "Func", "value": "mean", "max_args": 64}, "children": [
+                {"node": "atom", "token": {"type": "Var", "value": "x"}}
+            ]}}"#;
+        assert_eq!(AST::from_json(json).unwrap().rpn_repr(), "(Avg: x)");
+    }
+
+    #[test]
+    fn test_invalid_calls() {
+        let x = r#"{"node": "atom", "token": {"type": "Var", "value": "x"}}"#;
+        let call = |function: &str, max_args: &str, children: &[&str]| format!(
+            r#"{{"version": 2, "tree": {{"node": "expression", "token": {{"type": "Func", "value": "{}"{}}}, "children": [{}]}}}}"#,
+            function, max_args, children.join(", "));
+        assert!(AST::from_json(&call("sin", "", &[x])).is_ok());
+        assert!(AST::from_json(&call("sin", r#", "max_args": 1"#, &[x])).is_ok());
+        // `max_args` is the function's
+        assert!(matches!(AST::from_json(&call("sin", r#", "max_args": 2"#, &[x, x])), Err(ParsingError::InvalidJson(_))));
+        // the number of arguments
+        assert!(matches!(AST::from_json(&call("sin", "", &[])), Err(ParsingError::MissingArgument(_))));
+        assert!(matches!(AST::from_json(&call("sin", "", &[x, x])), Err(ParsingError::TooManyArguments(_))));
+        // the function arguments of `map()` and the local variables of `with()`
+        assert!(matches!(AST::from_json(&call("map", "", &[x, x])), Err(ParsingError::InvalidArgument(..))));
+        assert!(matches!(AST::from_json(&call("with", "", &[x, x])), Err(ParsingError::InvalidArgument(..))));
+    }
+
+    #[test]
+    fn test_invalid_operands() {
+        let x = r#"{"node": "atom", "token": {"type": "Var", "value": "x"}}"#;
+        let expression = |token: &str, children: &[&str]| format!(
+            r#"{{"version": 2, "tree": {{"node": "expression", "token": {}, "children": [{}]}}}}"#,
+            token, children.join(", "));
+        let invalid = |token: &str, children: &[&str]|
+            matches!(AST::from_json(&expression(token, children)), Err(ParsingError::InvalidJson(_)));
+        let (plus, mul) = (r#"{"type": "ArOp", "value": "+"}"#, r#"{"type": "ArOp", "value": "*"}"#);
+        assert!(AST::from_json(&expression(plus, &[x])).is_ok());
+        assert!(AST::from_json(&expression(mul, &[x, x])).is_ok());
+        assert!(invalid(plus, &[]));
+        assert!(invalid(plus, &[x, x, x]));
+        assert!(invalid(mul, &[x]));
+        assert!(invalid(r#"{"type": "ArOp", "value": "~"}"#, &[x, x]));
+        assert!(invalid(r#"{"type": "RelOp", "value": ">"}"#, &[x]));
+        assert!(invalid(r#"{"type": "LogicOp", "value": "and"}"#, &[x, x, x]));
+        assert!(invalid(r#"{"type": "LB"}"#, &[x]));
+        assert!(invalid(r#"{"type": "Arrow"}"#, &[]));
+        assert!(invalid(r#"{"type": "LP"}"#, &[x]));
+        // the tokens of the atoms
+        assert!(invalid(r#"{"type": "Number", "value": 1}"#, &[x]));
+        assert!(invalid(r#"{"type": "Var", "value": "y"}"#, &[]));
+        assert!(invalid(r#"{"type": "Str", "value": "y"}"#, &[x]));
+        assert!(invalid(r#"{"type": "Comma"}"#, &[x, x]));
+        // the assignment
+        let assign = r#"{"type": "AssignOp", "value": "="}"#;
+        assert!(invalid(assign, &[x]));
+        assert!(invalid(assign, &[x, x, x]));
+        assert!(AST::from_json(&expression(assign, &[x, x])).is_ok());
+    }
+}
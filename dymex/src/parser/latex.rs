@@ -1,5 +1,6 @@
 use crate::{Token, ArithmeticOperator, Function, };
-use super::Branch;
+use super::{AST, Branch};
+use super::printer::{base_parens, infix_parens, sign_parens};
 use crate::Latex;
 use crate::tokenizer::*;
 
@@ -16,23 +17,24 @@ impl Latex for Token {
             Self::ArOp(op) => match op {
                 ArithmeticOperator::Plus => "+",
                 ArithmeticOperator::Minus => "-",
-                ArithmeticOperator::Mul => "⋅",
+                ArithmeticOperator::Mul => r"\cdot",
                 ArithmeticOperator::Div => "#", // special case
                 ArithmeticOperator::Pow => "#", // special case
                 ArithmeticOperator::Negate => "~",
+                ArithmeticOperator::Xor => r"\oplus ",
                 ArithmeticOperator::Rem => "%",
             }.to_string(),
             Self::RelOp(op) => match op {
                 RelationalOperator::Eq => r"\stackrel{?}{=}",
-                RelationalOperator::Neq => r"\neq",
+                RelationalOperator::Neq => r"\neq ",
                 RelationalOperator::Greater => ">",
                 RelationalOperator::Lesser => "<",
-                RelationalOperator::Geq => r"\geq",
-                RelationalOperator::Leq => r"\leq",
+                RelationalOperator::Geq => r"\geq ",
+                RelationalOperator::Leq => r"\leq ",
             }.to_string(),
             Self::LogicOp(op) => match op {
-                LogicalOperator::And => r"\land",
-                LogicalOperator::Or => r"\lor",
+                LogicalOperator::And => r"\land ",
+                LogicalOperator::Or => r"\lor ",
             }.to_string(),
             Self::AssignOp(op) => match op {
                 AssignmentOperator::Assign => "=",
@@ -44,17 +46,18 @@ impl Latex for Token {
             Self::LP => r"\left (".to_string(),
             Self::RP => r"\right )".to_string(),
             Self::LB => r"\left [".to_string(),
-            Self::RB => r"\right [".to_string(),
+            Self::RB => r"\right ]".to_string(),
             Self::Comma => ",".to_string(),
             Self::Semicolon => ";".to_string(),
             Self::Dot => ".".to_string(),
             Self::Colon => ":".to_string(),
+            Self::Arrow => r"\mapsto ".to_string(),
             Self::Const(c) => match c {
                 Constant::Pi => r"\pi",
                 Constant::Pi2 => r"\pi^{2}",
                 Constant::PiTimes2 => r"2\pi",
                 Constant::Sqrt2 => r"\sqrt{2}",
-                Constant::Sqrt3 => r"\sqrt{2}",
+                Constant::Sqrt3 => r"\sqrt{3}",
                 Constant::SqrtPi => r"\sqrt{\pi}",
                 Constant::Euler => r"e",
             }.to_string(),
@@ -62,11 +65,12 @@ impl Latex for Token {
                 Function::Max => r"\max",
                 Function::Min => r"\min",
                 Function::Abs => "#", // special case
-                Function::Avg => r"\text{avg }",
-                Function::Std => "std dev",
+                Function::Avg => r"\operatorname{avg}",
+                Function::Std => r"\operatorname{std}",
                 Function::Sqrt => "#", // special case
                 Function::Sum => r"\sum",
-                Function::Range => r"(\max - \min)",
+                Function::Prod => r"\prod",
+                Function::Range => r"\operatorname{range}",
                 Function::Sin => r"\sin",
                 Function::Cos => r"\cos",
                 Function::Cot => r"\cot",
@@ -75,12 +79,46 @@ impl Latex for Token {
                 Function::Log2 => r"\log_{2}",
                 Function::Log10 => r"\log_{10}",
                 Function::Exp => "#", // special case
+                Function::Resample => r"\operatorname{resample}",
+                Function::Shift => r"\operatorname{shift}",
+                Function::Now => r"\operatorname{now}",
+                Function::Days => r"\operatorname{days}",
+                Function::Hours => r"\operatorname{hours}",
+                Function::Map => r"\operatorname{map}",
+                Function::Filter => r"\operatorname{filter}",
+                Function::Reduce => r"\operatorname{reduce}",
+                Function::With => r"\operatorname{with}",
+                Function::Between => r"\operatorname{between}",
+                Function::IsNa => r"\operatorname{isna}",
+                Function::Coalesce => r"\operatorname{coalesce}",
+                Function::Where => r"\operatorname{where}",
+                Function::Argmin => r"\operatorname{arg\,min}",
+                Function::Argmax => r"\operatorname{arg\,max}",
+                Function::Find => r"\operatorname{find}",
+                Function::Trapz => r"\operatorname{trapz}",
+                Function::Gradient => r"\operatorname{gradient}",
+                Function::Polyval => r"\operatorname{polyval}",
+                Function::Polyfit => r"\operatorname{polyfit}",
+                Function::Solve => r"\operatorname{solve}",
+                Function::Convert => r"\operatorname{convert}",
+                Function::RoundTo => r"\operatorname{round\_to}",
+                Function::FloorTo => r"\operatorname{floor\_to}",
+                Function::CeilTo => r"\operatorname{ceil\_to}",
+                Function::RoundEven => r"\operatorname{round\_even}",
+                Function::BitAnd => r"\operatorname{band}",
+                Function::BitOr => r"\operatorname{bor}",
+                Function::BitXor => r"\operatorname{bxor}",
+                Function::Shl => r"\operatorname{shl}",
+                Function::Shr => r"\operatorname{shr}",
+                Function::Format => r"\operatorname{format}",
             }.to_string(),
             Self::Var(s) => format_var_name(s),
             Self::Attr(a) => a.to_string(),
             Self::Newline => "".to_string(), // ! is this OK?
             Self::Eof => "".to_string(),
+            Self::Error => r"\boxed{?}".to_string(),
             Self::Number(x) => format!("{}", x),
+            Self::Str(s) => format!(r"\text{{``{}''}}", s),
         }
     }
 }
@@ -98,9 +136,6 @@ fn format_ending_digits(v: &str) -> String {
         let normal : String = name[0..name.len()-ending_digits].iter().collect();
         let subscript: String = name[name.len()-ending_digits..].iter().collect();
 
-        println!("{} {} {}", ending_digits, normal, subscript);
-
-
         formatted.push_str(&format!("{}_{{{}}}", normal, subscript));
         formatted
     } else {
@@ -133,6 +168,19 @@ fn is_greek_capital(v: &str) -> bool {
 
 
 
+impl AST {
+    /// The expression as LaTeX math, for rendering the parsed formula: divisions
+    /// are fractions, powers superscripts, function names upright and parentheses
+    /// are only added where the precedence of the operators requires them
+    /// (as in `to_expression()`). The result is meant for a math environment.
+    pub fn to_latex(&self) -> String {
+        match &self.assigned_to {
+            Some(varname) => format!("{} = {}", Token::Var(varname.clone()).latex(), self.tree.latex()),
+            None => self.tree.latex()
+        }
+    }
+}
+
 impl Latex for Branch {
     fn latex(&self) -> String {
         fn arg_list(args: &[Branch]) -> String {
@@ -143,30 +191,46 @@ impl Latex for Branch {
             .join(", ")
         }
         match self {
-            Self::Atom(tc) => return tc.token.latex(),
+            Self::Atom(tc) if let Token::Number(x) = tc.token && x.is_sign_negative() => {
+                format!("\\left ( {} \\right )", tc.literal.clone().unwrap_or_else(|| tc.token.latex()))
+            }
+            Self::Atom(tc) if let Some(literal) = &tc.literal => literal.clone(),
+            Self::Atom(tc) => tc.token.latex(),
             Self::Expression(tc, c) => {
-                match &tc.token {
-                    Token::ArOp(op) => match (op, c.len()) {
-                        (_, 1) => return [tc.token.latex(), c[0].latex()].concat(),
-                        (ArithmeticOperator::Div, _) => return format!("\\frac{{{}}}{{{}}}", c[0].latex(), c[1].latex()),
-                        (ArithmeticOperator::Pow, _) => return format!("({})^{{{}}}", c[0].latex(), c[1].latex()),
-                        _ => return [c[0].latex(), tc.token.latex(), c[1].latex()].concat()
+                match (&tc.token, c.as_slice()) {
+                    (Token::ArOp(ArithmeticOperator::Div), [a, b]) => format!("\\frac{{{}}}{{{}}}", a.latex(), b.latex()),
+                    (Token::ArOp(ArithmeticOperator::Pow), [a, b]) => {
+                        format!("{}^{{{}}}", operand(a, base_parens(a)), b.latex())
+                    }
+                    (Token::ArOp(_), [a]) => format!("{}{}", tc.token.latex(), operand(a, sign_parens(a))),
+                    (op, [lhs, rhs]) if let Some((lhs_parens, rhs_parens)) = infix_parens(op, lhs, rhs) => {
+                        format!("{} {} {}", operand(lhs, lhs_parens), tc.token.latex(), operand(rhs, rhs_parens))
                     }
-                    Token::RelOp(_)
-                    |Token::AssignOp(_)
-                    |Token::LogicOp(_) => return [c[0].latex(), tc.token.latex(), c[1].latex()].concat(),
-                    Token::Func(f, _) => match f {
-                        Function::Abs => return format!("\\left | {} \\right |", c[0].latex()),
-                        Function::Sqrt => return format!("\\sqrt {{{}}}", c[0].latex()),
-                        Function::Exp =>  return format!("e^{{{}}}", c[0].latex()),
-                        _ => return format!("{}\\left ( {} \\right )", tc.token.latex(), arg_list(c))
+                    (Token::Func(Function::Abs, _), [x]) => format!("\\left | {} \\right |", x.latex()),
+                    (Token::Func(Function::Sqrt, _), [x]) => format!("\\sqrt{{{}}}", x.latex()),
+                    (Token::Func(Function::Exp, _), [x]) => format!("e^{{{}}}", x.latex()),
+                    (Token::Func(..), args) => format!("{}\\left ( {} \\right )", tc.token.latex(), arg_list(args)),
+                    (Token::Arrow, [param, body]) => format!("{} {}{}", param.latex(), tc.token.latex(), body.latex()),
+                    (Token::Arrow, [params @ .., body]) => {
+                        format!("\\left ( {} \\right ) {}{}", arg_list(params), tc.token.latex(), body.latex())
                     }
+                    (Token::Dot, [obj, attr]) => format!("{}.{}", operand(obj, base_parens(obj)), attr.latex()),
+                    (Token::LB, [array, index]) => {
+                        format!("{}\\left [ {} \\right ]", operand(array, base_parens(array)), index.latex())
+                    }
+                    (Token::LP, elements) => format!("\\left ( {} \\right )", arg_list(elements)),
                     _ => tc.token.latex() // number, constant, variable, ()[],.;:
                 }
             }
         }
     }
+}
 
+fn operand(branch: &Branch, parens: bool) -> String {
+    match parens {
+        true => format!("\\left ( {} \\right )", branch.latex()),
+        false => branch.latex()
+    }
 }
 
 
@@ -183,4 +247,19 @@ mod tests {
 
     }
 
-}
\ No newline at end of file
+    fn latex(expr: &str) -> String {
+        AST::from_expression(expr).unwrap().to_latex()
+    }
+
+    #[test]
+    fn test_ast_to_latex() {
+        assert_eq!(latex("y = (a + b) / 2"), r"y = \frac{a + b}{2}");
+        assert_eq!(latex("(a + b) * c - d / e"), r"\left ( a + b \right ) \cdot c - \frac{d}{e}");
+        assert_eq!(latex("(x1 + 1) ** 2 + x ** (n - 1)"), r"\left ( x_{1} + 1 \right )^{2} + x^{n - 1}");
+        assert_eq!(latex("sqrt(alpha ** 2) + abs(-x)"), r"\sqrt{\alpha^{2}} + \left | -x \right |");
+        assert_eq!(latex("avg(v) * exp(-t)"), r"\operatorname{avg}\left ( v \right ) \cdot e^{-t}");
+        assert_eq!(latex("-(a - b) ** 2"), r"-\left ( a - b \right )^{2}");
+        assert_eq!(latex("a - (b - c)"), r"a - \left ( b - c \right )");
+        assert_eq!(latex("sin(x) * (-2)"), r"\sin\left ( x \right ) \cdot \left ( -2 \right )");
+    }
+}
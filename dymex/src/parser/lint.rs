@@ -0,0 +1,154 @@
+use crate::{ArithmeticOperator, Diagnostic, MessageId, Severity, Token, UserMessage};
+use super::{AST, Branch};
+
+
+/// Suspicious part of a valid expression, reported by `AST::warnings()` and
+/// `validate()`. These are the usual mistakes, the expression is evaluated as written.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Warning {
+    /// The input variable is not used by the expression
+    UnusedVariable(String),
+    /// Comparison of identical operands or of numbers (`x == x`, `2 > 1`), the
+    /// result does not depend on the inputs
+    ConstantComparison(usize),
+    /// Division of integer literals (`1 / 3`), it is not an integer division:
+    /// the result is `0.333...`, not `0`
+    IntegerDivision(usize),
+    /// Assignment to an input variable (`x = x * 2`), the result hides the input
+    ShadowedAssignment(String),
+}
+
+impl Warning {
+    /// Stable code of the warning, `W001` to `W099`
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UnusedVariable(_) => "W001",
+            Self::ConstantComparison(_) => "W002",
+            Self::IntegerDivision(_) => "W003",
+            Self::ShadowedAssignment(_) => "W004",
+        }
+    }
+
+    pub fn user_message(&self) -> UserMessage {
+        let message = match self {
+            Self::UnusedVariable(name) => UserMessage::new(
+                    format!("Unused variable: `{}`", name),
+                    None,
+                    None,
+                    None)
+                .with_id(MessageId::UnusedVariable)
+                .with_arg("name", name),
+            Self::ConstantComparison(i) => UserMessage::new(
+                    "The result of the comparison is always the same:",
+                    Some(*i),
+                    None,
+                    None)
+                .with_id(MessageId::ConstantComparison),
+            Self::IntegerDivision(i) => UserMessage::new(
+                    "Division of integers:",
+                    Some(*i),
+                    Some("The result is not rounded: 1 / 3 = 0.333..., use floor(1 / 3) for integer division"),
+                    None)
+                .with_id(MessageId::IntegerDivision),
+            Self::ShadowedAssignment(name) => UserMessage::new(
+                    format!("Assignment to the input variable `{}`", name),
+                    None,
+                    None,
+                    None)
+                .with_id(MessageId::ShadowedAssignment)
+                .with_arg("name", name),
+        };
+        message.with_code(self.code())
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        Diagnostic { severity: Severity::Warning, ..self.user_message().diagnostic() }
+    }
+}
+
+impl AST {
+    /// Suspicious parts of the expression, see `Warning`. `variables` are the
+    /// inputs, for the unused and shadowed ones.
+    pub fn warnings(&self, variables: &[&str]) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+        if let Some(name) = &self.assigned_to
+        && variables.contains(&name.as_str()) {
+            warnings.push(Warning::ShadowedAssignment(name.clone()));
+        }
+        collect_warnings(&self.tree, &mut warnings);
+        let used: Vec<String> = self.tree.free_variables().into_iter()
+            .map(|path| path.split('.').next().unwrap_or(&path).to_string())
+            .collect();
+        for variable in variables {
+            let root = variable.split('.').next().unwrap_or(variable);
+            if !used.iter().any(|u| u == root) {
+                warnings.push(Warning::UnusedVariable(variable.to_string()));
+            }
+        }
+        warnings
+    }
+}
+
+fn collect_warnings(branch: &Branch, warnings: &mut Vec<Warning>) {
+    let Branch::Expression(tc, args) = branch else {
+        return;
+    };
+    match (&tc.token, args.as_slice()) {
+        (Token::RelOp(_), [lhs, rhs]) if lhs.to_string() == rhs.to_string() || (is_number(lhs) && is_number(rhs)) => {
+            warnings.push(Warning::ConstantComparison(tc.at));
+        }
+        (Token::ArOp(ArithmeticOperator::Div), [lhs, rhs]) if is_integer(lhs) && is_integer(rhs) => {
+            warnings.push(Warning::IntegerDivision(tc.at));
+        }
+        _ => {}
+    }
+    for arg in args {
+        collect_warnings(arg, warnings);
+    }
+}
+
+fn is_number(branch: &Branch) -> bool {
+    matches!(branch, Branch::Atom(tc) if matches!(tc.token, Token::Number(_)))
+}
+
+/// A number literal written as an integer (`3`, not `3.0` or `3e0`)
+fn is_integer(branch: &Branch) -> bool {
+    match branch {
+        Branch::Atom(tc) => match tc.token {
+            Token::Number(x) => x.fract() == 0.0 && !tc.literal.as_ref().is_some_and(|l| l.contains(['.', 'e', 'E'])),
+            _ => false
+        },
+        _ => false
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn warnings(expression: &str, variables: &[&str]) -> Vec<Warning> {
+        AST::from_expression(expression).unwrap().warnings(variables)
+    }
+
+    #[test]
+    fn test_warnings() {
+        assert_eq!(warnings("x * 2", &["x", "y"]), [Warning::UnusedVariable("y".into())]);
+        assert_eq!(warnings("s.a + 1", &["s"]), []);
+        assert_eq!(warnings("x + (x == x)", &["x"]), [Warning::ConstantComparison(7)]);
+        assert_eq!(warnings("sin(x + 1) >= sin(x + 1)", &["x"]), [Warning::ConstantComparison(11)]);
+        assert_eq!(warnings("2 > 1", &[]), [Warning::ConstantComparison(2)]);
+        assert_eq!(warnings("x * (1 / 3)", &["x"]), [Warning::IntegerDivision(7)]);
+        assert_eq!(warnings("x * 1.0 / 3 + x / 3", &["x"]), []);
+        assert_eq!(warnings("x = x * 2", &["x"]), [Warning::ShadowedAssignment("x".into())]);
+
+        let info = validate("a / 2 + 4 / 2", &["a", "b"]).unwrap();
+        assert_eq!(info.warnings, [Warning::IntegerDivision(10), Warning::UnusedVariable("b".into())]);
+        assert_eq!(info.warnings[1].diagnostic(), Diagnostic {
+            code: "W001",
+            span: None,
+            severity: Severity::Warning,
+            message: "Unused variable: `b`".into()
+        });
+    }
+}
@@ -0,0 +1,220 @@
+//! MathML rendering of expressions for web interfaces. Every node of the tree
+//! is an element with the id `{prefix}{position}` (the position of its token is
+//! the node id) and the source span of the subtree in `data-start` and `data-end`,
+//! so the rendered formula can be linked back to the expression: hovering a
+//! part of the formula can highlight the source, and the node under the cursor
+//! of an editor can be highlighted in the formula.
+//! ```ignore
+//! let ast = AST::from_expression("y = (a + b) / 2")?;
+//! let html = format!("<p>{}</p>", ast.to_mathml("f1-"));
+//! ```
+
+use std::fmt::Write;
+use crate::{ArithmeticOperator, AssignmentOperator, Constant, Function, LogicalOperator, RelationalOperator, Token};
+use super::{AST, Branch};
+use super::printer::{base_parens, infix_parens, sign_parens};
+
+
+impl AST {
+    /// The expression as a `<math>` element, see `parser/mathml.rs`. The node ids
+    /// are prefixed by `id_prefix`, to keep them unique when a page shows several formulas.
+    pub fn to_mathml(&self, id_prefix: &str) -> String {
+        let mut s = String::from("<math display=\"block\"><mrow>");
+        if let Some(varname) = &self.assigned_to {
+            write!(s, "<mi>{}</mi><mo>=</mo>", escape(varname)).unwrap(); // writing to a String does not fail
+        }
+        write_node(&self.tree, id_prefix, &mut s);
+        s.push_str("</mrow></math>");
+        s
+    }
+}
+
+fn write_node(branch: &Branch, prefix: &str, s: &mut String) {
+    let tc = branch.tc();
+    let span = branch.span();
+    let attributes = format!(" id=\"{}{}\" data-start=\"{}\" data-end=\"{}\"", prefix, tc.at, span.start, span.end);
+    let args: &[Branch] = match branch {
+        Branch::Atom(_) => &[],
+        Branch::Expression(_, args) => args
+    };
+    match (&tc.token, args) {
+        (Token::Number(x), []) => {
+            let text = tc.literal.clone().unwrap_or_else(|| x.to_string());
+            match x.is_sign_negative() {
+                true => write!(s, "<mo>(</mo><mn{}>{}</mn><mo>)</mo>", attributes, escape(&text)).unwrap(),
+                false => write!(s, "<mn{}>{}</mn>", attributes, escape(&text)).unwrap()
+            }
+        }
+        (Token::Var(name) | Token::Attr(name), []) => write!(s, "<mi{}>{}</mi>", attributes, escape(name)).unwrap(),
+        (Token::Const(c), []) => write!(s, "<mi{}>{}</mi>", attributes, constant(c)).unwrap(),
+        (Token::Str(text), []) => write!(s, "<ms{}>{}</ms>", attributes, escape(text)).unwrap(),
+        (Token::ArOp(ArithmeticOperator::Div), [a, b]) => {
+            write!(s, "<mfrac{}>", attributes).unwrap();
+            write_row(a, false, prefix, s);
+            write_row(b, false, prefix, s);
+            s.push_str("</mfrac>");
+        }
+        (Token::ArOp(ArithmeticOperator::Pow), [a, b]) => {
+            write!(s, "<msup{}>", attributes).unwrap();
+            write_row(a, base_parens(a), prefix, s);
+            write_row(b, false, prefix, s);
+            s.push_str("</msup>");
+        }
+        (Token::Func(Function::Sqrt, _), [x]) => {
+            write!(s, "<msqrt{}>", attributes).unwrap();
+            write_node(x, prefix, s);
+            s.push_str("</msqrt>");
+        }
+        (Token::Func(Function::Abs, _), [x]) => {
+            write!(s, "<mrow{}><mo>|</mo>", attributes).unwrap();
+            write_node(x, prefix, s);
+            s.push_str("<mo>|</mo></mrow>");
+        }
+        (op, [a]) if matches!(op, Token::ArOp(_)) => {
+            write!(s, "<mrow{}><mo>{}</mo>", attributes, operator(op)).unwrap();
+            write_parenthesized(a, sign_parens(a), prefix, s);
+            s.push_str("</mrow>");
+        }
+        (op, [lhs, rhs]) if let Some((lhs_parens, rhs_parens)) = infix_parens(op, lhs, rhs) => {
+            write!(s, "<mrow{}>", attributes).unwrap();
+            write_parenthesized(lhs, lhs_parens, prefix, s);
+            write!(s, "<mo>{}</mo>", operator(op)).unwrap();
+            write_parenthesized(rhs, rhs_parens, prefix, s);
+            s.push_str("</mrow>");
+        }
+        (Token::Func(f, _), args) => {
+            write!(s, "<mrow{}><mi mathvariant=\"normal\">{}</mi><mo>&#x2061;</mo>", attributes, f.name()).unwrap();
+            write_list(args, prefix, s);
+            s.push_str("</mrow>");
+        }
+        (Token::LP, elements) => {
+            write!(s, "<mrow{}>", attributes).unwrap();
+            write_list(elements, prefix, s);
+            s.push_str("</mrow>");
+        }
+        (Token::Arrow, [params @ .., body]) => {
+            write!(s, "<mrow{}>", attributes).unwrap();
+            match params {
+                [param] => write_node(param, prefix, s),
+                params => write_list(params, prefix, s),
+            }
+            s.push_str("<mo>&#x21A6;</mo>");
+            write_node(body, prefix, s);
+            s.push_str("</mrow>");
+        }
+        (Token::Dot, [obj, attr]) => {
+            write!(s, "<mrow{}>", attributes).unwrap();
+            write_parenthesized(obj, base_parens(obj), prefix, s);
+            s.push_str("<mo>.</mo>");
+            write_node(attr, prefix, s);
+            s.push_str("</mrow>");
+        }
+        (Token::LB, [array, index]) => {
+            write!(s, "<mrow{}>", attributes).unwrap();
+            write_parenthesized(array, base_parens(array), prefix, s);
+            s.push_str("<mo>[</mo>");
+            write_node(index, prefix, s);
+            s.push_str("<mo>]</mo></mrow>");
+        }
+        (token, _) => write!(s, "<mo{}>{}</mo>", attributes, escape(&token.to_string())).unwrap()
+    }
+}
+
+/// The node as a single element (`<mfrac>` and `<msup>` take exactly one per operand)
+fn write_row(branch: &Branch, parens: bool, prefix: &str, s: &mut String) {
+    s.push_str("<mrow>");
+    write_parenthesized(branch, parens, prefix, s);
+    s.push_str("</mrow>");
+}
+
+fn write_parenthesized(branch: &Branch, parens: bool, prefix: &str, s: &mut String) {
+    if parens {
+        s.push_str("<mo>(</mo>");
+        write_node(branch, prefix, s);
+        s.push_str("<mo>)</mo>");
+    } else {
+        write_node(branch, prefix, s);
+    }
+}
+
+/// `(a, b, c)`
+fn write_list(elements: &[Branch], prefix: &str, s: &mut String) {
+    s.push_str("<mrow><mo>(</mo>");
+    for (i, element) in elements.iter().enumerate() {
+        if i > 0 {
+            s.push_str("<mo>,</mo>");
+        }
+        write_node(element, prefix, s);
+    }
+    s.push_str("<mo>)</mo></mrow>");
+}
+
+fn operator(op: &Token) -> String {
+    match op {
+        Token::ArOp(op) => match op {
+            ArithmeticOperator::Plus => "+",
+            ArithmeticOperator::Minus => "&#x2212;",
+            ArithmeticOperator::Mul => "&#x22C5;",
+            ArithmeticOperator::Div => "/",
+            ArithmeticOperator::Pow => "^",
+            ArithmeticOperator::Rem => "mod",
+            ArithmeticOperator::Xor => "&#x2295;",
+            ArithmeticOperator::Negate => "~",
+        }.to_string(),
+        Token::RelOp(op) => match op {
+            RelationalOperator::Eq => "=",
+            RelationalOperator::Neq => "&#x2260;",
+            RelationalOperator::Greater => "&gt;",
+            RelationalOperator::Lesser => "&lt;",
+            RelationalOperator::Geq => "&#x2265;",
+            RelationalOperator::Leq => "&#x2264;",
+        }.to_string(),
+        Token::LogicOp(op) => match op {
+            LogicalOperator::And => "&#x2227;",
+            LogicalOperator::Or => "&#x2228;",
+        }.to_string(),
+        Token::AssignOp(AssignmentOperator::Assign) => "=".to_string(),
+        token => escape(&token.to_string())
+    }
+}
+
+fn constant(c: &Constant) -> &'static str {
+    match c {
+        Constant::Pi => "&#x3C0;",
+        Constant::Pi2 => "&#x3C0;²",
+        Constant::PiTimes2 => "2&#x3C0;",
+        Constant::Sqrt2 => "&#x221A;2",
+        Constant::Sqrt3 => "&#x221A;3",
+        Constant::SqrtPi => "&#x221A;&#x3C0;",
+        Constant::Euler => "e",
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_mathml() {
+        let ast = AST::from_expression("y = (a + b) / 2").unwrap();
+        assert_eq!(ast.to_mathml("f-"), concat!(
+            "<math display=\"block\"><mrow><mi>y</mi><mo>=</mo>",
+            "<mfrac id=\"f-12\" data-start=\"5\" data-end=\"15\">",
+            "<mrow><mrow id=\"f-7\" data-start=\"5\" data-end=\"10\"><mi id=\"f-5\" data-start=\"5\" data-end=\"6\">a</mi>",
+            "<mo>+</mo><mi id=\"f-9\" data-start=\"9\" data-end=\"10\">b</mi></mrow></mrow>",
+            "<mrow><mn id=\"f-14\" data-start=\"14\" data-end=\"15\">2</mn></mrow></mfrac></mrow></math>"
+        ));
+
+        let expression = "2 * max(x, -1.50) ** 2 < sqrt(x)";
+        let mathml = AST::from_expression(expression).unwrap().to_mathml("");
+        assert!(mathml.contains("<mi mathvariant=\"normal\">max</mi>"));
+        assert!(mathml.contains("<msup id=\"18\" data-start=\"4\" data-end=\"22\">"), "{}", mathml);
+        assert!(mathml.contains("<mo>(</mo><mn id=\"11\" data-start=\"11\" data-end=\"16\">-1.50</mn><mo>)</mo>"), "{}", mathml);
+        assert!(mathml.contains("<mo>&lt;</mo><msqrt id=\"25\""));
+    }
+}
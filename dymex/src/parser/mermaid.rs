@@ -45,6 +45,7 @@ fn assign_mermaid_class(t: &Token) -> &str {
         |Token::AssignOp(_)
         |Token::Dot => "mmdOp",
         Token::Number(_)
+        |Token::Str(_)
         |Token::Const(_) => "mmdConst",
         Token::Var(_) => "mmdVar",
         Token::Func(_, _) => "mmdFunc",
@@ -85,6 +86,7 @@ pub struct MermaidGraph {
     id_counter: usize,
     expression: Option<String>,
     variables: Vec::<String>,
+    annotations: HashMap<usize, String>,
     pub style_options: MermaidStyle
 }
 
@@ -98,6 +100,7 @@ impl MermaidGraph {
             id_counter: 0,
             expression: None,
             variables: Vec::new(),
+            annotations: HashMap::new(),
             style_options: MermaidStyle::new(),
         }
     }
@@ -107,6 +110,13 @@ impl MermaidGraph {
         self
     }
 
+    /// Extra text for the nodes, keyed by the position of their token in the
+    /// expression (e.g. timings from `Profile::annotations()`)
+    pub fn with_annotations(mut self, annotations: HashMap<usize, String>) -> Self {
+        self.annotations = annotations;
+        self
+    }
+
     pub fn from_ast(ast: &Branch) -> Self {
         let mut graph = Self::new();
         graph.ast = Some(ast.clone());
@@ -219,29 +229,47 @@ impl MermaidGraph {
         self.id_counter += 1;
     }
 
+    /// Annotation of the node as an additional line of a quoted label
+    fn annotation(&self, tc: &TokenContext) -> String {
+        match self.annotations.get(&tc.at) {
+            Some(text) => format!("<br/>{}", text),
+            None => String::new()
+        }
+    }
+
+    /// Label of the node, quoted if it is annotated
+    fn label(&self, tc: &TokenContext) -> String {
+        match self.annotations.get(&tc.at) {
+            Some(text) => format!("\"{}<br/>{}\"", tc.text(), text),
+            None => tc.text()
+        }
+    }
+
     fn plain_node(&self, tc: &TokenContext) -> String {
         match tc.token {
             // TODO: fill in missing cases
-            Token::ArOp(x) => format!("{{\" \\{} \"}}", x),
-            Token::Func(_, 1) => format!("[\\ {} /]", tc.token),
-            Token::Func(_, _) => format!("> {} ]", tc.token),
-            Token::Const(_) => format!("[[ {} ]]", tc.token),
-            Token::Number(_)=> format!("[ {} ]", tc.token),
-            Token::Var(_)=> format!("( {} )", tc.token),
-            _ => format!("[\" {} \"]", tc.token)
+            Token::ArOp(x) => format!("{{\" \\{}{} \"}}", x, self.annotation(tc)),
+            Token::Func(_, 1) => format!("[\\ {} /]", self.label(tc)),
+            Token::Func(_, _) => format!("> {} ]", self.label(tc)),
+            Token::Const(_) => format!("[[ {} ]]", self.label(tc)),
+            Token::Number(_)=> format!("[ {} ]", self.label(tc)),
+            Token::Str(ref s) => format!("[\" #quot;{}#quot;{} \"]", s, self.annotation(tc)),
+            Token::Var(_)=> format!("( {} )", self.label(tc)),
+            _ => format!("[\" {}{} \"]", tc.token, self.annotation(tc))
         }
     }
 
     fn fancy_node(&self, tc: &TokenContext) -> String {
         let mut base = match tc.token {
             // TODO: fill in missing cases
-            Token::ArOp(x) => format!("(\" \\{} \")", x),
-            Token::Func(_, 1) => format!("( {} )", tc.token),
-            Token::Func(_, _) => format!("( {} )", tc.token),
-            Token::Const(_) => format!("( {} )", tc.token),
-            Token::Number(_)=> format!("( {} )", tc.token),
-            Token::Var(_)=> format!("( {} )", tc.token),
-            _ => format!("(\" {} \")", tc.token)
+            Token::ArOp(x) => format!("(\" \\{}{} \")", x, self.annotation(tc)),
+            Token::Func(_, 1) => format!("( {} )", self.label(tc)),
+            Token::Func(_, _) => format!("( {} )", self.label(tc)),
+            Token::Const(_) => format!("( {} )", self.label(tc)),
+            Token::Number(_)=> format!("( {} )", self.label(tc)),
+            Token::Str(ref s) => format!("(\" #quot;{}#quot;{} \")", s, self.annotation(tc)),
+            Token::Var(_)=> format!("( {} )", self.label(tc)),
+            _ => format!("(\" {}{} \")", tc.token, self.annotation(tc))
         };
         base.push_str(":::");
         base.push_str(assign_mermaid_class(&tc.token));
@@ -294,6 +322,22 @@ mod tests {
         };
     }
 
+    #[test]
+    fn annotate_with_profile() {
+        let expr = "sin(x) + 1";
+        let mut evaluator = Evaluator::new(expr, &["x"]).unwrap();
+        evaluator.enable_profiling();
+        let mut inputs = InputVars::new();
+        inputs.insert_owned("x".into(), 0.5);
+        evaluator.evaluate(&inputs).unwrap();
+
+        let annotations = evaluator.profile().unwrap().annotations();
+        let graph = MermaidGraph::from_expr(expr.into(), &["x"]).with_annotations(annotations).to_string();
+        assert!(graph.contains("[\\ \"Sin<br/>1 calls"));
+        assert!(graph.contains("{\" \\+<br/>1 calls"));
+        assert!(graph.contains("( x )"));
+    }
+
     #[test]
     fn create_assignment() {
         let input_var = &["x", "y"];
@@ -1,8 +1,9 @@
 /// Turn a stream of tokens into an anstract syntax tree
 
-use std::{collections::{HashMap, VecDeque}, fmt::Write};
+use std::{collections::VecDeque, fmt, ops::Range};
 use colored::{Colorize, Color};
-use crate::{ArithmeticOperator, AssignmentOperator, Token, TokenContext, TokenStream};
+use crate::{AssignmentOperator, Function, Token, TokenContext, TokenStream};
+use crate::tokenizer::MAX_FUNC_ARGS;
 
 mod latex;
 pub use latex::*;
@@ -10,6 +11,36 @@ mod error;
 pub use error::ParsingError;
 mod mermaid;
 pub use mermaid::*;
+mod mathml;
+mod codegen;
+mod translate;
+pub(crate) use translate::{Emitter, translate};
+#[cfg(feature = "json")]
+mod json;
+#[cfg(feature = "polars")]
+mod polars;
+#[cfg(feature = "polars")]
+pub use self::polars::UdfMode;
+mod builder;
+pub use builder::*;
+mod info;
+pub use info::*;
+mod lint;
+pub use lint::*;
+mod suggest;
+pub use suggest::{closest_name, levenshtein};
+mod diff;
+pub use diff::*;
+mod canonical;
+pub use canonical::Fingerprint;
+mod printer;
+mod flat;
+pub use flat::*;
+mod partial;
+pub use partial::*;
+mod precedence;
+pub use precedence::{Associativity, Fixity, OperatorRule, ParserConfig, PRECEDENCE_TABLE};
+use precedence::{infix_precedence, prefix_precedence};
 
 /// Abstract syntax tree
 #[derive(Debug, Clone)]
@@ -19,19 +50,29 @@ pub struct AST {
 }
 
 impl AST {
-    pub fn new(mut ts: TokenStream) -> Result<Self, ParsingError> {
+    pub fn new(ts: TokenStream) -> Result<Self, ParsingError> {
+        Self::with_config(ts, &ParserConfig::default())
+    }
+
+    /// Parse with custom operator precedences, see `ParserConfig`
+    pub fn with_config(mut ts: TokenStream, config: &ParserConfig) -> Result<Self, ParsingError> {
 
         if let Err(e) = Self::check_parens(&ts) {
             return Err(e);
         }
-        if let Err(e) = Self::check_tokens(&ts) {
-            return Err(e);
-        }
 
-        let tree = match pratt_parser(&mut ts, 0) {
+        let tree = match pratt_parser(&mut ts, 0, config, &mut None) {
             Err(e) => return Err(e),
             Ok(branch) => Some(branch)
         };
+        // tokens that are not operators in the config: `2 ** 3` without `**`
+        let rest = ts.next();
+        if rest.token != Token::Eof {
+            return Err(ParsingError::UnexpectedToken(rest.at));
+        }
+        if let Some(tree) = &tree {
+            check_lambdas(tree)?;
+        }
 
         let ast = AST {
             tree: tree.unwrap(),
@@ -52,10 +93,27 @@ impl AST {
         self.tree.as_rpn_str()
     }
 
-    pub fn flatten_ast(&self) -> FlatAst {
-        let mut ast = FlatAst::new();
-        traverse_ast(&self.tree, &mut ast, 0);
-        ast
+    /// The subtree of the node with the id `id` (the position of its token, as in
+    /// the Mermaid graph and the MathML output) as an expression of its own.
+    /// The assignment of the whole expression is not included.
+    pub fn subexpression(&self, id: usize) -> Option<AST> {
+        DFSBranchIter::new(&self.tree)
+            .find(|branch| branch.tc().at == id)
+            .map(|branch| AST { tree: branch.clone(), assigned_to: None })
+    }
+
+    /// The id of the innermost node whose source span (see `Branch::span()`)
+    /// contains the character position `position`, like the cursor of an editor
+    pub fn node_at(&self, position: usize) -> Option<usize> {
+        let mut branch = &self.tree;
+        if !branch.span().contains(&position) {
+            return None;
+        }
+        while let Branch::Expression(_, children) = branch
+            && let Some(child) = children.iter().find(|child| child.span().contains(&position)) {
+            branch = child;
+        }
+        Some(branch.tc().at)
     }
 
     fn check_parens(ts: &TokenStream) -> Result<(), ParsingError> {
@@ -75,25 +133,6 @@ impl AST {
         }
     }
 
-    fn check_tokens(ts: &TokenStream) -> Result<(), ParsingError> {
-        for tc in ts.tokens() {
-            let token = &tc.token;
-            match token {
-                // Token::AssignOp(x) if *x != AssignmentOperator::Assign => {
-                //     return Err(ParsingError::InvalidOperation(tc.at, "Only simple assignment is implemented.".into()));
-                // }
-                Token::LogicOp(_) => {
-                    return Err(ParsingError::InvalidOperation(tc.at, "Logical operators are not implemented.".into()));
-                }
-                Token::RelOp(_) => {
-                    return Err(ParsingError::InvalidOperation(tc.at, "Comparison operators are not implemented.".into()));
-                }
-                _ => {}
-            }
-        }
-        Ok(())
-    }
-
     fn check_assigment(mut self) -> Result<Self, ParsingError> {
         // Check for top level assignement, and transform self accordingly
         //    Self {tree: `varname = expression`, assigned_to = None}
@@ -115,7 +154,8 @@ impl AST {
                                 self.assigned_to = Some(assigned_to.clone());
                                 self.tree = rhs.clone(); // unnecessary clone, non-trivial to circumvent
                             },
-                            _ => panic!("Assignement should have exactly two arguments, found instead {}", children.len())
+                            _ => return Err(ParsingError::InvalidAssignment(
+                                format!("Assignement should have exactly two arguments, found instead {}", children.len()), tc.at))
                         }
                     }
                     _ => {}
@@ -123,30 +163,27 @@ impl AST {
             }
         }
         // Check for deeper assignements
-        for branch in self.tree.iter_dfs() {
-            if let Branch::Expression(tc, _ ) = branch
-            && let Token::AssignOp(assignment) = &tc.token
-            && let AssignmentOperator::Assign = assignment {
-                return Err(ParsingError::InvalidAssignment("Only top level assignement is supported".to_string(), tc.at));
-            }
+        if let Some(tc) = nested_assignment(&self.tree) {
+            return Err(ParsingError::InvalidAssignment("Only top level assignement is supported".to_string(), tc.at));
         }
         Ok(self)
     }
 
     pub fn check_input_vars<S: AsRef<str>>(&self, inputs: &[S]) -> Result<(), ParsingError> {
         let variables: Vec<&str> = inputs.iter().map(|s| s.as_ref()).collect();
-        for t in self.tree.iter_dfs() {
-            match &t.tc().token {
-                Token::Var(varname) => {
-                    if !variables.contains(&varname.as_str()) {
-                        return Err(ParsingError::UndefinedVariable(varname.clone(), t.tc().at));
-                    }
-                },
-                _ => {}
+        let mut undefined = None;
+        visit_free_variables(&self.tree, &mut Vec::new(), &mut |branch, path| {
+            let varname = path.split('.').next().unwrap_or(&path);
+            if undefined.is_none() && !variables.contains(&varname) {
+                let at = branch.iter_dfs().find(|b| matches!(b, Branch::Atom(_))).map_or(0, |b| b.tc().at);
+                let suggestion = suggest::suggest_identifier(varname, &variables);
+                undefined = Some(ParsingError::UndefinedVariable(varname.to_string(), at, suggestion));
             }
+        });
+        match undefined {
+            Some(err) => Err(err),
+            None => Ok(())
         }
-
-        Ok(())
     }
 }
 
@@ -188,7 +225,38 @@ impl Branch {
     /// Print the (sub)tree in reverse polish notation.
     pub fn as_rpn_str(&self) -> String {
         let mut s = String::new();
-        self.recurse_tree_rpn(&mut s)
+        self.write_rpn(&mut s).unwrap();
+        s
+    }
+
+    /// Stream the (sub)tree in reverse polish notation into `w`. The tree is
+    /// traversed with an explicit stack, so arbitrarily deep trees can be printed.
+    pub fn write_rpn<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        enum Step<'a> {
+            Node(&'a Branch),
+            Separator,
+            Close,
+        }
+        let mut stack = vec![Step::Node(self)];
+        while let Some(step) = stack.pop() {
+            match step {
+                Step::Node(Self::Atom(tc)) => write!(w, "{}", tc.token)?,
+                Step::Node(Self::Expression(tc, children)) if children.is_empty() => write!(w, "({})", tc.token)?,
+                Step::Node(Self::Expression(tc, children)) => {
+                    write!(w, "({}: ", tc.token)?;
+                    stack.push(Step::Close);
+                    for (i, branch) in children.iter().enumerate().rev() {
+                        stack.push(Step::Node(branch));
+                        if i > 0 {
+                            stack.push(Step::Separator);
+                        }
+                    }
+                }
+                Step::Separator => w.write_str(", ")?,
+                Step::Close => w.write_str(")")?,
+            }
+        }
+        Ok(())
     }
 
     /// Print expression with syntax highlighting
@@ -230,20 +298,50 @@ impl Branch {
         }
     }
 
-    fn recurse_tree_rpn(&self, s: &mut String) -> String {
+    /// Character positions in the source covered by the tokens of the (sub)tree,
+    /// from the start of the first to the end of the last. The closing brackets
+    /// of calls and groups are not tokens of the tree.
+    pub fn span(&self) -> Range<usize> {
+        let mut span = self.tc().at..self.tc().at + self.tc().len;
+        for branch in DFSBranchIter::new(self) {
+            let tc = branch.tc();
+            span = span.start.min(tc.at)..span.end.max(tc.at + tc.len);
+        }
+        span
+    }
+
+    /// Returns the name of the variable the branch refers to: `a` for a
+    /// variable, or `a.b.c` for (nested) field accesses of a variable.
+    pub fn variable_path(&self) -> Option<String> {
         match self {
-            Self::Atom(tc) => write!(s, "{}", tc.token).unwrap(),
-            Self::Expression(tc, children) => {
-                write!(s, "({}: ", tc.token).unwrap();
-                for branch in children {
-                    branch.recurse_tree_rpn(s);
-                    write!(s, ", ").unwrap();
+            Branch::Atom(tc) => match &tc.token {
+                Token::Var(v) => Some(v.clone()),
+                _ => None
+            },
+            Branch::Expression(tc, args) if tc.token == Token::Dot => {
+                match (args.get(0), args.get(1)) {
+                    (Some(obj), Some(Branch::Atom(attr))) => match &attr.token {
+                        Token::Attr(field) => obj.variable_path().map(|p| format!("{}.{}", p, field)),
+                        _ => None
+                    },
+                    _ => None
                 }
-                s.pop(); s.pop(); // remove trailing ", "
-                write!(s, ")").unwrap()
             }
+            _ => None
         }
-        s.clone()
+    }
+
+    /// Variables referenced by the (sub)tree in order of appearance, fields as
+    /// dotted paths (see `variable_path()`). The parameters of anonymous
+    /// functions (`x` in `map(v, x -> x * a)`) are not included.
+    pub fn free_variables(&self) -> Vec<String> {
+        let mut variables = Vec::new();
+        visit_free_variables(self, &mut Vec::new(), &mut |_, path| {
+            if !variables.contains(&path) {
+                variables.push(path);
+            }
+        });
+        variables
     }
 
     fn iter_dfs(&self) -> DFSBranchIter<'_>
@@ -262,6 +360,13 @@ impl Branch {
 }
 
 
+/// Reverse polish notation, see `Branch::write_rpn()`
+impl fmt::Display for Branch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_rpn(f)
+    }
+}
+
 
 pub struct DFSBranchIter<'a> {
     queue: VecDeque<&'a Branch>,
@@ -314,109 +419,367 @@ impl<'a,> Iterator for DFSBranchIter<'a> {
 
 
 
+/// Call `f` with the branch and path of every variable reference in `branch`,
+/// except for the references to `bound` names and to lambda parameters
+fn visit_free_variables<'a, F: FnMut(&'a Branch, String)>(branch: &'a Branch, bound: &mut Vec<String>, f: &mut F) {
+    if let Some(path) = branch.variable_path() {
+        let varname = path.split('.').next().unwrap_or(&path);
+        if !bound.iter().any(|b| b == varname) {
+            f(branch, path);
+        }
+        return;
+    }
+    match branch {
+        Branch::Atom(_) => {}
+        Branch::Expression(tc, args) if tc.token == Token::Arrow => {
+            let outer = bound.len();
+            if let Some((body, params)) = args.split_last() {
+                bound.extend(params.iter().filter_map(|p| p.variable_path()));
+                visit_free_variables(body, bound, f);
+            }
+            bound.truncate(outer);
+        }
+        // the local variables of `with()` are bound in the later arguments
+        Branch::Expression(tc, args) if matches!(tc.token, Token::Func(Function::With, _)) => {
+            let outer = bound.len();
+            for arg in args {
+                match named_argument(arg) {
+                    Some((name, value)) => {
+                        visit_free_variables(value, bound, f);
+                        if let Token::Var(name) = &name.token {
+                            bound.push(name.clone());
+                        }
+                    }
+                    None => visit_free_variables(arg, bound, f)
+                }
+            }
+            bound.truncate(outer);
+        }
+        // the names of the tuple elements and of the keyword arguments are not variables
+        Branch::Expression(tc, elements) if tc.token == Token::LP
+        || matches!(&tc.token, Token::Func(f, _) if !f.keyword_params().is_empty()) => {
+            for element in elements {
+                visit_free_variables(named_argument(element).map_or(element, |(_, value)| value), bound, f);
+            }
+        }
+        Branch::Expression(_, args) => {
+            for arg in args {
+                visit_free_variables(arg, bound, f);
+            }
+        }
+    }
+}
+
+/// The checks of the function calls done while parsing, for the trees that
+/// were not parsed from text (`AST::from_json()`, `ExprBuilder`): the number
+/// of arguments, the local variables of `with()` and the anonymous functions
+pub(crate) fn check_calls(tree: &Branch) -> Result<(), ParsingError> {
+    for branch in DFSBranchIter::new(tree) {
+        if let Branch::Expression(tc, args) = branch
+            && let Token::Func(function, max_args) = &tc.token
+        {
+            if *function == Function::With {
+                check_bindings(tc, args)?;
+            } else if args.len() < function.min_args() {
+                return Err(ParsingError::MissingArgument(tc.at));
+            } else if args.len() > *max_args {
+                return Err(ParsingError::TooManyArguments(tc.at));
+            }
+        }
+    }
+    check_lambdas(tree)
+}
+
+/// Anonymous functions are only valid as the function arguments of `map`,
+/// `filter`, `reduce`, `sum` and `prod` (see `Function::lambda_arity()`),
+/// with the expected number of distinct parameters
+fn check_lambdas(branch: &Branch) -> Result<(), ParsingError> {
+    let Branch::Expression(tc, args) = branch else {
+        return Ok(());
+    };
+    match &tc.token {
+        Token::Arrow => return Err(ParsingError::InvalidOperation(tc.at,
+            "Anonymous functions are only valid as arguments of map, filter, reduce, sum, prod and solve.".into())),
+        Token::Func(function, _) => for (i, arg) in args.iter().enumerate() {
+            match (function.lambda_arity(i), arg) {
+                (Some(arity), Branch::Expression(arrow, lambda)) if arrow.token == Token::Arrow => {
+                    let (body, params) = lambda.split_last().expect("lambdas have a body");
+                    // `sum(i -> 1 / i ** 2, 1, 10)`
+                    if function.max_args() == MAX_FUNC_ARGS && args.len() != 3 {
+                        let details = format!("`{}` of a function expects the first and last index: `{}(i -> i ** 2, 1, 10)`",
+                            function.name(), function.name());
+                        return Err(ParsingError::InvalidArgument(details, tc.at));
+                    }
+                    if params.len() != arity {
+                        let details = format!("`{}` expects a function of {} parameter(s), found {}",
+                            function.name(), arity, params.len());
+                        return Err(ParsingError::InvalidArgument(details, arrow.at));
+                    }
+                    for (j, param) in params.iter().enumerate() {
+                        if params[..j].iter().any(|p| p.tc().token == param.tc().token) {
+                            let details = format!("parameter `{}` is repeated", param.tc().token);
+                            return Err(ParsingError::InvalidArgument(details, param.tc().at));
+                        }
+                    }
+                    check_lambdas(body)?;
+                }
+                // the function argument of variadic functions is optional
+                (Some(_), arg) if function.max_args() == MAX_FUNC_ARGS => check_lambdas(arg)?,
+                (Some(_), arg) => {
+                    let details = format!("`{}` of `{}` should be a function, e.g. `x -> x * x`",
+                        function.param_names()[i], function.name());
+                    return Err(ParsingError::InvalidArgument(details, arg.tc().at));
+                }
+                (None, arg) => check_lambdas(arg)?
+            }
+        }
+        _ => for arg in args {
+            check_lambdas(arg)?;
+        }
+    }
+    Ok(())
+}
+
+/// Parameters of an anonymous function if the next tokens are `x ->` or
+/// `(a, b) ->`, and the number of tokens before the arrow
+fn lambda_params(ts: &TokenStream) -> Option<(Vec<TokenContext>, usize)> {
+    let first = ts.peek_nth(0);
+    match first.token {
+        Token::Var(_) if ts.peek_nth(1).token == Token::Arrow => Some((vec![first.clone()], 1)),
+        Token::LP => {
+            let mut params = Vec::new();
+            loop {
+                let param = ts.peek_nth(2 * params.len() + 1);
+                if !matches!(param.token, Token::Var(_)) {
+                    return None;
+                }
+                params.push(param.clone());
+                match ts.peek_nth(2 * params.len()).token {
+                    Token::Comma => {},
+                    Token::RP => break,
+                    _ => return None
+                }
+            }
+            let n = 2 * params.len() + 1;
+            (ts.peek_nth(n).token == Token::Arrow).then_some((params, n))
+        }
+        _ => None
+    }
+}
+
 /// This function build the AST from the provided TokenStream
 fn parse_tokenstream(ts: &mut TokenStream) -> Result<Branch, ParsingError> {
-    pratt_parser(ts, 0)
+    pratt_parser(ts, 0, &ParserConfig::default(), &mut None)
+}
+
+/// Fail with `err`, or in recovery mode (`errors` is `Some`) record it and
+/// return an error node at `at` in place of the invalid part
+fn recover(err: ParsingError, at: usize, len: usize, errors: &mut Option<Vec<ParsingError>>)
+-> Result<Branch, ParsingError> {
+    match errors {
+        None => Err(err),
+        Some(errors) => {
+            errors.push(err);
+            Ok(Branch::Atom(TokenContext::new(Token::Error, at, len)))
+        }
+    }
+}
+
+/// Skip the parenthesized arguments of a call
+fn skip_arguments(ts: &mut TokenStream) {
+    let mut depth = 0;
+    loop {
+        match ts.next().token {
+            Token::LP => depth += 1,
+            Token::RP if depth == 1 => return,
+            Token::RP => depth -= 1,
+            Token::Eof => return,
+            _ => {}
+        }
+    }
 }
 
 /// Pratt-parser inspired by: matklad's "Simple but Powerful Pratt Parsing"
 /// See: https://matklad.github.io/2020/04/13/simple-but-powerful-pratt-parsing.html
-fn pratt_parser(ts: &mut TokenStream, min_precedence: usize) -> Result<Branch, ParsingError> {
-    let next = ts.next();
-
-    let mut lhs = match next.token {
+///
+/// If `errors` is `Some`, the parser does not fail: the errors are collected
+/// and the invalid or missing parts become `Token::Error` atoms.
+fn pratt_parser(ts: &mut TokenStream, min_precedence: usize, config: &ParserConfig,
+    errors: &mut Option<Vec<ParsingError>>) -> Result<Branch, ParsingError> {
+    // anonymous function: the body extends as far as possible
+    if let Some((params, n)) = lambda_params(ts) {
+        for _ in 0..n {
+            ts.next();
+        }
+        let arrow = ts.next();
+        let body = pratt_parser(ts, 0, config, errors)?;
+        let mut args: Vec<Branch> = params.into_iter().map(Branch::Atom).collect();
+        args.push(body);
+        return Ok(Branch::Expression(arrow, args));
+    }
+    let mut lhs = match &ts.peek().token {
+        // call of an unknown function: `sgrt(x)`
+        Token::Var(name) if ts.peek_nth(1).token == Token::LP => {
+            let name = name.clone();
+            let tc = ts.next();
+            let suggestion = suggest::suggest_function(&name);
+            if errors.is_some() {
+                skip_arguments(ts);
+            }
+            recover(ParsingError::UnknownFunction(name, tc.at, suggestion), tc.at, tc.len, errors)?
+        }
         // atom -> move to loop
-        Token::Var(_) | Token::Const(_) | Token::Number(_) | Token::Attr(_) => {
-            Branch::Atom(next.clone())
+        Token::Var(_) | Token::Const(_) | Token::Number(_) | Token::Str(_) | Token::Attr(_) | Token::Error => {
+            Branch::Atom(ts.next())
         }
         // (    -> recursion
         Token::LP => {
-            let res = pratt_parser(ts, 0);
-            match res {
-                Ok(lhs) if ts.next().token == Token::RP => lhs,
-                Ok(_) => return Err(ParsingError::MissingRP(1)), // ! FIXME:
-                Err(e) => return Err(e),
+            let next = ts.next();
+            let lhs = pratt_parser(ts, 0, config, errors)?;
+            // `(min(v), max(v))` or `(lo = min(v), hi = max(v))`: a tuple
+            let mut elements = vec![lhs];
+            while ts.peek().token == Token::Comma {
+                ts.next();
+                elements.push(pratt_parser(ts, 0, config, errors)?);
+            }
+            let closing = ts.peek();
+            if closing.token == Token::RP {
+                ts.next();
+            } else if errors.is_some() {
+                recover(ParsingError::MissingRP(1), closing.at, 0, errors)?;
+            } else {
+                return Err(ParsingError::MissingRP(1)); // ! FIXME:
+            }
+            match elements.len() {
+                1 => elements.pop().unwrap(),
+                _ => {
+                    if let Err(e) = check_tuple_names(&elements) {
+                        recover(e, next.at, next.len, errors)?;
+                    }
+                    Branch::Expression(next, elements)
+                }
             }
         }
         // found a function
-        Token::Func(_, _) => {
+        Token::Func(function, max_args) => {
+            let (function, max_args) = (function.clone(), *max_args);
+            let next = ts.next();
             let mut args = Vec::<Branch>::new();
-            if ts.next().token != Token::LP {
-                return Err(ParsingError::UnexpectedToken(1))
+            let lp = ts.peek();
+            if lp.token != Token::LP {
+                let arg = recover(ParsingError::UnexpectedToken(lp.at), lp.at, 0, errors)?;
+                return Ok(Branch::Expression(next, vec![arg]));
             }
-            loop {
-                let res = pratt_parser(ts, 0);
-                match res {
-                    Ok(arg) => {args.push(arg)},
-                    Err(e) => {return Err(e);}
-                }
-                let next = ts.next();
-                match next.token {
-                    Token::RP => { break; },
-                    Token::Comma => {},
-                    _ => return Err(ParsingError::UnexpectedToken(next.at))
+            ts.next();
+            // empty argument list: `now()`, the arity is checked below
+            let empty = ts.peek().token == Token::RP;
+            if empty {
+                ts.next();
+            }
+            while !empty {
+                args.push(pratt_parser(ts, 0, config, errors)?);
+                let separator = ts.peek();
+                match separator.token {
+                    Token::RP => { ts.next(); break; },
+                    Token::Comma => { ts.next(); },
+                    Token::Eof => {
+                        recover(ParsingError::MissingRP(1), separator.at, 0, errors)?;
+                        break;
+                    }
+                    _ => {
+                        recover(ParsingError::UnexpectedToken(separator.at), separator.at, separator.len, errors)?;
+                        ts.next();
+                    }
                 };
             }
-            if args.len() == 0 {
-                return Err(ParsingError::MissingArgument(next.at));
+            let args = if function == Function::With {
+                // the named arguments of `with()` are local variables
+                if let Err(e) = check_bindings(&next, &args) {
+                    recover(e, next.at, next.len, errors)?;
+                }
+                args
+            } else {
+                match bind_arguments(&function, &next, args) {
+                    Ok(args) => args,
+                    Err((e, args)) => {
+                        recover(e, next.at, next.len, errors)?;
+                        args
+                    }
+                }
+            };
+            if args.len() < function.min_args() {
+                recover(ParsingError::MissingArgument(next.at), next.at, next.len, errors)?;
+            }
+            if args.len() > max_args {
+                recover(ParsingError::TooManyArguments(next.at), next.at, next.len, errors)?;
             }
-            Branch::Expression(next.clone(), args)
+            Branch::Expression(next, args)
         }
 
         // operator -> recursion
-        _=> {
-            if let Some((_, r_bp)) = prefix_precedence(&next.token) {
-                let try_rhs = pratt_parser(ts, r_bp);
-                match try_rhs {
-                    Ok(rhs) => return Ok(Branch::Expression(next, vec![rhs])),
-                    Err(err) => return Err(err)
-                }
+        token => {
+            if let Some((_, r_bp)) = config.prefix(token) {
+                let next = ts.next();
+                let rhs = pratt_parser(ts, r_bp, config, errors)?;
+                Branch::Expression(next, vec![rhs])
             } else {
-                return Err(ParsingError::UnexpectedToken(next.at)); // prefix operator that is not + -
+                let next = ts.peek();
+                // prefix operator that is not + -, the token is left for the caller,
+                // e.g. the missing lhs of an infix operator
+                let len = if next.token == Token::Eof { 0 } else { next.len };
+                recover(ParsingError::UnexpectedToken(next.at), next.at, len, errors)?
             }
         }
     };
 
     loop {
         let peeked = ts.peek();
-        let op = match peeked.token.clone() {
+        match peeked.token {
             Token::Eof => break,
-            Token::Number(_) | Token::Const(_) | Token::Var(_) =>
-                return Err(ParsingError::UnexpectedToken(peeked.at)),
-            t => t,
+            Token::Number(_) | Token::Str(_) | Token::Const(_) | Token::Var(_) => {
+                // stray operand, skipped in recovery mode
+                recover(ParsingError::UnexpectedToken(peeked.at), peeked.at, peeked.len, errors)?;
+                ts.next();
+                continue;
+            }
+            // invalid characters, already reported by the tokenizer
+            Token::Error => {
+                ts.next();
+                continue;
+            }
+            _ => {}
         };
 
         // postfix
-        if let Some((l_bp, _)) = postfix_precedence(&op) {
+        if let Some((l_bp, _)) = config.postfix(&peeked.token) {
             if l_bp < min_precedence {
                 break;
             }
-            ts.next();
-            lhs = if op == Token::LB {
-                if let Ok(rhs) = pratt_parser(ts, 0) {
-                    assert_eq!(ts.next().token, Token::RB); // TODO: fix error handling
-                    Branch::Expression(peeked, vec![lhs, rhs])
+            let op = ts.next();
+            lhs = if op.token == Token::LB {
+                let rhs = pratt_parser(ts, 0, config, errors)?;
+                let closing = ts.peek();
+                if closing.token == Token::RB {
+                    ts.next();
                 } else {
-                    return Err(ParsingError::UnexpectedToken(peeked.at));
+                    recover(ParsingError::UnexpectedToken(closing.at), closing.at, 0, errors)?;
                 }
+                Branch::Expression(op, vec![lhs, rhs])
             } else {
-                Branch::Expression(peeked, vec![lhs])
+                Branch::Expression(op, vec![lhs])
             };
             continue;
         }
 
         // infix
-        if let Some((l_bp, r_bp)) = infix_precedence(&op) {
+        if let Some((l_bp, r_bp)) = config.infix(&peeked.token) {
             if l_bp < min_precedence {
                 break;
             }
-            ts.next();
+            let op = ts.next();
 
-            lhs = if let Ok(rhs) = pratt_parser(ts, r_bp) {
-                Branch::Expression(peeked, vec![lhs, rhs])
-            } else {
-                return Err(ParsingError::UnexpectedToken(peeked.at));
-            };
+            let rhs = pratt_parser(ts, r_bp, config, errors)?;
+            lhs = Branch::Expression(op, vec![lhs, rhs]);
             continue;
         }
         break;
@@ -424,109 +787,162 @@ fn pratt_parser(ts: &mut TokenStream, min_precedence: usize) -> Result<Branch, P
     Ok(lhs)
 }
 
-// Field expressions: left to right
-// Function calls, array indexing
-// **
-// * / %	left to right
-// + -	left to right binary and unary
-// == != < > <= >=	Require parentheses
-// &&	left to right
-// ||	left to right
-// .. ..=	Require parentheses
-// = += -= *= /= %=
-
-fn prefix_precedence(t: & Token) -> Option<(usize, usize)> {
-    use ArithmeticOperator as AO;
-    match t {
-        Token::ArOp(o) => match o {
-            AO::Plus | AO::Minus => Some((0, 9)),
-            _ => None,
-        },
-        _ => None
+/// Returns the first assignment in `branch`, except for the local variables of
+/// `with()` and the names of tuple elements
+fn nested_assignment(branch: &Branch) -> Option<&TokenContext> {
+    match branch {
+        Branch::Atom(_) => None,
+        Branch::Expression(tc, _) if tc.token == Token::AssignOp(AssignmentOperator::Assign) => Some(tc),
+        Branch::Expression(tc, args) if matches!(&tc.token, Token::Func(Function::With, _) | Token::LP)
+            || matches!(&tc.token, Token::Func(f, _) if !f.keyword_params().is_empty()) => {
+            args.iter().find_map(|arg| nested_assignment(named_argument(arg).map_or(arg, |(_, value)| value)))
+        }
+        Branch::Expression(_, args) => args.iter().find_map(nested_assignment)
     }
 }
 
-fn postfix_precedence(t: & Token) -> Option<(usize, usize)> {
-    match t {
-        Token::LB => Some((11, 0)),
-        _ => None
+/// The arguments of `with()` are local variables (`name = value`) followed by the body
+fn check_bindings(call: &TokenContext, args: &[Branch]) -> Result<(), ParsingError> {
+    let Some((body, bindings)) = args.split_last() else {
+        return Err(ParsingError::MissingArgument(call.at));
+    };
+    if let Some(binding) = bindings.iter().find(|b| named_argument(b).is_none()) {
+        let details = "local variables are defined as `name = value`".to_string();
+        return Err(ParsingError::InvalidArgument(details, binding.tc().at));
+    }
+    if let Some((name, _)) = named_argument(body) {
+        let details = "the last argument of `with` should be the result, not a variable".to_string();
+        return Err(ParsingError::InvalidArgument(details, name.at));
     }
+    if bindings.is_empty() {
+        return Err(ParsingError::MissingArgument(call.at));
+    }
+    Ok(())
 }
 
-fn infix_precedence(t: & Token) -> Option<(usize, usize)> {
-    use ArithmeticOperator as AO;
-    match t {
-        Token::ArOp(o) => match o {
-            AO::Plus | AO::Minus => Some((10, 11)),
-            AO::Mul | AO::Div => Some((12, 13)),
-            AO::Pow => Some((14, 15)),
-            _ => None,
-        },
-        Token::RelOp(_) => Some((2, 1)),
-        Token::LogicOp(_) => Some((4, 3)),
-        Token::AssignOp(_) => Some((2, 1)),
-        Token::Dot => Some((14, 13)),
-        Token::Colon => Some((6, 5)),
-        _ => None
+/// The names of the elements of a tuple (`(lo = min(v), hi = max(v))`) are unique
+fn check_tuple_names(elements: &[Branch]) -> Result<(), ParsingError> {
+    let names: Vec<&TokenContext> = elements.iter().filter_map(named_argument).map(|(name, _)| name).collect();
+    for (i, name) in names.iter().enumerate() {
+        if names[..i].iter().any(|other| other.token == name.token) {
+            let details = format!("the tuple has more than one element named `{}`", name.token);
+            return Err(ParsingError::InvalidArgument(details, name.at));
+        }
     }
+    Ok(())
 }
 
-fn is_atom(t: & Token) -> bool {
-    match t {
-        Token::Var(_) | Token::Number(_) | Token::Const(_) => true,
-        _=> false
+/// Returns the name and the value of a named argument: `name = value`
+pub(crate) fn named_argument(arg: &Branch) -> Option<(&TokenContext, &Branch)> {
+    match arg {
+        Branch::Expression(tc, args) if tc.token == Token::AssignOp(AssignmentOperator::Assign) => {
+            match args.as_slice() {
+                [Branch::Atom(name), value] => match &name.token {
+                    Token::Var(_) => Some((name, value)),
+                    _ => None
+                },
+                _ => None
+            }
+        }
+        _ => None
     }
 }
 
-
-/// Non-recursive representation of the AST
-///
-pub struct FlatAst {
-    nodes: HashMap<u8, TokenContext>,
-    edges: HashMap<u8, u8>,
-    node_id: u8,
-}
-impl FlatAst {
-    fn new() -> Self {
-        Self {
-            nodes: HashMap::new(),
-            edges: HashMap::new(),
-            node_id: 0,
+/// The keyword arguments (`avg(m, axis = 0)`) follow the positional ones, which
+/// are the values of the function, each keyword is given at most once. They
+/// stay in the tree as named arguments.
+fn check_keywords(function: &Function, call: &TokenContext, args: &[Branch]) -> Result<(), ParsingError> {
+    let positional = args.iter().take_while(|a| named_argument(a).is_none()).count();
+    if let Some(lambda) = args.iter().find(|a| matches!(a, Branch::Expression(tc, _) if tc.token == Token::Arrow)) {
+        let details = format!("`{}` of a function has no keyword arguments", function.name());
+        return Err(ParsingError::InvalidArgument(details, lambda.tc().at));
+    }
+    let mut given: Vec<&Token> = Vec::new();
+    for arg in &args[positional..] {
+        let Some((name, _)) = named_argument(arg) else {
+            let details = "positional argument after named arguments".to_string();
+            return Err(ParsingError::InvalidArgument(details, arg.tc().at));
+        };
+        if !function.keyword_params().iter().any(|p| Token::Var(p.to_string()) == name.token) {
+            let details = format!("`{}` has no parameter `{}`", function.name(), name.token);
+            return Err(ParsingError::InvalidArgument(details, name.at));
+        }
+        if given.contains(&&name.token) {
+            let details = format!("`{}` is given more than once", name.token);
+            return Err(ParsingError::InvalidArgument(details, name.at));
         }
+        given.push(&name.token);
+    }
+    if positional == 0 {
+        return Err(ParsingError::MissingArgument(call.at));
     }
-    fn add_node(&mut self, tc: TokenContext, parent: u8) -> u8 {
-        let id = self.node_id;
-        self.nodes.insert(id, tc);
-        self.node_id += 1;
-        self.add_edge(parent, id);
-        id
+    Ok(())
+}
+
+/// Move the named arguments (`name = value`) of a call to the position of the
+/// parameter `name`, so the evaluator only sees positional arguments. On error,
+/// the values are returned in their original order (for error recovery).
+fn bind_arguments(function: &Function, call: &TokenContext, args: Vec<Branch>)
+-> Result<Vec<Branch>, (ParsingError, Vec<Branch>)> {
+    if !args.iter().any(|a| named_argument(a).is_some()) {
+        return Ok(args);
     }
-    fn add_edge(&mut self, op: u8, arg: u8) {
-        self.edges.insert(arg, op);
+    if !function.keyword_params().is_empty() {
+        return match check_keywords(function, call, &args) {
+            Ok(()) => Ok(args),
+            Err(e) => {
+                let values = args.iter().map(|a| named_argument(a).map_or(a, |(_, v)| v).clone()).collect();
+                Err((e, values))
+            }
+        };
     }
-    fn print_ast(&self) {
-        println!("Nodes:");
-        for (node_id, node) in &self.nodes {
-            println!("  {node_id:3}: {}", node.token);
+    let params = function.param_names();
+    let mut bound: Vec<Option<Branch>> = Vec::new();
+    let mut error = None;
+    for (i, arg) in args.iter().enumerate() {
+        let (position, value) = match named_argument(arg) {
+            Some((name, value)) => match params.iter().position(|p| Token::Var(p.to_string()) == name.token) {
+                Some(position) => (position, value),
+                None => {
+                    let details = format!("`{}` has no parameter `{}`", function.name(), name.token);
+                    error = Some(ParsingError::InvalidArgument(details, name.at));
+                    break;
+                }
+            },
+            None if bound.len() > i => {
+                let details = "positional argument after named arguments".to_string();
+                error = Some(ParsingError::InvalidArgument(details, arg.tc().at));
+                break;
+            }
+            None => (i, arg),
+        };
+        if bound.len() <= position {
+            bound.resize(position + 1, None);
         }
-        println!("Edges:");
-        for (fr, to) in &self.edges {
-            println!("  {fr:3} -> {to:3}");
+        if bound[position].is_some() {
+            let details = format!("`{}` is given more than once", params[position]);
+            let at = named_argument(arg).map_or(arg.tc().at, |(name, _)| name.at);
+            error = Some(ParsingError::InvalidArgument(details, at));
+            break;
+        }
+        bound[position] = Some(value.clone());
+    }
+    if error.is_none() && bound.iter().any(|b| b.is_none()) {
+        error = Some(ParsingError::MissingArgument(call.at));
+    }
+    match error {
+        None => Ok(bound.into_iter().flatten().collect()),
+        Some(e) => {
+            let values = args.iter().map(|a| named_argument(a).map_or(a, |(_, v)| v).clone()).collect();
+            Err((e, values))
         }
     }
 }
 
-fn traverse_ast(branch: &Branch, ast: &mut FlatAst, parent: u8) {
-    match branch {
-        Branch::Atom(tc) => {
-            let _ = ast.add_node(tc.clone(), parent);
-        },
-        Branch::Expression(tc, args) => {
-            let id = ast.add_node(tc.clone(), parent);
-            for arg in args {
-                traverse_ast(arg, ast, id);
-            }
-        }
+fn is_atom(t: & Token) -> bool {
+    match t {
+        Token::Var(_) | Token::Number(_) | Token::Str(_) | Token::Const(_) => true,
+        _=> false
     }
 }
 
@@ -589,6 +1005,33 @@ use crate::*;
         assert_eq!(num_lp, 8);
     }
 
+    #[test]
+    fn test_rpn_deep_tree() {
+        use TokenContext as TC;
+        let mut tree = Branch::Atom(TC::dummy(Token::Var("x".into())));
+        for _ in 0..100_000 {
+            tree = Branch::Expression(TC::dummy(Token::Func(Function::Sin, 1)), vec![tree]);
+        }
+        let mut n = 0;
+        struct Counter<'a>(&'a mut usize);
+        impl std::fmt::Write for Counter<'_> {
+            fn write_str(&mut self, s: &str) -> std::fmt::Result {
+                *self.0 += s.len();
+                Ok(())
+            }
+        }
+        tree.write_rpn(&mut Counter(&mut n)).unwrap();
+        assert_eq!(n, 100_000 * "(Sin: )".len() + 1);
+        std::mem::forget(tree); // dropping is recursive
+    }
+
+    #[test]
+    fn test_rpn_display() {
+        let ast = AST::from_expression("max(1, x) - now()").unwrap();
+        assert_eq!(ast.tree.to_string(), "(-: (Max: 1, x), (Now))");
+        assert_eq!(format!("{}", ast.tree), ast.rpn_repr());
+    }
+
     #[test]
     fn test_simple_expressions() {
 
@@ -602,6 +1045,87 @@ use crate::*;
         test_parsing("max(0, sqrt(min(1,2,3,4)))", &vec![], "(Max: 0, (Sqrt: (Min: 1, 2, 3, 4)))");
     }
 
+    #[test]
+    fn test_too_many_arguments() {
+        assert_matches!(AST::from_expression("sin(x, 2)"), Err(ParsingError::TooManyArguments(0)));
+        let args = vec!["1"; 65].join(", ");
+        assert_matches!(AST::from_expression(&format!("max({})", args)), Err(ParsingError::TooManyArguments(_)));
+        assert!(AST::from_expression(&format!("max({})", &args[3..])).is_ok());
+        assert_matches!(AST::from_expression("now(1)"), Err(ParsingError::TooManyArguments(0)));
+    }
+
+    #[test]
+    fn test_named_arguments() {
+        test_parsing("shift(v, n = 2)", &vec!["v"], "(Shift: v, 2)");
+        test_parsing("shift(n = 1 + 1, series = v)", &vec!["v"], "(Shift: v, (+: 1, 1))");
+        test_parsing("sin(x = a)", &vec!["a"], "(Sin: a)");
+        assert_matches!(AST::from_expression("shift(v, dt = 2)"), Err(ParsingError::InvalidArgument(_, 9)));
+        assert_matches!(AST::from_expression("shift(n = 2, v)"), Err(ParsingError::InvalidArgument(_, 13)));
+        assert_matches!(AST::from_expression("shift(v, series = v)"), Err(ParsingError::InvalidArgument(_, 9)));
+        assert_matches!(AST::from_expression("shift(n = 2)"), Err(ParsingError::MissingArgument(0)));
+        assert_matches!(AST::from_expression("max(a = 1)"), Err(ParsingError::InvalidArgument(_, _)));
+    }
+
+    #[test]
+    fn test_keyword_arguments() {
+        assert!(AST::from_expression("avg(m, axis = 0)").is_ok());
+        assert!(AST::from_expression("sum(m, keepdims = 1, axis = 1)").is_ok());
+        assert_matches!(AST::from_expression("avg(axis = 0)"), Err(ParsingError::MissingArgument(0)));
+        assert_matches!(AST::from_expression("avg(m, axis = 0, axis = 1)"), Err(ParsingError::InvalidArgument(_, 17)));
+        assert_matches!(AST::from_expression("avg(m, axis = 0, m)"), Err(ParsingError::InvalidArgument(_, 17)));
+        assert_matches!(AST::from_expression("avg(m, ddof = 1)"), Err(ParsingError::InvalidArgument(_, 7)));
+        assert_matches!(AST::from_expression("sin(m, axis = 0)"), Err(ParsingError::InvalidArgument(_, _)));
+    }
+
+    #[test]
+    fn test_lambdas() {
+        test_parsing("map(v, x -> x * x)", &vec!["v"], "(Map: v, (->: x, (*: x, x)))");
+        test_parsing("reduce(v, (a, b) -> a + b * c, 0)", &vec!["v", "c"], "(Reduce: v, (->: a, b, (+: a, (*: b, c))), 0)");
+        test_parsing("filter(v, predicate = (x) -> x - 1)", &vec!["v"], "(Filter: v, (->: x, (-: x, 1)))");
+        test_parsing("sum(map(v, x -> -x))", &vec!["v"], "(Sum: (Map: v, (->: x, (-: x))))");
+        let ast = AST::from_expression("map(v, x -> x * a)").unwrap();
+        assert_eq!(ast.tree.free_variables(), vec!["v", "a"]);
+        assert_matches!(ast.check_input_vars(&["v"]), Err(ParsingError::UndefinedVariable(_, 16, _)));
+        assert_matches!(AST::from_expression("map(v, (a, b) -> a)"), Err(ParsingError::InvalidArgument(_, 14)));
+        assert_matches!(AST::from_expression("reduce(v, (a, a) -> a, 0)"), Err(ParsingError::InvalidArgument(_, 14)));
+        assert_matches!(AST::from_expression("map(v, sin(v))"), Err(ParsingError::InvalidArgument(_, _)));
+        assert_matches!(AST::from_expression("max(v, x -> x)"), Err(ParsingError::InvalidOperation(9, _)));
+        assert_matches!(AST::from_expression("x -> x"), Err(ParsingError::InvalidOperation(2, _)));
+    }
+
+    #[test]
+    fn test_comprehension() {
+        test_parsing("sum(i -> 1 / i ** 2, 1, n)", &vec!["n"], "(Sum: (->: i, (/: 1, (**: i, 2))), 1, n)");
+        test_parsing("prod(v)", &vec!["v"], "(Prod: v)");
+        assert_matches!(AST::from_expression("sum(i -> i, 10)"), Err(ParsingError::InvalidArgument(_, 0)));
+        assert_matches!(AST::from_expression("sum(1, i -> i, 10)"), Err(ParsingError::InvalidOperation(9, _)));
+        let a = AST::from_expression("sum(i -> i, 1, 10)").unwrap();
+        let b = AST::from_expression("sum(i -> i, 10, 1)").unwrap();
+        assert_ne!(a.fingerprint(), b.fingerprint());
+    }
+
+    #[test]
+    fn test_local_variables() {
+        test_parsing("with(t = x * 2, sin(t) + t)", &vec!["x"], "(With: (=: t, (*: x, 2)), (+: (Sin: t), t))");
+        let ast = AST::from_expression("y = with(a = b, c = a + d, a * c) + a").unwrap();
+        assert_eq!(ast.tree.free_variables(), vec!["b", "d", "a"]);
+        assert_eq!(ast.assigned_to.as_deref(), Some("y"));
+        assert_matches!(AST::from_expression("with(t)"), Err(ParsingError::MissingArgument(0)));
+        assert_matches!(AST::from_expression("with(1, t)"), Err(ParsingError::InvalidArgument(_, 5)));
+        assert_matches!(AST::from_expression("with(t = 1, u = t)"), Err(ParsingError::InvalidArgument(_, 12)));
+        assert_matches!(AST::from_expression("with(t = (u = 1), t)"), Err(ParsingError::InvalidAssignment(_, 12)));
+    }
+
+    #[test]
+    fn test_empty_argument_list() {
+        test_parsing("now() - t", &vec!["t"], "(-: (Now), t)");
+        test_parsing("1 + now( )", &vec![], "(+: 1, (Now))");
+        assert_matches!(AST::from_expression("sin()"), Err(ParsingError::MissingArgument(0)));
+        assert_matches!(AST::from_expression("2 * max()"), Err(ParsingError::MissingArgument(4)));
+        assert_matches!(AST::from_expression("shift(x)"), Err(ParsingError::MissingArgument(0)));
+        assert_matches!(AST::from_expression("max(,)"), Err(ParsingError::UnexpectedToken(4)));
+    }
+
     #[test]
     fn test_indexing() {
         test_parsing("v[1:-1]", &vec!["v"], "([: v, (:: 1, -1))");
@@ -640,7 +1164,17 @@ use crate::*;
         let ast = AST::from_expression("x = y = 3");
         assert_matches!(ast, Err(ParsingError::InvalidAssignment(_, _)));
 
+        let ast = AST::from_expression("x + 2*(1 + (y = 2))");
+        assert_matches!(ast, Err(ParsingError::InvalidAssignment(_, _)));
+
+        // in a function call it is a named argument
         let ast = AST::from_expression("x + 2*(1 + max(3, y = 2))");
+        assert_matches!(ast, Err(ParsingError::InvalidArgument(_, _)));
+
+        // a tree that was not parsed from text
+        let x = Branch::Atom(TokenContext::dummy(Token::Var("x".into())));
+        let assign = TokenContext::dummy(Token::AssignOp(AssignmentOperator::Assign));
+        let ast = AST { tree: Branch::Expression(assign, vec![x]), assigned_to: None }.check_assigment();
         assert_matches!(ast, Err(ParsingError::InvalidAssignment(_, _)));
     }
 
@@ -668,7 +1202,11 @@ use crate::*;
         ast.check_input_vars(var).unwrap();
         let flat_ast = ast.flatten_ast();
         println!("Expression: {}", expr);
-        flat_ast.print_ast();
+        for node in flat_ast.iter() {
+            println!("  {:3}: {}", node.id(), node.tc().token);
+        }
+        assert_eq!(flat_ast.len(), 10);
+        assert_eq!(flat_ast.root().as_rpn_str(), ast.rpn_repr());
     }
 
     #[test]
@@ -728,7 +1266,7 @@ use crate::*;
         let result_fail = ast.check_input_vars(&["x"]);
 
         assert_matches!(result_ok,  Ok(()));
-        assert_matches!(result_fail, Err(ParsingError::UndefinedVariable(_, 6))); // TODO
+        assert_matches!(result_fail, Err(ParsingError::UndefinedVariable(_, 6, _))); // TODO
     }
 
 
@@ -0,0 +1,117 @@
+use crate::{DymexError, Token, TokenContext, TokenStream, TokenizerConfig};
+use super::{AST, ParserConfig, ParsingError, pratt_parser};
+
+
+/// Result of parsing with error recovery, see `AST::parse_partial()`
+#[derive(Debug, Clone)]
+pub struct PartialAST {
+    /// The tree of the valid parts, the invalid or missing parts are
+    /// `Token::Error` atoms
+    pub ast: AST,
+    /// Every lexical and parsing error, in the order they were found
+    pub errors: Vec<DymexError>,
+}
+
+impl PartialAST {
+    /// Returns true if the expression had no errors
+    pub fn is_complete(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// The error nodes of the tree, their position is where the expression
+    /// is invalid or incomplete
+    pub fn error_nodes(&self) -> Vec<&TokenContext> {
+        self.ast.tree.iter_dfs()
+            .map(|b| b.tc())
+            .filter(|tc| tc.token == Token::Error)
+            .collect()
+    }
+}
+
+impl AST {
+    /// Parse an expression without failing, e.g. for syntax highlighting in an
+    /// editor while the expression is being typed. Invalid characters, missing
+    /// operands and unexpected tokens become `Token::Error` atoms in the tree,
+    /// missing closing parentheses are assumed. For valid expressions, the tree
+    /// is the same as the one of `AST::from_expression()`.
+    pub fn parse_partial(expression: &str) -> PartialAST {
+        Self::parse_partial_with_config(expression, TokenizerConfig::default())
+    }
+
+    pub fn parse_partial_with_config(expression: &str, config: TokenizerConfig) -> PartialAST {
+        let (mut ts, lexical_errors) = TokenStream::with_recovery(expression, config);
+        let mut errors: Vec<DymexError> = lexical_errors.into_iter().map(DymexError::LexicalError).collect();
+
+        let mut parsing_errors = Some(Vec::new());
+        let tree = pratt_parser(&mut ts, 0, &ParserConfig::default(), &mut parsing_errors)
+            .expect("parsing does not fail in recovery mode");
+        let mut parsing_errors = parsing_errors.unwrap_or_default();
+        // tokens after a complete expression: `(a + b))`
+        loop {
+            let tc = ts.next();
+            match tc.token {
+                Token::Eof => break,
+                Token::Error => {},
+                _ => parsing_errors.push(ParsingError::UnexpectedToken(tc.at))
+            }
+        }
+        errors.extend(parsing_errors.into_iter().map(DymexError::ParsingError));
+
+        let ast = AST { tree, assigned_to: None };
+        let ast = match ast.clone().check_assigment() {
+            Ok(ast) => ast,
+            Err(e) => {
+                errors.push(DymexError::ParsingError(e));
+                ast
+            }
+        };
+        PartialAST { ast, errors }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::assert_matches;
+    use crate::*;
+
+    fn partial(expr: &str) -> (String, usize) {
+        let partial = AST::parse_partial(expr);
+        (partial.ast.rpn_repr(), partial.errors.len())
+    }
+
+    #[test]
+    fn test_complete_expressions() {
+        for expr in ["y = 2 * (x + 1)", "max(a, -b, c.d) ** 2", "now() - t"] {
+            let partial = AST::parse_partial(expr);
+            assert!(partial.is_complete());
+            assert!(partial.error_nodes().is_empty());
+            let ast = AST::from_expression(expr).unwrap();
+            assert_eq!(partial.ast.tree, ast.tree);
+            assert_eq!(partial.ast.assigned_to, ast.assigned_to);
+        }
+    }
+
+    #[test]
+    fn test_incomplete_expressions() {
+        assert_eq!(partial("2 * (x + "), ("(*: 2, (+: x, ⚠))".into(), 2));
+        assert_eq!(partial("sin(x"), ("(Sin: x)".into(), 1));
+        assert_eq!(partial("max(a, , b)"), ("(Max: a, ⚠, b)".into(), 1));
+        assert_eq!(partial("* 2"), ("(*: ⚠, 2)".into(), 1));
+        assert_eq!(partial(""), ("⚠".into(), 1));
+        assert_eq!(partial("(a + b))"), ("(+: a, b)".into(), 1));
+    }
+
+    #[test]
+    fn test_lexical_errors() {
+        let partial = AST::parse_partial("a + $ * b");
+        assert_eq!(partial.ast.rpn_repr(), "(+: a, (*: ⚠, b))");
+        assert_matches!(partial.errors[..], [DymexError::LexicalError(TokenizerError::InvalidCharacter('$', 4))]);
+        let error = partial.error_nodes()[0];
+        assert_eq!((error.at, error.len), (4, 1));
+
+        let partial = AST::parse_partial("x * 2.0.1");
+        assert_matches!(partial.errors[..], [DymexError::LexicalError(TokenizerError::InvalidNumberFormat(4))]);
+        assert_eq!(partial.ast.rpn_repr(), "(*: x, ⚠)");
+    }
+}
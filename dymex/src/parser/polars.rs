@@ -0,0 +1,238 @@
+//! Translation of expressions into polars `Expr`s, so formulas validated with
+//! dymex can be used in DataFrame queries:
+//! ```ignore
+//! let ast = AST::from_expression("bmi = weight / (height / 100) ** 2")?;
+//! let df = df.lazy().with_column(ast.to_polars_expr()?).collect()?;
+//! ```
+//! Variables are columns, numbers and constants literals. Arithmetic, comparisons,
+//! `and`/`or`, `where()`, the elementwise functions `abs`, `sqrt`, `exp`, `log`s and
+//! trigonometric functions and the single argument reductions (`sum(v)`, `avg(v)`, ...)
+//! are translated to the native polars operations. Other constructs (fields,
+//! lambdas, `trapz()`, ...) are only supported by `to_polars_expr_with_udf()`,
+//! which evaluates them with an `Evaluator` inside the query.
+
+use polars::prelude::*;
+use crate::{ArithmeticOperator, Category, DynMath, EvaluationError, Evaluator, Float, Function, InputVars,
+    LogicalOperator, Missing, RelationalOperator, Token};
+use super::{AST, Branch, ParsingError};
+
+
+/// How `AST::to_polars_expr_with_udf()` evaluates the parts without a polars equivalent
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UdfMode {
+    /// Evaluate once, the columns are the array variables (`sum(v)` is the sum of the column)
+    Columns,
+    /// Evaluate for each row, the variables are the numbers in the row
+    Rows,
+}
+
+impl AST {
+    /// The equivalent polars expression, aliased to the assigned variable (if any).
+    /// Fails for the constructs without a native polars equivalent.
+    pub fn to_polars_expr(&self) -> Result<Expr, ParsingError> {
+        let expr = to_expr(&self.tree, &mut |branch| Err(ParsingError::InvalidOperation(
+            branch.tc().at, format!("`{}` has no polars equivalent", branch.tc().token)
+        )))?;
+        Ok(self.alias(expr))
+    }
+
+    /// The equivalent polars expression, where the subexpressions without a native
+    /// equivalent are evaluated by dymex (as user defined functions) in `mode`
+    pub fn to_polars_expr_with_udf(&self, mode: UdfMode) -> Expr {
+        let expr = to_expr(&self.tree, &mut |branch| Ok(udf(branch, mode)))
+            .expect("every unsupported subexpression is a UDF");
+        self.alias(expr)
+    }
+
+    fn alias(&self, expr: Expr) -> Expr {
+        match &self.assigned_to {
+            Some(name) => expr.alias(name.as_str()),
+            None => expr
+        }
+    }
+}
+
+fn to_expr<F>(branch: &Branch, unsupported: &mut F) -> Result<Expr, ParsingError>
+where F: FnMut(&Branch) -> Result<Expr, ParsingError> {
+    let children = match branch {
+        Branch::Atom(tc) => return match &tc.token {
+            Token::Number(x) => Ok(lit(*x as f64)),
+            Token::Const(c) => Ok(lit(c.value() as f64)),
+            Token::Var(name) => Ok(col(name.as_str())),
+            _ => unsupported(branch)
+        },
+        Branch::Expression(_, children) => children,
+    };
+    let mut operand = |i: usize| to_expr(&children[i], unsupported);
+    let expr = match (&branch.tc().token, children.len()) {
+        (Token::ArOp(op), 2) => {
+            let (a, b) = (operand(0)?, operand(1)?);
+            match op {
+                ArithmeticOperator::Plus => a + b,
+                ArithmeticOperator::Minus => a - b,
+                ArithmeticOperator::Mul => a * b,
+                ArithmeticOperator::Div => a / b,
+                ArithmeticOperator::Rem => a % b,
+                ArithmeticOperator::Pow => a.pow(b),
+                _ => return unsupported(branch)
+            }
+        }
+        (Token::ArOp(ArithmeticOperator::Minus), 1) => lit(0.0) - operand(0)?,
+        (Token::RelOp(op), 2) => {
+            let (a, b) = (operand(0)?, operand(1)?);
+            match op {
+                RelationalOperator::Greater => a.gt(b),
+                RelationalOperator::Lesser => a.lt(b),
+                RelationalOperator::Eq => a.eq(b),
+                RelationalOperator::Neq => a.neq(b),
+                RelationalOperator::Leq => a.lt_eq(b),
+                RelationalOperator::Geq => a.gt_eq(b),
+            }
+        }
+        (Token::LogicOp(op), 2) => {
+            let (a, b) = (operand(0)?, operand(1)?);
+            match op {
+                LogicalOperator::And => a.and(b),
+                LogicalOperator::Or => a.or(b),
+            }
+        }
+        (Token::Func(Function::Where, _), 3) => when(operand(0)?).then(operand(1)?).otherwise(operand(2)?),
+        (Token::Func(f, _), 1) => {
+            let x = operand(0)?;
+            match f {
+                Function::Abs => x.abs(),
+                Function::Sqrt => x.sqrt(),
+                Function::Exp => x.exp(),
+                Function::Log => x.log(std::f64::consts::E),
+                Function::Log2 => x.log(2.0),
+                Function::Log10 => x.log(10.0),
+                Function::Sin => x.sin(),
+                Function::Cos => x.cos(),
+                Function::Tan => x.tan(),
+                Function::Cot => x.cot(),
+                Function::Min => x.min(),
+                Function::Max => x.max(),
+                Function::Sum => x.sum(),
+                Function::Prod => x.product(),
+                Function::Avg => x.mean(),
+                // population standard deviation, as `std()` of dymex
+                Function::Std => x.std(0),
+                Function::Range => x.clone().max() - x.min(),
+                _ => return unsupported(branch)
+            }
+        }
+        _ => return unsupported(branch)
+    };
+    Ok(expr)
+}
+
+/// `branch` evaluated by an `Evaluator`, with its free variables as the input columns
+fn udf(branch: &Branch, mode: UdfMode) -> Expr {
+    let variables = branch.free_variables();
+    let inputs: Vec<Expr> = variables.iter().map(|name| col(name.as_str())).collect();
+    let ast = AST { tree: branch.clone(), assigned_to: None };
+    let function = move |columns: &mut [Column]| -> PolarsResult<Option<Column>> {
+        let names: Vec<&str> = variables.iter().map(|v| v.as_str()).collect();
+        let mut evaluator = Evaluator::from_ast(ast.clone());
+        let columns = columns.iter()
+            .map(|c| c.cast(&DataType::Float64).and_then(|c| Ok(c.f64()?.clone())))
+            .collect::<PolarsResult<Vec<Float64Chunked>>>()?;
+        let result = match mode {
+            UdfMode::Columns => {
+                let mut inputs = InputVars::new();
+                for (name, column) in names.iter().zip(&columns) {
+                    match column.null_count() {
+                        0 => inputs.insert_owned(name.to_string(), column.into_no_null_iter().map(|x| x as Float).collect::<Vec<Float>>()),
+                        _ => inputs.insert_owned(name.to_string(), column.iter().map(|x| x.map(|x| x as Float)).collect::<Vec<Option<Float>>>())
+                    }
+                }
+                to_series(&*evaluator.evaluate(&inputs).map_err(compute_error)?)?
+            }
+            UdfMode::Rows => {
+                let rows = columns.iter().map(|c| c.len()).max().unwrap_or(0);
+                let mut values: Vec<Option<f64>> = Vec::with_capacity(rows);
+                for row in 0..rows {
+                    let mut inputs = InputVars::new();
+                    for (name, column) in names.iter().zip(&columns) {
+                        // columns of length 1 are broadcast literals
+                        match column.get(if column.len() == 1 { 0 } else { row }) {
+                            Some(x) => inputs.insert_owned(name.to_string(), x as Float),
+                            None => inputs.insert_owned(name.to_string(), Missing)
+                        }
+                    }
+                    let value = evaluator.evaluate(&inputs).map_err(compute_error)?;
+                    values.push(match value.category() {
+                        Category::Number => Some(value.as_number() as f64),
+                        _ if value.is::<Missing>() => None,
+                        _ => polars_bail!(ComputeError: "a row evaluates to `{}`, not a number", value.type_name())
+                    });
+                }
+                Series::new("dymex".into(), values)
+            }
+        };
+        Ok(Some(result.into_column()))
+    };
+    map_multiple(function, inputs, GetOutput::from_type(DataType::Float64))
+}
+
+/// A result as a `Float64` series: numbers (broadcast by polars), arrays and arrays with missing values
+fn to_series(value: &dyn DynMath) -> PolarsResult<Series> {
+    let name = PlSmallStr::from_static("dymex");
+    Ok(match value.category() {
+        Category::Number => Series::new(name, [value.as_number() as f64]),
+        Category::Array => Series::new(name, value.iterate().map(|x| *x as f64).collect::<Vec<f64>>()),
+        Category::Unqiue => if let Some(v) = value.downcast_ref::<Vec<Option<Float>>>() {
+            Series::new(name, v.iter().map(|x| x.map(|x| x as f64)).collect::<Vec<Option<f64>>>())
+        } else if value.is::<Missing>() {
+            Series::new(name, [None::<f64>])
+        } else {
+            polars_bail!(ComputeError: "`{}` cannot be converted to a polars column", value.type_name())
+        }
+    })
+}
+
+fn compute_error(e: EvaluationError) -> PolarsError {
+    PolarsError::ComputeError(e.to_string().into())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use polars::prelude::*;
+    use crate::*;
+
+    fn frame() -> DataFrame {
+        df!("a" => [1.0, 2.0, 4.0], "b" => [2.0, 2.0, 2.0]).unwrap()
+    }
+
+    fn column(df: DataFrame, name: &str) -> Vec<Option<f64>> {
+        df.column(name).unwrap().f64().unwrap().into_iter().collect()
+    }
+
+    #[test]
+    fn test_polars_expr() {
+        let ast = AST::from_expression("c = where(a > b, sqrt(a) * 2, -b) + pi * 0").unwrap();
+        let df = frame().lazy().with_column(ast.to_polars_expr().unwrap()).collect().unwrap();
+        assert_eq!(column(df, "c"), [Some(-2.0), Some(-2.0), Some(4.0)]);
+
+        let ast = AST::from_expression("a - min(a)").unwrap();
+        let df = frame().lazy().select([ast.to_polars_expr().unwrap().alias("d")]).collect().unwrap();
+        assert_eq!(column(df, "d"), [Some(0.0), Some(1.0), Some(3.0)]);
+
+        let err = AST::from_expression("trapz(a, b)").unwrap().to_polars_expr().unwrap_err();
+        assert!(matches!(err, ParsingError::InvalidOperation(0, _)));
+    }
+
+    #[test]
+    fn test_polars_udf() {
+        // `trapz()` has no polars equivalent, it is evaluated on the columns
+        let ast = AST::from_expression("t = trapz(b, a) + 1").unwrap();
+        let df = frame().lazy().select([ast.to_polars_expr_with_udf(UdfMode::Columns)]).collect().unwrap();
+        assert_eq!(column(df, "t"), [Some(7.0)]);
+
+        // `max(a, b)` of numbers, row by row
+        let ast = AST::from_expression("m = max(a, b) * 2").unwrap();
+        let df = frame().lazy().with_column(ast.to_polars_expr_with_udf(UdfMode::Rows)).collect().unwrap();
+        assert_eq!(column(df, "m"), [Some(4.0), Some(4.0), Some(8.0)]);
+    }
+}
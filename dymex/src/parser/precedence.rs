@@ -0,0 +1,230 @@
+//! Precedence and associativity of the operators.
+//!
+//! | precedence | operators                      | fixity  | associativity |
+//! |-----------:|--------------------------------|---------|---------------|
+//! | 12         | `.` (field access)             | infix   | left          |
+//! | 11         | `[]` (indexing)                | postfix | -             |
+//! | 10         | `**` (`^` by default)          | infix   | right         |
+//! | 9          | `+` `-` signs                  | prefix  | -             |
+//! | 8          | `*` `/` `%`                    | infix   | left          |
+//! | 7          | `+` `-`                        | infix   | left          |
+//! | 6          | `xor` (`^` with `Caret::Xor`)  | infix   | left          |
+//! | 5          | `:`                            | infix   | right         |
+//! | 4          | `==` `≠` `<` `>` `≤` `≥`       | infix   | left          |
+//! | 3          | `and`                          | infix   | right         |
+//! | 2          | `or`                           | infix   | right         |
+//! | 1          | `=` `+=` `-=` `*=` `/=`        | infix   | right         |
+//!
+//! So `-x ** 2` is `-(x ** 2)`, `2 ** 3 ** 2` is `2 ** 9`, `-a * b` is
+//! `(-a) * b`, `s.x ** 2` is `(s.x) ** 2` and `x != 0 and 1 / x > 2` is
+//! `(x != 0) and ((1 / x) > 2)`. Every other token (numbers,
+//! names, functions, parentheses, commas) is not an operator.
+//!
+//! The table can be changed with `ParserConfig::with_rule()`.
+use crate::{ArithmeticOperator, AssignmentOperator, LogicalOperator, RelationalOperator, Token};
+
+
+/// Position of an operator relative to its operands
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Fixity {
+    /// `-x`
+    Prefix,
+    /// `a + b`
+    Infix,
+    /// `a[i]`
+    Postfix,
+}
+
+/// Grouping of a chain of infix operators with the same precedence
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Associativity {
+    /// `a - b - c` is `(a - b) - c`
+    Left,
+    /// `a ** b ** c` is `a ** (b ** c)`
+    Right,
+}
+
+/// Precedence and associativity of an operator token, higher precedence binds tighter
+#[derive(Debug, PartialEq, Clone)]
+pub struct OperatorRule {
+    pub token: Token,
+    pub fixity: Fixity,
+    pub precedence: u8,
+    /// Only used for infix operators
+    pub associativity: Associativity,
+}
+
+impl OperatorRule {
+    pub const fn new(token: Token, fixity: Fixity, precedence: u8, associativity: Associativity) -> Self {
+        Self { token, fixity, precedence, associativity }
+    }
+
+    const fn infix(token: Token, precedence: u8, associativity: Associativity) -> Self {
+        Self::new(token, Fixity::Infix, precedence, associativity)
+    }
+
+    /// Left and right binding power for the Pratt parser
+    fn binding_power(&self) -> (usize, usize) {
+        let p = 2 * self.precedence as usize;
+        match (self.fixity, self.associativity) {
+            (Fixity::Prefix, _) => (0, p),
+            (Fixity::Postfix, _) => (p, 0),
+            (Fixity::Infix, Associativity::Left) => (p, p + 1),
+            (Fixity::Infix, Associativity::Right) => (p + 1, p),
+        }
+    }
+}
+
+use Associativity::{Left, Right};
+use ArithmeticOperator as AO;
+
+/// The default rules, see the module documentation
+pub const PRECEDENCE_TABLE: [OperatorRule; 26] = [
+    OperatorRule::infix(Token::Dot, 12, Left),
+    OperatorRule::new(Token::LB, Fixity::Postfix, 11, Left),
+    OperatorRule::infix(Token::ArOp(AO::Pow), 10, Right),
+    OperatorRule::new(Token::ArOp(AO::Plus), Fixity::Prefix, 9, Right),
+    OperatorRule::new(Token::ArOp(AO::Minus), Fixity::Prefix, 9, Right),
+    OperatorRule::new(Token::ArOp(AO::Negate), Fixity::Prefix, 9, Right),
+    OperatorRule::infix(Token::ArOp(AO::Mul), 8, Left),
+    OperatorRule::infix(Token::ArOp(AO::Div), 8, Left),
+    OperatorRule::infix(Token::ArOp(AO::Rem), 8, Left),
+    OperatorRule::infix(Token::ArOp(AO::Plus), 7, Left),
+    OperatorRule::infix(Token::ArOp(AO::Minus), 7, Left),
+    OperatorRule::infix(Token::ArOp(AO::Xor), 6, Left),
+    OperatorRule::infix(Token::Colon, 5, Right),
+    OperatorRule::infix(Token::RelOp(RelationalOperator::Eq), 4, Left),
+    OperatorRule::infix(Token::RelOp(RelationalOperator::Neq), 4, Left),
+    OperatorRule::infix(Token::RelOp(RelationalOperator::Greater), 4, Left),
+    OperatorRule::infix(Token::RelOp(RelationalOperator::Lesser), 4, Left),
+    OperatorRule::infix(Token::RelOp(RelationalOperator::Geq), 4, Left),
+    OperatorRule::infix(Token::RelOp(RelationalOperator::Leq), 4, Left),
+    OperatorRule::infix(Token::LogicOp(LogicalOperator::And), 3, Right),
+    OperatorRule::infix(Token::LogicOp(LogicalOperator::Or), 2, Right),
+    OperatorRule::infix(Token::AssignOp(AssignmentOperator::Assign), 1, Right),
+    OperatorRule::infix(Token::AssignOp(AssignmentOperator::PlusEq), 1, Right),
+    OperatorRule::infix(Token::AssignOp(AssignmentOperator::MinusEq), 1, Right),
+    OperatorRule::infix(Token::AssignOp(AssignmentOperator::TimesEq), 1, Right),
+    OperatorRule::infix(Token::AssignOp(AssignmentOperator::DivideEq), 1, Right),
+];
+
+/// Binding powers of `token` used as a `fixity` operator
+fn lookup(rules: &[OperatorRule], token: &Token, fixity: Fixity) -> Option<(usize, usize)> {
+    rules.iter()
+        .find(|r| r.fixity == fixity && r.token == *token)
+        .map(|r| r.binding_power())
+}
+
+pub(super) fn prefix_precedence(t: &Token) -> Option<(usize, usize)> {
+    lookup(&PRECEDENCE_TABLE, t, Fixity::Prefix)
+}
+
+pub(super) fn infix_precedence(t: &Token) -> Option<(usize, usize)> {
+    lookup(&PRECEDENCE_TABLE, t, Fixity::Infix)
+}
+
+
+/// Options of the parser
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParserConfig {
+    rules: Vec<OperatorRule>,
+}
+
+impl Default for ParserConfig {
+    fn default() -> Self {
+        Self { rules: PRECEDENCE_TABLE.to_vec() }
+    }
+}
+
+impl ParserConfig {
+    /// Add a rule, or replace the rule of the same token and fixity, e.g. to
+    /// make `**` left associative:
+    ///
+    /// ```ignore
+    /// let config = ParserConfig::default()
+    ///     .with_rule(OperatorRule::new(Token::ArOp(ArithmeticOperator::Pow), Fixity::Infix, 10, Associativity::Left));
+    /// ```
+    ///
+    /// Note that `AST::to_expression()` prints with the default rules.
+    pub fn with_rule(mut self, rule: OperatorRule) -> Self {
+        match self.rules.iter_mut().find(|r| r.fixity == rule.fixity && r.token == rule.token) {
+            Some(existing) => *existing = rule,
+            None => self.rules.push(rule),
+        }
+        self
+    }
+
+    /// Remove the rule of `token` as a `fixity` operator, so it is not accepted
+    /// in that position
+    pub fn without_rule(mut self, token: &Token, fixity: Fixity) -> Self {
+        self.rules.retain(|r| !(r.fixity == fixity && r.token == *token));
+        self
+    }
+
+    pub fn rules(&self) -> &[OperatorRule] {
+        &self.rules
+    }
+
+    pub(super) fn prefix(&self, t: &Token) -> Option<(usize, usize)> {
+        lookup(&self.rules, t, Fixity::Prefix)
+    }
+
+    pub(super) fn infix(&self, t: &Token) -> Option<(usize, usize)> {
+        lookup(&self.rules, t, Fixity::Infix)
+    }
+
+    pub(super) fn postfix(&self, t: &Token) -> Option<(usize, usize)> {
+        lookup(&self.rules, t, Fixity::Postfix)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn rpn(expr: &str) -> String {
+        AST::from_expression(expr).unwrap().rpn_repr()
+    }
+
+    #[test]
+    fn test_default_rules() {
+        assert_eq!(rpn("2 ** 3 ** 2"), "(**: 2, (**: 3, 2))");
+        assert_eq!(rpn("-x ** 2"), "(-: (**: x, 2))");
+        assert_eq!(rpn("-a + b"), "(+: (-: a), b)");
+        assert_eq!(rpn("-a * b"), "(*: (-: a), b)");
+        assert_eq!(rpn("s.x ** 2"), "(**: (.: s, x), 2)");
+        assert_eq!(rpn("a * b[0]"), "(*: a, ([: b, 0))");
+        assert_eq!(rpn("x != 0 and 1 / x > 2"), "(and: (≠: x, 0), (>: (/: 1, x), 2))");
+        assert_eq!(rpn("a or b and c"), "(or: a, (and: b, c))");
+    }
+
+    #[test]
+    fn test_every_operator_has_a_rule() {
+        let ops = ["+", "-", "*", "/", "**", "==", "<", ">", "=", "+=", "-=", "*=", "/=", ".", ":", "and", "or"];
+        for op in ops {
+            let ts = TokenStream::new(op).unwrap();
+            let token = &ts.tokens()[0].token;
+            assert!(PRECEDENCE_TABLE.iter().any(|r| r.token == *token && r.fixity == Fixity::Infix), "{}", op);
+        }
+    }
+
+    #[test]
+    fn test_overrides() {
+        let pow = Token::ArOp(ArithmeticOperator::Pow);
+        let config = ParserConfig::default()
+            .with_rule(OperatorRule::new(pow.clone(), Fixity::Infix, 10, Associativity::Left));
+        let ast = AST::with_config(TokenStream::new("2 ** 3 ** 2").unwrap(), &config).unwrap();
+        assert_eq!(ast.rpn_repr(), "(**: (**: 2, 3), 2)");
+
+        // signs bind looser than `+`, as in earlier versions
+        let minus = Token::ArOp(ArithmeticOperator::Minus);
+        let config = ParserConfig::default()
+            .with_rule(OperatorRule::new(minus, Fixity::Prefix, 4, Associativity::Right));
+        let ast = AST::with_config(TokenStream::new("-a + b").unwrap(), &config).unwrap();
+        assert_eq!(ast.rpn_repr(), "(-: (+: a, b))");
+
+        let config = ParserConfig::default().without_rule(&pow, Fixity::Infix);
+        assert!(AST::with_config(TokenStream::new("2 ** 3").unwrap(), &config).is_err());
+    }
+}
@@ -0,0 +1,262 @@
+use std::fmt::{self, Write};
+use crate::{ArithmeticOperator, Constant, Token, TokenContext};
+use super::{AST, Branch, infix_precedence, prefix_precedence};
+
+
+impl AST {
+    /// Print the AST as an expression that parses back to the same tree,
+    /// e.g. `y = 2 * (x + 1) ** 2`. Parentheses are only added where the
+    /// precedence of the operators requires them, and around negated operands.
+    pub fn to_expression(&self) -> String {
+        let mut s = String::new();
+        if let Some(varname) = &self.assigned_to {
+            s.push_str(&Token::Var(varname.clone()).to_string());
+            s.push_str(" = ");
+        }
+        self.tree.write_expression(&mut s).unwrap(); // writing to a String does not fail
+        s
+    }
+}
+
+impl Branch {
+    /// Print the (sub)tree as an expression, see `AST::to_expression()`
+    pub fn to_expression(&self) -> String {
+        let mut s = String::new();
+        self.write_expression(&mut s).unwrap();
+        s
+    }
+
+    /// Write the (sub)tree as an expression into `w`
+    pub fn write_expression<W: Write>(&self, w: &mut W) -> fmt::Result {
+        match self {
+            Branch::Atom(tc) => write_atom(tc, w),
+            Branch::Expression(tc, args) => match (&tc.token, args.as_slice()) {
+                (Token::Func(f, _), args) => {
+                    write!(w, "{}(", f.name())?;
+                    for (i, arg) in args.iter().enumerate() {
+                        if i > 0 {
+                            w.write_str(", ")?;
+                        }
+                        arg.write_expression(w)?;
+                    }
+                    w.write_char(')')
+                }
+                (Token::Arrow, [param, body]) => {
+                    param.write_expression(w)?;
+                    w.write_str(" -> ")?;
+                    body.write_expression(w)
+                }
+                (Token::Arrow, [params @ .., body]) => {
+                    w.write_char('(')?;
+                    for (i, param) in params.iter().enumerate() {
+                        if i > 0 {
+                            w.write_str(", ")?;
+                        }
+                        param.write_expression(w)?;
+                    }
+                    w.write_str(") -> ")?;
+                    body.write_expression(w)
+                }
+                (Token::LP, elements) => {
+                    w.write_char('(')?;
+                    for (i, element) in elements.iter().enumerate() {
+                        if i > 0 {
+                            w.write_str(", ")?;
+                        }
+                        element.write_expression(w)?;
+                    }
+                    w.write_char(')')
+                }
+                (Token::Dot, [obj, attr]) => {
+                    write_operand(obj, !is_postfix_operand(obj), w)?;
+                    w.write_char('.')?;
+                    attr.write_expression(w)
+                }
+                (Token::LB, [array, index]) => {
+                    write_operand(array, !is_postfix_operand(array), w)?;
+                    w.write_char('[')?;
+                    index.write_expression(w)?;
+                    w.write_char(']')
+                }
+                (op, [arg]) if prefix_precedence(op).is_some() => {
+                    // `-2` would be read as a negative number instead of a negation
+                    let parens = !is_postfix_operand(arg) || matches!(arg.tc().token, Token::Number(_));
+                    write!(w, "{}", op)?;
+                    write_operand(arg, parens, w)
+                }
+                (op, [lhs, rhs]) if let Some((l_bp, r_bp)) = infix_precedence(op) => {
+                    // `lhs` would absorb `op` if its operator binds weaker from the right
+                    let lhs_parens = match infix_operator(lhs) {
+                        Some((_, lhs_r_bp)) => l_bp >= lhs_r_bp,
+                        None => is_prefix_expression(lhs)
+                    };
+                    // `op` would absorb the operator of `rhs` if it binds stronger from the right
+                    let rhs_parens = match infix_operator(rhs) {
+                        Some((rhs_l_bp, _)) => rhs_l_bp < r_bp,
+                        None => is_prefix_expression(rhs)
+                    };
+                    write_operand(lhs, lhs_parens, w)?;
+                    write!(w, " {} ", op)?;
+                    write_operand(rhs, rhs_parens, w)
+                }
+                (op, _) => write!(w, "{}", op)
+            }
+        }
+    }
+}
+
+fn write_atom<W: Write>(tc: &TokenContext, w: &mut W) -> fmt::Result {
+    match &tc.token {
+        // numbers as written: `1e-9` and not `0.000000001`
+        Token::Number(x) if x.is_sign_negative() => match &tc.literal {
+            Some(literal) => write!(w, "({})", literal),
+            None => write!(w, "(-{})", -x)
+        },
+        Token::Number(_) if let Some(literal) = &tc.literal => w.write_str(literal),
+        Token::Const(c) => match c {
+            Constant::Pi => w.write_str("pi"),
+            Constant::Euler => w.write_str("e"),
+            Constant::Sqrt2 => w.write_str("sqrt2"),
+            Constant::Sqrt3 => w.write_str("sqrt3"),
+            c => write!(w, "{}", c.value()),
+        },
+        token => write!(w, "{}", token)
+    }
+}
+
+fn write_operand<W: Write>(branch: &Branch, parens: bool, w: &mut W) -> fmt::Result {
+    if parens {
+        w.write_char('(')?;
+        branch.write_expression(w)?;
+        w.write_char(')')
+    } else {
+        branch.write_expression(w)
+    }
+}
+
+/// Binding powers of the binary operator of `branch`, if it is one
+fn infix_operator(branch: &Branch) -> Option<(usize, usize)> {
+    match branch {
+        Branch::Expression(tc, args) if args.len() == 2 && tc.token != Token::Dot => infix_precedence(&tc.token),
+        _ => None
+    }
+}
+
+/// Signs bind looser than `**` (`-a ** b` is `-(a ** b)`) and the precedences
+/// may be overridden, so signed operands are always parenthesized
+fn is_prefix_expression(branch: &Branch) -> bool {
+    matches!(branch, Branch::Expression(tc, args)
+        if args.len() == 1 && matches!(tc.token, Token::ArOp(ArithmeticOperator::Plus | ArithmeticOperator::Minus)))
+}
+
+/// Operands that need no parentheses before `.field`, `[index]`, or after a sign
+fn is_postfix_operand(branch: &Branch) -> bool {
+    match branch {
+        Branch::Atom(tc) => !matches!(tc.token, Token::Number(x) if x.is_sign_negative()),
+        Branch::Expression(tc, _) => matches!(tc.token, Token::Func(..) | Token::Dot | Token::LB | Token::LP),
+    }
+}
+
+
+/// Parentheses around the operands of the binary operator `op` (not `.` and `->`) in
+/// the typeset outputs (LaTeX, MathML): as in `to_expression()`, but fractions need none
+pub(super) fn infix_parens(op: &Token, lhs: &Branch, rhs: &Branch) -> Option<(bool, bool)> {
+    let (l_bp, r_bp) = infix_precedence(op)?;
+    if matches!(op, Token::Dot | Token::Arrow) {
+        return None;
+    }
+    let lhs_parens = match infix_operator(lhs) {
+        _ if is_fraction(lhs) => false,
+        Some((_, lhs_r_bp)) => l_bp >= lhs_r_bp,
+        None => is_prefix_expression(lhs)
+    };
+    let rhs_parens = match infix_operator(rhs) {
+        _ if is_fraction(rhs) => false,
+        Some((rhs_l_bp, _)) => rhs_l_bp < r_bp,
+        None => is_prefix_expression(rhs)
+    };
+    Some((lhs_parens, rhs_parens))
+}
+
+/// Parentheses around the operand of a sign: `-x^{2}` and `-\frac{a}{b}`
+/// read as negations of the power and the fraction
+pub(super) fn sign_parens(operand: &Branch) -> bool {
+    !(is_postfix_operand(operand) || is_fraction(operand) || is_power(operand)) || is_number(operand)
+}
+
+/// Parentheses around the base of a power
+pub(super) fn base_parens(base: &Branch) -> bool {
+    !is_postfix_operand(base) || is_number(base)
+}
+
+fn is_fraction(branch: &Branch) -> bool {
+    matches!(branch, Branch::Expression(tc, args) if args.len() == 2 && tc.token == Token::ArOp(ArithmeticOperator::Div))
+}
+
+fn is_power(branch: &Branch) -> bool {
+    matches!(branch, Branch::Expression(tc, args) if args.len() == 2 && tc.token == Token::ArOp(ArithmeticOperator::Pow))
+}
+
+fn is_number(branch: &Branch) -> bool {
+    matches!(branch, Branch::Atom(tc) if matches!(tc.token, Token::Number(_)))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn reprint(expr: &str) -> String {
+        AST::from_expression(expr).unwrap().to_expression()
+    }
+
+    #[test]
+    fn test_minimal_parens() {
+        assert_eq!(reprint("y=2*x+sin (  x)**2"), "y = 2 * x + sin(x) ** 2");
+        assert_eq!(reprint("(a - b) - c"), "a - b - c");
+        assert_eq!(reprint("a - (b - c)"), "a - (b - c)");
+        assert_eq!(reprint("(a + b) * (c / d)"), "(a + b) * (c / d)");
+        assert_eq!(reprint("((a)) ** 2"), "a ** 2");
+        assert_eq!(reprint("mean(a, b, -c)"), "avg(a, b, -c)");
+    }
+
+    #[test]
+    fn test_signs_and_fields() {
+        assert_eq!(reprint("-(a + b)"), "-(a + b)");
+        assert_eq!(reprint("-a"), "-a");
+        assert_eq!(reprint("-2 + -(2)"), "(-2) + (-(2))");
+        assert_eq!(reprint("s.x * -sin(s.y)"), "s.x * (-sin(s.y))");
+        assert_eq!(reprint("(a + b).x"), "(a + b).x");
+        assert_eq!(reprint("pi * `my var`"), "pi * `my var`");
+        assert_eq!(reprint("( min(v),max(v) )"), "(min(v), max(v))");
+        assert_eq!(reprint("-(a, b)"), "-(a, b)");
+    }
+
+    #[test]
+    fn test_number_literals() {
+        assert_eq!(reprint("x * 1e-9 + 2.50 - 1_000"), "x * 1e-9 + 2.50 - 1_000");
+        assert_eq!(reprint("0.1 + 0.2 + -1.5E3"), "0.1 + 0.2 + (-1.5E3)");
+        assert_eq!(reprint("0.30000000000000004"), "0.30000000000000004");
+        let ast = AST::from_expression("1e-9 * x").unwrap();
+        assert_eq!(ast.tree.latex(), AST::from_expression(&ast.to_expression()).unwrap().tree.latex());
+        assert!(ast.tree.latex().contains("1e-9"));
+        // the literal is only kept where the value is printed differently
+        let ts = TokenStream::new("1.5 + 1.50").unwrap();
+        assert_eq!(ts.tokens()[0].literal, None);
+        assert_eq!(ts.tokens()[2].literal.as_deref(), Some("1.50"));
+    }
+
+    #[test]
+    fn test_reparse() {
+        for expr in ["a - (b - c) * d ** (e - 1)", "-(x) - s.field", "z = max(1, -2, x ** -y)",
+            "now() - t", "(-2) ** 2", "a * -b + c", "map(v, x -> x * a)", "reduce(v, (a, b) -> a + b, 0)",
+            "with(t = x * 2, sin(t) + t)", "(min(v), max(v) + 1)", "(lo = min(v), hi = max(v)).lo", "(a, b).1",
+            "convert(x, \"eV\", \"nm\")", "m - avg(m, axis = 0, keepdims = 1)"] {
+            let ast = AST::from_expression(expr).unwrap();
+            let printed = ast.to_expression();
+            let reparsed = AST::from_expression(&printed).unwrap();
+            assert!(ast.tree.same_structure(&reparsed.tree), "{} -> {}", expr, printed);
+            assert_eq!(ast.assigned_to, reparsed.assigned_to);
+        }
+    }
+}
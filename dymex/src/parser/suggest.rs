@@ -0,0 +1,103 @@
+//! "Did you mean" suggestions for misspelled identifiers
+
+use crate::Function;
+
+/// Names of the constants, see `parse_const()` of the tokenizer
+const CONSTANTS: [&str; 4] = ["e", "pi", "sqrt2", "sqrt3"];
+
+
+/// Edit distance of `a` and `b`: the number of inserted, deleted or replaced characters
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let replaced = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = replaced.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// The closest of `candidates` to the misspelled `name`, if it is close enough
+/// to be a typo: at most a third of the characters differ (at least one, but
+/// not all of them). The first one of the equally close candidates.
+pub fn closest_name<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let len = name.chars().count();
+    let limit = (len / 3).max(1);
+    candidates.into_iter()
+        .map(|candidate| (levenshtein(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= limit && *distance < len)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Suggestion for the unknown function `name`
+pub(crate) fn suggest_function(name: &str) -> Option<String> {
+    closest_name(name, Function::ALL.iter().map(|f| f.name())).map(str::to_string)
+}
+
+/// Names of the built in functions: `min, max, ...`
+pub(crate) fn function_list() -> String {
+    Function::ALL.iter().map(|f| f.name()).collect::<Vec<_>>().join(", ")
+}
+
+/// Suggestion for the undefined variable `name`: one of the `variables`, a function or a constant
+pub(crate) fn suggest_identifier(name: &str, variables: &[&str]) -> Option<String> {
+    let builtins = Function::ALL.iter().map(|f| f.name()).chain(CONSTANTS);
+    closest_name(name, variables.iter().copied().chain(builtins)).map(str::to_string)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use super::suggest_identifier;
+
+    #[test]
+    fn test_suggestions() {
+        assert_eq!(levenshtein("sgrt", "sqrt"), 1);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("ϕ0", "ϕ1"), 1);
+
+        assert_eq!(suggest_identifier("sgrt", &["x"]).as_deref(), Some("sqrt"));
+        assert_eq!(suggest_identifier("voltage_", &["voltage", "current"]).as_deref(), Some("voltage"));
+        assert_eq!(suggest_identifier("pii", &[]).as_deref(), Some("pi"));
+        assert_eq!(suggest_identifier("x", &["y"]), None);
+        assert_eq!(suggest_identifier("temperature", &["pressure"]), None);
+
+        let err = validate("sin(x) + sgrt", &["x"]).unwrap_err();
+        let message = err.user_message();
+        assert!(matches!(err, DymexError::ParsingError(ParsingError::UndefinedVariable(_, 9, Some(s))) if s == "sqrt"));
+        assert_eq!(message.hint(), Some("Did you mean `sqrt`?"));
+        assert_eq!(message.args(), [("name", "sgrt".to_string()), ("suggestion", "sqrt".to_string())]);
+        assert_eq!(validate("x + zzz", &["x"]).unwrap_err().user_message().hint(), None);
+    }
+
+    #[test]
+    fn test_unknown_functions() {
+        let err = AST::from_expression("1 + sgrt(x * (2 + y))").unwrap_err();
+        assert_eq!(err, ParsingError::UnknownFunction("sgrt".into(), 4, Some("sqrt".into())));
+        let message = err.user_message();
+        assert_eq!(message.code(), Some("E113"));
+        assert!(message.hint().unwrap().starts_with("Did you mean `sqrt`? The functions are: min, max, avg"));
+        let err = AST::from_expression("fourier(x)").unwrap_err();
+        assert_eq!(err, ParsingError::UnknownFunction("fourier".into(), 0, None));
+        assert!(err.user_message().hint().unwrap().starts_with("The functions are: "));
+        // also with late bound variables
+        assert!(matches!(Evaluator::with_policy("avrg(x)", &[], VariablePolicy::Permissive),
+            Err(DymexError::ParsingError(ParsingError::UnknownFunction(_, 0, Some(s)))) if s == "avg"));
+
+        // the arguments are skipped by the error recovery
+        let partial = AST::parse_partial("sgrt(x, (y)) + 1");
+        assert_eq!(partial.ast.rpn_repr(), "(+: ⚠, 1)");
+        assert_eq!(partial.errors.len(), 1);
+
+        assert_eq!(Function::from_name("mean"), Some(Function::Avg));
+        assert_eq!(Function::from_name("sgrt"), None);
+    }
+}
@@ -0,0 +1,123 @@
+//! The subset of the expressions that is translated to code by `AST::to_rust()`,
+//! `JitEvaluator` and `GpuEvaluator`: numbers, constants, variables, the arithmetic
+//! operators (`+ - * / **`), the elementwise functions (`abs`, `sqrt`, `exp`, `log`s
+//! and the trigonometric functions), `min()` and `max()` of several values and
+//! `where()` with comparisons and `and`/`or` as the condition.
+//!
+//! `translate()` walks the tree and checks the subset, the backends implement
+//! `Emitter` to emit the code of each node.
+
+use crate::{ArithmeticOperator, Constant, Float, Function, LogicalOperator, RelationalOperator, Token};
+use super::Branch;
+
+/// The elementwise functions of a single number in the subset
+const UNARY: [Function; 10] = [Function::Abs, Function::Sqrt, Function::Exp, Function::Log,
+Function::Log2, Function::Log10, Function::Sin, Function::Cos, Function::Tan, Function::Cot];
+
+/// The code of the nodes of the subset for a backend. `Number` and `Bool` are
+/// the code of the numeric and boolean subexpressions, the operands are emitted
+/// before the operation, from left to right.
+pub(crate) trait Emitter {
+    type Number;
+    type Bool;
+    type Error;
+
+    /// The error of a node outside of the subset
+    fn unsupported(&mut self, branch: &Branch) -> Self::Error;
+
+    fn number(&mut self, x: Float) -> Result<Self::Number, Self::Error>;
+
+    /// A named constant, its value by default
+    fn constant(&mut self, c: &Constant) -> Result<Self::Number, Self::Error> {
+        self.number(c.value())
+    }
+
+    /// The variable `name` at the position `at` of the expression
+    fn variable(&mut self, name: &str, at: usize) -> Result<Self::Number, Self::Error>;
+
+    fn negate(&mut self, x: Self::Number) -> Result<Self::Number, Self::Error>;
+
+    /// `a op b` of the operators `+ - * /`
+    fn arithmetic(&mut self, op: &ArithmeticOperator, a: Self::Number, b: Self::Number) -> Result<Self::Number, Self::Error>;
+
+    /// `base ** exponent`, `rhs` is the node of the exponent (for the literal exponents)
+    fn power(&mut self, base: Self::Number, exponent: Self::Number, rhs: &Branch) -> Result<Self::Number, Self::Error>;
+
+    /// One of the functions of `UNARY`
+    fn unary(&mut self, f: &Function, x: Self::Number) -> Result<Self::Number, Self::Error>;
+
+    /// `min(a, b)` or `max(a, b)`, more arguments are folded from the left
+    fn extremum(&mut self, f: &Function, a: Self::Number, b: Self::Number) -> Result<Self::Number, Self::Error>;
+
+    /// `where(condition, a, b)`
+    fn select(&mut self, condition: Self::Bool, a: Self::Number, b: Self::Number) -> Result<Self::Number, Self::Error>;
+
+    fn compare(&mut self, op: &RelationalOperator, a: Self::Number, b: Self::Number) -> Result<Self::Bool, Self::Error>;
+
+    fn logical(&mut self, op: &LogicalOperator, a: Self::Bool, b: Self::Bool) -> Result<Self::Bool, Self::Error>;
+}
+
+/// The code of a numeric node, the error of the first node outside of the subset
+pub(crate) fn translate<E: Emitter>(branch: &Branch, emitter: &mut E) -> Result<E::Number, E::Error> {
+    let args = match branch {
+        Branch::Atom(tc) => return match &tc.token {
+            Token::Number(x) => emitter.number(*x),
+            Token::Const(c) => emitter.constant(c),
+            Token::Var(name) => emitter.variable(name, tc.at),
+            _ => Err(emitter.unsupported(branch))
+        },
+        Branch::Expression(_, args) => args.as_slice()
+    };
+    match (&branch.tc().token, args) {
+        (Token::ArOp(ArithmeticOperator::Plus), [x]) => translate(x, emitter),
+        (Token::ArOp(ArithmeticOperator::Minus), [x]) => {
+            let x = translate(x, emitter)?;
+            emitter.negate(x)
+        }
+        (Token::ArOp(op @ (ArithmeticOperator::Plus | ArithmeticOperator::Minus | ArithmeticOperator::Mul
+            | ArithmeticOperator::Div | ArithmeticOperator::Pow)), [lhs, rhs]) => {
+            let (a, b) = (translate(lhs, emitter)?, translate(rhs, emitter)?);
+            match op {
+                ArithmeticOperator::Pow => emitter.power(a, b, rhs),
+                op => emitter.arithmetic(op, a, b)
+            }
+        }
+        (Token::Func(Function::Where, _), [condition_, a, b]) => {
+            let condition = condition(condition_, emitter)?;
+            let (a, b) = (translate(a, emitter)?, translate(b, emitter)?);
+            emitter.select(condition, a, b)
+        }
+        // a single argument is the minimum of an array
+        (Token::Func(f @ (Function::Min | Function::Max), _), [first, rest @ ..]) if !rest.is_empty() => {
+            let mut result = translate(first, emitter)?;
+            for arg in rest {
+                let x = translate(arg, emitter)?;
+                result = emitter.extremum(f, result, x)?;
+            }
+            Ok(result)
+        }
+        (Token::Func(f, _), [x]) if UNARY.contains(f) => {
+            let x = translate(x, emitter)?;
+            emitter.unary(f, x)
+        }
+        _ => Err(emitter.unsupported(branch))
+    }
+}
+
+/// The code of a comparison or a logical operation
+fn condition<E: Emitter>(branch: &Branch, emitter: &mut E) -> Result<E::Bool, E::Error> {
+    let Branch::Expression(tc, args) = branch else {
+        return Err(emitter.unsupported(branch))
+    };
+    match (&tc.token, args.as_slice()) {
+        (Token::RelOp(op), [lhs, rhs]) => {
+            let (a, b) = (translate(lhs, emitter)?, translate(rhs, emitter)?);
+            emitter.compare(op, a, b)
+        }
+        (Token::LogicOp(op), [lhs, rhs]) => {
+            let (a, b) = (condition(lhs, emitter)?, condition(rhs, emitter)?);
+            emitter.logical(op, a, b)
+        }
+        _ => Err(emitter.unsupported(branch))
+    }
+}
@@ -0,0 +1,649 @@
+//! Utilities for testing expressions, in this crate and in crates that add
+//! their own types.
+//!
+//! Besides a few helpers, the module implements a data driven test corpus, so
+//! regression cases can be added without writing Rust. A corpus is a CSV file
+//! with the columns `expression`, `variables` and `expected`:
+//!
+//! ```text
+//! expression,variables,expected
+//! # lines starting with '#' are comments
+//! "max(a, b) * 2","a = 1; b = 3",6
+//! v * 2,"v = [1, 2, 3]","[2, 4, 6]"
+//! x + y,x = 1,!UndefinedVariable
+//! ```
+//!
+//! Fields containing commas are quoted (`""` is a quote inside quotes).
+//! Variables are separated by `;`, their values are numbers or arrays. The
+//! expected value is a number, an array, or `!` followed by an error code: the
+//! name of the `ParsingError`, `TokenizerError` or `EvaluationError` variant.
+//!
+//! For property based tests, `ExprGenerator` produces random valid expressions.
+//! `robustness_report()` evaluates a formula with boundary values of its inputs.
+use std::fmt;
+use crate::{DymexError, DynMath, Evaluator, EvaluationError, InputVars, Missing, ParsingError, Category, Tuple};
+use crate::{AST, Constant, ExprBuilder, Function};
+use crate::Float;
+use crate::float;
+use crate::Token;
+
+
+const ZERO_THR: Float = 1E2 * float::EPSILON;
+const REL_ERR: Float  = 1E2 * float::EPSILON;
+
+/// Compare two numbers and return true if they are close enoguh (test util)
+// This is meant to be a fairly lenient comparison as our goal is not to test
+// floating-point artihmetic, but to see if the evaluated result is what we excepted
+pub fn approx_eq(x1: Float, x2: Float) -> bool {
+    if x1.abs() < ZERO_THR
+    && x2.abs() < ZERO_THR {
+        return true
+    }
+    if 2.0 * (x1-x2).abs() / (x1 + x2).abs() > REL_ERR {
+        return false
+    }
+    true
+}
+
+/// Compare two results with the tolerance `tol`: numbers are equal if
+/// `|x - y| <= tol * max(1, |x|, |y|)`, i.e. `tol` is an absolute tolerance
+/// around zero and a relative one for larger values, and NaN equals NaN.
+/// Arrays are compared elementwise and need the same length, a number equals
+/// an array if it equals each element: `dyn_eq(&*result, &0.0, 1e-9)` checks
+/// that every element is zero. Missing values (`NA`) equal each other, tuples
+/// are compared element by element, booleans and strings exactly.
+pub fn dyn_eq(a: &dyn DynMath, b: &dyn DynMath, tol: Float) -> bool {
+    let same = |x: Float, y: Float| (x.is_nan() && y.is_nan()) || x == y
+        || (x - y).abs() <= tol * x.abs().max(y.abs()).max(1.0);
+    match (a.category(), b.category()) {
+        (Category::Number, Category::Number) => same(a.as_number(), b.as_number()),
+        (Category::Number, Category::Array) => b.iterate().all(|y| same(a.as_number(), *y)),
+        (Category::Array, Category::Number) => a.iterate().all(|x| same(*x, b.as_number())),
+        (Category::Array, Category::Array) => a.shape() == b.shape()
+            && a.iterate().zip(b.iterate()).all(|(x, y)| same(*x, *y)),
+        (Category::Unqiue, Category::Unqiue) => {
+            if let (Some(a), Some(b)) = (a.downcast_ref::<Tuple>(), b.downcast_ref::<Tuple>()) {
+                a.len() == b.len() && a.names() == b.names() && a.iter().zip(b.iter()).all(|(x, y)| dyn_eq(x, y, tol))
+            } else if let (Some(v), Some(w)) = (a.downcast_ref::<Vec<Option<Float>>>(), b.downcast_ref::<Vec<Option<Float>>>()) {
+                v.len() == w.len() && v.iter().zip(w).all(|(x, y)| match (x, y) {
+                    (Some(x), Some(y)) => same(*x, *y),
+                    (x, y) => x.is_none() && y.is_none()
+                })
+            } else if let (Some(x), Some(y)) = (a.downcast_ref::<bool>(), b.downcast_ref::<bool>()) {
+                x == y
+            } else if let (Some(v), Some(w)) = (a.downcast_ref::<Vec<bool>>(), b.downcast_ref::<Vec<bool>>()) {
+                v == w
+            } else if let (Some(s), Some(t)) = (a.downcast_ref::<String>(), b.downcast_ref::<String>()) {
+                s == t
+            } else {
+                a.is::<Missing>() && b.is::<Missing>()
+            }
+        }
+        _ => false
+    }
+}
+
+pub fn same_num_tokens(t1: Token, t2: Token) -> bool {
+    match (t1, t2) {
+        (Token::Number(x1), Token::Number(x2)) => approx_eq(x1, x2),
+        _ => false
+    }
+}
+
+pub fn charslice(s: &str) -> Vec<char> {
+    s.chars().collect()
+}
+
+/// Name of the error variant, e.g. `UnexpectedToken` for `UnexpectedToken(3)`
+fn variant_name(error: &impl fmt::Debug) -> String {
+    format!("{:?}", error).chars().take_while(|c| c.is_alphanumeric() || *c == '_').collect()
+}
+
+/// Code of an error that occurred while building an `Evaluator`: the name of
+/// the innermost `ParsingError` or `TokenizerError` variant
+pub fn error_code(error: &DymexError) -> String {
+    match error {
+        DymexError::LexicalError(e) => variant_name(e),
+        DymexError::ParsingError(ParsingError::LexingError(e)) => variant_name(e),
+        DymexError::ParsingError(e) => variant_name(e),
+    }
+}
+
+/// Code of an evaluation error: the name of the `EvaluationError` variant
+pub fn evaluation_error_code(error: &EvaluationError) -> String {
+    variant_name(error)
+}
+
+
+/// Value of an input variable or the expected result of a golden case
+#[derive(Debug, Clone, PartialEq)]
+pub enum GoldenValue {
+    Number(Float),
+    Array(Vec<Float>),
+}
+
+/// Expected outcome of a golden case
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expected {
+    Value(GoldenValue),
+    /// Error code, see `error_code()`
+    Error(String),
+}
+
+/// A single case of a test corpus, see the module documentation
+#[derive(Debug, Clone, PartialEq)]
+pub struct GoldenCase {
+    /// Line of the case in the corpus file (1-based)
+    pub line: usize,
+    pub expression: String,
+    pub variables: Vec<(String, GoldenValue)>,
+    pub expected: Expected,
+}
+
+/// Malformed line in a corpus file
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorpusError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CorpusError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// A golden case whose outcome differs from the expected one
+#[derive(Debug, Clone, PartialEq)]
+pub struct GoldenFailure {
+    pub line: usize,
+    pub expression: String,
+    pub message: String,
+}
+
+impl fmt::Display for GoldenFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: `{}`: {}", self.line, self.expression, self.message)
+    }
+}
+
+/// Parse a CSV test corpus, see the module documentation for the format.
+/// The first non-comment line is the header.
+pub fn parse_corpus(csv: &str) -> Result<Vec<GoldenCase>, CorpusError> {
+    let mut cases = Vec::new();
+    let mut header = true;
+    for (i, line) in csv.lines().enumerate() {
+        let line_no = i + 1;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let error = |message: String| CorpusError { line: line_no, message };
+        let fields = split_csv_line(line).map_err(error)?;
+        if header {
+            if fields != ["expression", "variables", "expected"] {
+                return Err(error("expected the header `expression,variables,expected`".into()));
+            }
+            header = false;
+            continue;
+        }
+        if fields.len() != 3 {
+            return Err(error(format!("expected 3 fields, found {}", fields.len())));
+        }
+        let variables = parse_variables(&fields[1]).map_err(error)?;
+        let expected = parse_expected(&fields[2]).map_err(error)?;
+        cases.push(GoldenCase { line: line_no, expression: fields[0].clone(), variables, expected });
+    }
+    Ok(cases)
+}
+
+/// Run every case of a CSV test corpus and return the failures
+pub fn run_corpus(csv: &str) -> Result<Vec<GoldenFailure>, CorpusError> {
+    let cases = parse_corpus(csv)?;
+    Ok(cases.iter().filter_map(|case| case.run().err()).collect())
+}
+
+impl GoldenCase {
+    /// Evaluate the expression and compare the outcome with the expected one
+    pub fn run(&self) -> Result<(), GoldenFailure> {
+        let failure = |message: String| GoldenFailure {
+            line: self.line,
+            expression: self.expression.clone(),
+            message
+        };
+        let mut inputs = InputVars::new();
+        for (name, value) in &self.variables {
+            match value {
+                GoldenValue::Number(x) => inputs.insert_owned(name.clone(), *x),
+                GoldenValue::Array(v) => inputs.insert_owned(name.clone(), v.clone()),
+            }
+        }
+
+        let mut evaluator = match Evaluator::new(&self.expression, &inputs.names()) {
+            Ok(evaluator) => evaluator,
+            Err(e) => {
+                let code = error_code(&e);
+                return match &self.expected {
+                    Expected::Error(expected) if *expected == code => Ok(()),
+                    expected => Err(failure(format!("expected {}, got error {}", expected, code)))
+                }
+            }
+        };
+        let result = match evaluator.evaluate(&inputs) {
+            Ok(result) => result,
+            Err(e) => {
+                let code = evaluation_error_code(&e);
+                return match &self.expected {
+                    Expected::Error(expected) if *expected == code => Ok(()),
+                    expected => Err(failure(format!("expected {}, got error {}", expected, code)))
+                }
+            }
+        };
+
+        let actual = match result.category() {
+            Category::Number => GoldenValue::Number(result.as_number()),
+            Category::Array => GoldenValue::Array(result.iterate().copied().collect()),
+            Category::Unqiue => return Err(failure(format!("unsupported result type {}", result.type_name())))
+        };
+        match &self.expected {
+            Expected::Value(expected) if same_values(expected, &actual) => Ok(()),
+            expected => Err(failure(format!("expected {}, got {}", expected, actual)))
+        }
+    }
+}
+
+fn same_number(x: Float, y: Float) -> bool {
+    (x.is_nan() && y.is_nan()) || x == y || approx_eq(x, y)
+}
+
+fn same_values(a: &GoldenValue, b: &GoldenValue) -> bool {
+    match (a, b) {
+        (GoldenValue::Number(x), GoldenValue::Number(y)) => same_number(*x, *y),
+        (GoldenValue::Array(v), GoldenValue::Array(w)) => {
+            v.len() == w.len() && v.iter().zip(w).all(|(x, y)| same_number(*x, *y))
+        }
+        _ => false
+    }
+}
+
+impl fmt::Display for GoldenValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GoldenValue::Number(x) => write!(f, "{}", x),
+            GoldenValue::Array(v) => {
+                let items: Vec<String> = v.iter().map(|x| x.to_string()).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expected::Value(v) => write!(f, "{}", v),
+            Expected::Error(code) => write!(f, "error {}", code),
+        }
+    }
+}
+
+/// Split a CSV line into fields, handling double quoted fields
+fn split_csv_line(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            ',' if !quoted => fields.push(std::mem::take(&mut field).trim().to_owned()),
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("unclosed quote".into());
+    }
+    fields.push(field.trim().to_owned());
+    Ok(fields)
+}
+
+fn parse_number(s: &str) -> Result<Float, String> {
+    match s.trim() {
+        "nan" | "NaN" => Ok(float::NAN),
+        "inf" => Ok(float::INFINITY),
+        "-inf" => Ok(float::NEG_INFINITY),
+        s => s.parse::<Float>().map_err(|_| format!("invalid number `{}`", s))
+    }
+}
+
+fn parse_value(s: &str) -> Result<GoldenValue, String> {
+    let s = s.trim();
+    match s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+        Some(items) if items.trim().is_empty() => Ok(GoldenValue::Array(Vec::new())),
+        Some(items) => {
+            let v = items.split(',').map(parse_number).collect::<Result<_, _>>()?;
+            Ok(GoldenValue::Array(v))
+        }
+        None => Ok(GoldenValue::Number(parse_number(s)?))
+    }
+}
+
+/// Parse `a = 1; v = [1, 2]`
+fn parse_variables(s: &str) -> Result<Vec<(String, GoldenValue)>, String> {
+    let mut variables = Vec::new();
+    for assignment in s.split(';').filter(|a| !a.trim().is_empty()) {
+        let Some((name, value)) = assignment.split_once('=') else {
+            return Err(format!("expected `name = value`, found `{}`", assignment.trim()));
+        };
+        variables.push((name.trim().to_owned(), parse_value(value)?));
+    }
+    Ok(variables)
+}
+
+fn parse_expected(s: &str) -> Result<Expected, String> {
+    match s.strip_prefix('!') {
+        Some(code) if !code.trim().is_empty() => Ok(Expected::Error(code.trim().to_owned())),
+        Some(_) => Err("missing error code after `!`".into()),
+        None => Ok(Expected::Value(parse_value(s)?))
+    }
+}
+
+
+/// Outcome of the evaluation of an expression with boundary values, see `robustness_report()`
+#[derive(Debug)]
+pub struct RobustnessCase {
+    /// Description of the inputs: `zeros`, `x = NaN`, ...
+    pub name: String,
+    pub inputs: Vec<(String, GoldenValue)>,
+    /// The result (see `DynMath::fmt_value()`) or the error
+    pub result: Result<String, EvaluationError>,
+}
+
+/// Outcomes of the boundary value cases of an expression, to check a formula
+/// before it is deployed. `Display` lists the failing cases.
+#[derive(Debug)]
+pub struct RobustnessReport {
+    pub cases: Vec<RobustnessCase>,
+}
+
+impl RobustnessReport {
+    /// The cases that failed with an error
+    pub fn failures(&self) -> impl Iterator<Item = &RobustnessCase> {
+        self.cases.iter().filter(|case| case.result.is_err())
+    }
+}
+
+impl fmt::Display for RobustnessReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let failures: Vec<&RobustnessCase> = self.failures().collect();
+        writeln!(f, "{} of {} cases failed", failures.len(), self.cases.len())?;
+        for case in failures {
+            if let Err(err) = &case.result {
+                writeln!(f, "{}: {} ({})", case.name, err, err.code())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Evaluate the expression of `evaluator` with boundary values of its inputs:
+/// zeros, negative numbers, NaN, infinity, arrays, empty arrays and arrays of
+/// different lengths for all inputs at once. Then zero, negative and NaN values
+/// for each input, with the others set to 1 (or to arrays, if the expression
+/// fails with numbers). The inputs are numbers or arrays, the fields of inputs
+/// (`s.x`) cannot be given.
+pub fn robustness_report(evaluator: &mut Evaluator) -> RobustnessReport {
+    let names: Vec<String> = evaluator.inputs().into_iter().map(str::to_string).collect();
+    let all = |value: GoldenValue| names.iter().map(|name| (name.clone(), value.clone())).collect::<Vec<_>>();
+    let mut cases = vec![
+        ("ones".to_string(), all(GoldenValue::Number(1.0))),
+        ("zeros".to_string(), all(GoldenValue::Number(0.0))),
+        ("negative".to_string(), all(GoldenValue::Number(-1.0))),
+        ("NaN".to_string(), all(GoldenValue::Number(float::NAN))),
+        ("infinity".to_string(), all(GoldenValue::Number(float::INFINITY))),
+        ("arrays".to_string(), all(array(1.0))),
+        ("empty arrays".to_string(), all(GoldenValue::Array(vec![]))),
+    ];
+    if names.len() > 1 {
+        let lengths = names.iter().enumerate()
+            .map(|(i, name)| (name.clone(), GoldenValue::Array(vec![1.0; i + 1])))
+            .collect();
+        cases.push(("different lengths".to_string(), lengths));
+    }
+    let mut cases: Vec<RobustnessCase> = cases.into_iter().map(|(name, inputs)| evaluate_case(evaluator, name, inputs)).collect();
+
+    // the values of a single input, the others are valid
+    let value: Option<fn(Float) -> GoldenValue> = match (&cases[0].result, &cases[5].result) {
+        (Ok(_), _) => Some(GoldenValue::Number),
+        (_, Ok(_)) => Some(array),
+        _ => None
+    };
+    if let Some(value) = value && names.len() > 1 {
+        for (i, name) in names.iter().enumerate() {
+            for (label, x) in [("0", 0.0), ("-1", -1.0), ("NaN", float::NAN)] {
+                let mut inputs = all(value(1.0));
+                inputs[i].1 = value(x);
+                cases.push(evaluate_case(evaluator, format!("{} = {}", name, label), inputs));
+            }
+        }
+    }
+    RobustnessReport { cases }
+}
+
+/// `[x, 2x, 3x]`
+fn array(x: Float) -> GoldenValue {
+    GoldenValue::Array(vec![x, 2.0 * x, 3.0 * x])
+}
+
+fn evaluate_case(evaluator: &mut Evaluator, name: String, inputs: Vec<(String, GoldenValue)>) -> RobustnessCase {
+    let mut vars = InputVars::new();
+    for (name, value) in &inputs {
+        match value {
+            GoldenValue::Number(x) => vars.insert_owned(name.clone(), *x),
+            GoldenValue::Array(v) => vars.insert_owned(name.clone(), v.clone()),
+        }
+    }
+    let result = evaluator.evaluate(&vars).map(|value| value.to_string());
+    RobustnessCase { name, inputs, result }
+}
+
+
+/// Generator of random, syntactically valid expressions for property based
+/// tests. The generator is deterministic: the same seed gives the same
+/// sequence of expressions, so a failing case can be reproduced from its seed.
+///
+/// The expressions use the arithmetic operators, the built in constants and
+/// the functions of numbers, so every expression can be evaluated with number
+/// or array inputs.
+#[derive(Debug, Clone)]
+pub struct ExprGenerator {
+    state: u64,
+    max_depth: usize,
+    variables: Vec<String>,
+}
+
+impl ExprGenerator {
+    /// Functions that are applicable to numbers and arrays
+    const FUNCTIONS: &'static [Function] = &[Function::Min, Function::Max, Function::Avg,
+        Function::Std, Function::Sum, Function::Range, Function::Abs, Function::Sin,
+        Function::Cos, Function::Tan, Function::Cot, Function::Exp, Function::Log,
+        Function::Log2, Function::Log10, Function::Sqrt];
+    /// Constants that have a name in expressions
+    const CONSTANTS: &'static [Constant] = &[Constant::Pi, Constant::Euler, Constant::Sqrt2, Constant::Sqrt3];
+    const NUMBERS: &'static [Float] = &[0.0, 0.5, 1.0, 2.0, 3.0, 10.0, 0.25, 1.5, 1e-3, 1e6];
+
+    pub fn new(seed: u64) -> Self {
+        Self {
+            // xorshift needs a non-zero state
+            state: seed.wrapping_mul(0x9e3779b97f4a7c15) | 1,
+            max_depth: 5,
+            variables: vec!["x".into(), "y".into()],
+        }
+    }
+
+    /// Names of the input variables that may appear in the expressions
+    pub fn with_variables(mut self, variables: &[&str]) -> Self {
+        self.variables = variables.iter().map(|v| v.to_string()).collect();
+        self
+    }
+
+    /// Maximal depth of the expression trees
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn variables(&self) -> Vec<&str> {
+        self.variables.iter().map(|v| v.as_str()).collect()
+    }
+
+    /// Next random expression
+    pub fn expression(&mut self) -> ExprBuilder {
+        self.subexpression(self.max_depth)
+    }
+
+    /// Next random expression, as an AST
+    pub fn ast(&mut self) -> AST {
+        self.expression().build().expect("generated expressions are valid")
+    }
+
+    /// xorshift64*
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    /// Random index below `n`
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    fn atom(&mut self) -> ExprBuilder {
+        match self.below(4) {
+            0 => ExprBuilder::constant(Self::CONSTANTS[self.below(Self::CONSTANTS.len())].clone()),
+            1 if !self.variables.is_empty() => {
+                let i = self.below(self.variables.len());
+                ExprBuilder::var(&self.variables[i])
+            }
+            _ => ExprBuilder::num(Self::NUMBERS[self.below(Self::NUMBERS.len())]),
+        }
+    }
+
+    fn subexpression(&mut self, depth: usize) -> ExprBuilder {
+        if depth == 0 || self.below(4) == 0 {
+            return self.atom();
+        }
+        match self.below(8) {
+            0 => self.subexpression(depth - 1).add(self.subexpression(depth - 1)),
+            1 => self.subexpression(depth - 1).sub(self.subexpression(depth - 1)),
+            2 => self.subexpression(depth - 1).mul(self.subexpression(depth - 1)),
+            3 => self.subexpression(depth - 1).div(self.subexpression(depth - 1)),
+            4 => self.subexpression(depth - 1).pow(self.subexpression(depth - 1)),
+            5 => self.subexpression(depth - 1).neg(),
+            _ => {
+                let function = Self::FUNCTIONS[self.below(Self::FUNCTIONS.len())].clone();
+                let n_args = 1 + self.below(function.max_args().min(4));
+                let args: Vec<ExprBuilder> = (0..n_args).map(|_| self.subexpression(depth - 1)).collect();
+                ExprBuilder::func(function, args)
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_csv_line() {
+        assert_eq!(split_csv_line("a,b , c").unwrap(), vec!["a", "b", "c"]);
+        assert_eq!(split_csv_line(r#""max(a, b)","a = 1; b = 2",2"#).unwrap(), vec!["max(a, b)", "a = 1; b = 2", "2"]);
+        assert_eq!(split_csv_line(r#""say ""hi""",,"#).unwrap(), vec![r#"say "hi""#, "", ""]);
+        assert!(split_csv_line(r#""a,b"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_corpus() {
+        let corpus = "# comment\nexpression,variables,expected\n\n\"x * 2\",\"x = [1, 2]\",\"[2, 4]\"\nx +,,!MissingArgument\n";
+        let cases = parse_corpus(corpus).unwrap();
+        assert_eq!(cases, vec![
+            GoldenCase {
+                line: 4,
+                expression: "x * 2".into(),
+                variables: vec![("x".into(), GoldenValue::Array(vec![1.0, 2.0]))],
+                expected: Expected::Value(GoldenValue::Array(vec![2.0, 4.0])),
+            },
+            GoldenCase {
+                line: 5,
+                expression: "x +".into(),
+                variables: vec![],
+                expected: Expected::Error("MissingArgument".into()),
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_corpus_errors() {
+        assert_eq!(parse_corpus("x,y,z").unwrap_err().line, 1);
+        assert_eq!(parse_corpus("expression,variables,expected\nx,x=1").unwrap_err().line, 2);
+        assert_eq!(parse_corpus("expression,variables,expected\nx,x,1").unwrap_err().line, 2);
+        assert_eq!(parse_corpus("expression,variables,expected\nx,,!").unwrap_err().line, 2);
+    }
+
+    #[test]
+    fn test_run_corpus() {
+        let corpus = "expression,variables,expected\n1 + 1,,2\n1 + 1,,3\nx,,!UndefinedVariable\n";
+        let failures = run_corpus(corpus).unwrap();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].to_string(), "line 3: `1 + 1`: expected 3, got 2");
+    }
+
+    #[test]
+    fn test_robustness_report() {
+        let mut evaluator = Evaluator::new("sum(v) / n", &["v", "n"]).unwrap();
+        let report = robustness_report(&mut evaluator);
+        // the reductions of empty arrays are errors by default
+        let failures: Vec<&str> = report.failures().map(|case| case.name.as_str()).collect();
+        assert_eq!(failures, ["empty arrays"]);
+        assert_eq!(report.cases.len(), 8 + 2 * 3);
+        assert_eq!(report.cases[0].inputs, [("n".into(), GoldenValue::Number(1.0)), ("v".into(), GoldenValue::Number(1.0))]);
+        assert_eq!(report.cases[0].result.as_ref().unwrap(), "1");
+        assert!(report.to_string().starts_with("1 of 14 cases failed\nempty arrays: "));
+        evaluator.set_empty_reduction(crate::EmptyReduction::Identity);
+        assert_eq!(robustness_report(&mut evaluator).to_string(), "0 of 14 cases failed\n");
+
+        // the inputs are arrays in the cases of single inputs
+        let mut evaluator = Evaluator::new("v[2] / n", &["v", "n"]).unwrap();
+        let report = robustness_report(&mut evaluator);
+        let failures: Vec<&str> = report.failures().map(|case| case.name.as_str()).collect();
+        assert_eq!(failures, ["ones", "zeros", "negative", "NaN", "infinity", "empty arrays", "different lengths"]);
+        assert_eq!(report.cases[8].name, "n = 0");
+        assert_eq!(report.cases[8].inputs[0].1, GoldenValue::Array(vec![0.0, 0.0, 0.0]));
+        assert!(report.to_string().starts_with("7 of 14 cases failed\nones: "));
+        assert!(report.to_string().contains("empty arrays: ") && report.to_string().contains("(E206)"));
+    }
+
+    #[test]
+    fn test_generator_is_deterministic() {
+        let rpn = |seed| ExprGenerator::new(seed).ast().rpn_repr();
+        assert_eq!(rpn(42), rpn(42));
+        let distinct: std::collections::HashSet<String> = (0..20).map(rpn).collect();
+        assert!(distinct.len() > 10);
+
+        let mut generator = ExprGenerator::new(1).with_max_depth(0).with_variables(&[]);
+        for _ in 0..20 {
+            assert!(matches!(generator.ast().tree, crate::Branch::Atom(_)));
+        }
+    }
+}
@@ -0,0 +1,316 @@
+//! Tokenization of `Syntax::Compat`: the syntax of other expression languages is
+//! translated into the tokens of the equivalent dymex expression, so the parser
+//! builds the canonical AST:
+//!
+//! | compat         | dymex              |
+//! |----------------|--------------------|
+//! | `a && b`       | `a and b`          |
+//! | `a \|\| b`     | `a or b`           |
+//! | `c ? a : b`    | `where(c, a, b)`   |
+//! | `pow(a, b)`    | `(a) ** (b)`       |
+//!
+//! The ternary has the lowest precedence (above assignments) and groups to the
+//! right. `!=` is accepted by both syntaxes. Other constructs of these languages
+//! (`!x`, bitwise `&` and `|`) are reported with their position.
+
+use crate::{ArithmeticOperator, Function, LogicalOperator, Token, TokenContext, TokenizerConfig, TokenizerError};
+use super::{blank_comments, line_column, tokenize_line};
+
+
+/// A token of the compat syntax
+enum Lexeme {
+    Token(TokenContext),
+    /// `?` of a ternary, as the `where` token it becomes
+    Question(TokenContext),
+}
+
+/// Operand of a ternary: the tokens of a bracketed group are kept together
+enum Piece {
+    Plain(Vec<TokenContext>),
+    Question(TokenContext),
+    Colon(TokenContext),
+}
+
+pub(super) fn tokenize_compat(input: &str, config: &TokenizerConfig) -> Result<Vec<TokenContext>, TokenizerError> {
+    // the operators unknown to the tokenizer are replaced by spaces, so the
+    // positions of the other tokens do not change
+    let mut chars: Vec<char> = blank_comments(input)?.chars().collect();
+    let mut operators: Vec<TokenContext> = Vec::new();
+    let mut cursor = 0;
+    while cursor < chars.len() {
+        let c = chars[cursor];
+        let next = chars.get(cursor + 1).copied();
+        let (token, len) = match (c, next) {
+            ('"' | '`', _) => {
+                cursor = chars[cursor+1..].iter().position(|q| *q == c).map_or(chars.len(), |i| cursor + i + 2);
+                continue;
+            }
+            ('&', Some('&')) => (Token::LogicOp(LogicalOperator::And), 2),
+            ('|', Some('|')) => (Token::LogicOp(LogicalOperator::Or), 2),
+            ('?', _) => (Token::Func(Function::Where, Function::Where.max_args()), 1),
+            ('&', _) => return Err(unsupported("bitwise `&`, use `band(a, b)`", cursor)),
+            ('|', _) => return Err(unsupported("bitwise `|`, use `bor(a, b)`", cursor)),
+            ('!', next) if next != Some('=') => return Err(unsupported("negation `!`, use `x == 0`", cursor)),
+            _ => {
+                cursor += 1;
+                continue;
+            }
+        };
+        let (line, column) = line_column(input, cursor);
+        operators.push(TokenContext { token, at: cursor, len, line, column, literal: None });
+        chars[cursor..cursor + len].fill(' ');
+        cursor += len;
+    }
+    let masked: String = chars.into_iter().collect();
+
+    let mut lexemes: Vec<Lexeme> = Vec::new();
+    let mut operators = operators.into_iter().peekable();
+    for tc in tokenize_line(&masked, config)? {
+        while let Some(op) = operators.next_if(|op| op.at < tc.at) {
+            lexemes.push(lexeme(op));
+        }
+        lexemes.push(Lexeme::Token(tc));
+    }
+    lexemes.extend(operators.map(lexeme));
+
+    let mut rewriter = Rewriter { lexemes, pos: 0 };
+    let mut tokens = Vec::new();
+    loop {
+        tokens.extend(rewriter.group()?);
+        // unbalanced closing brackets are left for the parser
+        match rewriter.next() {
+            Some(closing) => tokens.push(closing),
+            None => break
+        }
+    }
+    Ok(tokens)
+}
+
+fn lexeme(op: TokenContext) -> Lexeme {
+    match op.token {
+        Token::Func(Function::Where, _) => Lexeme::Question(op),
+        _ => Lexeme::Token(op)
+    }
+}
+
+pub(super) fn unsupported(construct: &str, at: usize) -> TokenizerError {
+    TokenizerError::UnsupportedSyntax(construct.into(), at)
+}
+
+/// Token without source text, positioned at `tc`
+pub(super) fn synthetic(token: Token, tc: &TokenContext) -> TokenContext {
+    TokenContext { token, at: tc.at, len: 0, line: tc.line, column: tc.column, literal: None }
+}
+
+struct Rewriter {
+    lexemes: Vec<Lexeme>,
+    pos: usize,
+}
+
+impl Rewriter {
+    /// The next token, `?` is only valid inside a group
+    fn next(&mut self) -> Option<TokenContext> {
+        let lexeme = self.lexemes.get(self.pos)?;
+        self.pos += 1;
+        match lexeme {
+            Lexeme::Token(tc) | Lexeme::Question(tc) => Some(tc.clone())
+        }
+    }
+
+    /// Rewrite the tokens up to the closing bracket of the current group (or the end)
+    fn group(&mut self) -> Result<Vec<TokenContext>, TokenizerError> {
+        let mut tokens = Vec::new();
+        let mut segment: Vec<Piece> = Vec::new();
+        while let Some(lexeme) = self.lexemes.get(self.pos) {
+            let tc = match lexeme {
+                Lexeme::Question(tc) => {
+                    segment.push(Piece::Question(tc.clone()));
+                    self.pos += 1;
+                    continue;
+                }
+                Lexeme::Token(tc) => tc.clone()
+            };
+            match tc.token {
+                Token::RP | Token::RB => break,
+                // the ternary is within the arguments, statements and lambda bodies
+                Token::Comma | Token::Semicolon | Token::Newline | Token::AssignOp(_) | Token::Arrow => {
+                    self.pos += 1;
+                    tokens.extend(ternary(std::mem::take(&mut segment))?);
+                    tokens.push(tc);
+                }
+                Token::Colon => {
+                    self.pos += 1;
+                    segment.push(Piece::Colon(tc));
+                }
+                Token::LP | Token::LB => {
+                    self.pos += 1;
+                    let inner = self.group()?;
+                    let closing = self.next();
+                    let pow = match segment.last() {
+                        Some(Piece::Plain(f)) if tc.token == Token::LP
+                            && matches!(f.as_slice(), [TokenContext { token: Token::Var(name), .. }] if name == "pow") => {
+                            f[0].clone()
+                        }
+                        _ => {
+                            segment.push(Piece::Plain([vec![tc], inner, closing.into_iter().collect()].concat()));
+                            continue;
+                        }
+                    };
+                    segment.pop();
+                    segment.push(Piece::Plain(power(&pow, inner)?));
+                }
+                _ => {
+                    self.pos += 1;
+                    segment.push(Piece::Plain(vec![tc]));
+                }
+            }
+        }
+        tokens.extend(ternary(segment)?);
+        Ok(tokens)
+    }
+}
+
+/// `pow(a, b)` as `((a) ** (b))`, `args` are the tokens between the parentheses
+fn power(pow: &TokenContext, args: Vec<TokenContext>) -> Result<Vec<TokenContext>, TokenizerError> {
+    let args = split_arguments(&args);
+    if args.len() != 2 {
+        return Err(unsupported("`pow()` with other than two arguments", pow.at));
+    }
+    Ok(join_arguments(pow, &args, Token::ArOp(ArithmeticOperator::Pow)))
+}
+
+/// The arguments of a call (the tokens between the parentheses), with the
+/// separating comma before each but the first
+pub(super) fn split_arguments(tokens: &[TokenContext]) -> Vec<(Option<&TokenContext>, &[TokenContext])> {
+    let mut args = Vec::new();
+    let (mut start, mut depth, mut comma) = (0, 0, None);
+    for (i, tc) in tokens.iter().enumerate() {
+        match tc.token {
+            Token::LP | Token::LB => depth += 1,
+            Token::RP | Token::RB => depth -= 1,
+            Token::Comma if depth == 0 => {
+                args.push((comma, &tokens[start..i]));
+                (start, comma) = (i + 1, Some(tc));
+            }
+            _ => {}
+        }
+    }
+    if !tokens.is_empty() {
+        args.push((comma, &tokens[start..]));
+    }
+    args
+}
+
+/// The arguments of the call `f` joined by the infix operator `op`: `((a) op (b) op (c))`.
+/// The operators take the positions of the commas.
+pub(super) fn join_arguments(f: &TokenContext, args: &[(Option<&TokenContext>, &[TokenContext])], op: Token)
+-> Vec<TokenContext> {
+    let (lp, rp) = (synthetic(Token::LP, f), synthetic(Token::RP, f));
+    let mut tokens = vec![lp.clone()];
+    for (comma, arg) in args {
+        if let Some(comma) = comma {
+            tokens.push(TokenContext { token: op.clone(), ..(*comma).clone() });
+        }
+        tokens.push(lp.clone());
+        tokens.extend_from_slice(arg);
+        tokens.push(rp.clone());
+    }
+    tokens.push(rp);
+    tokens
+}
+
+/// `c ? a : b` as `where(c, a, b)`, nested ternaries group to the right:
+/// `c ? a : d ? b : e` is `c ? a : (d ? b : e)`
+fn ternary(mut pieces: Vec<Piece>) -> Result<Vec<TokenContext>, TokenizerError> {
+    let Some(question) = pieces.iter().position(|p| matches!(p, Piece::Question(_))) else {
+        return Ok(pieces.into_iter().flat_map(|p| match p {
+            Piece::Plain(tokens) => tokens,
+            Piece::Question(tc) | Piece::Colon(tc) => vec![tc]
+        }).collect());
+    };
+    // the `:` of this `?`, skipping those of the ternaries in the `a` branch
+    let mut nested = 0;
+    let mut colon = None;
+    for (i, piece) in pieces.iter().enumerate().skip(question + 1) {
+        match piece {
+            Piece::Question(_) => nested += 1,
+            Piece::Colon(_) if nested == 0 => {
+                colon = Some(i);
+                break;
+            }
+            Piece::Colon(_) => nested -= 1,
+            Piece::Plain(_) => {}
+        }
+    }
+    let where_tc = match &pieces[question] {
+        Piece::Question(tc) => tc.clone(),
+        _ => unreachable!()
+    };
+    let Some(colon) = colon else {
+        return Err(unsupported("`?` without `:`", where_tc.at));
+    };
+    let otherwise = pieces.split_off(colon + 1);
+    pieces.pop();
+    let then = pieces.split_off(question + 1);
+    pieces.pop();
+
+    let (lp, comma, rp) = (synthetic(Token::LP, &where_tc), synthetic(Token::Comma, &where_tc), synthetic(Token::RP, &where_tc));
+    let mut tokens = vec![where_tc, lp];
+    tokens.extend(ternary(pieces)?);
+    tokens.push(comma.clone());
+    tokens.extend(ternary(then)?);
+    tokens.push(comma);
+    tokens.extend(ternary(otherwise)?);
+    tokens.push(rp);
+    Ok(tokens)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn compat() -> TokenizerConfig {
+        TokenizerConfig { syntax: Syntax::Compat, ..Default::default() }
+    }
+
+    fn rpn(expression: &str, config: TokenizerConfig) -> String {
+        AST::new(TokenStream::with_config(expression, config).unwrap()).unwrap().rpn_repr()
+    }
+
+    #[test]
+    fn test_compat_translation() {
+        for (compat_expr, expected) in [
+            ("a > 0 && b != 1 || c", "a > 0 and b != 1 or c"),
+            ("a > b ? a : b", "where(a > b, a, b)"),
+            ("x = a ? 1 : b ? 2 : 3", "x = where(a, 1, where(b, 2, 3))"),
+            ("a ? b ? 1 : 2 : 3", "where(a, where(b, 1, 2), 3)"),
+            ("max(a ? v[0:2] : v, 1) * 2", "max(where(a, v[0:2], v), 1) * 2"),
+            ("pow(a + 1, pow(2, b)) / 2", "((a + 1) ** (2 ** b)) / 2"),
+            ("map(v, x -> x > 0 ? x : -x)", "map(v, x -> where(x > 0, x, -x))"),
+            ("s == \"a?b\" /* c ? */", "s == \"a?b\""),
+        ] {
+            assert_eq!(rpn(compat_expr, compat()), rpn(expected, TokenizerConfig::default()), "{}", compat_expr);
+        }
+
+        let mut variables = InputVars::new();
+        variables.insert_owned("a".to_owned(), 3.0);
+        variables.insert_owned("b".to_owned(), 4.0);
+        let mut evaluator = Evaluator::with_config("a >= b || a < 0 ? pow(a, 2) : b", &variables.names(),
+            VariablePolicy::Strict, compat()).unwrap();
+        assert_eq!(evaluator.evaluate(&variables).unwrap().as_number(), 4.0);
+    }
+
+    #[test]
+    fn test_compat_errors() {
+        for (expression, at) in [("a & b", 2), ("!a", 0), ("a ? 1", 2), ("pow(a)", 0), ("b | a", 2), ("a || (b ? 1)", 8)] {
+            match TokenStream::with_config(expression, compat()) {
+                Err(TokenizerError::UnsupportedSyntax(_, position)) => assert_eq!(position, at, "{}", expression),
+                other => panic!("{}: {:?}", expression, other)
+            }
+        }
+        // the default syntax is unchanged
+        assert!(TokenStream::new("a && b").is_err());
+        assert!(TokenStream::new("a ? b : c").is_err());
+    }
+}
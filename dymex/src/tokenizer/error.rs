@@ -1,10 +1,10 @@
-use crate::UserMessage;
+use crate::{MessageId, UserMessage};
 
 
 pub(super) const VARNAME_ERR1: &str = "This is a reserved keyword, please choose a different name!";
 pub(super) const VARNAME_ERR2: &str = "Variable names may contain `_`, numbers or alphabetic characters.";
 pub(super) const VARNAME_ERR3: &str = "Variable names cannot start with a number.";
-const VARNAME_EXAMPLES: &str = "Valid: a, ϕ0, _mass, phase_x_2_, e0_π2, ...\n Invalid: π, e, 1a, 1_b, ... ";
+const VARNAME_EXAMPLES: &str = "Valid: a, ϕ0, _mass, phase_x_2_, e0_π2, `e`, `1a`, ...\n Invalid: π, e, 1a, 1_b, ... ";
 
 
 /// An error reported by the parser.
@@ -14,39 +14,97 @@ pub enum TokenizerError {
     SyntaxError(usize),
     InvalidCharacter(char, usize),
     InvalidNumberFormat(usize),
+    /// Backtick quoted identifier without closing backtick
+    UnclosedEscape(usize),
+    /// String without closing double quote
+    UnclosedString(usize),
+    /// Block comment without closing `*/`
+    UnclosedComment(usize),
     // UndefinedVariable(usize, String),
     InvalidVariableName(String, &'static str),
+    /// Construct of another language without a translation in `Syntax::Compat` or `Syntax::Excel`
+    UnsupportedSyntax(String, usize),
 }
 impl TokenizerError {
-    pub fn user_message(&self) -> UserMessage {
+    /// Stable code of the error, `E001` to `E099` for the tokenizer errors
+    pub fn code(&self) -> &'static str {
         match self {
+            Self::SyntaxError(_) => "E001",
+            Self::InvalidCharacter(..) => "E002",
+            Self::InvalidNumberFormat(_) => "E003",
+            Self::UnclosedEscape(_) => "E004",
+            Self::UnclosedString(_) => "E005",
+            Self::UnclosedComment(_) => "E006",
+            Self::InvalidVariableName(..) => "E007",
+            Self::UnsupportedSyntax(..) => "E008",
+        }
+    }
+
+    pub fn user_message(&self) -> UserMessage {
+        let message = match self {
             Self::SyntaxError(i) => {
                 UserMessage::new(
                     format!("Syntax error:"),
                     Some(*i),
                     None,
                     None)
+                .with_id(MessageId::SyntaxError)
             }
             Self::InvalidCharacter(c, i) => {
                 UserMessage::new(format!("Invalid character: {}", c),
                 Some(*i),
-                Some("The following charaters are invalid: #?˝`\'&|$@%{}"),
+                Some("The following charaters are invalid: ?˝\'&|$@%{}"),
                 None
             )
+                .with_id(MessageId::InvalidCharacter)
+                .with_arg("char", c)
             }
             Self::InvalidNumberFormat(i) => {
                 UserMessage::new(format!("Invalid number formatting:"),
                 Some(*i),
                 Some("Valid formats are: 1, 3.14, 1e-10, 1.23E10, 1E+9, 1_000_000"),
                 None)
+                .with_id(MessageId::InvalidNumberFormat)
+            }
+            Self::UnclosedEscape(i) => {
+                UserMessage::new(format!("Missing closing backtick:"),
+                Some(*i),
+                Some("Names colliding with functions or constants can be written as `e`, `min`, ..."),
+                None)
+                .with_id(MessageId::UnclosedEscape)
+            }
+            Self::UnclosedString(i) => {
+                UserMessage::new(format!("Missing closing double quote:"),
+                Some(*i),
+                Some("Strings are written in double quotes: \"eV\""),
+                None)
+                .with_id(MessageId::UnclosedString)
+            }
+            Self::UnclosedComment(i) => {
+                UserMessage::new(format!("Missing end of comment:"),
+                Some(*i),
+                Some("Block comments are closed by */, line comments start with #"),
+                None)
+                .with_id(MessageId::UnclosedComment)
             }
             Self::InvalidVariableName(varname, hint) => {
                 UserMessage::new(format!("Invalid variable name: {}", varname),
                 None,
                 Some(*hint),
                 Some(VARNAME_EXAMPLES))
+                .with_id(MessageId::InvalidVariableName)
+                .with_arg("name", varname)
             }
-        }
+            Self::UnsupportedSyntax(construct, i) => {
+                UserMessage::new(format!("Unsupported syntax: {}", construct),
+                Some(*i),
+                Some("The supported constructs of the other syntaxes are listed in the documentation of `Syntax`"),
+                None)
+                .with_id(MessageId::UnsupportedSyntax)
+                .with_arg("construct", construct)
+            }
+        };
+        message.with_code(self.code())
     }
 }
 
@@ -58,7 +116,7 @@ mod test_prevalidation {
 
 #[cfg(test)]
 mod test_numbers {
-    use crate::same_num_tokens;
+    use crate::testing::{charslice, same_num_tokens};
     use crate::tokenizer::*;
 
 
@@ -85,7 +143,7 @@ mod test_numbers {
     #[test]
     fn test_number_errors1() {
         let expr = "y*(x + 12.34.5)";
-        let res = tokenize_lines(expr);
+        let res = tokenize_lines(expr, &crate::TokenizerConfig::default());
         if let Err(e) = res {
             assert!(matches!(e, crate::TokenizerError::InvalidNumberFormat(7)));
             print!("{}", e.user_message());
@@ -95,7 +153,7 @@ mod test_numbers {
     #[test]
     fn test_number_errors2() {
         let expr = "   12.34e-x";
-        let res = tokenize_lines(expr);
+        let res = tokenize_lines(expr, &crate::TokenizerConfig::default());
         if let Err(e) = res {
             assert!(matches!(e, crate::TokenizerError::InvalidNumberFormat(3)));
             print!("{}", e.user_message());
@@ -0,0 +1,257 @@
+//! Tokenization of `Syntax::Excel`: a subset of the spreadsheet formula syntax
+//! is translated into the tokens of the equivalent dymex expression, so formulas
+//! copied from a workbook can be evaluated on variables:
+//!
+//! | Excel                    | dymex                          |
+//! |--------------------------|--------------------------------|
+//! | `=A1 + 1` (leading `=`)  | `A1 + 1`                       |
+//! | `$A$1`                   | `A1`                           |
+//! | `A1:A10` (range)         | `A1_A10`, an array variable    |
+//! | `a = b`, `a <> b`        | `a == b`, `a != b`             |
+//! | `50%`                    | `0.5`                          |
+//! | `IF(c, a, b)`            | `where(c, a, b)`               |
+//! | `AND(a, b, ...)`         | `a and b and ...`              |
+//! | `OR(a, b, ...)`          | `a or b or ...`                |
+//! | `POWER(a, b)`, `a ^ b`   | `a ** b`                       |
+//! | `SUM`, `AVERAGE`, `MIN`, `MAX`, `PRODUCT`, `STDEV.P` | `sum`, `avg`, `min`, `max`, `prod`, `std` |
+//! | `ABS`, `SQRT`, `EXP`, `LN`, `LOG10`, `SIN`, `COS`, `TAN` | `abs`, `sqrt`, `exp`, `log`, ... |
+//!
+//! Function names are case insensitive, `;` separates arguments as `,`. Other
+//! functions, `TRUE`/`FALSE`, text concatenation (`&`), sheet references (`!`)
+//! and array constants (`{1, 2}`) are reported with their position.
+
+use crate::{ArithmeticOperator, Function, LogicalOperator, RelationalOperator, Token, TokenContext, TokenizerError};
+use super::{line_column, parse_number};
+use super::compat::{join_arguments, split_arguments, unsupported};
+
+
+/// A token of the formula, or a function translated to an operator
+enum Lexeme {
+    Token(TokenContext),
+    /// `AND(`, `OR(` or `POWER(`, with the operator joining the arguments
+    Call(TokenContext, Token),
+}
+
+pub(super) fn tokenize_excel(input: &str) -> Result<Vec<TokenContext>, TokenizerError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut lexemes: Vec<Lexeme> = Vec::new();
+    let mut cursor = match chars.iter().position(|c| !c.is_whitespace()) {
+        Some(i) if chars[i] == '=' => i + 1,
+        _ => 0
+    };
+    while cursor < chars.len() {
+        let c = chars[cursor];
+        let next = chars.get(cursor + 1).copied();
+        let (line, column) = line_column(input, cursor);
+        let mut push = |token: Token, at: usize, len: usize, literal: Option<String>| {
+            lexemes.push(Lexeme::Token(TokenContext { token, at, len, line, column, literal }));
+        };
+        if c.is_whitespace() {
+            cursor += 1;
+        } else if c.is_ascii_digit() || (c == '.' && next.is_some_and(|n| n.is_ascii_digit())) {
+            let Some((Token::Number(x), len)) = parse_number(&chars[cursor..]) else {
+                return Err(TokenizerError::InvalidNumberFormat(cursor));
+            };
+            let text: String = chars[cursor..cursor + len].iter().collect();
+            if chars.get(cursor + len) == Some(&'%') {
+                push(Token::Number(x / 100.0), cursor, len + 1, None);
+                cursor += len + 1;
+            } else {
+                let literal = (text != Token::Number(x).to_string()).then_some(text);
+                push(Token::Number(x), cursor, len, literal);
+                cursor += len;
+            }
+        } else if c.is_alphabetic() || c == '_' || c == '$' {
+            let len = word_len(&chars[cursor..]);
+            let word: String = chars[cursor..cursor + len].iter().collect();
+            let end = cursor + len;
+            match chars.get(end) {
+                Some('(') => {
+                    let name = word.to_uppercase();
+                    let op = match name.as_str() {
+                        "AND" => Token::LogicOp(LogicalOperator::And),
+                        "OR" => Token::LogicOp(LogicalOperator::Or),
+                        "POWER" => Token::ArOp(ArithmeticOperator::Pow),
+                        _ => {
+                            match excel_function(&name) {
+                                Some(f) => push(Token::Func(f.clone(), f.max_args()), cursor, len, None),
+                                None => return Err(unsupported(&format!("function `{}`", word), cursor))
+                            }
+                            cursor = end;
+                            continue;
+                        }
+                    };
+                    // the call token includes the `(`
+                    let f = TokenContext { token: Token::LP, at: cursor, len: len + 1, line, column, literal: None };
+                    lexemes.push(Lexeme::Call(f, op));
+                    cursor = end + 1;
+                }
+                Some('!') => return Err(unsupported("sheet reference `!`", end)),
+                _ if matches!(word.to_uppercase().as_str(), "TRUE" | "FALSE") => {
+                    return Err(unsupported("`TRUE` and `FALSE`, use `1 > 0` or `1 < 0`", cursor));
+                }
+                // range of cells: `A1:B3`
+                Some(':') if chars.get(end + 1).is_some_and(|c| c.is_alphanumeric() || *c == '$') => {
+                    let len2 = word_len(&chars[end + 1..]);
+                    let to: String = chars[end + 1..end + 1 + len2].iter().collect();
+                    let name = format!("{}_{}", reference(&word), reference(&to));
+                    push(Token::Var(name), cursor, len + 1 + len2, None);
+                    cursor = end + 1 + len2;
+                }
+                _ => {
+                    push(Token::Var(reference(&word)), cursor, len, None);
+                    cursor = end;
+                }
+            }
+        } else {
+            let (token, len) = match (c, next) {
+                ('+', _) => (Token::ArOp(ArithmeticOperator::Plus), 1),
+                ('-', _) => (Token::ArOp(ArithmeticOperator::Minus), 1),
+                ('*', _) => (Token::ArOp(ArithmeticOperator::Mul), 1),
+                ('/', _) => (Token::ArOp(ArithmeticOperator::Div), 1),
+                ('^', _) => (Token::ArOp(ArithmeticOperator::Pow), 1),
+                ('<', Some('>')) => (Token::RelOp(RelationalOperator::Neq), 2),
+                ('<', Some('=')) => (Token::RelOp(RelationalOperator::Leq), 2),
+                ('>', Some('=')) => (Token::RelOp(RelationalOperator::Geq), 2),
+                ('<', _) => (Token::RelOp(RelationalOperator::Lesser), 1),
+                ('>', _) => (Token::RelOp(RelationalOperator::Greater), 1),
+                ('=', _) => (Token::RelOp(RelationalOperator::Eq), 1),
+                ('(', _) => (Token::LP, 1),
+                (')', _) => (Token::RP, 1),
+                (',' | ';', _) => (Token::Comma, 1),
+                ('"', _) => match chars[cursor + 1..].iter().position(|q| *q == '"') {
+                    Some(end) => (Token::Str(chars[cursor + 1..cursor + 1 + end].iter().collect()), end + 2),
+                    None => return Err(TokenizerError::UnclosedString(cursor))
+                },
+                ('&', _) => return Err(unsupported("text concatenation `&`, use `format()`", cursor)),
+                ('{', _) => return Err(unsupported("array constant", cursor)),
+                ('%', _) => return Err(unsupported("percent of a reference, use `/ 100`", cursor)),
+                _ => return Err(TokenizerError::InvalidCharacter(c, cursor))
+            };
+            push(token, cursor, len, None);
+            cursor += len;
+        }
+    }
+    let mut lexemes = lexemes.into_iter().peekable();
+    let mut tokens = Vec::new();
+    loop {
+        tokens.extend(rewrite(&mut lexemes)?);
+        // unbalanced closing brackets are left for the parser
+        match lexemes.next() {
+            Some(Lexeme::Token(closing)) => tokens.push(closing),
+            _ => break
+        }
+    }
+    Ok(tokens)
+}
+
+/// Length of a name or cell reference (`A1`, `$B$2`, `STDEV.P`, `rate_2`)
+fn word_len(chars: &[char]) -> usize {
+    chars.iter().take_while(|c| c.is_alphanumeric() || matches!(c, '_' | '$' | '.')).count()
+}
+
+/// The variable name of a reference: `$A$1` is `A1`
+fn reference(word: &str) -> String {
+    word.replace('$', "")
+}
+
+/// The dymex function of the uppercase Excel function `name`
+fn excel_function(name: &str) -> Option<Function> {
+    Some(match name {
+        "SUM" => Function::Sum,
+        "AVERAGE" => Function::Avg,
+        "MIN" => Function::Min,
+        "MAX" => Function::Max,
+        "PRODUCT" => Function::Prod,
+        "STDEV.P" | "STDEVP" => Function::Std,
+        "ABS" => Function::Abs,
+        "SQRT" => Function::Sqrt,
+        "EXP" => Function::Exp,
+        "LN" => Function::Log,
+        "LOG10" => Function::Log10,
+        "SIN" => Function::Sin,
+        "COS" => Function::Cos,
+        "TAN" => Function::Tan,
+        "IF" => Function::Where,
+        _ => return None
+    })
+}
+
+/// Rewrite the calls of `AND()`, `OR()` and `POWER()` up to the closing bracket
+/// of the current group (or the end)
+fn rewrite<I>(lexemes: &mut std::iter::Peekable<I>) -> Result<Vec<TokenContext>, TokenizerError>
+where I: Iterator<Item = Lexeme> {
+    let mut tokens = Vec::new();
+    while let Some(lexeme) = lexemes.next_if(|l| !matches!(l, Lexeme::Token(TokenContext { token: Token::RP, .. }))) {
+        match lexeme {
+            Lexeme::Token(tc) => {
+                let group = tc.token == Token::LP;
+                tokens.push(tc);
+                if group {
+                    tokens.extend(rewrite(lexemes)?);
+                    if let Some(Lexeme::Token(closing)) = lexemes.next() {
+                        tokens.push(closing);
+                    }
+                }
+            }
+            Lexeme::Call(f, op) => {
+                let inner = rewrite(lexemes)?;
+                if lexemes.next().is_none() {
+                    return Err(TokenizerError::SyntaxError(f.at));
+                }
+                let args = split_arguments(&inner);
+                let two_args = matches!(op, Token::ArOp(_));
+                if args.is_empty() || (two_args && args.len() != 2) {
+                    return Err(unsupported("`POWER()` with other than two arguments, `AND()` or `OR()` without arguments", f.at));
+                }
+                tokens.extend(join_arguments(&f, &args, op));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn excel() -> TokenizerConfig {
+        TokenizerConfig { syntax: Syntax::Excel, ..Default::default() }
+    }
+
+    fn rpn(expression: &str, config: TokenizerConfig) -> String {
+        AST::new(TokenStream::with_config(expression, config).unwrap()).unwrap().rpn_repr()
+    }
+
+    #[test]
+    fn test_excel_translation() {
+        for (formula, expected) in [
+            ("=IF(A1 > 0, SUM(B1:B3) / 3, -A1)", "where(A1 > 0, sum(B1_B3) / 3, -A1)"),
+            ("=average($B$1:$B$3) * 50%", "avg(B1_B3) * 0.5"),
+            ("=IF(AND(A1 >= 1, OR(A2 <> 2, A3 = 3), A4), 1; 2)", "where(A1 >= 1 and (A2 != 2 or A3 == 3) and A4, 1, 2)"),
+            ("=POWER(A1, 2) + LN(A2) - A3 ^ 2", "A1 ** 2 + ln(A2) - A3 ** 2"),
+            ("=STDEV.P(C2:C4) + MAX(C2:C4, 1)", "std(C2_C4) + max(C2_C4, 1)"),
+        ] {
+            assert_eq!(rpn(formula, excel()), rpn(expected, TokenizerConfig::default()), "{}", formula);
+        }
+
+        let mut variables = InputVars::new();
+        variables.insert_owned("A1".to_owned(), 2.0);
+        variables.insert_owned("B1_B3".to_owned(), vec![1.0, 2.0, 6.0]);
+        let mut evaluator = Evaluator::with_config("=IF(A1 > 1, SUM(B1:B3) * 10%, 0)", &variables.names(),
+            VariablePolicy::Strict, excel()).unwrap();
+        assert!((evaluator.evaluate(&variables).unwrap().as_number() - 0.9).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_excel_errors() {
+        for (formula, at) in [("=A1 & B1", 4), ("=VLOOKUP(A1, B1:C3, 2)", 1), ("=Sheet1!A1", 7), ("=TRUE", 1),
+            ("={1, 2}", 1), ("=POWER(A1)", 1), ("=A1%", 3)] {
+            match TokenStream::with_config(formula, excel()) {
+                Err(TokenizerError::UnsupportedSyntax(_, position)) => assert_eq!(position, at, "{}", formula),
+                other => panic!("{}: {:?}", formula, other)
+            }
+        }
+    }
+}
@@ -7,52 +7,138 @@ mod token;
 pub use token::*;
 mod error;
 pub use error::*;
+mod compat;
+mod excel;
 
 
-const MAX_FUNC_ARGS: usize = 64;
-const INVALIDCHAR : &str = "#?˝`\'&|$@%{}";
-const SPECIAL_CHARS  : &str = "()[].,:+-*/^=<>!π";
-const FORBIDDEN_IDS: [&str; 19] = ["min", "max", "avg", "mean", "std", "sin", "cos", "abs",
-"tan", "cotan", "exp", "log", "log2", "log10", "sqrt", "pi", "e", "sqrt2", "sqrt3"];
+pub(crate) const MAX_FUNC_ARGS: usize = 64;
+const INVALIDCHAR : &str = "?˝\'&|$@%{}";
+const SPECIAL_CHARS  : &str = "()[].,:+-*/^=<>!π≠≤≥";
+const FORBIDDEN_IDS: [&str; 57] = ["min", "max", "avg", "mean", "std", "sum", "prod", "range", "sin", "cos", "abs",
+"tan", "cotan", "exp", "ln", "log", "log2", "log10", "sqrt", "pi", "e", "sqrt2", "sqrt3", "resample",
+"shift", "now", "days", "hours", "map", "filter", "reduce", "with", "between", "isna", "coalesce", "where",
+"argmin", "argmax", "find", "trapz", "gradient",
+"polyval", "polyfit", "solve", "convert", "round_to", "floor_to", "ceil_to", "round_even",
+"band", "bor", "bxor", "shl", "shr", "format", "and", "or"];
 
 
 /// A token with additional context. The position in the original expression
 /// and the length of the string representation is stored in `at` and `len`.
 /// As `at` is unique for each token, it also serves as an ID.
+/// `line` and `column` are the zero based position of `at` in multi-line expressions.
 #[derive(Debug, PartialEq, Clone)]
 pub struct TokenContext {
     pub token: Token,
     pub at: usize,
-    pub len: usize
+    pub len: usize,
+    pub line: usize,
+    pub column: usize,
+    /// Source text of a number literal that is not printed the same way as
+    /// its value (`1e-9`, `2.50`, `1_000`), so the formula prints as written
+    pub literal: Option<String>
 }
 impl TokenContext {
     pub fn new(token: Token, at: usize, len: usize) -> Self {
-        Self { token, at, len }
+        Self { token, at, len, line: 0, column: at, literal: None }
     }
     /// Returns a TokenContext without context (e.g for tests).
     pub fn dummy(token: Token) -> Self {
-        Self { token, at: 0, len: 0 }
+        Self { token, at: 0, len: 0, line: 0, column: 0, literal: None }
+    }
+
+    /// The token as written in the expression: the `literal` of numbers, otherwise
+    /// the displayed token
+    pub fn text(&self) -> String {
+        match &self.literal {
+            Some(literal) => literal.clone(),
+            None => self.token.to_string()
+        }
     }
 }
 
 
 
-/// Contains the original single line expression, the list of variable keys
-/// and the list /// of tokens in normal (for debugging) and reversed (for
-/// parsing) order.
+/// Options of the tokenizer
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TokenizerConfig {
+    /// Case sensitivity of the names, see `Case`
+    pub case: Case,
+    /// Meaning of `^`, power (`**`) by default
+    pub caret: Caret,
+    /// Accepted syntax, see `Syntax::Compat` and `Syntax::Excel`
+    pub syntax: Syntax,
+}
+impl Default for TokenizerConfig {
+    fn default() -> Self {
+        Self { case: Case::Builtins, caret: Caret::Pow, syntax: Syntax::Dymex }
+    }
+}
+
+/// Case sensitivity of the identifiers
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Case {
+    /// Function names, constants (except `e`) and `and`/`or` are case insensitive
+    /// (`SIN(x)` is `sin(x)`, `PI` is `pi`), variables are case sensitive. A variable
+    /// named like a function (`Max`) has to be quoted: `` `Max` ``.
+    #[default]
+    Builtins,
+    /// Everything is case sensitive: `sin` is the function and `Sin` a variable
+    Sensitive,
+    /// Everything is case insensitive (`SIN(X)` is `sin(x)`), variable names are lowercased
+    Insensitive,
+}
+
+/// Interpretation of the `^` operator
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Caret {
+    /// `a ^ b` is `a ** b` (as in math notation and spreadsheets)
+    #[default]
+    Pow,
+    /// `a ^ b` is the bitwise exclusive or of integers (as in C, Python, Rust)
+    Xor,
+}
+
+/// Syntax of the expressions
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Syntax {
+    #[default]
+    Dymex,
+    /// For formulas written for other expression engines (C, JavaScript, Rhai, ...):
+    /// `&&`, `||`, `c ? a : b` and `pow(a, b)` are also accepted and translated to
+    /// `and`, `or`, `where(c, a, b)` and `a ** b`. Both branches of the ternary are evaluated.
+    Compat,
+    /// For spreadsheet formulas: `=IF(A1 > 0, SUM(B1:B3), 0)` is `where(A1 > 0, sum(B1_B3), 0)`.
+    /// Cell ranges are array variables named after the corners (`B1_B3`), see `tokenizer/excel.rs`
+    /// for the supported functions and operators.
+    Excel,
+}
+
+
+/// Contains the original single line expression and its tokens. The parser
+/// consumes the tokens with a cursor, `peek()` borrows the upcoming token.
 #[derive(Debug, PartialEq, Clone)]
 pub struct TokenStream {
     tokens: Vec<TokenContext>,
-    tokens_reversed: Vec<TokenContext>,
+    /// Index of the next token
+    cursor: usize,
+    /// Returned after the last token
+    eof: TokenContext,
     expr: String,
+    config: TokenizerConfig,
 }
 impl TokenStream {
 
     pub fn new(expression: &str) -> Result<Self, TokenizerError>  {
+        Self::with_config(expression, TokenizerConfig::default())
+    }
+
+    pub fn with_config(expression: &str, config: TokenizerConfig) -> Result<Self, TokenizerError>  {
         let mut instance = Self {
             tokens: Vec::new(),
-            tokens_reversed: Vec::new(),
-            expr: expression.into()
+            cursor: 0,
+            eof: eof_token(expression),
+            expr: expression.into(),
+            config
         };
         match instance.update(expression) {
             Ok(_) => return Ok(instance),
@@ -72,25 +158,52 @@ impl TokenStream {
         &self.tokens
     }
 
+    pub fn config(&self) -> TokenizerConfig {
+        self.config
+    }
+
     pub fn eof(&self) -> TokenContext {
-        TokenContext { token: Token::Eof, at: self.expr.len(), len: 0 }
+        self.eof.clone()
     }
 
+    /// Consume the next token, `Token::Eof` after the last one
     pub fn next(&mut self) -> TokenContext {
-        self.tokens_reversed.pop().unwrap_or(self.eof())
+        match self.tokens.get(self.cursor) {
+            Some(tc) => {
+                self.cursor += 1;
+                tc.clone()
+            }
+            None => self.eof()
+        }
+    }
+
+    pub fn peek(&self) -> &TokenContext {
+        self.peek_nth(0)
     }
 
-    pub fn peek(&mut self) -> TokenContext {
-        self.tokens_reversed.last().cloned().unwrap_or(self.eof())
+    /// The `n`-th upcoming token without consuming anything, `peek_nth(0)` is `peek()`
+    pub fn peek_nth(&self, n: usize) -> &TokenContext {
+        self.tokens.get(self.cursor + n).unwrap_or(&self.eof)
     }
 
     /// Update the expression and variable keys, tokenize the expression if changed
     pub fn update(&mut self, expression: &str)
     -> Result<(), TokenizerError> {
         self.expr = expression.into();
+        self.eof = eof_token(expression);
         self.tokenize()
     }
 
+    /// Tokenize the expression without failing: the invalid parts become
+    /// `Token::Error` tokens and the errors are returned alongside
+    pub fn with_recovery(expression: &str, config: TokenizerConfig) -> (Self, Vec<TokenizerError>) {
+        let mut errors = Vec::new();
+        let tokens = tokenize_line_with(expression, &config, Some(&mut errors))
+            .expect("tokenization does not fail in recovery mode");
+        let instance = Self { tokens, cursor: 0, eof: eof_token(expression), expr: expression.into(), config };
+        (instance, errors)
+    }
+
     /// Return the names of the variables referenced in the expression
     /// This is a subset of the list of input variables
     pub fn variable_names(&self) -> Vec<String> {
@@ -107,22 +220,54 @@ impl TokenStream {
     }
 
     fn tokenize(&mut self) -> Result<(), TokenizerError> {
-        let res = tokenize_line(&self.expr);
+        let res = match self.config.syntax {
+            Syntax::Dymex => tokenize_line(&self.expr, &self.config),
+            Syntax::Compat => compat::tokenize_compat(&self.expr, &self.config),
+            Syntax::Excel => excel::tokenize_excel(&self.expr),
+        };
         match res {
             Ok(v) => {
-                self.tokens = v.clone();
-                self.tokens_reversed = v;
-                self.tokens_reversed.reverse();
+                self.tokens = v;
+                self.cursor = 0;
                 Ok(())
             }
             Err(e) => {
                 self.tokens.clear();
-                self.tokens_reversed.clear();
+                self.cursor = 0;
                 Err(e)
             }
         }
     }
 
+    /// Re-emit the expression with normalized spacing: one space around binary
+    /// operators and after commas, none after function names, unary signs, or
+    /// inside parentheses and brackets. The tokens are written as in the original
+    /// expression (identifiers, `mean`, ...), except for the power operator which
+    /// is always written as `**`. Comments are dropped.
+    pub fn to_pretty_string(&self) -> String {
+        let chars = self.expr.chars().collect::<Vec<char>>();
+        let mut res = String::with_capacity(self.expr.len());
+        let mut previous: Option<&TokenContext> = None;
+        let mut unary = false;
+        for tc in &self.tokens {
+            if let Some(prev) = previous {
+                if tc.line > prev.line {
+                    res.push('\n');
+                } else if !unary && space_between(&prev.token, &tc.token) {
+                    res.push(' ');
+                }
+            }
+            unary = is_unary_sign(previous.map(|p| &p.token), &tc.token);
+            if tc.token == Token::ArOp(ArithmeticOperator::Pow) {
+                res.push_str("**");
+            } else {
+                res.extend(&chars[tc.at..tc.at + tc.len]);
+            }
+            previous = Some(tc);
+        }
+        res
+    }
+
     fn num_lines(&self) -> usize {
         1 + self.tokens.iter().filter(|&tc| tc.token == Token::Newline).count()
     }
@@ -140,72 +285,143 @@ impl TokenizedLines {
     }
 }
 
-pub (super) fn tokenize_lines(input: &str) -> Result<Vec<TokenContext>, TokenizerError> {
+pub (super) fn tokenize_lines(input: &str, config: &TokenizerConfig) -> Result<Vec<TokenContext>, TokenizerError> {
     // if let Err(e) = check_input_variables(variables) { return Err(e); }
+    let input = &blank_comments(input)?;
     if let Err(e) = check_illegal_characters(input) { return Err(e); }
 
 
-    let mut tokens = Vec::new();
-    for line in input.lines() {
-        if line.trim().is_empty() {
-            continue;
-        }
-        match &mut tokenize_line(line) {
-            Ok(ts) => {
-                tokens.append(ts);
-                tokens.push(TokenContext { token: Token::Newline, at: 0, len: 1 }); // newline position is discarded
-            },
-            Err(err) => return Err(err.clone())
+    let mut tokens: Vec<TokenContext> = Vec::new();
+    for tc in tokenize_line(input, config)? {
+        if let Some(last) = tokens.last()
+        && last.line < tc.line {
+            // the line break right before the line of `tc`
+            let at = tc.at - tc.column - 1;
+            let (line, column) = line_column(input, at);
+            tokens.push(TokenContext { token: Token::Newline, at, len: 1, line, column, literal: None });
         }
+        tokens.push(tc);
     }
-    tokens.pop_if(|tc| tc.token == Token::Newline);
     Ok(tokens)
 }
 
 /// Turns the string representation fo a single line into tokens with additional context
-pub(super) fn tokenize_line(input: &str) -> Result<Vec<TokenContext>, TokenizerError> {
+pub(super) fn tokenize_line(input: &str, config: &TokenizerConfig) -> Result<Vec<TokenContext>, TokenizerError> {
+    tokenize_line_with(input, config, None)
+}
+
+/// Tokenize, stopping at the first error, or if `errors` is given, recording
+/// the errors and emitting `Token::Error` for the invalid characters instead
+fn tokenize_line_with(input: &str, config: &TokenizerConfig, mut errors: Option<&mut Vec<TokenizerError>>)
+-> Result<Vec<TokenContext>, TokenizerError> {
 
     let mut res: Vec<TokenContext> = vec![];
     let mut cursor = 0;
-
-    let expression = input.chars().collect::<Vec<char>>();
+    let mut line = 0;
+    let mut line_start = 0;
+
+    let expression = match (blank_comments(input), &mut errors) {
+        (Ok(blanked), _) => blanked,
+        (Err(e), None) => return Err(e),
+        // an unclosed block comment runs until the end of the expression
+        (Err(e), Some(errors)) => {
+            let start = match e { TokenizerError::UnclosedComment(at) => at, _ => 0 };
+            errors.push(e);
+            input.chars().enumerate()
+                .map(|(i, c)| if i < start || c == '\n' { c } else { ' ' })
+                .collect()
+        }
+    }.chars().collect::<Vec<char>>();
+
+    // In recovery mode: record the error and skip `len` characters as an error token
+    macro_rules! fail {
+        ($error:expr, $len:expr) => {
+            match &mut errors {
+                None => return Err($error),
+                Some(errors) => {
+                    errors.push($error);
+                    let len = ($len).max(1);
+                    res.push(TokenContext { token: Token::Error, at: cursor, len, line, column: cursor - line_start, literal: None });
+                    cursor += len;
+                    continue;
+                }
+            }
+        };
+    }
 
     loop {
         if let Some(next) = expression.get(cursor) {
             let nextnext = expression.get(cursor+1).unwrap_or(&' ');
-            if next.is_whitespace() {
+            if *next == '\n' {
+                cursor += 1;
+                line += 1;
+                line_start = cursor;
+            } else if next.is_whitespace() {
                 cursor +=1;
             } else if next.is_alphabetic() || *next == '_' {
                 let prev_token = res.last().map(|tc| &tc.token);
-                match parse_identifier(&expression[cursor..], prev_token) {
+                match parse_identifier(&expression[cursor..], prev_token, config) {
                     Ok((t, wordsize)) => {
-                        res.push(TokenContext { token: t, at: cursor, len: wordsize });
+                        res.push(TokenContext { token: t, at: cursor, len: wordsize, line, column: cursor - line_start, literal: None });
                         cursor += wordsize;
                         continue;
                     }
                     Err(e) => {
-                        return Err(e);
+                        let wordsize = expression[cursor..].iter()
+                            .take_while(|c| c.is_alphanumeric() || **c == '_').count();
+                        fail!(e, wordsize)
+                    }
+                }
+            } else if *next == '`' {
+                let prev_token = res.last().map(|tc| &tc.token);
+                match parse_escaped_identifier(&expression[cursor..], prev_token, config) {
+                    Some((t, wordsize)) => {
+                        res.push(TokenContext { token: t, at: cursor, len: wordsize, line, column: cursor - line_start, literal: None });
+                        cursor += wordsize;
+                        continue;
+                    }
+                    None => fail!(TokenizerError::UnclosedEscape(cursor), expression.len() - cursor)
+                }
+            } else if *next == '"' {
+                match expression[cursor+1..].iter().position(|c| *c == '"') {
+                    Some(end) => {
+                        let text: String = expression[cursor+1..cursor+1+end].iter().collect();
+                        res.push(TokenContext { token: Token::Str(text), at: cursor, len: end + 2, line, column: cursor - line_start, literal: None });
+                        cursor += end + 2;
+                        continue;
                     }
+                    None => fail!(TokenizerError::UnclosedString(cursor), expression.len() - cursor)
                 }
+            } else if next.is_ascii_digit() && res.last().is_some_and(|tc| tc.token == Token::Dot) {
+                // positional field of a tuple: `t.0`
+                let wordsize = expression[cursor..].iter().take_while(|c| c.is_ascii_digit()).count();
+                let attr = expression[cursor..cursor + wordsize].iter().collect();
+                res.push(TokenContext { token: Token::Attr(attr), at: cursor, len: wordsize, line, column: cursor - line_start, literal: None });
+                cursor += wordsize;
+                continue;
             } else if next.is_ascii_digit()
                 || (next == &'-' && nextnext.is_ascii_digit() ) {
                 if let Some((t, wordsize)) = parse_number(&expression[cursor..]) {
-                    res.push(TokenContext { token: t, at: cursor, len: wordsize });
+                    let text: String = expression[cursor..cursor + wordsize].iter().collect();
+                    let literal = (text != t.to_string()).then_some(text);
+                    res.push(TokenContext { token: t, at: cursor, len: wordsize, line, column: cursor - line_start, literal });
                     cursor += wordsize;
                     continue;
                 } else {
-                    return Err(TokenizerError::InvalidNumberFormat(cursor));
+                    let wordsize = 1 + expression[cursor+1..].iter()
+                        .take_while(|c| c.is_ascii_digit() || matches!(c, '.' | '_' | 'e' | 'E')).count();
+                    fail!(TokenizerError::InvalidNumberFormat(cursor), wordsize)
                 }
             } else if SPECIAL_CHARS.contains(*next) {
-                if let Some((t, advance)) = parse_special_characters(*next, *nextnext) {
-                    res.push(TokenContext { token: t, at: cursor, len: advance });
+                if let Some((t, advance)) = parse_special_characters(*next, *nextnext, config) {
+                    res.push(TokenContext { token: t, at: cursor, len: advance, line, column: cursor - line_start, literal: None });
                     cursor += advance;
                     continue;
                 } else {
-                    return Err(TokenizerError::SyntaxError(cursor))
+                    fail!(TokenizerError::SyntaxError(cursor), 1)
                 }
             } else {
-                return Err(TokenizerError::InvalidCharacter(*next, cursor));
+                fail!(TokenizerError::InvalidCharacter(*next, cursor), 1)
             }
         } else {
             break;
@@ -215,12 +431,103 @@ pub(super) fn tokenize_line(input: &str) -> Result<Vec<TokenContext>, TokenizerE
 }
 
 
-/// Check if there are any illegal characters in the expression
+/// Returns true if `token` is a `+` or `-` sign (`-x`, `2*(+x)`)
+fn is_unary_sign(previous: Option<&Token>, token: &Token) -> bool {
+    if !matches!(token, Token::ArOp(ArithmeticOperator::Minus | ArithmeticOperator::Plus)) {
+        return false;
+    }
+    match previous {
+        None => true,
+        Some(prev) => matches!(prev, Token::LP | Token::LB | Token::Comma | Token::Colon | Token::Arrow | Token::Newline
+            | Token::ArOp(_) | Token::RelOp(_) | Token::LogicOp(_) | Token::AssignOp(_))
+    }
+}
+
+/// Returns true if the pretty printed expression has a space between the tokens
+fn space_between(left: &Token, right: &Token) -> bool {
+    match (left, right) {
+        (Token::LP | Token::LB | Token::Dot | Token::Colon, _) => false,
+        (_, Token::RP | Token::RB | Token::Comma | Token::Dot | Token::Colon | Token::LB) => false,
+        (Token::Func(..), Token::LP) => false,
+        _ => true
+    }
+}
+
+/// The `Token::Eof` at the end of `expression`
+fn eof_token(expression: &str) -> TokenContext {
+    let at = expression.chars().count();
+    let (line, column) = line_column(expression, at);
+    TokenContext { token: Token::Eof, at, len: 0, line, column, literal: None }
+}
+
+/// Returns the zero based line and column (in characters) of the character
+/// position `at` in `expression`
+pub fn line_column(expression: &str, at: usize) -> (usize, usize) {
+    let mut line = 0;
+    let mut column = 0;
+    for c in expression.chars().take(at) {
+        if c == '\n' {
+            line += 1;
+            column = 0;
+        } else {
+            column += 1;
+        }
+    }
+    (line, column)
+}
+
+/// Replace `# line comments` and `/* block comments */` with spaces, keeping
+/// the newlines, so the positions of the remaining characters are unchanged.
+pub fn blank_comments(input: &str) -> Result<String, TokenizerError> {
+    let chars = input.chars().collect::<Vec<char>>();
+    let mut res = String::with_capacity(input.len());
+    let mut cursor = 0;
+    while cursor < chars.len() {
+        let c = chars[cursor];
+        if c == '`' || c == '"' {
+            // `#` in an escaped identifier or a string is not a comment
+            let end = chars[cursor+1..].iter().position(|q| *q == c)
+                .map_or(chars.len(), |i| cursor + i + 2);
+            res.extend(&chars[cursor..end]);
+            cursor = end;
+        } else if c == '#' {
+            while cursor < chars.len() && chars[cursor] != '\n' {
+                res.push(' ');
+                cursor += 1;
+            }
+        } else if c == '/' && chars.get(cursor+1) == Some(&'*') {
+            let start = cursor;
+            res.push_str("  ");
+            cursor += 2;
+            loop {
+                match chars.get(cursor) {
+                    None => return Err(TokenizerError::UnclosedComment(start)),
+                    Some('*') if chars.get(cursor+1) == Some(&'/') => {
+                        res.push_str("  ");
+                        cursor += 2;
+                        break;
+                    }
+                    Some('\n') => res.push('\n'),
+                    Some(_) => res.push(' ')
+                }
+                cursor += 1;
+            }
+        } else {
+            res.push(c);
+            cursor += 1;
+        }
+    }
+    Ok(res)
+}
+
+/// Check if there are any illegal characters in the expression (outside of strings)
 fn check_illegal_characters(s: &str) -> Result<(), TokenizerError>{
-    for invchar in INVALIDCHAR.chars() {
-        if let Some(at) = s.chars().position(|c| c == invchar )
-        {
-            return Err(TokenizerError::InvalidCharacter(invchar, at));
+    let mut in_string = false;
+    for (at, c) in s.chars().enumerate() {
+        if c == '"' {
+            in_string = !in_string;
+        } else if !in_string && INVALIDCHAR.contains(c) {
+            return Err(TokenizerError::InvalidCharacter(c, at));
         }
     }
     Ok(())
@@ -245,10 +552,13 @@ fn check_input_variables__(variables: &[&str]) -> Result<(), TokenizerError> {
 }
 
 /// Parses c1, c2 if c1 is special character.
-fn parse_special_characters(c1: char, c2: char) -> Option<(Token, usize)> {
+fn parse_special_characters(c1: char, c2: char, config: &TokenizerConfig) -> Option<(Token, usize)> {
     if let Some(t) = parse_double_char_token(c1 , c2) {
         return Some((t, 2));
     }
+    if c1 == '^' && config.caret == Caret::Xor {
+        return Some((Token::ArOp(ArithmeticOperator::Xor), 1));
+    }
     if let Some(t) = parse_single_char_token(c1) {
         return Some((t, 1));
     }
@@ -273,6 +583,9 @@ fn parse_single_char_token(c: char) -> Option<Token> {
         '=' => Some(Token::AssignOp(AssignmentOperator::Assign)),
         '>' => Some(Token::RelOp(RelationalOperator::Greater)),
         '<' => Some(Token::RelOp(RelationalOperator::Lesser)),
+        '≠' => Some(Token::RelOp(RelationalOperator::Neq)),
+        '≤' => Some(Token::RelOp(RelationalOperator::Leq)),
+        '≥' => Some(Token::RelOp(RelationalOperator::Geq)),
         'π' => Some(Token::Const(Constant::Pi)),
         _ => None
     }
@@ -290,6 +603,7 @@ fn parse_double_char_token(c1: char, c2: char) -> Option<Token> {
         ('>', '=') => Some(Token::RelOp(RelationalOperator::Geq)),
         ('<', '=') => Some(Token::RelOp(RelationalOperator::Leq)),
         ('!', '=') => Some(Token::RelOp(RelationalOperator::Neq)),
+        ('-', '>') => Some(Token::Arrow),
         _ => None
     }
 }
@@ -354,9 +668,11 @@ fn is_ident_char(c: char) -> bool {
 /// variable, or UndefinedVariable error. The `start` parameter is only
 /// needed for error reporting. The previous token needs to be provided
 /// to allow `a.b` even if `b` is not a user defined variable.
+/// Functions and constants are matched according to `config.case`.
 fn parse_identifier(
     s: &[char],
-    previous: Option<&Token>
+    previous: Option<&Token>,
+    config: &TokenizerConfig
     ) -> Result<(Token, usize), TokenizerError> {
     let id_chars = match s.iter()
         .enumerate()
@@ -369,13 +685,25 @@ fn parse_identifier(
     //     .filter(|c| !c.is_whitespace())
     //     .take(2).map(|&c|c).collect::<Vec<char>>();
 
-    let id: String = id_chars.iter().collect();
-    if let Some(func) = parse_function(&id) {
+    let mut id: String = id_chars.iter().collect();
+    let builtin = match config.case {
+        Case::Sensitive => id.clone(),
+        Case::Builtins | Case::Insensitive => id.to_lowercase(),
+    };
+    if let Some(func) = parse_function(&builtin) {
         return Ok((func, id_chars.len()));
     }
-    if let Some(constant) = parse_const(&id) {
+    if let Some(op) = parse_logical_operator(&builtin) {
+        return Ok((op, id_chars.len()));
+    }
+    // `E` is a variable unless the variables are case insensitive too
+    let constant = if config.case == Case::Builtins && id == "E" { &id } else { &builtin };
+    if let Some(constant) = parse_const(constant) {
         return Ok((constant, id_chars.len()));
     }
+    if config.case == Case::Insensitive {
+        id = builtin;
+    }
     if let Some(prev_token) = previous
     && *prev_token == Token::Dot  {
         Ok((Token::Attr(id), id_chars.len()))
@@ -389,50 +717,76 @@ fn parse_identifier(
 }
 
 
-fn parse_function(word: &str) -> Option<Token>
+/// Parses a backtick quoted identifier (`` `min` ``, `` `signal 2` ``) which is always
+/// a variable (or attribute), even if it collides with a function or constant name.
+/// Returns None if the closing backtick is missing or the name is empty.
+fn parse_escaped_identifier(
+    s: &[char],
+    previous: Option<&Token>,
+    config: &TokenizerConfig
+    ) -> Option<(Token, usize)> {
+    let end = s[1..].iter().position(|c| *c == '`')? + 1;
+    if end == 1 {
+        return None;
+    }
+    let mut id: String = s[1..end].iter().collect();
+    if config.case == Case::Insensitive {
+        id = id.to_lowercase();
+    }
+    if let Some(prev_token) = previous
+    && *prev_token == Token::Dot  {
+        Some((Token::Attr(id), end + 1))
+    } else {
+        Some((Token::Var(id), end + 1))
+    }
+}
+
+/// Returns true if `name` can only be referenced as a backtick quoted identifier
+pub(crate) fn needs_escape(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' => {},
+        _ => return true
+    }
+    !chars.all(is_ident_char) || parse_function(name).is_some() || parse_const(name).is_some()
+        || parse_logical_operator(name).is_some()
+}
+
+/// Returns the function token for a function name (see `Function::name()`)
+/// or an alias (`mean`)
+pub(crate) fn parse_function(word: &str) -> Option<Token>
+{
+    Function::from_name(word).map(|f| {
+        let max_args = f.max_args();
+        Token::Func(f, max_args)
+    })
+}
+
+fn parse_logical_operator(word: &str) -> Option<Token>
 {
-    match word.to_lowercase().as_str() {
-        "min" => Some(Token::Func(Function::Min, MAX_FUNC_ARGS)),
-        "max" => Some(Token::Func(Function::Max, MAX_FUNC_ARGS)),
-        "avg" => Some(Token::Func(Function::Avg, MAX_FUNC_ARGS)),
-        "mean" => Some(Token::Func(Function::Avg, MAX_FUNC_ARGS)),
-        "std" => Some(Token::Func(Function::Std, MAX_FUNC_ARGS)),
-        "sum" => Some(Token::Func(Function::Sum, MAX_FUNC_ARGS)),
-        "abs" => Some(Token::Func(Function::Abs, 1)),
-        "sin" => Some(Token::Func(Function::Sin, 1)),
-        "cos" => Some(Token::Func(Function::Cos, 1)),
-        "tan" => Some(Token::Func(Function::Tan, 1)),
-        "cotan" => Some(Token::Func(Function::Cot, 1)),
-        "exp" => Some(Token::Func(Function::Exp, 1)),
-        "log" => Some(Token::Func(Function::Log2, 1)),
-        "log2" => Some(Token::Func(Function::Log2, 1)),
-        "log10" => Some(Token::Func(Function::Log10, 1)),
-        "sqrt" => Some(Token::Func(Function::Sqrt, 1)),
+    match word {
+        "and" => Some(Token::LogicOp(LogicalOperator::And)),
+        "or" => Some(Token::LogicOp(LogicalOperator::Or)),
         _ => None
     }
 }
 
 fn parse_const(word: &str) -> Option<Token>
 {
-    if word =="e" {
-        return Some(Token::Const(Constant::Euler))
-    }
-    match word.to_lowercase().as_str() {
+    match word {
+        "e" => Some(Token::Const(Constant::Euler)),
         "pi" | "π" => Some(Token::Const(Constant::Pi)),
         "sqrt2" => Some(Token::Const(Constant::Sqrt2)),
-        "sqrt3" => Some(Token::Const(Constant::Sqrt2)),
+        "sqrt3" => Some(Token::Const(Constant::Sqrt3)),
         _ => None
     }
 }
 
-pub(super) fn charslice(s: &str) -> Vec<char> {
-    s.chars().collect()
-}
-
 
 #[cfg(test)]
 mod tests {
     use crate::TokenizerError;
+    use crate::testing::charslice;
     use super::*;
     use std::assert_matches;
 
@@ -473,31 +827,31 @@ mod tests {
     #[test]
     fn test_identifier() {
         let id = &charslice("max(15)");
-        let res = parse_identifier(id, None);
+        let res = parse_identifier(id, None, &TokenizerConfig::default());
         assert_matches!(res, Ok((Token::Func(Function::Max, _), 3)) );
 
         let id = &charslice("_center");
-        let res = parse_identifier(id, None);
+        let res = parse_identifier(id, None, &TokenizerConfig::default());
         assert_eq!(res, Ok((Token::Var("_center".into()), 7)));
 
         let id = &charslice("center*5");
-        let res = parse_identifier(id, None);
+        let res = parse_identifier(id, None, &TokenizerConfig::default());
         assert_eq!(res, Ok((Token::Var("center".into()), 6)));
 
         let id = &charslice("pi^2");
-        let res = parse_identifier(id, None);
+        let res = parse_identifier(id, None, &TokenizerConfig::default());
         assert_eq!(res, Ok((Token::Const(Constant::Pi), 2)));
 
         let id = &charslice("π^2");
-        let res = parse_identifier(id, None);
+        let res = parse_identifier(id, None, &TokenizerConfig::default());
         assert_eq!(res, Ok((Token::Const(Constant::Pi), 1)));
 
         let id = &charslice("eV2nm * λ");
-        let res = parse_identifier(id, None);
+        let res = parse_identifier(id, None, &TokenizerConfig::default());
         assert_eq!(res, Ok((Token::Var("eV2nm".into()), 5)));
 
         let id = &charslice("len");
-        let res = parse_identifier(id, Some(&Token::Dot));
+        let res = parse_identifier(id, Some(&Token::Dot), &TokenizerConfig::default());
         assert_eq!(res, Ok((Token::Attr("len".into()), 3)));
 
         // assignment:
@@ -513,6 +867,163 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_unicode_positions() {
+        let ts = TokenStream::new("λ·π + ϕ₀").unwrap_err();
+        assert_eq!(ts, TokenizerError::InvalidCharacter('·', 1));
+
+        let mut ts = TokenStream::new("λ * π + ϕ0").unwrap();
+        let tokens = ts.tokens().to_vec();
+        assert_eq!(tokens.iter().map(|tc| tc.at).collect::<Vec<_>>(), vec![0, 2, 4, 6, 8]);
+        assert_eq!(tokens[4].len, 2);
+        while ts.next().token != Token::Eof {}
+        assert_eq!(ts.eof().at, 10);
+    }
+
+    #[test]
+    fn test_pretty_string() {
+        let pretty = |expr: &str| TokenStream::new(expr).unwrap().to_pretty_string();
+        assert_eq!(pretty("y=2*x+sin (  x)**2"), "y = 2 * x + sin(x) ** 2");
+        assert_eq!(pretty("mean( a,b ,  -c)/ -2"), "mean(a, b, -c) / -2");
+        assert_eq!(pretty(" -( x )-s . field  "), "-(x) - s.field");
+        assert_eq!(pretty("v[ 0 : -1 ]>=`min`"), "v[0:-1] >= `min`");
+        assert_eq!(pretty("now( )"), "now()");
+        assert_eq!(pretty("x^2 + y**2"), "x ** 2 + y ** 2");
+        assert_eq!(pretty("z=x # comment\n  z  /* c */ *λ"), "z = x\nz * λ");
+
+        let expr = "y = 2 * x + sin(x) ** 2";
+        assert!(TokenStream::new(expr).unwrap().identical(&TokenStream::new(&pretty(expr)).unwrap()));
+    }
+
+    #[test]
+    fn test_line_column() {
+        let config = TokenizerConfig::default();
+        let res = tokenize_lines("x = 1\n\n  y = x * 2", &config).unwrap();
+        let y = res.iter().find(|tc| tc.token == Token::Var("y".into())).unwrap();
+        assert_eq!((y.at, y.line, y.column), (9, 2, 2));
+        let nl = res.iter().find(|tc| tc.token == Token::Newline).unwrap();
+        assert_eq!((nl.at, nl.line, nl.column), (6, 1, 0));
+        assert_eq!(res.iter().filter(|tc| tc.token == Token::Newline).count(), 1);
+
+        assert_eq!(line_column("ab\ncd", 4), (1, 1));
+        assert_eq!(line_column("ab", 1), (0, 1));
+    }
+
+    #[test]
+    fn test_comments() {
+        assert_eq!(blank_comments("a + b # sum").unwrap(), "a + b      ");
+        assert_eq!(blank_comments("a /* x\ny */ * b").unwrap(), "a     \n     * b");
+        assert_eq!(blank_comments("`#a` # b").unwrap(), "`#a`    ");
+        assert_eq!(blank_comments("a /* b"), Err(TokenizerError::UnclosedComment(2)));
+
+        let config = TokenizerConfig::default();
+        let res = tokenize_line("x /* scale */ * 2 # twice", &config).unwrap();
+        assert_eq!(unwrap_contexts(&res), vec![Token::Var("x".into()), Token::ArOp(ArithmeticOperator::Mul),
+            Token::Number(2.0)]);
+        assert_eq!(res[1].at, 14);
+        assert_eq!(res[2].at, 16);
+
+        let res = tokenize_lines("# header\nx + 1 # first\n/* block\ncomment */ y", &config).unwrap();
+        assert_eq!(unwrap_contexts(&res), vec![Token::Var("x".into()), Token::ArOp(ArithmeticOperator::Plus),
+            Token::Number(1.0), Token::Newline, Token::Var("y".into())]);
+    }
+
+    #[test]
+    fn test_escaped_identifier() {
+        let config = TokenizerConfig::default();
+        let res = tokenize_line("`e` * `min` + `signal 2`.x", &config).unwrap();
+        assert_eq!(unwrap_contexts(&res), vec![Token::Var("e".into()), Token::ArOp(ArithmeticOperator::Mul),
+            Token::Var("min".into()), Token::ArOp(ArithmeticOperator::Plus), Token::Var("signal 2".into()),
+            Token::Dot, Token::Attr("x".into())]);
+        assert_eq!(res[2].at, 6);
+        assert_eq!(res[2].len, 5);
+
+        assert_eq!(tokenize_line("1 + `min", &config), Err(TokenizerError::UnclosedEscape(4)));
+        assert_eq!(tokenize_line("``", &config), Err(TokenizerError::UnclosedEscape(0)));
+
+        assert_eq!(Token::Var("min".into()).to_string(), "`min`");
+        assert_eq!(Token::Var("minimum".into()).to_string(), "minimum");
+        assert_eq!(Token::Var("1a".into()).to_string(), "`1a`");
+    }
+
+    #[test]
+    fn test_positional_attribute() {
+        let res = tokenize_line("t.10 + 1.5", &TokenizerConfig::default()).unwrap();
+        assert_eq!(unwrap_contexts(&res), vec![Token::Var("t".into()), Token::Dot, Token::Attr("10".into()),
+            Token::ArOp(ArithmeticOperator::Plus), Token::Number(1.5)]);
+        assert_eq!(Token::Attr("10".into()).to_string(), "10");
+    }
+
+    #[test]
+    fn test_string() {
+        let config = TokenizerConfig::default();
+        let res = tokenize_lines("convert(x, \"°C\", \"K\") # \"%\"", &config).unwrap();
+        assert_eq!(unwrap_contexts(&res)[4..], [Token::Str("°C".into()), Token::Comma, Token::Str("K".into()), Token::RP]);
+        assert_eq!(res[4].at, 11);
+        assert_eq!(res[4].len, 4);
+        assert_eq!(tokenize_lines("\"{x} %\"", &config).unwrap()[0].token, Token::Str("{x} %".into()));
+
+        assert_eq!(tokenize_line("1 + \"eV", &config), Err(TokenizerError::UnclosedString(4)));
+        assert_eq!(Token::Str("eV".into()).to_string(), "\"eV\"");
+    }
+
+    #[test]
+    fn test_caret() {
+        let pow = tokenize_line("a^2 ** b", &TokenizerConfig::default()).unwrap();
+        assert_eq!(unwrap_contexts(&pow), vec![Token::Var("a".into()), Token::ArOp(ArithmeticOperator::Pow),
+            Token::Number(2.0), Token::ArOp(ArithmeticOperator::Pow), Token::Var("b".into())]);
+
+        let config = TokenizerConfig { caret: Caret::Xor, ..Default::default() };
+        let xor = tokenize_line("a^2 ** b", &config).unwrap();
+        assert_eq!(unwrap_contexts(&xor), vec![Token::Var("a".into()), Token::ArOp(ArithmeticOperator::Xor),
+            Token::Number(2.0), Token::ArOp(ArithmeticOperator::Pow), Token::Var("b".into())]);
+    }
+
+    #[test]
+    fn test_function_names() {
+        for f in Function::ALL {
+            assert_eq!(parse_function(f.name()), Some(Token::Func(f.clone(), f.max_args())));
+            assert!(FORBIDDEN_IDS.contains(&f.name()));
+        }
+        assert_eq!(parse_function("log"), Some(Token::Func(Function::Log2, 1)));
+        assert_eq!(parse_function("ln"), Some(Token::Func(Function::Log, 1)));
+        assert_eq!(parse_function("mean"), Some(Token::Func(Function::Avg, MAX_FUNC_ARGS)));
+    }
+
+    #[test]
+    fn test_case_sensitivity() {
+        let sensitive = TokenizerConfig { case: Case::Sensitive, ..Default::default() };
+        let insensitive = TokenizerConfig { case: Case::Insensitive, ..Default::default() };
+        let builtins = TokenizerConfig::default();
+
+        let res = parse_identifier(&charslice("Max"), None, &sensitive);
+        assert_eq!(res, Ok((Token::Var("Max".into()), 3)));
+        let res = parse_identifier(&charslice("SIN"), None, &sensitive);
+        assert_eq!(res, Ok((Token::Var("SIN".into()), 3)));
+        let res = parse_identifier(&charslice("PI"), None, &sensitive);
+        assert_eq!(res, Ok((Token::Var("PI".into()), 2)));
+
+        let res = parse_identifier(&charslice("SIN"), None, &insensitive);
+        assert_matches!(res, Ok((Token::Func(Function::Sin, _), 3)));
+        let res = parse_identifier(&charslice("E"), None, &insensitive);
+        assert_eq!(res, Ok((Token::Const(Constant::Euler), 1)));
+        let res = parse_identifier(&charslice("Width"), None, &insensitive);
+        assert_eq!(res, Ok((Token::Var("width".into()), 5)));
+
+        let res = parse_identifier(&charslice("Max"), None, &builtins);
+        assert_matches!(res, Ok((Token::Func(Function::Max, _), 3)));
+        let res = parse_identifier(&charslice("PI"), None, &builtins);
+        assert_eq!(res, Ok((Token::Const(Constant::Pi), 2)));
+        let res = parse_identifier(&charslice("OR"), None, &builtins);
+        assert_eq!(res, Ok((Token::LogicOp(LogicalOperator::Or), 2)));
+        let res = parse_identifier(&charslice("E"), None, &builtins);
+        assert_eq!(res, Ok((Token::Var("E".into()), 1)));
+        let res = parse_identifier(&charslice("Width"), None, &builtins);
+        assert_eq!(res, Ok((Token::Var("Width".into()), 5)));
+        let res = parse_escaped_identifier(&charslice("`Max`"), None, &builtins);
+        assert_eq!(res, Some((Token::Var("Max".into()), 5)));
+    }
+
     #[test]
     fn test_tokenizer_expr1() {
         // let input_var = &["x", "y"];
@@ -527,9 +1038,9 @@ mod tests {
         Token::LP, Token::Number(1.0), Token::ArOp(ArithmeticOperator::Plus), Token::Func(Function::Sqrt, 1),
         Token::LP, Token::Var("y".into()), Token::RP, Token::Comma, Token::Number(0.0), Token::RP];
 
-        let res1 = tokenize_lines(expr1).unwrap();
-        let res2 = tokenize_lines(expr2).unwrap();
-        let res3 = tokenize_lines(expr3).unwrap();
+        let res1 = tokenize_lines(expr1, &TokenizerConfig::default()).unwrap();
+        let res2 = tokenize_lines(expr2, &TokenizerConfig::default()).unwrap();
+        let res3 = tokenize_lines(expr3, &TokenizerConfig::default()).unwrap();
         assert!(same_tokens(&unwrap_contexts(&res1), target));
         assert!(same_tokens(&unwrap_contexts(&res2), target));
         assert!(same_tokens(&unwrap_contexts(&res3), target));
@@ -538,7 +1049,7 @@ mod tests {
     #[test]
     fn test_tokenizer_expr2() {
         let expr = "spectrum.x[-1] - spectrum.x[0]";
-        let res = tokenize_lines(expr).unwrap();
+        let res = tokenize_lines(expr, &TokenizerConfig::default()).unwrap();
         let target = &[Token::Var("spectrum".into()), Token::Dot,
             Token::Var("x".into()), Token::LB, Token::Number(-1.0), Token::RB,
             Token::ArOp(ArithmeticOperator::Minus), Token::Var("spectrum".into()),
@@ -569,7 +1080,7 @@ mod tests {
             Token::Number(0.0), Token::Colon, Token::Number(-1.0), Token::RB
             ];
 
-        let res = tokenize_lines(expr).unwrap();
+        let res = tokenize_lines(expr, &TokenizerConfig::default()).unwrap();
         assert!(same_tokens(&unwrap_contexts(&res), target));
     }
 
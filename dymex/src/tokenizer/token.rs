@@ -21,13 +21,20 @@ pub enum Token {
     Semicolon,
     Dot,
     Colon,
+    /// `->` of anonymous functions: `x -> x * x`
+    Arrow,
     Number(Float),
+    /// Text in double quotes: `"eV"`
+    Str(String),
     Const(Constant),
     Var(String),
     Attr(String),
     Func(Function, usize),
     Newline,
-    Eof
+    Eof,
+    /// Invalid or missing part of the expression, only produced in error
+    /// recovery mode (see `AST::parse_partial()`)
+    Error,
 }
 
 impl fmt::Display for Token {
@@ -45,13 +52,23 @@ impl fmt::Display for Token {
             Token::Semicolon => write!(f, ";"),
             Token::Dot => write!(f, "."),
             Token::Colon => write!(f, ":"),
+            Token::Arrow => write!(f, "->"),
             Token::Number(x) => write!(f, "{}", x),
+            Token::Str(s) => write!(f, "\"{}\"", s),
             Token::Const(c) => write!(f, "{}", c),
-            Token::Var(s) => write!(f, "{}", s),
-            Token::Attr(s) => write!(f, "{}", s),
+            // positional field of a tuple: `t.0`
+            Token::Attr(s) if s.chars().all(|c| c.is_ascii_digit()) => write!(f, "{}", s),
+            Token::Var(s) | Token::Attr(s) => {
+                if super::needs_escape(s) {
+                    write!(f, "`{}`", s)
+                } else {
+                    write!(f, "{}", s)
+                }
+            }
             Token::Func(func, _) => write!(f, "{}", func),
             Token::Newline => write!(f, "⏎"),
             Token::Eof => write!(f, "Eof"),
+            Token::Error => write!(f, "⚠"),
         }
     }
 }
@@ -66,6 +83,8 @@ pub enum  ArithmeticOperator {
     Rem,
     Pow,
     Negate,
+    /// Bitwise exclusive or of integers, see `Caret::Xor`
+    Xor,
 }
 impl fmt::Display for ArithmeticOperator {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -77,6 +96,7 @@ impl fmt::Display for ArithmeticOperator {
             ArithmeticOperator::Pow => write!(f, "**"),
             ArithmeticOperator::Rem => write!(f, "%"),
             ArithmeticOperator::Negate => write!(f, "~"),
+            ArithmeticOperator::Xor => write!(f, "xor"),
         }
     }
 }
@@ -102,6 +122,19 @@ impl fmt::Display for RelationalOperator {
         }
     }
 }
+impl RelationalOperator {
+    /// The operator with swapped operands: `a < b` is `b > a`
+    pub fn reversed(&self) -> Self {
+        match self {
+            RelationalOperator::Greater => RelationalOperator::Lesser,
+            RelationalOperator::Lesser => RelationalOperator::Greater,
+            RelationalOperator::Leq => RelationalOperator::Geq,
+            RelationalOperator::Geq => RelationalOperator::Leq,
+            RelationalOperator::Eq => RelationalOperator::Eq,
+            RelationalOperator::Neq => RelationalOperator::Neq,
+        }
+    }
+}
 #[derive(Debug, PartialEq, Clone)]
 pub enum  LogicalOperator {
     And,
@@ -145,6 +178,7 @@ pub enum Function {
     Avg,
     Std,
     Sum,
+    Prod,
     Range,
     Abs,
     Sin,
@@ -156,6 +190,207 @@ pub enum Function {
     Log2,
     Log10,
     Sqrt,
+    Resample,
+    Shift,
+    Now,
+    Days,
+    Hours,
+    Map,
+    Filter,
+    Reduce,
+    With,
+    Between,
+    IsNa,
+    Coalesce,
+    Where,
+    Argmin,
+    Argmax,
+    Find,
+    Trapz,
+    Gradient,
+    Polyval,
+    Polyfit,
+    Solve,
+    Convert,
+    RoundTo,
+    FloorTo,
+    CeilTo,
+    RoundEven,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    Format,
+}
+impl Function {
+    /// Every built in function
+    pub const ALL: &'static [Function] = &[Function::Min, Function::Max, Function::Avg,
+        Function::Std, Function::Sum, Function::Prod, Function::Range, Function::Abs, Function::Sin,
+        Function::Cos, Function::Tan, Function::Cot, Function::Exp, Function::Log,
+        Function::Log2, Function::Log10, Function::Sqrt, Function::Resample,
+        Function::Shift, Function::Now, Function::Days, Function::Hours, Function::Map,
+        Function::Filter, Function::Reduce, Function::With, Function::Between,
+        Function::IsNa, Function::Coalesce, Function::Where,
+        Function::Argmin, Function::Argmax, Function::Find,
+        Function::Trapz, Function::Gradient, Function::Polyval, Function::Polyfit,
+        Function::Solve, Function::Convert,
+        Function::RoundTo, Function::FloorTo, Function::CeilTo, Function::RoundEven,
+        Function::BitAnd, Function::BitOr, Function::BitXor, Function::Shl, Function::Shr,
+        Function::Format];
+
+    /// The function called `name` in expressions (see `name()`), or by an alias:
+    /// `mean`, and `log` for `log2` as in the formulas saved before `ln`
+    pub fn from_name(name: &str) -> Option<Function> {
+        match name {
+            "mean" => Some(Function::Avg),
+            "log" => Some(Function::Log2),
+            _ => Function::ALL.iter().find(|f| f.name() == name).cloned()
+        }
+    }
+
+    /// Name of the function in expressions
+    pub fn name(&self) -> &'static str {
+        match self {
+            Function::Min => "min",
+            Function::Max => "max",
+            Function::Avg => "avg",
+            Function::Std => "std",
+            Function::Sum => "sum",
+            Function::Prod => "prod",
+            Function::Range => "range",
+            Function::Abs => "abs",
+            Function::Sin => "sin",
+            Function::Cos => "cos",
+            Function::Tan => "tan",
+            Function::Cot => "cotan",
+            Function::Exp => "exp",
+            Function::Log => "ln",
+            Function::Log2 => "log2",
+            Function::Log10 => "log10",
+            Function::Sqrt => "sqrt",
+            Function::Resample => "resample",
+            Function::Shift => "shift",
+            Function::Now => "now",
+            Function::Days => "days",
+            Function::Hours => "hours",
+            Function::Map => "map",
+            Function::Filter => "filter",
+            Function::Reduce => "reduce",
+            Function::With => "with",
+            Function::Between => "between",
+            Function::IsNa => "isna",
+            Function::Coalesce => "coalesce",
+            Function::Where => "where",
+            Function::Argmin => "argmin",
+            Function::Argmax => "argmax",
+            Function::Find => "find",
+            Function::Trapz => "trapz",
+            Function::Gradient => "gradient",
+            Function::Polyval => "polyval",
+            Function::Polyfit => "polyfit",
+            Function::Solve => "solve",
+            Function::Convert => "convert",
+            Function::RoundTo => "round_to",
+            Function::FloorTo => "floor_to",
+            Function::CeilTo => "ceil_to",
+            Function::RoundEven => "round_even",
+            Function::BitAnd => "band",
+            Function::BitOr => "bor",
+            Function::BitXor => "bxor",
+            Function::Shl => "shl",
+            Function::Shr => "shr",
+            Function::Format => "format",
+        }
+    }
+
+    /// Names of the parameters, for named arguments (`shift(v, n = 2)`).
+    /// Variadic functions have no parameter names, the named arguments of
+    /// `with(t = x * 2, sin(t) + t)` are local variables.
+    pub fn param_names(&self) -> &'static [&'static str] {
+        match self {
+            Function::Min | Function::Max | Function::Avg | Function::Std
+            | Function::Sum | Function::Prod | Function::Range | Function::Now | Function::With
+            | Function::Coalesce | Function::Format => &[],
+            Function::Resample => &["series", "dt"],
+            Function::Shift => &["series", "n"],
+            Function::Map => &["array", "f"],
+            Function::Filter => &["array", "predicate"],
+            Function::Reduce => &["array", "f", "init"],
+            Function::Between => &["x", "lo", "hi"],
+            Function::Where => &["mask", "a", "b"],
+            Function::Find => &["array", "x"],
+            Function::Trapz | Function::Gradient => &["y", "x"],
+            Function::Polyval => &["coeffs", "x"],
+            Function::Polyfit => &["x", "y", "deg"],
+            Function::Convert => &["x", "from", "to"],
+            Function::RoundTo | Function::FloorTo | Function::CeilTo | Function::RoundEven => &["x", "increment"],
+            Function::BitAnd | Function::BitOr | Function::BitXor => &["a", "b"],
+            Function::Shl | Function::Shr => &["x", "n"],
+            Function::Solve => &["f", "target", "lo", "hi"],
+            _ => &["x"]
+        }
+    }
+
+    /// Keyword-only parameters of the variadic functions, given after the
+    /// positional arguments: `avg(m, axis = 0)`, see `DynMath::reduce()`, and
+    /// the delta degrees of freedom of `std(v, ddof = 1)`
+    pub fn keyword_params(&self) -> &'static [&'static str] {
+        match self {
+            Function::Std => &["axis", "keepdims", "ddof"],
+            Function::Min | Function::Max | Function::Avg
+            | Function::Sum | Function::Prod | Function::Range => &["axis", "keepdims"],
+            _ => &[]
+        }
+    }
+
+    /// Minimal number of arguments, an empty argument list is only valid if this is 0
+    pub fn min_args(&self) -> usize {
+        match self {
+            Function::Resample | Function::Shift | Function::Map | Function::Filter
+            | Function::With | Function::Coalesce | Function::Find | Function::Trapz
+            | Function::Gradient | Function::Polyval | Function::RoundTo | Function::FloorTo
+            | Function::CeilTo | Function::RoundEven | Function::BitAnd | Function::BitOr
+            | Function::BitXor | Function::Shl | Function::Shr => 2,
+            Function::Reduce | Function::Between | Function::Where | Function::Polyfit
+            | Function::Convert => 3,
+            Function::Solve => 4,
+            Function::Now => 0,
+            _ => 1
+        }
+    }
+
+    /// Maximum number of arguments accepted by the function
+    pub fn max_args(&self) -> usize {
+        match self {
+            Function::Min | Function::Max | Function::Avg | Function::Std
+            | Function::Sum | Function::Prod | Function::Range | Function::With
+            | Function::Coalesce | Function::Format => super::MAX_FUNC_ARGS,
+            Function::Resample | Function::Shift | Function::Map | Function::Filter
+            | Function::Find | Function::Trapz | Function::Gradient | Function::Polyval
+            | Function::RoundTo | Function::FloorTo | Function::CeilTo | Function::RoundEven
+            | Function::BitAnd | Function::BitOr | Function::BitXor | Function::Shl | Function::Shr => 2,
+            Function::Reduce | Function::Between | Function::Where | Function::Polyfit
+            | Function::Convert => 3,
+            Function::Solve => 4,
+            Function::Now => 0,
+            _ => 1
+        }
+    }
+
+    /// Number of parameters of the anonymous function expected at argument
+    /// `position`: `map(v, x -> x * x)`, `reduce(v, (a, b) -> a + b, 0)`.
+    /// For `sum` and `prod` the function of the index is optional:
+    /// `sum(i -> 1 / i ** 2, 1, 10)` sums over `i = 1, 2, ..., 10`.
+    /// `solve(x -> x ** 3, 8, 0, 10)` finds the root of `x ** 3 - 8` in `[0, 10]`.
+    pub fn lambda_arity(&self, position: usize) -> Option<usize> {
+        match (self, position) {
+            (Function::Map | Function::Filter, 1) => Some(1),
+            (Function::Sum | Function::Prod | Function::Solve, 0) => Some(1),
+            (Function::Reduce, 1) => Some(2),
+            _ => None
+        }
+    }
 }
 impl fmt::Display for Function {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -188,6 +423,10 @@ impl fmt::Display for Constant {
     }
 }
 impl Constant {
+    /// Every built in constant
+    pub const ALL: &'static [Constant] = &[Constant::Pi, Constant::Euler, Constant::Sqrt2,
+        Constant::Sqrt3, Constant::PiTimes2, Constant::SqrtPi, Constant::Pi2];
+
     pub fn value(&self) -> Float {
         match self {
             Constant::Pi => float::consts::PI,
@@ -0,0 +1,88 @@
+#![cfg(feature = "derive")]
+use std::any::Any;
+use dymex::*;
+
+
+#[derive(Clone, DynFields)]
+struct Spectrum {
+    x: Vec<Float>,
+    y: Vec<Float>,
+    #[dymex(rename = "t")]
+    temperature: Float,
+    #[dymex(skip)]
+    #[allow(dead_code)]
+    label: String,
+}
+
+impl DynMath for Spectrum {
+    fn clone_boxed(&self) -> Box<dyn DynMath> { Box::new(self.clone()) }
+    fn category(&self) -> Category { Category::Unqiue }
+    fn shape(&self) -> [usize; MAXDIM] { [0; MAXDIM] }
+    fn type_name(&self) -> &'static str { "Spectrum" }
+    fn as_any(&self) -> &dyn Any { self }
+    fn get_field(&self, field_name: &str) -> Result<Box<dyn DynMath>, EvaluationError> {
+        self.dyn_field(field_name)
+    }
+}
+
+fn spectrum() -> Spectrum {
+    Spectrum {
+        x: vec![1.0, 2.0, 3.0, 4.0],
+        y: vec![0.5, 2.0, 1.0, 0.5],
+        temperature: 300.0,
+        label: "sample".into(),
+    }
+}
+
+
+#[test]
+fn test_field_names() {
+    assert_eq!(spectrum().field_names(), &["x", "y", "t"]);
+}
+
+#[test]
+fn test_scalar_field() {
+    let expression = "s.t / 100 + a";
+    let mut variables = InputVars::new();
+    variables.insert_owned("s".to_owned(), spectrum());
+    variables.insert_owned("a".to_owned(), 1.0);
+
+    let mut evalutor = Evaluator::new(&expression, &variables.names()).unwrap();
+    let result = evalutor.evaluate(&variables).unwrap();
+    assert_eq!(result.as_number(), 4.0);
+}
+
+#[test]
+fn test_array_fields() {
+    let expression = "sum(s.x * s.y) / sum(s.y)";
+    let mut variables = InputVars::new();
+    variables.insert_owned("s".to_owned(), spectrum());
+
+    let mut evalutor = Evaluator::new(&expression, &variables.names()).unwrap();
+    let result = evalutor.evaluate(&variables).unwrap();
+    assert!(approx_eq(result.as_number(), 9.5 / 4.0));
+}
+
+#[test]
+fn test_downcast_result() {
+    let expression = "2 * s.x";
+    let mut variables = InputVars::new();
+    variables.insert_owned("s".to_owned(), spectrum());
+
+    let mut evalutor = Evaluator::new(&expression, &variables.names()).unwrap();
+    let result = evalutor.evaluate(&variables).unwrap();
+    assert!(result.is::<Vec<Float>>());
+    assert_eq!(result.downcast_ref::<Vec<Float>>().unwrap(), &vec![2.0, 4.0, 6.0, 8.0]);
+}
+
+#[test]
+fn test_invalid_field() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("s".to_owned(), spectrum());
+
+    for expression in ["s.z + 1", "s.label"] {
+        let mut evalutor = Evaluator::new(&expression, &variables.names()).unwrap();
+        let result = evalutor.evaluate(&variables);
+        assert!(matches!(result, Err(EvaluationError::InvalidField { type_name: "Spectrum", .. })));
+    }
+}
@@ -0,0 +1,61 @@
+#![cfg(feature = "derive")]
+use dymex::*;
+
+
+#[derive(DymexVars)]
+struct Detector {
+    gain: Float,
+    #[dymex(rename = "bg")]
+    background: Vec<Float>,
+}
+
+#[derive(DymexVars)]
+struct Measurement {
+    t: Float,
+    counts: Vec<Float>,
+    detector: Detector,
+    #[dymex(skip)]
+    #[allow(dead_code)]
+    comment: String,
+}
+
+fn measurement() -> Measurement {
+    Measurement {
+        t: 2.0,
+        counts: vec![10.0, 20.0, 30.0],
+        detector: Detector { gain: 0.5, background: vec![1.0, 2.0, 3.0] },
+        comment: "calibration run".into(),
+    }
+}
+
+
+#[test]
+fn test_variable_names() {
+    let variables = measurement().to_input_vars();
+    let mut names = variables.names();
+    names.sort();
+    assert_eq!(names, vec!["counts", "detector.bg", "detector.gain", "t"]);
+}
+
+#[test]
+fn test_dotted_paths() {
+    let expression = "sum((counts - detector.bg) * detector.gain) / t";
+    let variables = InputVars::from_vars(&measurement());
+
+    let mut evalutor = Evaluator::new(&expression, &variables.names()).unwrap();
+    let result = evalutor.evaluate(&variables).unwrap();
+    assert_eq!(result.as_number(), 13.5);
+}
+
+#[test]
+fn test_missing_path() {
+    let expression = "detector.offset + t";
+    let variables = measurement().to_input_vars();
+
+    let result = Evaluator::new(&expression, &variables.names());
+    assert!(matches!(result, Err(DymexError::ParsingError(ParsingError::UndefinedVariable(..)))));
+
+    let mut evalutor = Evaluator::with_policy(&expression, &variables.names(), VariablePolicy::Permissive).unwrap();
+    let result = evalutor.evaluate(&variables);
+    assert!(matches!(result, Err(EvaluationError::MissingInputVariable { .. })));
+}
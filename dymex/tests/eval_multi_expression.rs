@@ -28,3 +28,102 @@ fn multi_expression_scalar() {
     let result = evalutor.evaluate( &variables).unwrap();
     assert_eq!(24.0, result.as_number());
 }
+
+#[test]
+fn multi_expression_comments() {
+    let expression = "# saved formula\nx = a*b+c  # x = 5\n/* y = 0\n*/ y = 2*c-b/a\n(x*3 + y*2) - 2 /* result */";
+
+    let mut variables= InputVars::new();
+    variables.insert_owned("a".to_owned(), 2.0);
+    variables.insert_owned("b".to_owned(), 1.0);
+    variables.insert_owned("c".to_owned(), 3.0);
+
+    let mut evalutor = MultiExpEvaluator::new(&expression, &variables.names()).unwrap();
+
+    let result = evalutor.evaluate( &variables).unwrap();
+    assert_eq!(24.0, result.as_number());
+}
+
+#[test]
+fn multi_expression_outputs() {
+    let expression = "centered = v - avg(v); out mean = avg(v); out spread = std(v)\nout peak = max(abs(centered))";
+
+    let mut variables = InputVars::new();
+    variables.insert_owned("v".to_owned(), vec![1.0, 2.0, 6.0]);
+
+    let mut evalutor = MultiExpEvaluator::new(&expression, &variables.names()).unwrap();
+    assert_eq!(evalutor.outputs(), ["mean", "spread", "peak"]);
+
+    let outputs = evalutor.evaluate_outputs(&variables).unwrap();
+    assert_eq!(outputs.len(), 3);
+    assert_eq!(outputs["mean"].as_number(), 3.0);
+    assert_eq!(outputs["peak"].as_number(), 3.0);
+    assert!(outputs.contains_key("spread"));
+
+    // without a final expression, the result is the tuple of the outputs
+    let result = evalutor.evaluate(&variables).unwrap();
+    let tuple = result.downcast_ref::<Tuple>().unwrap();
+    assert_eq!(tuple.field("mean").unwrap().as_number(), 3.0);
+    assert_eq!(tuple.get(2).unwrap().as_number(), 3.0);
+
+    // outputs can be used by the later statements, `;` in strings does not separate statements
+    let expression = "out lo = min(v); out hi = max(v)\nformat(\"{}; {}\", lo, hi)";
+    let mut evalutor = MultiExpEvaluator::new(&expression, &variables.names()).unwrap();
+    let result = evalutor.evaluate(&variables).unwrap();
+    assert_eq!(result.downcast_ref::<String>().map(String::as_str), Some("1; 6"));
+
+    // a variable named `out`
+    let mut evalutor = MultiExpEvaluator::new("out = 2 * v[0]; outer = out + 1; outer", &variables.names()).unwrap();
+    assert!(evalutor.outputs().is_empty());
+    assert_eq!(evalutor.evaluate(&variables).unwrap().as_number(), 3.0);
+
+    assert!(MultiExpEvaluator::new("out avg(v)", &variables.names()).is_err());
+}
+
+#[test]
+fn multi_expression_dead_code() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("v".to_owned(), vec![1.0, 2.0, 6.0]);
+
+    // `unused` and the first `m` are never read, `debug` is not the final result
+    let expression = "m = v * \"a\"\nunused = sum(v)\nm = max(v)\ndebug = m * 2\nd = m - min(v)\nd / 2";
+    let mut evalutor = MultiExpEvaluator::new(expression, &variables.names()).unwrap();
+    assert_eq!(evalutor.statement_count(), 3);
+    assert_eq!(evalutor.evaluate(&variables).unwrap().as_number(), 2.5);
+
+    // the statements used by the outputs are kept
+    let expression = "s = sum(v); t = s * 2; out total = s; avg(v)";
+    let mut evalutor = MultiExpEvaluator::new(expression, &variables.names()).unwrap();
+    assert_eq!(evalutor.statement_count(), 3);
+    assert_eq!(evalutor.evaluate_outputs(&variables).unwrap()["total"].as_number(), 9.0);
+
+    // the assignments of the variables with a declared range are checked
+    let expression = "s = sum(v); t = s * 2; avg(v)";
+    let mut evalutor = MultiExpEvaluator::new(expression, &variables.names()).unwrap();
+    assert_eq!(evalutor.statement_count(), 1);
+    evalutor.declare_range("t", 0.0, 10.0);
+    assert_eq!(evalutor.statement_count(), 3);
+    assert!(matches!(evalutor.evaluate(&variables), Err(EvaluationError::OutOfBounds(v)) if v.variable == "t"));
+}
+
+#[test]
+fn multi_expression_nested_equals() {
+    // only the `=` outside of parentheses is an assignment
+    let expression = "y = with(t = a * 2, t + 1)\nm = avg(v, axis = 0)\nsame = y == 5; (same, a >= 2, y != m, y + m)";
+
+    let mut variables = InputVars::new();
+    variables.insert_owned("a".to_owned(), 2.0);
+    variables.insert_owned("v".to_owned(), vec![1.0, 2.0, 6.0]);
+
+    let mut evalutor = MultiExpEvaluator::new(&expression, &variables.names()).unwrap();
+    let result = evalutor.evaluate(&variables).unwrap();
+    let tuple = result.downcast_ref::<Tuple>().unwrap();
+    assert_eq!(tuple.get(0).unwrap().downcast_ref::<bool>(), Some(&true));
+    assert_eq!(tuple.get(1).unwrap().downcast_ref::<bool>(), Some(&true));
+    assert_eq!(tuple.get(2).unwrap().downcast_ref::<bool>(), Some(&true));
+    assert_eq!(tuple.get(3).unwrap().as_number(), 8.0);
+
+    // a final expression with named arguments only
+    let mut evalutor = MultiExpEvaluator::new("with(t = a, t * 3)", &variables.names()).unwrap();
+    assert_eq!(evalutor.evaluate(&variables).unwrap().as_number(), 6.0);
+}
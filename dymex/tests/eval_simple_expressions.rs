@@ -1,4 +1,5 @@
 use dymex::*;
+use std::time::{Duration, Instant};
 
 
 #[test]
@@ -86,4 +87,772 @@ fn nested_expression2() {
 
     let result = evalutor.evaluate( &variables).unwrap();
     assert!(approx_eq(result.as_number(), 0.0));
-}
\ No newline at end of file
+}
+
+#[test]
+fn time_functions() {
+    let expression = "(now() - t) / hours(1) + days(1) / hours(24)";
+    let mut variables = InputVars::new();
+    let t = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap();
+    variables.insert_owned("t".into(), t.as_secs_f64() - 7200.0);
+
+    let mut evalutor = Evaluator::new(&expression, &variables.names()).unwrap();
+
+    let result = evalutor.evaluate( &variables).unwrap();
+    assert!((result.as_number() - 3.0).abs() < 0.01);
+}
+
+#[test]
+fn case_sensitivity() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("Max".into(), 2.0);
+    variables.insert_owned("x".into(), 3.0);
+
+    // by default only the functions and constants are case insensitive
+    let mut evalutor = Evaluator::new("MAX(`Max`, x) + SIN(0) + PI", &variables.names()).unwrap();
+    let result = evalutor.evaluate( &variables).unwrap();
+    assert!(approx_eq(result.as_number(), 3.0 + std::f64::consts::PI));
+    assert!(Evaluator::new("max(X)", &variables.names()).is_err());
+
+    let config = TokenizerConfig { case: Case::Sensitive, ..Default::default() };
+    let mut evalutor = Evaluator::with_config("max(Max, x)", &variables.names(), VariablePolicy::Strict, config).unwrap();
+    let result = evalutor.evaluate( &variables).unwrap();
+    assert_eq!(result.as_number(), 3.0);
+    assert!(Evaluator::with_config("SIN(x)", &variables.names(), VariablePolicy::Strict, config).is_err());
+
+    let config = TokenizerConfig { case: Case::Insensitive, ..Default::default() };
+    let mut evalutor = Evaluator::with_config("SIN(X) + PI", &variables.names(), VariablePolicy::Strict, config).unwrap();
+    let result = evalutor.evaluate( &variables).unwrap();
+    assert!(approx_eq(result.as_number(), (3.0 as Float).sin() + std::f64::consts::PI));
+}
+
+#[test]
+fn escaped_variables() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("e".into(), 2.0);
+    variables.insert_owned("min".into(), 3.0);
+
+    let mut evalutor = Evaluator::new("`e` * `min` + min(`min`, 1)", &variables.names()).unwrap();
+    let result = evalutor.evaluate( &variables).unwrap();
+    assert_eq!(result.as_number(), 7.0);
+}
+
+#[test]
+fn every_function_dispatches() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("x".into(), 2.0);
+    variables.insert_owned("ts".into(), TimeSeries::new(vec![0.0, 1.0, 2.0], vec![1.0, 2.0, 3.0]));
+
+    for f in Function::ALL {
+        if cfg!(not(feature = "polyfit")) && *f == Function::Polyfit {
+            continue;
+        }
+        let expression = match (f.lambda_arity(1), f.max_args()) {
+            (Some(1), _) => format!("{}(ts.values, v -> v * x)", f.name()),
+            (Some(_), _) => format!("{}(ts.values, (a, b) -> a + b, x)", f.name()),
+            (None, 4) if *f == Function::Solve => format!("{}(y -> y * x, 1, 0, 5)", f.name()),
+            (None, _) if *f == Function::With => format!("{}(t = x, t * 2)", f.name()),
+            (None, _) if *f == Function::Format => format!("{}(\"x = {{}}\", x)", f.name()),
+            (None, _) if f.min_args() == 2 && f.max_args() > 2 => format!("{}(x, 1)", f.name()),
+            (None, 0) => format!("{}()", f.name()),
+            (None, 2) if *f == Function::Find => format!("{}(ts.values, 2)", f.name()),
+            (None, 2) if *f == Function::Polyval => format!("{}(ts.values, x)", f.name()),
+            (None, 2) if matches!(f, Function::Trapz | Function::Gradient) => format!("{}(ts.values, ts.t)", f.name()),
+            (None, 2) if f.param_names() == ["x", "increment"] => format!("{}(ts.values, 0.5)", f.name()),
+            (None, 2) if matches!(f.param_names(), ["a", "b"] | ["x", "n"]) => format!("{}(x, 1)", f.name()),
+            (None, 2) => format!("{}(ts, 1)", f.name()),
+            (None, 3) if *f == Function::Polyfit => format!("{}(ts.t, ts.values, 1)", f.name()),
+            (None, 3) if *f == Function::Where => format!("{}(x > 1, x, 3)", f.name()),
+            (None, 3) if *f == Function::Convert => format!("{}(x, \"eV\", \"nm\")", f.name()),
+            (None, 3) => format!("{}(x, 1, 3)", f.name()),
+            (None, _) => format!("{}(x)", f.name()),
+        };
+        let mut evalutor = Evaluator::new(&expression, &variables.names()).unwrap();
+        let result = evalutor.evaluate(&variables);
+        assert!(result.is_ok(), "{}: {:?}", expression, result.err());
+    }
+}
+
+#[test]
+fn logarithms() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("x".into(), 100.0);
+
+    for (expression, expected) in [("ln(x)", (100.0 as Float).ln()), ("log(x)", (100.0 as Float).log2()), ("log2(x)", (100.0 as Float).log2()),
+        ("log10(x)", 2.0), ("range(x, 1, 3)", 99.0), ("abs(-x)", 100.0)] {
+        let mut evalutor = Evaluator::new(expression, &variables.names()).unwrap();
+        let result = evalutor.evaluate(&variables).unwrap();
+        assert!(approx_eq(result.as_number(), expected), "{}", expression);
+    }
+}
+
+#[test]
+fn caret_operator() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("x".into(), 5.0);
+
+    let mut evalutor = Evaluator::new("x^2 + 1", &variables.names()).unwrap();
+    assert_eq!(evalutor.evaluate(&variables).unwrap().as_number(), 26.0);
+
+    let config = TokenizerConfig { caret: Caret::Xor, ..Default::default() };
+    let mut evalutor = Evaluator::with_config("x^2 + 1", &variables.names(), VariablePolicy::Strict, config).unwrap();
+    assert_eq!(evalutor.evaluate(&variables).unwrap().as_number(), 6.0);
+
+    let mut evalutor = Evaluator::with_config("x^0.5", &variables.names(), VariablePolicy::Strict, config).unwrap();
+    assert!(matches!(evalutor.evaluate(&variables), Err(EvaluationError::InvalidOperation { .. })));
+}
+
+#[test]
+fn single_atom() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("x".into(), 2.5);
+    for (expression, expected) in [("-3", -3.0), ("x", 2.5), ("pi", std::f64::consts::PI)] {
+        let mut evalutor = Evaluator::new(expression, &variables.names()).unwrap();
+        assert!(approx_eq(evalutor.evaluate(&variables).unwrap().as_number(), expected));
+    }
+}
+
+#[test]
+fn local_variables() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("x".into(), 2.0);
+    variables.insert_owned("v".into(), vec![1.0, 2.0]);
+
+    for (expression, expected) in [("with(t = x * 2, sin(t) + t)", (4.0 as Float).sin() + 4.0),
+        ("with(a = x, b = a * a, a + b)", 6.0), ("with(x = x + 1, x * 10) + x", 32.0),
+        ("with(t = 3, t)", 3.0), ("with(t = x, with(t = t * 5, t) + t)", 12.0),
+        ("with(k = 10, sum(map(v, y -> y * k)))", 30.0)] {
+        let mut evalutor = Evaluator::new(expression, &variables.names()).unwrap();
+        let result = evalutor.evaluate(&variables).unwrap();
+        assert!(approx_eq(result.as_number(), expected), "{}", expression);
+    }
+    // the local variables are only visible in `with()`
+    assert!(Evaluator::new("with(t = x, t) + t", &variables.names()).is_err());
+}
+
+#[test]
+fn logical_operators() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("x".into(), 0.0);
+    variables.insert_owned("y".into(), 0.5);
+
+    for (expression, expected) in [("x != 0 and 1 / x > 2", false), ("y != 0 and 1 / y > 1", true),
+        ("x == 0 or 1 / x > 2", true), ("x < y and y <= 1 or x > 1", true), ("y ≥ 1 or x ≠ 0", false)] {
+        let mut evalutor = Evaluator::new(expression, &variables.names()).unwrap();
+        let result = evalutor.evaluate(&variables).unwrap();
+        assert_eq!(result.downcast_ref::<bool>(), Some(&expected), "{}", expression);
+    }
+
+    // `xor` of non-integers fails, so the rhs must not be evaluated when the lhs decides the result
+    let config = TokenizerConfig { caret: Caret::Xor, ..Default::default() };
+    for (expression, expected) in [("x > 0 and (1 / x ^ 1) > 0", Some(false)), ("x == 0 or (1 / x ^ 1) > 0", Some(true)),
+        ("y > 0 and (1 / y ^ 1) > 0", Some(true)), ("y > 0 and (1 / x ^ 1) > 0", None)] {
+        let mut evalutor = Evaluator::with_config(expression, &variables.names(), VariablePolicy::Strict, config).unwrap();
+        let result = evalutor.evaluate(&variables);
+        assert_eq!(result.ok().and_then(|r| r.downcast_ref::<bool>().copied()), expected, "{}", expression);
+    }
+
+    let mut evalutor = Evaluator::new("(x == 0 and y == 0.5) + 1", &variables.names()).unwrap();
+    assert!(evalutor.evaluate(&variables).is_err());
+}
+
+#[test]
+fn range_checks() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("x".into(), 2.0);
+    variables.insert_owned("v".into(), vec![1.0, 2.0]);
+
+    for (expression, expected) in [("between(x, 1, 3)", true), ("between(x, 2, 2)", true),
+        ("between(x, 3, 1)", false), ("between(x * 2, 0, 3)", false), ("between(hi = 5, x = x, lo = 0)", true),
+        ("between(x, 0, 10) and between(-x, -10, 0)", true), ("between(x, 0, 0 / 0)", false)] {
+        let mut evalutor = Evaluator::new(expression, &variables.names()).unwrap();
+        let result = evalutor.evaluate(&variables).unwrap();
+        assert_eq!(result.downcast_ref::<bool>(), Some(&expected), "{}", expression);
+    }
+    assert!(Evaluator::new("between(x, 1)", &variables.names()).is_err());
+    // elementwise for arrays
+    let mut evalutor = Evaluator::new("between(v, 1.5, 3)", &variables.names()).unwrap();
+    let result = evalutor.evaluate(&variables).unwrap();
+    assert_eq!(result.downcast_ref::<Vec<bool>>(), Some(&vec![false, true]));
+}
+
+#[test]
+fn missing_values() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("x".into(), 2.0);
+    variables.insert_owned("na".into(), Missing);
+    variables.insert_owned("v".into(), vec![Some(1.0), None, Some(5.0)]);
+    variables.insert_owned("w".into(), vec![1.0, 2.0, 3.0]);
+
+    let evaluate = |expression: &str, policy: MissingPolicy| {
+        let mut evalutor = Evaluator::new(expression, &variables.names()).unwrap();
+        evalutor.set_missing_policy(policy);
+        evalutor.evaluate(&variables).unwrap()
+    };
+
+    // NA propagates through the operations, unlike NaN it is not a number
+    for expression in ["na + 1", "x * na", "sin(na)", "na > 1", "avg(v)", "max(x, na)", "na and x > 0"] {
+        assert!(evaluate(expression, MissingPolicy::Propagate).is::<Missing>(), "{}", expression);
+    }
+    assert_eq!(evaluate("na and x > 5", MissingPolicy::Propagate).downcast_ref::<bool>(), Some(&false));
+    assert_eq!(evaluate("x > 0 or na > 0", MissingPolicy::Propagate).downcast_ref::<bool>(), Some(&true));
+
+    // the reductions can skip the missing values
+    for (expression, expected) in [("avg(v)", 3.0), ("sum(v * 2)", 12.0), ("max(x, na, 1)", 2.0),
+        ("min(v + w)", 2.0), ("std(v)", 2.0)] {
+        let result = evaluate(expression, MissingPolicy::Skip);
+        assert!(approx_eq(result.as_number(), expected), "{}", expression);
+    }
+    assert!(evaluate("avg(na, na)", MissingPolicy::Skip).is::<Missing>());
+
+    assert_eq!(evaluate("isna(na)", MissingPolicy::Propagate).downcast_ref::<bool>(), Some(&true));
+    assert_eq!(evaluate("isna(0 / 0)", MissingPolicy::Propagate).downcast_ref::<bool>(), Some(&false));
+    assert_eq!(evaluate("coalesce(na, na, x)", MissingPolicy::Propagate).as_number(), 2.0);
+    assert_eq!(evaluate("coalesce(x, 1)", MissingPolicy::Propagate).as_number(), 2.0);
+    let filled = evaluate("coalesce(v, w * 10)", MissingPolicy::Propagate);
+    assert_eq!(filled.downcast_ref::<Vec<Float>>(), Some(&vec![1.0, 20.0, 5.0]));
+    let gaps = evaluate("coalesce(v, na)", MissingPolicy::Propagate);
+    assert_eq!(gaps.downcast_ref::<Vec<Option<Float>>>(), Some(&vec![Some(1.0), None, Some(5.0)]));
+}
+
+#[test]
+fn masks() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("v".into(), vec![1.0, 2.0, 3.0, 2.0, 40.0, 2.0]);
+    variables.insert_owned("w".into(), vec![Some(1.0), None, Some(3.0)]);
+
+    let evaluate = |expression: &str| {
+        let mut evalutor = Evaluator::new(expression, &variables.names()).unwrap();
+        evalutor.evaluate(&variables)
+    };
+
+    for (expression, expected) in [("v[v > 2]", vec![3.0, 40.0]), ("v[v < 2 or v > 10]", vec![1.0, 40.0]),
+        ("v[1 < v and v < 3]", vec![2.0, 2.0, 2.0]), ("v[v > 100]", vec![]),
+        ("where(v > 2, 0, v)", vec![1.0, 2.0, 0.0, 2.0, 0.0, 2.0]), ("where(v == 2, v * 10, -1)", vec![-1.0, 20.0, -1.0, 20.0, -1.0, 20.0]),
+        ("where(1 > 0, v, 0)", vec![1.0, 2.0, 3.0, 2.0, 40.0, 2.0])] {
+        let result = evaluate(expression).unwrap();
+        assert_eq!(result.downcast_ref::<Vec<Float>>(), Some(&expected), "{}", expression);
+    }
+
+    // outlier removal
+    let result = evaluate("avg(v[abs(v - avg(v)) < 2 * std(v)])").unwrap();
+    assert!(approx_eq(result.as_number(), 2.0));
+
+    assert_eq!(evaluate("isna(w)").unwrap().downcast_ref::<Vec<bool>>(), Some(&vec![false, true, false]));
+    assert_eq!(evaluate("(v > 2) == (v ≥ 3)").unwrap().downcast_ref::<Vec<bool>>(), Some(&vec![true; 6]));
+    assert!(evaluate("v[v[v > 1] > 2]").is_err());
+    assert!(evaluate("where(v, 1, 0)").is_err());
+    assert!(evaluate("where(v > 1, v[v > 1], 0)").is_err());
+}
+
+#[test]
+fn indices() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("v".into(), vec![3.0, 1.0, 4.0, 1.0, 5.0, 9.0, 2.0]);
+    variables.insert_owned("nan".into(), vec![Float::NAN, 2.0, Float::NAN, -1.0]);
+    variables.insert_owned("ts".into(), TimeSeries::new(vec![10.0, 20.0, 30.0], vec![1.0, 8.0, 2.0]));
+    variables.insert_owned("x".into(), 7.0);
+
+    let evaluate = |expression: &str| {
+        let mut evalutor = Evaluator::new(expression, &variables.names()).unwrap();
+        evalutor.evaluate(&variables)
+    };
+
+    for (expression, expected) in [("argmin(v)", 1.0), ("argmax(v)", 5.0), ("find(v, 1)", 1.0), ("find(v, 2)", 6.0),
+        ("argmax(nan)", 1.0), ("argmin(nan)", 3.0), ("argmax(x)", 0.0), ("v[argmax(v)]", 9.0), ("v[0] + v[-1]", 5.0),
+        ("ts.t[argmax(ts.values)]", 20.0), ("v[find(v, 4) + 2]", 5.0)] {
+        let result = evaluate(expression).unwrap();
+        assert_eq!(result.as_number(), expected, "{}", expression);
+    }
+
+    // no such element
+    assert!(evaluate("find(v, 7)").unwrap().is::<Missing>());
+    assert!(evaluate("v[find(v, 7)]").unwrap().is::<Missing>());
+    assert!(evaluate("argmax(v[v > 10])").unwrap().is::<Missing>());
+    for expression in ["v[7]", "v[-8]", "v[0.5]", "argmax(ts)"] {
+        assert!(evaluate(expression).is_err(), "{}", expression);
+    }
+}
+
+#[test]
+fn sampled_calculus() {
+    let x: Vec<Float> = (0..=100).map(|i| i as Float / 100.0).collect();
+    let mut variables = InputVars::new();
+    variables.insert_owned("y".into(), x.iter().map(|x| x * x).collect::<Vec<Float>>());
+    variables.insert_owned("x".into(), x);
+    variables.insert_owned("irregular".into(), vec![0.0, 1.0, 3.0, 4.0]);
+
+    let evaluate = |expression: &str| {
+        let mut evalutor = Evaluator::new(expression, &variables.names()).unwrap();
+        evalutor.evaluate(&variables)
+    };
+
+    assert!((evaluate("trapz(y, x)").unwrap().as_number() - 1.0 / 3.0).abs() < 1e-4);
+    assert!(approx_eq(evaluate("trapz(x * 0 + 2, x)").unwrap().as_number(), 2.0));
+    assert!(approx_eq(evaluate("trapz(irregular, irregular)").unwrap().as_number(), 8.0));
+
+    // exact for quadratics inside, one sided at the ends
+    let gradient = evaluate("gradient(y, x)").unwrap();
+    let gradient = gradient.downcast_ref::<Vec<Float>>().unwrap();
+    assert!(approx_eq(gradient[50], 1.0));
+    assert!(approx_eq(gradient[0], 0.01));
+    let gradient = evaluate("gradient(irregular ** 2, irregular)").unwrap();
+    let expected = [1.0, 2.0, 6.0, 7.0];
+    for (g, e) in gradient.downcast_ref::<Vec<Float>>().unwrap().iter().zip(expected) {
+        assert!(approx_eq(*g, e), "{} != {}", g, e);
+    }
+
+    for expression in ["trapz(y, irregular)", "gradient(y, 1)", "gradient(irregular[irregular < 1], irregular[irregular < 1])"] {
+        assert!(evaluate(expression).is_err(), "{}", expression);
+    }
+}
+
+#[test]
+fn polynomials() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("c".into(), vec![2.0, -3.0, 1.0]);
+    variables.insert_owned("x".into(), vec![0.0, 1.0, 2.0, 3.0]);
+    variables.insert_owned("y".into(), vec![1.0, 3.0, 5.0, 7.0]);
+
+    let evaluate = |expression: &str| {
+        let mut evalutor = Evaluator::new(expression, &variables.names()).unwrap();
+        evalutor.evaluate(&variables)
+    };
+
+    assert_eq!(evaluate("polyval(c, 2)").unwrap().as_number(), 3.0);
+    assert_eq!(evaluate("polyval(c, x)").unwrap().downcast_ref::<Vec<Float>>(), Some(&vec![1.0, 0.0, 3.0, 10.0]));
+    assert_eq!(evaluate("polyval(5, x[0])").unwrap().as_number(), 5.0);
+    assert_eq!(evaluate("polyval(c[c > 5], 1)").unwrap().as_number(), 0.0);
+
+    #[cfg(feature = "polyfit")]
+    {
+        let fitted = evaluate("polyfit(x, polyval(c, x), 2)").unwrap();
+        for (a, b) in fitted.downcast_ref::<Vec<Float>>().unwrap().iter().zip([2.0, -3.0, 1.0]) {
+            assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+        }
+        let line = evaluate("polyfit(x, y, 1)").unwrap();
+        let line = line.downcast_ref::<Vec<Float>>().unwrap();
+        assert!(approx_eq(line[0], 2.0) && approx_eq(line[1], 1.0));
+        for expression in ["polyfit(x, y, 4)", "polyfit(x * 0, y, 1)", "polyfit(x, y, 1.5)", "polyfit(x, c, 1)"] {
+            assert!(evaluate(expression).is_err(), "{}", expression);
+        }
+    }
+    #[cfg(not(feature = "polyfit"))]
+    assert!(evaluate("polyfit(x, y, 1)").is_err());
+}
+
+#[test]
+fn root_finding() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("p0".into(), 2.0);
+    variables.insert_owned("threshold".into(), 50.0);
+
+    let evaluate = |expression: &str| {
+        let mut evalutor = Evaluator::new(expression, &variables.names()).unwrap();
+        evalutor.evaluate(&variables)
+    };
+
+    for (expression, expected) in [("solve(x -> x ** 3, 8, 0, 10)", 2.0), ("solve(x -> x ** 2, 2, 0, 2)", (2.0 as Float).sqrt()),
+        ("solve(x -> x ** 2, 2, -2, 0)", -(2.0 as Float).sqrt()), ("solve(x -> cos(x), 0, 0, 3)", float::consts::FRAC_PI_2),
+        ("solve(t -> p0 * exp(t / 100), threshold, 0, 1000)", 100.0 * (25.0 as Float).ln()),
+        ("solve(x -> x, 0, 0, 1)", 0.0), ("solve(x -> x, 5, 10, 0)", 5.0)] {
+        let result = evaluate(expression).unwrap();
+        assert!(approx_eq(result.as_number(), expected), "{}: {}", expression, result.as_number());
+    }
+
+    for expression in ["solve(x -> x ** 2, -1, -5, 5)", "solve(x -> x, 1, 0, 1 / 0)", "solve(x -> sqrt(x), 1, -4, 4)"] {
+        assert!(evaluate(expression).is_err(), "{}", expression);
+    }
+    assert!(Evaluator::new("solve(x ** 2, 1, 0, 5)", &["x"]).is_err());
+    assert!(Evaluator::new("solve(x -> x, 1, 0)", &[]).is_err());
+}
+
+#[test]
+fn unit_conversion() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("energy".into(), 2.0);
+    variables.insert_owned("t".into(), vec![0.0, 100.0]);
+
+    let evaluate = |expression: &str| {
+        let mut evalutor = Evaluator::new(expression, &variables.names()).unwrap();
+        evalutor.evaluate(&variables)
+    };
+
+    for (expression, expected) in [("convert(1, \"km\", \"m\")", 1000.0), ("convert(5, \"in\", \"cm\")", 12.7),
+        ("convert(energy, \"eV\", \"nm\")", 619.920_984), ("convert(619.920984, \"nm\", \"eV\")", 2.0),
+        ("convert(1, \"eV\", \"THz\")", 241.798_924), ("convert(1000, \"1/cm\", \"um\")", 10.0),
+        ("convert(-40, \"°C\", \"°F\")", -40.0), ("convert(0, \"°C\", \"K\")", 273.15),
+        ("convert(180, \"deg\", \"rad\")", float::consts::PI), ("convert(1, \"atm\", \"bar\")", 1.013_25)] {
+        let result = evaluate(expression).unwrap();
+        assert!((result.as_number() - expected).abs() < 1e-6 * expected.abs(), "{}: {}", expression, result.as_number());
+    }
+    let result = evaluate("convert(t, \"degC\", \"degF\")").unwrap();
+    assert!(result.iterate().zip([32.0, 212.0]).all(|(x, y)| approx_eq(*x, y)));
+
+    for expression in ["convert(1, \"kg\", \"m\")", "convert(1, \"m\", \"parsec\")", "convert(1, 2, 3)",
+        "convert(\"m\", \"m\", \"m\")"] {
+        assert!(evaluate(expression).is_err(), "{}", expression);
+    }
+    assert!(Evaluator::new("convert(1, \"m)", &[]).is_err());
+
+    let mut units = UnitRegistry::default();
+    units.register("furlong", "length", 201.168);
+    let mut evalutor = Evaluator::new("convert(1, \"furlong\", \"yd\")", &[]).unwrap();
+    evalutor.set_unit_registry(units);
+    assert!(approx_eq(evalutor.evaluate(&variables).unwrap().as_number(), 220.0));
+    // the registry is shared with the body of anonymous functions
+    let mut evalutor = Evaluator::new("sum(i -> convert(i, \"furlong\", \"m\"), 1, 2)", &[]).unwrap();
+    let mut units = UnitRegistry::empty();
+    units.register("furlong", "length", 201.168);
+    units.register("m", "length", 1.0);
+    evalutor.set_unit_registry(units);
+    assert!(approx_eq(evalutor.evaluate(&variables).unwrap().as_number(), 603.504));
+}
+
+#[test]
+fn rounding() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("price".into(), 2.675);
+    variables.insert_owned("v".into(), vec![0.5, 1.5, 2.5, -0.5, -2.5]);
+
+    let evaluate = |expression: &str| {
+        let mut evalutor = Evaluator::new(expression, &variables.names()).unwrap();
+        evalutor.evaluate(&variables)
+    };
+
+    for (expression, expected) in [("round_to(price, 0.01)", 2.68), ("round_to(price, 0.05)", 2.7),
+        ("floor_to(price, 0.01)", 2.67), ("ceil_to(price, 0.01)", 2.68), ("round_even(price, 0.01)", 2.68),
+        ("round_even(2.665, 0.01)", 2.66), ("floor_to(0.3, 0.1)", 0.3), ("ceil_to(0.3, 0.1)", 0.3),
+        ("round_to(1234, 100)", 1200.0), ("ceil_to(1201, 100)", 1300.0), ("floor_to(-1.5, 1)", -2.0),
+        ("round_to(-1.005, 0.01)", -1.01), ("round_to(7, 2.5)", 7.5)] {
+        let result = evaluate(expression).unwrap();
+        assert_eq!(result.as_number(), expected, "{}", expression);
+    }
+
+    for (expression, expected) in [("round_to(v, 1)", [1.0, 2.0, 3.0, -1.0, -3.0]),
+        ("round_even(v, 1)", [0.0, 2.0, 2.0, -0.0, -2.0]), ("floor_to(v, 1)", [0.0, 1.0, 2.0, -1.0, -3.0])] {
+        let result = evaluate(expression).unwrap();
+        assert_eq!(result.iterate().as_slice(), &expected, "{}", expression);
+    }
+
+    for expression in ["round_to(price, 0)", "round_to(price, -0.01)", "round_to(price, v)", "round_to(\"a\", 1)"] {
+        assert!(evaluate(expression).is_err(), "{}", expression);
+    }
+    assert!(Evaluator::new("round_to(price)", &["price"]).is_err());
+}
+
+#[test]
+fn bitwise_functions() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("status".into(), 0b1011 as Float);
+    variables.insert_owned("registers".into(), vec![0.0, 4.0, 5.0, 12.0]);
+
+    let evaluate = |expression: &str| {
+        let mut evalutor = Evaluator::new(expression, &variables.names()).unwrap();
+        evalutor.evaluate(&variables)
+    };
+
+    for (expression, expected) in [("band(status, 2)", 2.0), ("band(status, 4)", 0.0), ("bor(status, 4)", 15.0),
+        ("bxor(status, 1)", 10.0), ("shl(1, 10)", 1024.0), ("shr(status, 1)", 5.0), ("shr(-8, 1)", -4.0),
+        ("band(shr(status, 3), 1) == 1", 1.0), ("band(-1, 255)", 255.0)] {
+        let result = evaluate(expression).unwrap();
+        let value = result.downcast_ref::<bool>().map_or_else(|| result.as_number(), |b| *b as u8 as Float);
+        assert_eq!(value, expected, "{}", expression);
+    }
+
+    let result = evaluate("band(registers, 4) > 0").unwrap();
+    assert_eq!(result.downcast_ref::<Vec<bool>>(), Some(&vec![false, true, true, true]));
+    let result = evaluate("shl(1, registers)").unwrap();
+    assert_eq!(result.iterate().as_slice(), &[1.0, 16.0, 32.0, 4096.0]);
+
+    for expression in ["band(status, 0.5)", "shl(1, 64)", "shr(1, -1)", "bor(registers, isna(status))"] {
+        assert!(evaluate(expression).is_err(), "{}", expression);
+    }
+}
+
+#[test]
+fn formatted_messages() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("peak".into(), 532.123);
+    variables.insert_owned("v".into(), vec![1.0, 2.5]);
+
+    let evaluate = |expression: &str| {
+        let mut evalutor = Evaluator::new(expression, &variables.names()).unwrap();
+        evalutor.evaluate(&variables)
+    };
+
+    for (expression, expected) in [("format(\"peak at {} nm\", peak)", "peak at 532.123 nm"),
+        ("format(\"peak at {:.1} nm\", peak)", "peak at 532.1 nm"),
+        ("format(\"{} of {:.0}: {}\", v, peak * 2, peak > 500)", "[1, 2.5] of 1064: true"),
+        ("format(\"{{{}}} {}\", coalesce(isna(peak), 1), argmin(v - 9))", "{false} 0"),
+        ("format(\"no values\")", "no values"), ("format(\"{}\", \"text\")", "text"),
+        ("format(\"{}\", find(v, 3))", "NA")] {
+        let result = evaluate(expression).unwrap();
+        assert_eq!(result.downcast_ref::<String>().map(String::as_str), Some(expected), "{}", expression);
+    }
+
+    for expression in ["format(\"{} {}\", peak)", "format(\"{}\", peak, peak)", "format(\"{:x}\", peak)",
+        "format(\"{\", peak)", "format(\"}\")", "format(peak)"] {
+        assert!(evaluate(expression).is_err(), "{}", expression);
+    }
+}
+
+#[test]
+fn tuple_results() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("v".into(), vec![3.0, 1.0, 2.0]);
+
+    let evaluate = |expression: &str| {
+        let mut evalutor = Evaluator::new(expression, &variables.names()).unwrap();
+        evalutor.evaluate(&variables)
+    };
+
+    let result = evaluate("(min(v), max(v), avg(v))").unwrap();
+    let stats = result.downcast_ref::<Tuple>().unwrap();
+    assert_eq!(stats.len(), 3);
+    assert_eq!(stats.iter().map(|x| x.as_number()).collect::<Vec<Float>>(), [1.0, 3.0, 2.0]);
+    assert_eq!(result.get_field("1").unwrap().as_number(), 3.0);
+
+    let result = evaluate("(lo = min(v), hi = max(v), argmax(v), v * 2)").unwrap();
+    let stats = result.downcast_ref::<Tuple>().unwrap();
+    assert_eq!(stats.field("lo").unwrap().as_number(), 1.0);
+    assert_eq!(stats.field("hi").unwrap().as_number(), 3.0);
+    assert_eq!(stats.get(2).unwrap().as_number(), 0.0);
+    assert_eq!(stats.get(3).unwrap().iterate().as_slice(), &[6.0, 2.0, 4.0]);
+    assert_eq!(stats.names(), &[Some("lo".into()), Some("hi".into()), None, None]);
+    assert!(stats.field("mid").is_none());
+    assert!(result.get_field("4").is_err());
+
+    // deep copies of results
+    let copy = result.clone();
+    drop(result);
+    assert_eq!(copy.get_field("hi").unwrap().as_number(), 3.0);
+    let kept: Vec<Box<dyn DynMath>> = vec![copy, evaluate("v").unwrap()];
+    let copies = kept.clone();
+    assert_eq!(copies[1].iterate().as_slice(), &[3.0, 1.0, 2.0]);
+    assert_eq!(copies[0].to_string(), kept[0].to_string());
+
+    // elements of a tuple result, and parentheses without a comma are not tuples
+    assert_eq!(evaluate("(lo = min(v), hi = max(v)).hi - (1, 2).0").unwrap().as_number(), 2.0);
+    assert_eq!(evaluate("(v[0])").unwrap().as_number(), 3.0);
+    assert!(evaluate("(1, 2) + 1").is_err());
+    assert!(Evaluator::new("(a = 1, a = 2)", &[]).is_err());
+    assert!(Evaluator::new("(1, 2", &[]).is_err());
+}
+
+#[test]
+fn memory_limit() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("v".into(), vec![1.0; 1000]);
+
+    // three intermediate arrays of 1000 numbers
+    let mut evalutor = Evaluator::new("sum(v * 2 + v * 3)", &variables.names()).unwrap();
+    assert_eq!(evalutor.evaluate(&variables).unwrap().as_number(), 5000.0);
+
+    evalutor.set_memory_limit(Some(30_000));
+    assert_eq!(evalutor.evaluate(&variables).unwrap().as_number(), 5000.0);
+    evalutor.set_memory_limit(Some(20_000));
+    assert!(matches!(evalutor.evaluate(&variables), Err(EvaluationError::MemoryLimitExceeded { limit: 20_000 })));
+    // the limit is per evaluation
+    evalutor.set_memory_limit(Some(30_000));
+    assert!(evalutor.evaluate(&variables).is_ok());
+    assert!(evalutor.evaluate(&variables).is_ok());
+    evalutor.set_memory_limit(None);
+    assert!(evalutor.evaluate(&variables).is_ok());
+
+    // the bodies of anonymous functions have the same limit
+    let mut evalutor = Evaluator::new("sum(map(v, x -> sum(v * x)))", &variables.names()).unwrap();
+    evalutor.set_memory_limit(Some(4_000));
+    assert!(matches!(evalutor.evaluate(&variables), Err(EvaluationError::MemoryLimitExceeded { .. })));
+    evalutor.set_memory_limit(Some(10_000));
+    assert_eq!(evalutor.evaluate(&variables).unwrap().as_number(), 1e6);
+}
+
+#[test]
+fn timeout_and_cancellation() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("v".into(), vec![1.0; 3000]);
+
+    // millions of calls of the inner anonymous function
+    let expression = "sum(map(v, x -> sum(map(v, y -> x * y))))";
+    let mut evalutor = Evaluator::new(expression, &variables.names()).unwrap();
+    evalutor.set_timeout(Some(Duration::from_millis(20)));
+    let start = Instant::now();
+    assert!(matches!(evalutor.evaluate(&variables), Err(EvaluationError::TimedOut { .. })));
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    // the body is a single value, checked before every call
+    let mut evalutor = Evaluator::new("sum(i -> i, 1, 1e7)", &[]).unwrap();
+    evalutor.set_timeout(Some(Duration::from_millis(20)));
+    let start = Instant::now();
+    assert!(matches!(evalutor.evaluate(&variables), Err(EvaluationError::TimedOut { .. })));
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    let mut evalutor = Evaluator::new("sum(v * 2)", &variables.names()).unwrap();
+    evalutor.set_timeout(Some(Duration::from_secs(10)));
+    assert_eq!(evalutor.evaluate(&variables).unwrap().as_number(), 6000.0);
+
+    let handle = CancelHandle::new();
+    evalutor.set_cancel_handle(Some(handle.clone()));
+    handle.cancel();
+    assert!(matches!(evalutor.evaluate(&variables), Err(EvaluationError::Cancelled)));
+    handle.reset();
+    assert!(evalutor.evaluate(&variables).is_ok());
+
+    // cancelled from another thread
+    let mut evalutor = Evaluator::new(expression, &variables.names()).unwrap();
+    evalutor.set_cancel_handle(Some(handle.clone()));
+    let canceller = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(20));
+        handle.cancel();
+    });
+    assert!(matches!(evalutor.evaluate(&variables), Err(EvaluationError::Cancelled)));
+    canceller.join().unwrap();
+}
+
+#[test]
+fn result_formatting() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("x".into(), 1234567.891);
+    variables.insert_owned("v".into(), vec![0.5, 2.0, 1e-7]);
+    let mut eval = |expr: &str| Evaluator::new(expr, &variables.names()).unwrap().evaluate(&variables).unwrap();
+
+    let plain = ResultFormatter::new();
+    assert_eq!(plain.format(&*eval("x")), "1234567.891");
+    assert_eq!(plain.format(&*eval("v")), "[0.5, 2, 1e-7]");
+    assert_eq!(plain.format(&*eval("(lo = min(v), hi = max(v), x > 1)")), "(lo = 1e-7, hi = 2, true)");
+    assert_eq!(plain.format(&*eval("1 / 0")), "inf");
+
+    let formatter = ResultFormatter::new().with_significant_digits(4).with_thousands_separator(',');
+    assert_eq!(formatter.format(&*eval("x")), "1.235e6");
+    assert_eq!(formatter.format(&*eval("x / 1000")), "1,235");
+    assert_eq!(formatter.format(&*eval("-x / 1000")), "-1,235");
+    assert_eq!(ResultFormatter::new().with_thousands_separator(' ').format(&*eval("-x")), "-1 234 567.891");
+    assert_eq!(formatter.format(&*eval("9.9996")), "10.00");
+    assert_eq!(formatter.format(&*eval("v")), "[0.5000, 2.000, 1.000e-7]");
+
+    let fixed = ResultFormatter::new().with_notation(Notation::Fixed).with_significant_digits(3);
+    assert_eq!(fixed.format(&*eval("x")), "1230000");
+    assert_eq!(fixed.format(&*eval("pi / 1000")), "0.00314");
+    let scientific = ResultFormatter::new().with_notation(Notation::Scientific);
+    assert_eq!(scientific.format(&*eval("x")), "1.234567891e6");
+
+    let units = ResultFormatter::new()
+        .with_significant_digits(3)
+        .with_unit_suffix(|x| (!matches!(x.category(), Category::Unqiue)).then(|| "nm".to_string()));
+    assert_eq!(format!("{}", units.display(&*eval("x / 1e4"))), "123 nm");
+    assert_eq!(units.format(&*eval("(x / 1e4, \"a\")")), "(123 nm, a)");
+}
+
+#[test]
+fn series() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("n".into(), 1000.0);
+    variables.insert_owned("x".into(), 0.5);
+
+    for (expression, expected) in [("sum(i -> 1 / i ** 2, 1, n)", 1.6439345666815615),
+        ("prod(k -> k, 1, 5)", 120.0), ("sum(k -> x ** k / prod(j -> j, 1, k), 0, 20)", (0.5 as Float).exp()),
+        ("sum(i -> i, 5, 1)", 0.0), ("prod(i -> i, 1, 0)", 1.0), ("prod(2, 3, x)", 3.0)] {
+        let mut evalutor = Evaluator::new(expression, &variables.names()).unwrap();
+        let result = evalutor.evaluate(&variables).unwrap();
+        assert!(approx_eq(result.as_number(), expected), "{}: {}", expression, result.as_number());
+    }
+    let mut evalutor = Evaluator::new("sum(i -> i, 0, 1e9)", &variables.names()).unwrap();
+    assert!(matches!(evalutor.evaluate(&variables), Err(EvaluationError::InvalidArguments { .. })));
+}
+
+#[test]
+fn subexpressions() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("a".into(), 2.0);
+    variables.insert_owned("v".into(), vec![1.0, 2.0, 3.0]);
+    let expression = "y = a * (max(v) - 1) + sum(v)";
+    let ast = AST::from_expression(expression).unwrap();
+    assert_eq!(ast.subexpression(16).unwrap().to_expression(), "max(v) - 1");
+    assert_eq!(ast.subexpression(9).unwrap().to_expression(), "max(v)");
+    assert!(ast.subexpression(8).is_none());
+
+    // the innermost node at the cursor
+    assert_eq!(ast.node_at(13), Some(13));
+    assert_eq!(ast.node_at(17), Some(16));
+    assert_eq!(ast.node_at(6), Some(6));
+    assert_eq!(ast.node_at(7), Some(6));
+    assert_eq!(ast.node_at(40), None);
+
+    let evaluator = Evaluator::new(expression, &variables.names()).unwrap();
+    let node = ast.node_at(15).unwrap();
+    assert_eq!(evaluator.evaluate_node(node, &variables).unwrap().as_number(), 2.0);
+    assert_eq!(evaluator.evaluate_node(6, &variables).unwrap().as_number(), 4.0);
+    assert_eq!(evaluator.evaluate_node(27, &variables).unwrap().iterate().as_slice(), &[1.0, 2.0, 3.0]);
+    assert!(matches!(evaluator.evaluate_node(3, &variables), Err(EvaluationError::UnknownNode { id: 3 })));
+
+    // variables bound inside the expression are not defined in the subexpression
+    let evaluator = Evaluator::new("map(v, x -> x * a)", &variables.names()).unwrap();
+    assert!(evaluator.evaluate_node(14, &variables).is_err());
+}
+
+#[test]
+fn watched_subexpressions() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("x".into(), 3.0);
+    variables.insert_owned("v".into(), vec![1.0, 2.0]);
+    let expression = "with(t = x * 2, s = sum(v), t / s + 1)";
+    let mut evaluator = Evaluator::new(expression, &variables.names()).unwrap();
+    evaluator.watch_binding("t").unwrap();
+    evaluator.watch_binding("s").unwrap();
+    evaluator.watch(30, "ratio").unwrap();
+    evaluator.watch(0, "result").unwrap();
+    evaluator.watch(9, "x").unwrap();
+    assert!(evaluator.watch_binding("u").is_err());
+    assert!(matches!(evaluator.watch(100, "?"), Err(EvaluationError::UnknownNode { id: 100 })));
+
+    assert_eq!(evaluator.evaluate(&variables).unwrap().as_number(), 3.0);
+    let values: Vec<(&str, Float)> = evaluator.watched().iter()
+        .map(|w| (w.label.as_str(), w.value.as_ref().unwrap().as_number())).collect();
+    assert_eq!(values, [("t", 6.0), ("s", 3.0), ("ratio", 2.0), ("result", 3.0), ("x", 3.0)]);
+
+    // the values computed before a failure are kept, the skipped ones are not evaluated
+    let mut evaluator = Evaluator::new("x > 5 and max(v) > 1 or sum(v * \"a\") > 0", &variables.names()).unwrap();
+    evaluator.watch(10, "max").unwrap();
+    evaluator.watch(2, "check").unwrap();
+    assert!(evaluator.evaluate(&variables).is_err());
+    assert!(evaluator.watched()[0].value.is_none());
+    assert_eq!(evaluator.watched()[1].value.as_ref().unwrap().downcast_ref::<bool>(), Some(&false));
+    assert_eq!(format!("{:?}", evaluator.watched()[0]), "max = (not evaluated)");
+}
+
+#[test]
+fn constant_expressions() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("x".into(), 3.0);
+    for (expression, constant) in [("2 * pi + 1", true), ("with(t = 2, t ** 10)", true), ("sum(i -> i, 1, 4)", true),
+        ("x + 1", false), ("with(t = x, t)", false), ("map(v, y -> y * 2)", false), ("now() - 1", false)] {
+        let mut evaluator = Evaluator::with_policy(expression, &variables.names(), VariablePolicy::Permissive).unwrap();
+        assert_eq!(evaluator.is_constant(), constant, "{}", expression);
+        assert_eq!(evaluator.constant_value().is_some(), constant, "{}", expression);
+    }
+
+    let mut evaluator = Evaluator::new("sum(i -> i, 1, 4) / 2", &[]).unwrap();
+    assert_eq!(evaluator.constant_value().unwrap().as_number(), 5.0);
+    assert_eq!(evaluator.evaluate(&variables).unwrap().as_number(), 5.0);
+
+    // failures are reported by `evaluate()`
+    let mut evaluator = Evaluator::new("sum(i -> i, 0, 1e9)", &[]).unwrap();
+    assert!(evaluator.is_constant());
+    assert!(evaluator.constant_value().is_none());
+    assert!(evaluator.evaluate(&variables).is_err());
+
+    // computed by the first evaluation, under the limits set by then
+    let mut evaluator = Evaluator::new("2 * pi + 1", &[]).unwrap();
+    let handle = CancelHandle::new();
+    handle.cancel();
+    evaluator.set_cancel_handle(Some(handle.clone()));
+    assert!(evaluator.constant_value().is_none());
+    assert!(matches!(evaluator.evaluate(&variables), Err(EvaluationError::Cancelled)));
+    handle.reset();
+    assert_eq!(evaluator.constant_value().unwrap().as_number(), 2.0 * float::consts::PI + 1.0);
+    // and again after the settings change
+    evaluator.set_empty_reduction(EmptyReduction::NaN);
+    handle.cancel();
+    assert!(evaluator.constant_value().is_none());
+}
@@ -0,0 +1,69 @@
+use dymex::*;
+
+
+fn series(t: &[Float], values: &[Float]) -> TimeSeries {
+    TimeSeries::new(t.to_vec(), values.to_vec())
+}
+
+#[test]
+fn test_alignment() {
+    let expression = "shift(a, 1) + 2 * b";
+    let mut variables = InputVars::new();
+    variables.insert_owned("a".to_owned(), series(&[0.0, 1.0, 2.0, 3.0], &[1.0, 2.0, 3.0, 4.0]));
+    variables.insert_owned("b".to_owned(), series(&[1.0, 2.0, 4.0], &[1.0, 1.0, 1.0]));
+
+    let mut evalutor = Evaluator::new(&expression, &variables.names()).unwrap();
+    let result = evalutor.evaluate(&variables).unwrap();
+    let ts = result.downcast_ref::<TimeSeries>().unwrap();
+    assert_eq!(ts.timestamps(), &[1.0, 2.0]);
+    assert_eq!(ts.values(), &[3.0, 4.0]);
+}
+
+#[test]
+fn test_shift_bounds() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("a".to_owned(), series(&[0.0, 1.0, 2.0], &[1.0, 2.0, 3.0]));
+    for expression in ["shift(a, -1e300)", "shift(a, 1e300)"] {
+        let result = Evaluator::new(expression, &variables.names()).unwrap().evaluate(&variables).unwrap();
+        assert!(result.downcast_ref::<TimeSeries>().unwrap().values().iter().all(|x| x.is_nan()), "{}", expression);
+    }
+    for expression in ["shift(a, 0 / 0)", "shift(a, 1 / 0)"] {
+        let result = Evaluator::new(expression, &variables.names()).unwrap().evaluate(&variables);
+        assert!(matches!(result, Err(EvaluationError::InvalidArguments { .. })), "{}", expression);
+    }
+}
+
+#[test]
+fn test_resample() {
+    let expression = "avg(resample(a, 0.5)) + 1 / max(a)";
+    let mut variables = InputVars::new();
+    variables.insert_owned("a".to_owned(), series(&[0.0, 1.0], &[1.0, 4.0]));
+
+    let mut evalutor = Evaluator::new(&expression, &variables.names()).unwrap();
+    let result = evalutor.evaluate(&variables).unwrap();
+    assert_eq!(result.as_number(), 2.75);
+}
+
+#[test]
+fn test_array_operand() {
+    let expression = "a + v";
+    let mut variables = InputVars::new();
+    variables.insert_owned("a".to_owned(), series(&[0.0, 1.0], &[1.0, 4.0]));
+    variables.insert_owned("v".to_owned(), vec![1.0, 2.0]);
+
+    let mut evalutor = Evaluator::new(&expression, &variables.names()).unwrap();
+    let result = evalutor.evaluate(&variables);
+    assert!(matches!(result, Err(EvaluationError::InvalidBinaryOperation { .. })));
+}
+
+#[test]
+fn test_named_arguments() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("a".to_owned(), series(&[0.0, 1.0], &[1.0, 4.0]));
+
+    for expression in ["avg(resample(a, dt = 0.5))", "avg(resample(dt = 0.5, series = a))"] {
+        let mut evalutor = Evaluator::new(&expression, &variables.names()).unwrap();
+        let result = evalutor.evaluate(&variables).unwrap();
+        assert_eq!(result.as_number(), 2.5);
+    }
+}
@@ -59,4 +59,160 @@ fn test_fermi() {
         },
         _ => {panic!("Evaluation error, expted vector")}
     }
-} //TODO clean up this test
\ No newline at end of file
+} //TODO clean up this test
+
+fn eval_array(expression: &str, v: Vec<Float>) -> Vec<Float> {
+    let mut variables = InputVars::new();
+    variables.insert_owned("v".to_owned(), v);
+    let mut evalutor = Evaluator::new(&expression, &variables.names()).unwrap();
+    let result = evalutor.evaluate(&variables).unwrap();
+    result.downcast_ref::<Vec<Float>>().unwrap().clone()
+}
+
+#[test]
+fn test_unary_functions() {
+    let v = vec![0.25, 1.0, 4.0];
+    for (expression, f) in [
+        ("sin(v)", Float::sin as fn(Float) -> Float),
+        ("exp(v)", Float::exp),
+        ("log10(v)", Float::log10),
+        ("sqrt(v)", Float::sqrt),
+        ("abs(-v)", Float::abs),
+    ] {
+        let res = eval_array(expression, v.clone());
+        assert_eq!(res.len(), v.len());
+        for (x, y) in v.iter().zip(res) {
+            assert!(approx_eq(f(*x), y), "{}", expression);
+        }
+    }
+}
+
+#[test]
+fn test_unary_functions_domain() {
+    // outside of the domain the elements become NaN (or -inf), like for numbers
+    let res = eval_array("sqrt(v) + log2(v)", vec![-1.0, 0.0, 1.0]);
+    assert!(res[0].is_nan());
+    assert_eq!(res[1], Float::NEG_INFINITY);
+    assert_eq!(res[2], 1.0);
+    assert!(eval_array("sin(v)", vec![]).is_empty());
+}
+
+/// Array type without its own `elementwise()` implementation
+#[derive(Clone)]
+struct Samples(Vec<Float>);
+
+impl DynMath for Samples {
+    fn clone_boxed(&self) -> Box<dyn DynMath> { Box::new(self.clone()) }
+    fn category(&self) -> Category { Category::Array }
+    fn shape(&self) -> [usize; MAXDIM] { [self.0.len(), 0, 0] }
+    fn as_any(&self) -> &dyn std::any::Any { self }
+    fn iterate(&self) -> std::slice::Iter<'_, Float> { self.0.iter() }
+}
+
+/// Unique type without unary operations
+#[derive(Clone)]
+struct Label;
+
+impl DynMath for Label {
+    fn clone_boxed(&self) -> Box<dyn DynMath> { Box::new(self.clone()) }
+    fn category(&self) -> Category { Category::Unqiue }
+    fn shape(&self) -> [usize; MAXDIM] { [0; MAXDIM] }
+    fn type_name(&self) -> &'static str { "Label" }
+    fn as_any(&self) -> &dyn std::any::Any { self }
+}
+
+#[test]
+fn test_unary_functions_custom_types() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("s".to_owned(), Samples(vec![1.0, 4.0, 9.0]));
+    variables.insert_owned("l".to_owned(), Label);
+
+    let mut evalutor = Evaluator::new("sqrt(s)", &variables.names()).unwrap();
+    let result = evalutor.evaluate(&variables).unwrap();
+    assert_eq!(result.downcast_ref::<Vec<Float>>().unwrap(), &vec![1.0, 2.0, 3.0]);
+
+    let mut evalutor = Evaluator::new("exp(l)", &variables.names()).unwrap();
+    let result = evalutor.evaluate(&variables);
+    assert!(matches!(result, Err(EvaluationError::InvalidUnaryOperation { operand, .. }) if operand == "Label"));
+}
+
+#[test]
+fn test_display_results() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("v".to_owned(), vec![0.5, 2.0, 3.0]);
+    variables.insert_owned("l".to_owned(), Label);
+    let mut eval = |expr: &str| Evaluator::new(expr, &variables.names()).unwrap().evaluate(&variables).unwrap();
+
+    assert_eq!(eval("sum(v)").to_string(), "5.5");
+    assert_eq!(format!("{}", eval("v * 2")), "[1, 4, 6]");
+    assert_eq!(format!("{:?}", eval("v > 1")), "Bool array([false, true, true])");
+    assert_eq!(format!("{:?}", eval("max(v)")), "Number(3)");
+    assert_eq!(eval("(n = max(v), \"ok\")").to_string(), "(n = 3, ok)");
+    // host types without their own `fmt_value()`
+    assert_eq!(eval("l").to_string(), "<Label>");
+    let shared: std::rc::Rc<dyn DynMath> = std::rc::Rc::from(eval("v"));
+    assert_eq!(format!("{}", shared), "[0.5, 2, 3]");
+    // `unwrap_err()` needs `Debug` results
+    let err = Evaluator::new("exp(l)", &variables.names()).unwrap().evaluate(&variables).unwrap_err();
+    assert!(matches!(err, EvaluationError::InvalidUnaryOperation { .. }));
+
+    assert!(eval("v / 3 * 3").approx_eq(&*eval("v")));
+    assert!(eval("0.1 + 0.2").approx_eq(&0.3));
+    assert!(eval("0 / 0").approx_eq(&Float::NAN));
+    assert!(!eval("v").approx_eq(&*eval("v[v > 1]")));
+    assert!(!eval("sum(v)").approx_eq(&*eval("v")));
+    assert!(eval("(1 / 3, \"a\")").approx_eq(&*eval("(0.1 / 0.3, \"a\")")));
+    assert!(!eval("(a = 1, 2)").approx_eq(&*eval("(1, 2)")));
+}
+
+#[test]
+fn test_dyn_eq() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("v".to_owned(), vec![0.5, 2.0, 3.0]);
+    let mut eval = |expr: &str| Evaluator::new(expr, &variables.names()).unwrap().evaluate(&variables).unwrap();
+
+    assert!(dyn_eq(&*eval("sum(v)"), &5.5, 0.0));
+    assert!(dyn_eq(&*eval("v * 1.0001"), &vec![0.5, 2.0, 3.0], 1e-3));
+    assert!(!dyn_eq(&*eval("v * 1.01"), &vec![0.5, 2.0, 3.0], 1e-3));
+    // absolute around zero, relative for larger values
+    assert!(dyn_eq(&*eval("1e-12"), &0.0, 1e-9));
+    assert!(dyn_eq(&*eval("1e12 + 1"), &1e12, 1e-9));
+    // a number equals an array if it equals every element
+    assert!(dyn_eq(&*eval("v - v"), &0.0, 1e-12));
+    assert!(dyn_eq(&1.0, &*eval("v / v"), 1e-12));
+    assert!(!dyn_eq(&*eval("v"), &0.5, 1e-12));
+    assert!(!dyn_eq(&*eval("v"), &vec![0.5, 2.0], 1e-12));
+    assert!(dyn_eq(&*eval("0 / 0"), &Float::NAN, 0.0));
+
+    assert!(dyn_eq(&*eval("(lo = min(v), hi = max(v) / 3)"), &*eval("(lo = 0.5, hi = 1)"), 1e-12));
+    assert!(!dyn_eq(&*eval("(lo = min(v), hi = max(v))"), &*eval("(lo = 0.5, mid = 3)"), 1e-12));
+    assert!(dyn_eq(&*eval("v > 1"), &vec![false, true, true], 0.0));
+    assert!(dyn_eq(&*eval("\"a\""), &String::from("a"), 0.0));
+    assert!(!dyn_eq(&*eval("v > 1"), &*eval("v"), 1.0));
+}
+
+#[test]
+fn test_map_filter_reduce() {
+    let mut variables = InputVars::new();
+    variables.insert_owned("v".to_owned(), vec![-2.0, 1.0, 0.0, 3.0]);
+    variables.insert_owned("a".to_owned(), 10.0);
+
+    let vector = |expression: &str| {
+        let mut evaluator = Evaluator::new(expression, &variables.names()).unwrap();
+        let result = evaluator.evaluate(&variables).unwrap();
+        result.as_any().downcast_ref::<Vec<Float>>().unwrap().clone()
+    };
+    assert_eq!(vector("map(v, x -> x * x)"), vec![4.0, 1.0, 0.0, 9.0]);
+    assert_eq!(vector("map(v, x -> x + a)"), vec![8.0, 11.0, 10.0, 13.0]);
+    assert_eq!(vector("filter(v, x -> abs(x) - 1)"), vec![-2.0, 0.0, 3.0]);
+    assert_eq!(vector("map(filter(v, x -> x), x -> x * a)"), vec![-20.0, 10.0, 30.0]);
+    assert_eq!(vector("map(v, x -> sum(map(v, y -> x * y)))"), vec![-4.0, 2.0, 0.0, 6.0]);
+
+    let mut evaluator = Evaluator::new("reduce(v, (acc, x) -> acc * a + x, 0)", &variables.names()).unwrap();
+    assert_eq!(evaluator.evaluate(&variables).unwrap().as_number(), -1897.0);
+    let mut evaluator = Evaluator::new("reduce(v, (a, b) -> max(a, b), -100) + a", &variables.names()).unwrap();
+    assert_eq!(evaluator.evaluate(&variables).unwrap().as_number(), 13.0);
+
+    let mut evaluator = Evaluator::new("map(v, x -> v)", &variables.names()).unwrap();
+    assert!(matches!(evaluator.evaluate(&variables), Err(EvaluationError::InvalidArguments { .. })));
+}
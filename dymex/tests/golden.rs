@@ -0,0 +1,11 @@
+use dymex::testing::run_corpus;
+
+
+#[test]
+fn golden_expressions() {
+    let failures = run_corpus(include_str!("golden/expressions.csv")).unwrap();
+    for failure in &failures {
+        eprintln!("{}", failure);
+    }
+    assert!(failures.is_empty(), "{} golden case(s) failed", failures.len());
+}
@@ -0,0 +1,67 @@
+use dymex::*;
+use dymex::testing::ExprGenerator;
+
+
+const CASES: u64 = 500;
+
+fn inputs() -> InputVars {
+    let mut variables = InputVars::new();
+    variables.insert_owned("x".to_owned(), 0.75);
+    variables.insert_owned("y".to_owned(), vec![-2.0, 0.0, 0.5, 4.0]);
+    variables
+}
+
+/// Results are compared bit by bit, as both evaluators perform the same operations
+fn same_result(a: &Result<Box<dyn DynMath>, EvaluationError>, b: &Result<Box<dyn DynMath>, EvaluationError>) -> bool {
+    let same = |x: &Float, y: &Float| x.to_bits() == y.to_bits() || (x.is_nan() && y.is_nan());
+    match (a, b) {
+        (Ok(a), Ok(b)) => match (a.category(), b.category()) {
+            (Category::Number, Category::Number) => same(&a.as_number(), &b.as_number()),
+            (Category::Array, Category::Array) => {
+                a.shape() == b.shape() && a.iterate().zip(b.iterate()).all(|(x, y)| same(x, y))
+            }
+            _ => false
+        },
+        (Err(a), Err(b)) => a.to_string() == b.to_string(),
+        _ => false
+    }
+}
+
+#[test]
+fn printed_expressions_parse_to_the_same_tree() {
+    for seed in 0..CASES {
+        let ast = ExprGenerator::new(seed).ast();
+        let printed = ast.to_expression();
+        let reparsed = AST::from_expression(&printed)
+            .unwrap_or_else(|e| panic!("seed {}: `{}` does not parse: {:?}", seed, printed, e));
+        assert!(ast.tree.same_structure(&reparsed.tree), "seed {}: `{}`", seed, printed);
+        // printing is stable
+        assert_eq!(reparsed.to_expression(), printed, "seed {}", seed);
+    }
+}
+
+#[test]
+fn pretty_printing_preserves_the_tree() {
+    for seed in 0..CASES {
+        let printed = ExprGenerator::new(seed).ast().to_expression();
+        let pretty = TokenStream::new(&printed).unwrap().to_pretty_string();
+        let a = AST::from_expression(&printed).unwrap();
+        let b = AST::from_expression(&pretty).unwrap();
+        assert!(a.tree.same_structure(&b.tree), "seed {}: `{}` -> `{}`", seed, printed, pretty);
+    }
+}
+
+#[test]
+fn parser_and_builder_agree_on_evaluation() {
+    let inputs = inputs();
+    for seed in 0..CASES {
+        let mut generator = ExprGenerator::new(seed);
+        let expression = generator.expression();
+        let printed = expression.clone().build().unwrap().to_expression();
+
+        let mut built = expression.build_evaluator().unwrap();
+        let mut parsed = Evaluator::new(&printed, &generator.variables()).unwrap();
+        assert_eq!(built.fingerprint(), parsed.fingerprint(), "seed {}: `{}`", seed, printed);
+        assert!(same_result(&built.evaluate(&inputs), &parsed.evaluate(&inputs)), "seed {}: `{}`", seed, printed);
+    }
+}
@@ -0,0 +1,134 @@
+//! The evaluation semantics that are decisions, not accidents of the
+//! implementation: precedence and associativity of the operators, division by
+//! zero, NaN and the reductions of empty arrays. Changing a row of these tables
+//! changes the meaning of saved formulas, so it should be deliberate.
+use dymex::*;
+
+
+#[derive(Debug)]
+enum Expect {
+    Number(Float),
+    NaN,
+    Bool(bool),
+    Array(&'static [Float]),
+    Error,
+}
+
+/// Evaluates `expression` with `x = 2`, `v = [1, 2, 3]`, `n = NaN`, the empty array
+/// `empty` and the empty `TimeSeries` and `LazyArray` `series` and `lazy`
+fn evaluate(expression: &str) -> Result<Box<dyn DynMath>, String> {
+    let mut inputs = InputVars::new();
+    inputs.insert_owned("x".into(), 2.0);
+    inputs.insert_owned("v".into(), vec![1.0, 2.0, 3.0]);
+    inputs.insert_owned("n".into(), float::NAN);
+    inputs.insert_owned("empty".into(), Vec::<Float>::new());
+    inputs.insert_owned("series".into(), TimeSeries::new(vec![], vec![]));
+    inputs.insert_owned("lazy".into(), LazyArray::from_fn(0, |i| i as Float));
+    let mut evaluator = Evaluator::new(expression, &["x", "v", "n", "empty", "series", "lazy"]).map_err(|e| format!("{:?}", e))?;
+    evaluator.evaluate(&inputs).map_err(|e| format!("{:?}", e))
+}
+
+fn matches(result: &Result<Box<dyn DynMath>, String>, expected: &Expect) -> bool {
+    match (result, expected) {
+        (Err(_), Expect::Error) => true,
+        (Err(_), _) | (Ok(_), Expect::Error) => false,
+        (Ok(r), Expect::Number(x)) => r.downcast_ref::<Float>() == Some(x),
+        (Ok(r), Expect::NaN) => r.downcast_ref::<Float>().is_some_and(|x| x.is_nan()),
+        (Ok(r), Expect::Bool(b)) => r.downcast_ref::<bool>() == Some(b),
+        (Ok(r), Expect::Array(v)) => r.downcast_ref::<Vec<Float>>().is_some_and(|r| r == v),
+    }
+}
+
+fn check(table: &[(&str, Expect)]) {
+    let failures: Vec<String> = table.iter()
+        .map(|(expression, expected)| (expression, expected, evaluate(expression)))
+        .filter(|(_, expected, result)| !matches(result, expected))
+        .map(|(expression, expected, result)| format!("{}: expected {:?}, got {:?}", expression, expected,
+            result.map(|r| format!("{} {}", r.type_name(), r))))
+        .collect();
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+
+#[test]
+fn precedence() {
+    check(&[
+        ("1 + 2 * 3", Expect::Number(7.0)),
+        ("(1 + 2) * 3", Expect::Number(9.0)),
+        ("2 * 3 ** 2", Expect::Number(18.0)),
+        ("10 - 4 - 3", Expect::Number(3.0)),
+        ("12 / 3 / 2", Expect::Number(2.0)),
+        ("1 + 2 > 2", Expect::Bool(true)),
+        ("x > 1 and x < 3", Expect::Bool(true)),
+        ("x < 1 or x > 1 and x < 3", Expect::Bool(true)),
+        ("v * 2 + 1", Expect::Array(&[3.0, 5.0, 7.0])),
+        // comparisons give bools, which are not numbers
+        ("x + (x > 1)", Expect::Error),
+    ]);
+}
+
+#[test]
+fn power() {
+    check(&[
+        // right associative
+        ("2 ** 3 ** 2", Expect::Number(512.0)),
+        ("(2 ** 3) ** 2", Expect::Number(64.0)),
+        ("2 ^ 3", Expect::Number(8.0)),
+        // the sign applies to the power
+        ("-x ** 2", Expect::Number(-4.0)),
+        ("(-x) ** 2", Expect::Number(4.0)),
+        ("x ** -1", Expect::Number(0.5)),
+        ("0 ** 0", Expect::Number(1.0)),
+        ("(-8) ** (1 / 3)", Expect::NaN),
+    ]);
+}
+
+#[test]
+fn division_by_zero() {
+    // IEEE 754: no error, infinities and NaN
+    check(&[
+        ("x / 0", Expect::Number(float::INFINITY)),
+        ("-x / 0", Expect::Number(float::NEG_INFINITY)),
+        ("0 / 0", Expect::NaN),
+        ("v / 0", Expect::Array(&[float::INFINITY, float::INFINITY, float::INFINITY])),
+        ("log(0)", Expect::Number(float::NEG_INFINITY)),
+        ("sqrt(-1)", Expect::NaN),
+    ]);
+}
+
+#[test]
+fn nan() {
+    // NaN propagates through arithmetic, comparisons with NaN are false
+    check(&[
+        ("n + 1", Expect::NaN),
+        ("n * 0", Expect::NaN),
+        ("sin(n)", Expect::NaN),
+        ("n == n", Expect::Bool(false)),
+        ("n != n", Expect::Bool(true)),
+        ("n > 0 or n <= 0", Expect::Bool(false)),
+        ("sum(v * n)", Expect::NaN),
+        ("avg(v + n)", Expect::NaN),
+        // NaN is a number, not a missing value
+        ("isna(n)", Expect::Bool(false)),
+        ("coalesce(n, 1)", Expect::NaN),
+    ]);
+}
+
+#[test]
+fn empty_array_reductions() {
+    // an error by default, see `EmptyReduction` for the identity values
+    check(&[
+        ("sum(empty)", Expect::Error),
+        ("prod(empty)", Expect::Error),
+        ("min(empty)", Expect::Error),
+        ("max(empty)", Expect::Error),
+        ("range(empty)", Expect::Error),
+        ("avg(empty)", Expect::Error),
+        ("std(empty)", Expect::Error),
+        ("avg(series)", Expect::Error),
+        ("min(series)", Expect::Error),
+        ("sum(lazy)", Expect::Error),
+        ("min(lazy)", Expect::Error),
+        ("empty * 2", Expect::Array(&[])),
+    ]);
+}
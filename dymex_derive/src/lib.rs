@@ -0,0 +1,128 @@
+//! Derive macros for exposing host types to dymex expressions
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitStr};
+
+
+/// Implements `dymex::DynFields` for a struct with named fields.
+///
+/// Every field has to implement `DynMath` (and is cloned on access).
+/// Fields can be renamed with `#[dymex(rename = "name")]` or hidden from
+/// expressions with `#[dymex(skip)]`.
+#[proc_macro_derive(DynFields, attributes(dymex))]
+pub fn derive_dyn_fields(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match impl_dyn_fields(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn impl_dyn_fields(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let (keys, idents) = exposed_fields(input)?;
+
+    Ok(quote! {
+        impl #impl_generics ::dymex::DynFields for #name #ty_generics #where_clause {
+            fn field_names(&self) -> &'static [&'static str] {
+                &[#(#keys),*]
+            }
+
+            fn dyn_field(&self, field_name: &str)
+            -> ::std::result::Result<::std::boxed::Box<dyn ::dymex::DynMath>, ::dymex::EvaluationError> {
+                match field_name {
+                    #(#keys => ::dymex::field_value(&self.#idents),)*
+                    _ => ::dymex::invalid_field(stringify!(#name), field_name)
+                }
+            }
+        }
+    })
+}
+
+/// Implements `dymex::DymexVars` for a struct with named fields.
+///
+/// Every field becomes an input variable named after the field. Fields have
+/// to implement `DymexVars` themselves: `Float` and `Vec<Float>` fields are
+/// inserted as values, nested structs deriving `DymexVars` are inserted with
+/// dotted names (`outer.inner.x`). Fields can be renamed with
+/// `#[dymex(rename = "name")]` or skipped with `#[dymex(skip)]`.
+#[proc_macro_derive(DymexVars, attributes(dymex))]
+pub fn derive_dymex_vars(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match impl_dymex_vars(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn impl_dymex_vars(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let (keys, idents) = exposed_fields(input)?;
+
+    Ok(quote! {
+        impl #impl_generics ::dymex::DymexVars for #name #ty_generics #where_clause {
+            fn insert_vars(&self, vars: &mut ::dymex::InputVars, prefix: &str) {
+                #(::dymex::DymexVars::insert_vars(&self.#idents, vars, &::dymex::var_path(prefix, #keys));)*
+            }
+        }
+    })
+}
+
+/// Names and identifiers of the fields that are not skipped
+fn exposed_fields(input: &DeriveInput) -> syn::Result<(Vec<String>, Vec<&syn::Ident>)> {
+    let mut keys = Vec::new();
+    let mut idents = Vec::new();
+    for field in named_fields(input)? {
+        let options = FieldOptions::parse(field)?;
+        if options.skip {
+            continue;
+        }
+        let ident = field.ident.as_ref().unwrap();
+        keys.push(options.rename.unwrap_or_else(|| ident.to_string()));
+        idents.push(ident);
+    }
+    Ok((keys, idents))
+}
+
+fn named_fields(input: &DeriveInput) -> syn::Result<&syn::punctuated::Punctuated<syn::Field, syn::token::Comma>> {
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => Ok(&fields.named),
+            _ => Err(syn::Error::new_spanned(&input.ident, "only structs with named fields are supported")),
+        },
+        _ => Err(syn::Error::new_spanned(&input.ident, "only structs are supported")),
+    }
+}
+
+/// Options set with `#[dymex(...)]` on a field
+#[derive(Default)]
+struct FieldOptions {
+    skip: bool,
+    rename: Option<String>,
+}
+impl FieldOptions {
+    fn parse(field: &syn::Field) -> syn::Result<Self> {
+        let mut options = Self::default();
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("dymex")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("skip") {
+                    options.skip = true;
+                    Ok(())
+                } else if meta.path.is_ident("rename") {
+                    let name: LitStr = meta.value()?.parse()?;
+                    options.rename = Some(name.value());
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported dymex attribute"))
+                }
+            })?;
+        }
+        Ok(options)
+    }
+}